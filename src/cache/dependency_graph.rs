@@ -31,6 +31,19 @@ impl DependencyGraph {
         println!("{dot:?}");
     }
 
+    /// Returns each definition in the graph along with every global definition it references
+    pub fn dependencies(&self) -> HashMap<DefinitionInfoId, Vec<DefinitionInfoId>> {
+        self.graph
+            .node_indices()
+            .map(|node| {
+                let mut dependencies: Vec<_> =
+                    self.graph.neighbors(node).map(|dependency| self.graph[dependency]).collect();
+                dependencies.sort();
+                (self.graph[node], dependencies)
+            })
+            .collect()
+    }
+
     pub fn set_definition(&mut self, definition: DefinitionInfoId) {
         self.current_definition = Some(definition);
     }
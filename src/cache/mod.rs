@@ -15,21 +15,25 @@
 //! kept in the ModuleCache and instead should be in a special data structure for
 //! the relevant phase. An example is the `llvm::Generator` in the llvm codegen phase.
 use crate::cache::unsafecache::UnsafeCache;
-use crate::error::location::{Locatable, Location};
+use crate::error::location::{Expansion, Locatable, Location};
 use crate::error::{Diagnostic, DiagnosticKind, ErrorType};
 use crate::nameresolution::NameResolver;
-use crate::parser::ast::{Ast, Definition, EffectDefinition, Extern, TraitDefinition, TraitImpl};
+use crate::parser::ast::{Ast, Definition, EffectDefinition, Extern, LambdaKind, TraitDefinition, TraitImpl};
 use crate::types::traits::{ConstraintSignature, RequiredImpl, RequiredTrait, TraitConstraintId};
-use crate::types::typechecker::UnificationBindings;
-use crate::types::{FunctionType, GeneralizedType, Kind, LetBindingLevel, TypeBinding};
+use crate::types::typechecker::{StructUnifyMode, TypeBindings, CURRENT_LEVEL};
+use crate::types::typed::clear_inferred_types;
+use crate::types::{FunctionType, GeneralizedType, Kind, LetBindingLevel, TypeBinding, INITIAL_LEVEL};
 use crate::types::{Type, TypeInfo, TypeInfoBody, TypeInfoId, TypeVariableId};
 use crate::util::{fmap, stdlib_dir};
 
 use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::Ordering;
 
 use self::dependency_graph::DependencyGraph;
 
@@ -66,6 +70,10 @@ pub struct ModuleCache<'a> {
     /// the lifetime of the file that was read from.
     pub filepaths: Vec<PathBuf>,
 
+    /// Holds every macro expansion referenced by Locations within generated code.
+    /// Like the parse trees, these are kept until the ModuleCache itself is freed.
+    pub expansions: UnsafeCache<'a, Expansion<'a>>,
+
     /// Maps DefinitionInfoId -> DefinitionInfo
     /// Filled out during name resolution.
     pub definition_infos: Vec<DefinitionInfo<'a>>,
@@ -82,6 +90,21 @@ pub struct ModuleCache<'a> {
     /// resolution and are unified during type inference
     pub type_bindings: Vec<TypeBinding>,
 
+    /// Memoizes the free type variables reachable from each bound type variable, in the order
+    /// they are found, to avoid repeatedly walking large types when generalizing. An entry is
+    /// removed when its type variable is rebound and is recomputed if any of its free type
+    /// variables have since been bound. See `typechecker::find_typevars_in_typevar_binding`.
+    pub free_typevars: RefCell<HashMap<TypeVariableId, Rc<Vec<TypeVariableId>>>>,
+
+    /// The number of times a bound type variable's free type variables were found in
+    /// `free_typevars` rather than by walking its binding again.
+    pub free_typevars_memo_hits: Cell<usize>,
+
+    /// Names pinned to specific type variables. When displaying an unbound type variable
+    /// these are used instead of the automatically-assigned a, b, c, etc. This is purely a
+    /// display aid, e.g. for stable and readable error output in tests.
+    pub type_variable_names: HashMap<TypeVariableId, Rc<str>>,
+
     /// Maps TypeInfoId -> TypeInfo
     /// Filled out during name resolution
     pub type_infos: Vec<TypeInfo<'a>>,
@@ -131,11 +154,100 @@ pub struct ModuleCache<'a> {
     /// The number of errors emitted by the program
     pub error_count: usize,
 
+    /// Maps DefinitionInfoId -> the location of each `ast::Variable` referring to it.
+    /// Filled out during type inference. Used by tooling to find all usages of a definition.
+    pub references: HashMap<DefinitionInfoId, Vec<Location<'a>>>,
+
+    /// Maps DefinitionInfoId -> the instantiation_mapping of each `ast::Variable` referring to it.
+    /// Filled out during type inference. Used to find which types each definition is used at.
+    pub instantiations: HashMap<DefinitionInfoId, Vec<Rc<TypeBindings>>>,
+
+    /// Each definition of a reference which was kept monomorphic by the value restriction
+    /// while the type it refers to still had type variables, e.g. `r = &[| |]`. Used to
+    /// explain why a later use of the reference at a different type fails.
+    pub monomorphic_references: HashSet<DefinitionInfoId>,
+
+    /// The location and type of each typed hole `_` found during inference whose type has
+    /// not been reported yet. Each is reported once its enclosing global definition is inferred.
+    pub type_holes: Vec<(Location<'a>, Type)>,
+
+    /// Set once the occurs check has warned about a deeply nested type,
+    /// so the warning is only issued once per program.
+    pub warned_deep_type_recursion: bool,
+
+    /// The return type of each function currently being inferred, innermost last. Used to
+    /// check the values given to `return` and `break` expressions.
+    pub return_types: Vec<ReturnTarget<'a>>,
+
+    /// If set, warn when a non-final statement in a sequence produces a value
+    /// which is discarded. Opt-in via `--warn-unused-values`.
+    pub warn_unused_values: bool,
+
+    /// How a struct type unifies with a data type it is used as.
+    /// Set to `ExactOnly` via `--exact-struct-unification`.
+    pub struct_unification: StructUnifyMode,
+
+    /// If set, an owned reference may be used wherever a shared one is expected.
+    /// Disabled via `--explicit-share`, requiring a call to `share` instead.
+    pub implicit_share: bool,
+
+    /// If set, an integer argument may be passed to a function expecting a larger integer
+    /// type it can be losslessly widened into. Opt-in via `--implicit-int-widening`.
+    pub implicit_integer_widening: bool,
+
+    /// If set, print the nested unifications and partial type bindings that led to each
+    /// failed unification. This is for debugging the typechecker itself and is opt-in via
+    /// `--trace-unification`.
+    pub trace_unification: bool,
+
+    /// The platform the program is being compiled for, which selects the
+    /// active branch of each `#if` expression. Defaults to the host platform.
+    pub target: CompilationTarget,
+
+    /// Each `comptime` expression found in a type during name resolution.
+    /// These are all evaluated at the start of type inference.
+    pub comptime_expressions: Vec<ComptimeExpression<'a>>,
+
     pub file_cache: FileCache,
+
+    /// If set, `infer_ast` saves the inference-related state of the cache before inference begins
+    /// so that a tool may later undo it with `reset_inference`. Off by default since the checkpoint
+    /// copies every type binding and definition type.
+    pub keep_inference_checkpoint: bool,
+
+    /// The inference-related state of the cache from just before type inference began.
+    /// This is used by `reset_inference` to allow type inference to be re-run on the same Ast.
+    inference_checkpoint: Option<InferenceCheckpoint>,
+}
+
+/// A snapshot of each part of the cache that is mutated during type inference.
+/// Name resolution never binds type variables, so every binding here is still unbound.
+#[derive(Debug)]
+struct InferenceCheckpoint {
+    type_bindings: Vec<TypeBinding>,
+    definition_types: Vec<(Option<GeneralizedType>, Vec<RequiredTrait>)>,
+
+    /// Diagnostics issued before inference, e.g. by name resolution, are kept by `reset_inference`
+    diagnostic_count: usize,
+    error_count: usize,
 }
 
 pub type FileCache = HashMap<PathBuf, String>;
 
+/// A function whose body is currently being inferred, which `return` and `break` expressions may target
+#[derive(Debug)]
+pub struct ReturnTarget<'a> {
+    pub return_type: Type,
+
+    /// Whether the function is the body of a loop
+    pub kind: LambdaKind,
+
+    /// Set if the function's return type is inferred from its body. Each value given to `return`
+    /// is then only checked once the body is inferred, so that a `return` which disagrees with the
+    /// body's final expression is reported at the `return` rather than at the final expression.
+    pub deferred_returns: Option<Vec<(Type, Location<'a>)>>,
+}
+
 #[derive(Debug)]
 pub struct MutualRecursionSet {
     pub root_definition: DefinitionInfoId,
@@ -246,6 +358,10 @@ pub struct DefinitionInfo<'a> {
     /// False by default.
     pub ignore_unused_warning: bool,
 
+    /// The message from this definition's `@deprecated("message")` attribute, if any.
+    /// Each use of a deprecated definition issues a warning.
+    pub deprecated: Option<String>,
+
     /// True if this was declared as a locally mutable variable.
     /// This is separate from whether the type is a mutable reference or not.
     pub mutable: bool,
@@ -286,6 +402,9 @@ pub struct VariableId(pub usize);
 pub struct VariableInfo<'a> {
     pub required_impls: Vec<RequiredImpl>,
     pub name: String,
+
+    /// The definition this variable referred to during name resolution, if any.
+    pub definition: Option<DefinitionInfoId>,
     pub location: Location<'a>,
 }
 
@@ -314,6 +433,22 @@ pub struct TraitInfo<'a> {
 
     pub location: Location<'a>,
 
+    /// The module this trait was declared in. Impls of a
+    /// sealed trait must be defined in this same module.
+    pub module: ModuleId,
+
+    /// True if this trait was declared `sealed`
+    pub sealed: bool,
+
+    /// The trait arguments of each impl required by a `@closed(...)` attribute, if any.
+    /// Filled out during name resolution and checked against this trait's impls afterward.
+    pub closed_impls: Option<Vec<Vec<Type>>>,
+
+    /// The superclasses from this trait's `given` clause, e.g. `Eq a` in `trait Ord a given Eq a`.
+    /// These refer to this trait's typeargs and are implied by any constraint on this trait.
+    /// They are filled out when the trait is defined during name resolution.
+    pub superclasses: Vec<ConstraintSignature>,
+
     /// The definitions included in this trait defintion.
     /// The term `defintion` is used somewhat loosely here
     /// since none of these functions/variables have bodies.
@@ -359,6 +494,10 @@ pub struct ImplInfo<'a> {
     /// They contain a unique TraitConstraintId that is used to map the
     /// constraints inside the impl's definitions.
     pub given: Vec<ConstraintSignature>,
+
+    /// The negated `not Trait a` constraints from the 'given' clause of a trait impl.
+    /// The impl only applies if none of these have a matching impl.
+    pub negative_given: Vec<ConstraintSignature>,
     pub trait_impl: &'a mut TraitImpl<'a>,
 }
 
@@ -387,6 +526,43 @@ impl<'a> Locatable<'a> for EffectInfo<'a> {
     }
 }
 
+/// A `comptime` expression used within a type, e.g. the `comptime n * 2` in `Array (comptime n * 2) I32`.
+/// Name resolution replaces the expression with `typ`, a fresh type variable which is bound
+/// to the expression's value once it is evaluated during type inference.
+#[derive(Debug)]
+pub struct ComptimeExpression<'a> {
+    pub expr: Box<Ast<'a>>,
+    pub typ: TypeVariableId,
+    pub location: Location<'a>,
+}
+
+/// The operating system and its family (e.g. `linux` and `unix`) that a program is compiled for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompilationTarget {
+    pub os: String,
+    pub family: String,
+}
+
+impl CompilationTarget {
+    /// The target of the platform the compiler itself is running on
+    pub fn host() -> Self {
+        CompilationTarget { os: std::env::consts::OS.to_string(), family: std::env::consts::FAMILY.to_string() }
+    }
+
+    /// True if the given `#if` configuration names this target's operating system or family
+    pub fn matches(&self, configuration: &str) -> bool {
+        configuration == self.os || configuration == self.family
+    }
+}
+
+/// Returns the global definitions each global definition depends on, as recorded during
+/// name resolution. Combined with the mutual recursion sets found during type inference,
+/// this can be used to find definitions which are independent of one another.
+#[allow(dead_code)]
+pub fn definition_dependency_graph(cache: &ModuleCache) -> HashMap<DefinitionInfoId, Vec<DefinitionInfoId>> {
+    cache.global_dependency_graph.dependencies()
+}
+
 pub fn cached_read<'a>(file_cache: &'a FileCache, path: &Path) -> Option<Cow<'a, str>> {
     match file_cache.get(path) {
         Some(contents) => Some(Cow::Borrowed(contents)),
@@ -411,9 +587,13 @@ impl<'a> ModuleCache<'a> {
             parse_trees: UnsafeCache::default(),
             name_resolvers: UnsafeCache::default(),
             filepaths: Vec::new(),
+            expansions: UnsafeCache::default(),
             definition_infos: Vec::new(),
             variable_infos: Vec::new(),
             type_bindings: Vec::new(),
+            free_typevars: RefCell::new(HashMap::new()),
+            free_typevars_memo_hits: Cell::new(0),
+            type_variable_names: HashMap::new(),
             type_infos: Vec::new(),
             trait_infos: Vec::new(),
             impl_infos: Vec::new(),
@@ -425,7 +605,22 @@ impl<'a> ModuleCache<'a> {
             global_dependency_graph: DependencyGraph::default(),
             diagnostics: Vec::new(),
             error_count: 0,
+            warned_deep_type_recursion: false,
+            references: HashMap::new(),
+            instantiations: HashMap::new(),
+            monomorphic_references: HashSet::new(),
+            type_holes: Vec::new(),
+            return_types: Vec::new(),
+            warn_unused_values: false,
+            struct_unification: StructUnifyMode::default(),
+            implicit_share: true,
+            implicit_integer_widening: false,
+            trace_unification: false,
+            target: CompilationTarget::host(),
+            comptime_expressions: Vec::new(),
             file_cache,
+            keep_inference_checkpoint: false,
+            inference_checkpoint: None,
         }
     }
 
@@ -446,6 +641,68 @@ impl<'a> ModuleCache<'a> {
         self.diagnostics.push(diagnostic);
     }
 
+    /// Remember the current state of type inference so that it may later be restored
+    /// by `reset_inference`. This is called by `infer_ast` before inference begins
+    /// if `keep_inference_checkpoint` is set.
+    pub(crate) fn save_inference_checkpoint(&mut self) {
+        let type_bindings = self.type_bindings.clone();
+        let definition_types = fmap(&self.definition_infos, |info| (info.typ.clone(), info.required_traits.clone()));
+        let (diagnostic_count, error_count) = (self.diagnostics.len(), self.error_count);
+        self.inference_checkpoint =
+            Some(InferenceCheckpoint { type_bindings, definition_types, diagnostic_count, error_count });
+    }
+
+    /// Undo the results of type inference so that `infer_ast` can be run on the same Ast again.
+    /// This clears the inferred types of every node in the given Ast and each parsed module,
+    /// restores all type variables and definition types to their state before inference,
+    /// and removes any diagnostics issued during inference. Does nothing unless
+    /// `keep_inference_checkpoint` was set before inference.
+    #[allow(dead_code)]
+    pub fn reset_inference(&mut self, ast: &mut Ast<'a>) {
+        let checkpoint = match self.inference_checkpoint.take() {
+            Some(checkpoint) => checkpoint,
+            None => return, // Type inference was never run so there is nothing to reset
+        };
+
+        clear_inferred_types(ast);
+        let mut module = 0;
+        while let Some(tree) = self.parse_trees.get_mut(module) {
+            clear_inferred_types(tree);
+            module += 1;
+        }
+
+        for comptime in &mut self.comptime_expressions {
+            clear_inferred_types(&mut comptime.expr);
+        }
+
+        self.type_bindings = checkpoint.type_bindings;
+        self.free_typevars.get_mut().clear();
+        self.definition_infos.truncate(checkpoint.definition_types.len());
+
+        for (info, (typ, required_traits)) in self.definition_infos.iter_mut().zip(checkpoint.definition_types) {
+            info.typ = typ;
+            info.required_traits = required_traits;
+            info.undergoing_type_inference = false;
+            info.mutually_recursive_set = None;
+            info.mutually_recursive_variables.clear();
+        }
+
+        for info in self.variable_infos.iter_mut() {
+            info.required_impls.clear();
+        }
+
+        self.mutual_recursion_sets.clear();
+        self.call_stack.clear();
+        self.diagnostics.truncate(checkpoint.diagnostic_count);
+        self.error_count = checkpoint.error_count;
+        self.references.clear();
+        self.instantiations.clear();
+        self.monomorphic_references.clear();
+        self.type_holes.clear();
+        self.warned_deep_type_recursion = false;
+        CURRENT_LEVEL.store(INITIAL_LEVEL, Ordering::SeqCst);
+    }
+
     pub fn display_diagnostics(&self) {
         for diagnostic in &self.diagnostics {
             let diagnostic = diagnostic.display(self);
@@ -481,6 +738,12 @@ impl<'a> ModuleCache<'a> {
         unsafe { std::mem::transmute(path) }
     }
 
+    /// Records a new expansion of the given macro for Locations within it to refer to
+    pub fn push_expansion(&mut self, macro_name: String, call_site: Location<'a>) -> &'a Expansion<'a> {
+        let index = self.expansions.push(Expansion { macro_name, call_site });
+        self.expansions.get_mut(index).unwrap()
+    }
+
     pub fn push_definition(&mut self, name: &str, global: bool, location: Location<'a>) -> DefinitionInfoId {
         let id = self.definition_infos.len();
         self.definition_infos.push(DefinitionInfo {
@@ -498,6 +761,7 @@ impl<'a> ModuleCache<'a> {
             undergoing_type_inference: false,
             mutually_recursive_variables: vec![],
             ignore_unused_warning: name.starts_with('_'),
+            deprecated: None,
         });
         DefinitionInfoId(id)
     }
@@ -508,7 +772,7 @@ impl<'a> ModuleCache<'a> {
 
     pub fn push_type_info(&mut self, name: String, args: Vec<TypeVariableId>, location: Location<'a>) -> TypeInfoId {
         let id = self.type_infos.len();
-        let type_info = TypeInfo { name, args, location, uses: 0, body: TypeInfoBody::Unknown };
+        let type_info = TypeInfo { name, args, location, uses: 0, body: TypeInfoBody::Unknown, linear: false };
         self.type_infos.push(type_info);
         TypeInfoId(id)
     }
@@ -538,9 +802,10 @@ impl<'a> ModuleCache<'a> {
 
     pub fn push_trait_definition(
         &mut self, name: String, typeargs: Vec<TypeVariableId>, fundeps: Vec<TypeVariableId>,
-        trait_node: Option<&'a mut TraitDefinition<'a>>, location: Location<'a>,
+        trait_node: Option<&'a mut TraitDefinition<'a>>, module: ModuleId, location: Location<'a>,
     ) -> TraitInfoId {
         let id = self.trait_infos.len();
+        let sealed = trait_node.as_ref().is_some_and(|node| node.sealed);
         self.trait_infos.push(TraitInfo {
             name,
             typeargs,
@@ -548,14 +813,20 @@ impl<'a> ModuleCache<'a> {
             definitions: vec![],
             trait_node,
             location,
+            module,
+            sealed,
+            closed_impls: None,
+            superclasses: vec![],
             uses: 0,
         });
         TraitInfoId(id)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn push_trait_impl(
         &mut self, trait_id: TraitInfoId, typeargs: Vec<Type>, definitions: Vec<DefinitionInfoId>,
-        trait_impl: &'a mut TraitImpl<'a>, given: Vec<ConstraintSignature>, location: Location<'a>,
+        trait_impl: &'a mut TraitImpl<'a>, given: Vec<ConstraintSignature>, negative_given: Vec<ConstraintSignature>,
+        location: Location<'a>,
     ) -> ImplInfoId {
         let id = self.impl_infos.len();
 
@@ -566,7 +837,8 @@ impl<'a> ModuleCache<'a> {
             self[*definition].trait_impl = Some(ImplInfoId(id));
         }
 
-        self.impl_infos.push(ImplInfo { trait_id, typeargs, definitions, location, given, trait_impl });
+        let info = ImplInfo { trait_id, typeargs, definitions, location, given, negative_given, trait_impl };
+        self.impl_infos.push(info);
         ImplInfoId(id)
     }
 
@@ -584,9 +856,11 @@ impl<'a> ModuleCache<'a> {
         ImplScopeId(id)
     }
 
-    pub fn push_variable(&mut self, name: String, location: Location<'a>) -> VariableId {
+    pub fn push_variable(
+        &mut self, name: String, definition: Option<DefinitionInfoId>, location: Location<'a>,
+    ) -> VariableId {
         let id = self.variable_infos.len();
-        self.variable_infos.push(VariableInfo { required_impls: vec![], name, location });
+        self.variable_infos.push(VariableInfo { required_impls: vec![], name, definition, location });
         VariableId(id)
     }
 
@@ -703,17 +977,18 @@ impl<'a> ModuleCache<'a> {
     }
 
     pub fn bind(&mut self, id: TypeVariableId, binding: Type) {
-        if crate::types::typechecker::occurs(
-            id,
-            LetBindingLevel(0),
-            &binding,
-            &mut UnificationBindings::empty(),
-            100,
-            self,
-        ) {
+        if crate::types::typechecker::contains_typevar(id, &binding, self) {
             eprintln!("Binding Recursive! {} occurs in {}", id.0, binding.debug(self));
         }
         self.type_bindings[id.0] = TypeBinding::Bound(binding);
+        self.free_typevars.get_mut().remove(&id);
+    }
+
+    /// Remove every type variable created after the first `len`, forgetting
+    /// any free type variables memoized for them since their ids may be reused.
+    pub fn truncate_type_bindings(&mut self, len: usize) {
+        self.type_bindings.truncate(len);
+        self.free_typevars.get_mut().retain(|id, _| id.0 < len);
     }
 
     pub fn follow_bindings_shallow<'b>(&'b self, typ: &'b Type) -> &'b Type {
@@ -730,7 +1005,7 @@ impl<'a> ModuleCache<'a> {
         let recur = |typ| self.follow_bindings(typ);
 
         match typ {
-            Type::Primitive(_) | Type::UserDefined(_) | Type::Tag(_) => typ.clone(),
+            Type::Primitive(_) | Type::UserDefined(_) | Type::Tag(_) | Type::Universe(_) => typ.clone(),
 
             Type::Function(function_type) => {
                 let parameters = fmap(&function_type.parameters, recur);
@@ -759,14 +1034,15 @@ impl<'a> ModuleCache<'a> {
                 let lifetime = Box::new(recur(lifetime));
                 Type::Ref { mutability, sharedness, lifetime }
             },
-            Type::Struct(fields, replacement) => {
+            Type::Struct(fields, replacement, kind) => {
                 if let TypeBinding::Bound(typ) = &self.type_bindings[replacement.0] {
                     return recur(typ);
                 }
 
                 let fields = fields.iter().map(|(name, typ)| (name.clone(), recur(typ))).collect();
-                Type::Struct(fields, *replacement)
+                Type::Struct(fields, *replacement, *kind)
             },
+            Type::Rec(binder, body) => Type::Rec(*binder, Box::new(recur(body))),
             Type::Effects(effect_set) => {
                 let mut effects = effect_set.flatten(self);
 
@@ -64,6 +64,35 @@ pub struct Cli {
     /// Print out the type of each definition
     #[arg(long, short = 't')]
     pub show_types: bool,
+
+    /// Print out the inferred type of each binding without a type annotation, for use as editor inlay hints
+    #[arg(long)]
+    pub show_inlay_hints: bool,
+
+    /// Print any warnings found while checking the program, even if there were no errors
+    #[arg(long)]
+    pub show_warnings: bool,
+
+    /// Warn when a statement in a block produces a non-unit value which is then discarded
+    #[arg(long)]
+    pub warn_unused_values: bool,
+
+    /// Require a struct type to have exactly the fields of a data type it is used as, rather than a subset
+    #[arg(long)]
+    pub exact_struct_unification: bool,
+
+    /// Require owned references to be explicitly converted with `share` before being used as shared references
+    #[arg(long)]
+    pub explicit_share: bool,
+
+    /// Allow an integer argument to be implicitly widened to a larger integer parameter type when no value is lost
+    #[arg(long)]
+    pub implicit_int_widening: bool,
+
+    /// Print the nested unifications and partial type bindings leading to each failed unification.
+    /// This is only meant for debugging the compiler itself.
+    #[arg(long)]
+    pub trace_unification: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone, ValueEnum)]
@@ -57,6 +57,19 @@ pub struct Location<'c> {
     pub filename: &'c Path,
     pub start: Position,
     pub end: EndPosition,
+
+    /// Set if this location refers to code generated by a macro expansion.
+    /// Following each expansion's call site gives the full expansion backtrace.
+    pub expansion: Option<&'c Expansion<'c>>,
+}
+
+/// A single macro expansion. Any code produced by the expansion has its
+/// Location point to this so diagnostics within it can be traced back to the macro.
+/// Expansions are created with `ModuleCache::push_expansion`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Expansion<'c> {
+    pub macro_name: String,
+    pub call_site: Location<'c>,
 }
 
 impl<'c> Ord for Location<'c> {
@@ -73,7 +86,7 @@ impl<'c> PartialOrd for Location<'c> {
 
 impl<'c> Location<'c> {
     pub fn new(filename: &'c Path, start: Position, end: EndPosition) -> Location<'c> {
-        Location { filename, start, end }
+        Location { filename, start, end, expansion: None }
     }
 
     /// Returns a location to an item that is built into the compiler and is not
@@ -97,10 +110,14 @@ impl<'c> Location<'c> {
         let start = if self.start.index < other.start.index { self.start } else { other.start };
         let end = if self.end.index < other.end.index { other.end } else { self.end };
 
-        Location { filename: self.filename, start, end }
+        Location { filename: self.filename, start, end, expansion: self.expansion }
+    }
+
+    /// Returns this location marked as being part of the given macro expansion
+    pub fn in_expansion(self, expansion: &'c Expansion<'c>) -> Location<'c> {
+        Location { expansion: Some(expansion), ..self }
     }
 
-    #[allow(dead_code)]
     pub fn contains_index(&self, idx: &usize) -> bool {
         (self.start.index..self.end.index).contains(idx)
     }
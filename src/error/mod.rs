@@ -9,12 +9,14 @@
 pub mod location;
 use crate::cache::{cached_read, ModuleCache};
 use crate::error::location::{Locatable, Location};
+use crate::parser::ast::ContractKind;
 
 use colored::ColoredString;
 use colored::*;
 use std::cmp::{max, min};
 use std::fmt::{Display, Formatter};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::SeqCst;
 
@@ -40,7 +42,9 @@ pub enum DiagnosticKind {
     TypeVariableAlreadyInScope(/*type variable name*/ String),
     ItemNotRequiredByTrait(/*item name*/ String, /*trait name*/ String),
     AlreadyInScope(/*item name*/ String),
+    ConflictingExternAndNativeDefinition(/*item name*/ String),
     PreviouslyDefinedHere(/*item name*/ String),
+    InExpansionOfMacro(/*macro name*/ String),
     IncorrectConstructorArgCount(/*item name*/ String, /*actual count*/ usize, /*expected count*/ usize),
 
     // This can be combined with IncorrectArgCount
@@ -52,6 +56,7 @@ pub enum DiagnosticKind {
 
     // Should this be combined with `NotInScope`?
     NoDeclarationFoundInScope(/*variable name*/ String),
+    AmbiguousVariant(/*variant name*/ String),
     CouldNotOpenFileForImport(/*file path*/ PathBuf),
     MissingImplDefinition(/*definition name*/ String),
     EffectsMustBeFunctions,
@@ -86,6 +91,10 @@ pub enum DiagnosticKind {
     InvalidSyntaxInIrrefutablePattern,
     FunctionParameterCountMismatch(/*type*/ String, /*actual*/ usize, /*expected*/ usize),
     MutRefToImmutableVariable(/*name*/ String),
+    MakeBindingMutable(/*name*/ String),
+    SuggestDereference,
+    SuggestReference,
+    SuggestIntegerCast(/*from*/ String, /*to*/ String),
     MutRefToTemporary,
 
     // Type errors are grouped together here for ease of passing different TypeErrorKinds to
@@ -96,11 +105,37 @@ pub enum DiagnosticKind {
     ImplCandidate(/*candidate index*/ usize),
     ImplCandidateWithMoreHidden(/*candidate index*/ usize, /*remaining hidden candidate count*/ usize),
     NoMatchingImpls(/*constraint*/ String),
+    NearMissImpl(/*impl*/ String, /*found arguments*/ String),
     UnreachablePattern,
-    MissingCase(/*case*/ String),
+    NonExhaustiveMatch(/*missing cases*/ Vec<String>),
     UnhandledEffectsInMain(/*effects*/ String),
+    EffectNotAllowed(/*effect*/ String),
+    InvalidMainSignature(/*actual type*/ String),
+    UnsatisfiableImplConstraints(/*constraint*/ String),
+    DerivingRequiresNewtype(/*type name*/ String),
+    DerivingViaMismatch(/*field name*/ String, /*field type*/ String),
+    InvalidVariantResultType(/*variant name*/ String, /*type name*/ String),
+    CannotDeriveTrait(/*trait name*/ String),
+    BreakOutsideOfLoop(/*label*/ Option<String>),
+    BreakOutOfNestedFunction,
+    CaptureNotDeclared(/*variable name*/ String),
     FunctionTypeMismatch(/*actual*/ String, /*expected*/ String),
+    MismatchedSubterms(/*actual*/ String, /*expected*/ String, /*path*/ Rc<String>),
     FunctionEffectsNotSpecified,
+    PureFunctionPerformsEffect(/*effects*/ String),
+    ComptimePerformsEffects(/*effects*/ String),
+    ComptimeNotConstant,
+    PolymorphicRecursionNeedsAnnotation(/*definition name*/ String),
+    MonomorphicDueToValueRestriction(/*definition name*/ String),
+    MonomorphicReference(/*definition name*/ String),
+    TypeVariableEscapes(/*type variable name*/ Rc<String>, /*captured variable name*/ String),
+    DeepTypeRecursion(/*depth*/ u32),
+    UnusedValue(/*type*/ String),
+    DeprecatedUsage(/*item name*/ String, /*message*/ String),
+    PotentialDanglingCapture(/*variable name*/ String),
+    RedundantFieldAssignment(/*field path*/ String),
+    MissingSuperclassImpl(/*impl constraint*/ String, /*superclass constraint*/ String),
+    TypeHole(/*type*/ String),
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -114,28 +149,51 @@ pub enum ErrorType {
 pub enum TypeErrorKind {
     ExpectedUnitTypeFromPattern,
     ExpectedPairTypeFromPattern,
+    ExpectedReferenceTypeFromPattern,
     VariableDoesNotMatchDeclaredType,
     PatternTypeDoesNotMatchAnnotatedType,
     PatternTypeDoesNotMatchDefinitionType,
     FunctionBodyDoesNotMatchReturnType,
+    ReturnedValueDoesNotMatchReturnType,
+    DefaultArgumentTypeMismatch,
+    BreakValueDoesNotMatchLoopType,
     CalledValueIsNotAFunction,
     ArgumentTypeMismatch,
     NonBoolInCondition,
+    NonBoolInContract(ContractKind),
     IfBranchMismatch,
+    ArrayElementMismatch,
     MatchPatternTypeDiffers,
+    MatchReferencePatternTypeDiffers,
     MatchReturnTypeDiffers,
     DoesNotMatchAnnotatedType,
     ExpectedMutable,
 
-    // This taking a String is the reason we can't have nice things (Copy)
-    NoFieldOfType(/*field name*/ String),
+    // This taking a String is the reason we can't have nice things (Copy).
+    // It is kept behind an Rc since a String here would grow every Diagnostic
+    // past the size clippy allows for the error variant of a Result.
+    NoFieldOfType(/*field name*/ Rc<String>),
     AssignToNonMutRef,
     AssignToWrongType,
     HandleBranchMismatch,
     PatternReturnTypeMismatch,
     MonomorphizationError,
     ResumeEnvironmentMismatch,
-    ResumeEffectsMismatch,
+    FunctionalDependencyViolation(/*trait name*/ Rc<String>),
+    OwnershipMismatch,
+    LinearValueUsedTwice(/*variable name*/ Rc<String>),
+    LinearValueUnused(/*variable name*/ Rc<String>),
+    LinearValueUsedInSomeBranches(/*variable name*/ Rc<String>),
+    LinearValueUsedInClosure(/*variable name*/ Rc<String>),
+    ImplForSealedTrait(/*trait name*/ Rc<String>),
+    SkolemEscape(/*type variable name*/ Rc<String>),
+    UniverseInconsistency,
+    CannotConvertToTraitObject(/*trait name*/ Rc<String>),
+    TraitNotObjectSafe(/*trait name*/ Rc<String>),
+    DuplicateFieldName(/*field name*/ Rc<String>),
+    MissingRequiredImpl(/*trait name*/ Rc<String>),
+    UnexpectedImpl(/*trait name*/ Rc<String>),
+    RecordUpdateMismatch,
 
     NeverShown,
 }
@@ -169,6 +227,12 @@ impl Display for DiagnosticKind {
             DiagnosticKind::AlreadyInScope(item) => {
                 write!(f, "{} is already in scope", item)
             },
+            DiagnosticKind::ConflictingExternAndNativeDefinition(item) => {
+                write!(f, "{} is declared both as an extern and as a native definition", item)
+            },
+            DiagnosticKind::InExpansionOfMacro(name) => {
+                write!(f, "in expansion of macro `{name}`")
+            },
             DiagnosticKind::PreviouslyDefinedHere(item) => {
                 write!(f, "{} was previously defined here", item)
             },
@@ -200,6 +264,9 @@ impl Display for DiagnosticKind {
             DiagnosticKind::NoDeclarationFoundInScope(item) => {
                 write!(f, "No declaration for `{}` was found in scope", item)
             },
+            DiagnosticKind::AmbiguousVariant(name) => {
+                write!(f, "`{name}` is ambiguous since multiple types in scope have a constructor with this name. Try qualifying it with its type name, e.g. `MyType.{name}`")
+            },
             DiagnosticKind::CouldNotOpenFileForImport(path) => {
                 write!(f, "Couldn't open file for import: {}.an", path.display())
             },
@@ -271,6 +338,9 @@ impl Display for DiagnosticKind {
             DiagnosticKind::TypeError(TypeErrorKind::ExpectedPairTypeFromPattern, actual, _expected) => {
                 write!(f, "Expected a pair type from this pattern, but found {actual}")
             },
+            DiagnosticKind::TypeError(TypeErrorKind::ExpectedReferenceTypeFromPattern, actual, _expected) => {
+                write!(f, "Expected a reference type from this pattern, but found {actual}")
+            },
             DiagnosticKind::TypeError(TypeErrorKind::VariableDoesNotMatchDeclaredType, actual, expected) => {
                 write!(f, "Variable type {actual} does not match its declared type of {expected}")
             },
@@ -283,6 +353,15 @@ impl Display for DiagnosticKind {
             DiagnosticKind::TypeError(TypeErrorKind::FunctionBodyDoesNotMatchReturnType, actual, expected) => {
                 write!(f, "Function body type {actual} does not match declared return type of {expected}")
             },
+            DiagnosticKind::TypeError(TypeErrorKind::ReturnedValueDoesNotMatchReturnType, actual, expected) => {
+                write!(f, "This returns a value of type {actual} but the function's return type is {expected}")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::DefaultArgumentTypeMismatch, actual, expected) => {
+                write!(f, "This default value of type {actual} does not match the parameter's type {expected}")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::BreakValueDoesNotMatchLoopType, actual, expected) => {
+                write!(f, "This breaks out of the loop with a value of type {actual} but the loop's result type is {expected}")
+            },
             DiagnosticKind::TypeError(TypeErrorKind::CalledValueIsNotAFunction, actual, _expected) => {
                 write!(f, "Value being called is not a function, it is a {actual}")
             },
@@ -292,15 +371,24 @@ impl Display for DiagnosticKind {
             DiagnosticKind::TypeError(TypeErrorKind::NonBoolInCondition, actual, expected) => {
                 write!(f, "{actual} should be a {expected} to be used in an if condition")
             },
+            DiagnosticKind::TypeError(TypeErrorKind::NonBoolInContract(kind), actual, expected) => {
+                write!(f, "{actual} should be a {expected} to be used in a `@{kind}` condition")
+            },
             DiagnosticKind::TypeError(TypeErrorKind::IfBranchMismatch, actual, expected) => {
                 write!(
                     f,
                     "Expected 'then' and 'else' branch types to match, but found {expected} and {actual} respectively"
                 )
             },
+            DiagnosticKind::TypeError(TypeErrorKind::ArrayElementMismatch, actual, expected) => {
+                write!(f, "This array element has type {actual} but the previous elements have type {expected}")
+            },
             DiagnosticKind::TypeError(TypeErrorKind::MatchPatternTypeDiffers, actual, expected) => {
                 write!(f, "This pattern of type {actual} does not match the type {expected} that is being matched on")
             },
+            DiagnosticKind::TypeError(TypeErrorKind::MatchReferencePatternTypeDiffers, actual, expected) => {
+                write!(f, "The type {actual} being matched on does not match this pattern of type {expected}")
+            },
             DiagnosticKind::TypeError(TypeErrorKind::MatchReturnTypeDiffers, actual, expected) => {
                 write!(
                     f,
@@ -335,8 +423,53 @@ impl Display for DiagnosticKind {
                     write!(f, "`resume` should be a closure with an environment type of {expected}, but it was inferred to be {actual}")
                 }
             },
-            DiagnosticKind::TypeError(TypeErrorKind::ResumeEffectsMismatch, actual, expected) => {
-                write!(f, "Expected `resume` to have effects {expected}, but found {actual}")
+            DiagnosticKind::TypeError(TypeErrorKind::FunctionalDependencyViolation(trait_name), actual, expected) => {
+                write!(f, "This impl of {trait_name} determines {actual}, but the previous impl `{expected}` has the same arguments")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::OwnershipMismatch, actual, expected) => {
+                write!(f, "Expected {expected} but found {actual}. Owned references must be converted with `share` before being used as shared ones")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::LinearValueUsedTwice(name), actual, _expected) => {
+                write!(
+                    f,
+                    "`{name}` is used more than once, but values of the linear type {actual} must be used exactly once"
+                )
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::LinearValueUnused(name), actual, _expected) => {
+                write!(f, "`{name}` is never used, but values of the linear type {actual} must be used exactly once")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::LinearValueUsedInSomeBranches(name), actual, _expected) => {
+                write!(f, "`{name}` is not used the same number of times in each branch, but values of the linear type {actual} must be used exactly once")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::LinearValueUsedInClosure(name), actual, _expected) => {
+                write!(f, "`{name}` is used within a closure or loop which may run more than once, but values of the linear type {actual} must be used exactly once")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::ImplForSealedTrait(name), actual, _expected) => {
+                write!(f, "Cannot impl {name} for {actual} outside of the module declaring it since {name} is sealed")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::SkolemEscape(name), actual, expected) => {
+                write!(f, "(ICE - Escaped type variable): `{name}` is not bound in the type of {expected}: {actual}")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::UniverseInconsistency, actual, expected) => {
+                write!(f, "Expected {expected} but found {actual}. A type may not be placed within its own universe or a smaller one")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::CannotConvertToTraitObject(name), actual, expected) => {
+                write!(f, "Cannot convert {actual} to {expected} since it does not implement {name}")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::TraitNotObjectSafe(name), actual, expected) => {
+                write!(f, "Cannot convert {actual} to {expected}: each definition of {name} must be a pure function taking the value as its first parameter and mentioning no other generics")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::RecordUpdateMismatch, actual, expected) => {
+                write!(f, "Cannot update a value of type {actual} with the fields {expected}")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::DuplicateFieldName(name), actual, _expected) => {
+                write!(f, "Field `{name}` is declared more than once in type {actual}")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::MissingRequiredImpl(name), actual, _expected) => {
+                write!(f, "{name} is closed over {actual} but has no impl for it")
+            },
+            DiagnosticKind::TypeError(TypeErrorKind::UnexpectedImpl(name), actual, expected) => {
+                write!(f, "{name} is closed so it may only be implemented for {expected}, not {actual}")
             },
             DiagnosticKind::TypeError(TypeErrorKind::NeverShown, actual, expected) => {
                 write!(f, "(ICE - This type error should never be shown): Expected {}, Actual {}", expected, actual)
@@ -359,15 +492,67 @@ impl Display for DiagnosticKind {
             DiagnosticKind::NoMatchingImpls(constraint) => {
                 write!(f, "No impl found for {constraint}")
             },
+            DiagnosticKind::NearMissImpl(impl_, found) => {
+                write!(f, "Found `{impl_}` but you have `{found}`")
+            },
             DiagnosticKind::UnreachablePattern => {
                 write!(f, "Unreachable pattern")
             },
-            DiagnosticKind::MissingCase(case) => {
-                write!(f, "Missing case {case}")
+            DiagnosticKind::NonExhaustiveMatch(cases) => {
+                if cases.len() == 1 {
+                    write!(f, "Missing case {}", cases[0])
+                } else {
+                    write!(f, "Missing cases {}", cases.join(", "))
+                }
             },
             DiagnosticKind::UnhandledEffectsInMain(effects) => {
                 write!(f, "Unhandled effects at top-level: {effects}")
             },
+            DiagnosticKind::EffectNotAllowed(effect) => {
+                write!(f, "function may perform effect `{effect}` which is not allowed here")
+            },
+            DiagnosticKind::InvalidMainSignature(typ) => {
+                write!(f, "main should have type Unit -> Unit, but it has type {typ}")
+            },
+            DiagnosticKind::UnsatisfiableImplConstraints(constraint) => {
+                write!(f, "This impl can never be used since it requires {constraint} to both hold and not hold")
+            },
+            DiagnosticKind::DerivingRequiresNewtype(name) => {
+                write!(f, "Only non-generic types with exactly one field can derive impls, but {name} is not one")
+            },
+            DiagnosticKind::DerivingViaMismatch(field, typ) => {
+                write!(f, "Field `{field}` has type {typ}, which differs from the type given by `via`")
+            },
+            DiagnosticKind::InvalidVariantResultType(variant, type_name) => {
+                write!(f, "{variant} must construct a value of type {type_name}")
+            },
+            DiagnosticKind::CannotDeriveTrait(name) => {
+                write!(f, "{name} cannot be derived. Only traits with a single type argument used directly as the parameter or return types of its declarations can be derived")
+            },
+            DiagnosticKind::BreakOutsideOfLoop(None) => {
+                write!(f, "`break` and `continue` can only be used within a loop")
+            },
+            DiagnosticKind::BreakOutsideOfLoop(Some(label)) => {
+                write!(f, "There is no enclosing loop labeled `{label}`")
+            },
+            DiagnosticKind::BreakOutOfNestedFunction => {
+                write!(f, "Cannot break out of or continue a loop from within a nested loop or closure")
+            },
+            DiagnosticKind::CaptureNotDeclared(name) => {
+                write!(f, "`{name}` is captured by this closure but is not in its capture list")
+            },
+            DiagnosticKind::MakeBindingMutable(name) => {
+                write!(f, "Make the binding mutable with `mut {name}` to allow this")
+            },
+            DiagnosticKind::SuggestDereference => {
+                write!(f, "did you mean to dereference this value with `@`?")
+            },
+            DiagnosticKind::SuggestReference => {
+                write!(f, "did you mean to pass a reference to this value?")
+            },
+            DiagnosticKind::SuggestIntegerCast(from, to) => {
+                write!(f, "did you mean to convert this {from} to {to} with `cast`?")
+            },
             DiagnosticKind::MutRefToImmutableVariable(name) => {
                 write!(f, "Cannot mutably reference `{name}`. It was declared as immutable")
             },
@@ -377,9 +562,54 @@ impl Display for DiagnosticKind {
             DiagnosticKind::FunctionTypeMismatch(actual, expected) => {
                 write!(f, "Expected function of type {expected}, but found {actual}")
             },
+            DiagnosticKind::MismatchedSubterms(actual, expected, path) => {
+                write!(f, "`{actual}` and `{expected}` differ in {path}")
+            },
             DiagnosticKind::FunctionEffectsNotSpecified => {
                 write!(f, "This function type's effects must be specified. Add `pure` if it shouldn't have any.")
             },
+            DiagnosticKind::PureFunctionPerformsEffect(effects) => {
+                write!(f, "This function is declared `pure` but its inferred effects are `{effects}`")
+            },
+            DiagnosticKind::ComptimePerformsEffects(effects) => {
+                write!(f, "A comptime expression must be pure, but its inferred effects are `{effects}`")
+            },
+            DiagnosticKind::PolymorphicRecursionNeedsAnnotation(name) => {
+                write!(f, "`{name}` calls itself at a different type, which requires a type signature. Try adding one to `{name}`")
+            },
+            DiagnosticKind::ComptimeNotConstant => {
+                write!(f, "This comptime expression could not be evaluated to a natural number. Only integer arithmetic on literals and global constants is supported")
+            },
+            DiagnosticKind::MonomorphicDueToValueRestriction(name) => {
+                write!(f, "`{name}` is not generalized since it is not defined as a function. Consider adding explicit parameters if it should be polymorphic")
+            },
+            DiagnosticKind::MonomorphicReference(name) => {
+                write!(f, "`{name}` is a reference that is not generalized since it is not defined as a function, so each use of it must refer to the same type")
+            },
+            DiagnosticKind::TypeVariableEscapes(name, captured) => {
+                write!(f, "`{name}` cannot be generalized since it escapes into the type of `{captured}`, which is captured from an outer scope")
+            },
+            DiagnosticKind::DeepTypeRecursion(depth) => {
+                write!(f, "This type is nested {depth} levels deep, which is close to the compiler's recursion limit")
+            },
+            DiagnosticKind::UnusedValue(typ) => {
+                write!(f, "The result of this expression of type {typ} is unused")
+            },
+            DiagnosticKind::DeprecatedUsage(item, message) => {
+                write!(f, "{item} is deprecated: {message}")
+            },
+            DiagnosticKind::PotentialDanglingCapture(name) => {
+                write!(f, "Captured reference `{name}` may not live as long as this closure")
+            },
+            DiagnosticKind::RedundantFieldAssignment(path) => {
+                write!(f, "`{path}` is assigned again before this value is read")
+            },
+            DiagnosticKind::MissingSuperclassImpl(constraint, superclass) => {
+                write!(f, "impl {constraint} requires an impl of its superclass {superclass}, but none was found")
+            },
+            DiagnosticKind::TypeHole(typ) => {
+                write!(f, "Found a hole of type {typ}")
+            },
             DiagnosticKind::ImplicitEffectVariableMustBeExplicit { explicit_arg_name } => {
                 write!(f, "This function's effects must be specified explicitly since a polymorphic effect variable `{explicit_arg_name}` was already previously used")
             },
@@ -405,10 +635,28 @@ impl DiagnosticKind {
             ImplicitEffectVariableMustBeExplicitNote { .. }
             | EffectVariableAlreadyUsedNote { .. }
             | PreviouslyDefinedHere(_)
+            | InExpansionOfMacro(_)
+            | MismatchedSubterms(..)
+            | EffectNotAllowed(_)
+            | MakeBindingMutable(_)
+            | SuggestDereference
+            | SuggestReference
+            | SuggestIntegerCast(..)
             | ImplCandidate(_)
-            | ImplCandidateWithMoreHidden(_, _) => Note,
-
-            Unused(_) | UnreachablePattern => Warning,
+            | ImplCandidateWithMoreHidden(_, _)
+            | NearMissImpl(..)
+            | MonomorphicDueToValueRestriction(_)
+            | MonomorphicReference(_)
+            | TypeVariableEscapes(..) => Note,
+
+            Unused(_)
+            | UnusedValue(_)
+            | DeprecatedUsage(..)
+            | UnreachablePattern
+            | DeepTypeRecursion(_)
+            | TypeHole(_)
+            | PotentialDanglingCapture(_)
+            | RedundantFieldAssignment(_) => Warning,
 
             LexerError(_)
             | ParserExpected(_)
@@ -416,6 +664,7 @@ impl DiagnosticKind {
             | TypeVariableAlreadyInScope(_)
             | ItemNotRequiredByTrait(..)
             | AlreadyInScope(_)
+            | ConflictingExternAndNativeDefinition(_)
             | IncorrectConstructorArgCount(..)
             | IncorrectImplTraitArgCount(..)
             | NonIntegerType(_)
@@ -423,6 +672,7 @@ impl DiagnosticKind {
             | NotInScope(_, _)
             | CouldNotFindModule(_)
             | NoDeclarationFoundInScope(_)
+            | AmbiguousVariant(_)
             | CouldNotOpenFileForImport(_)
             | MissingImplDefinition(_)
             | EffectsMustBeFunctions
@@ -438,15 +688,29 @@ impl DiagnosticKind {
             | TypeError(..)
             | MultipleMatchingImpls(_, _)
             | NoMatchingImpls(_)
-            | MissingCase(_)
+            | NonExhaustiveMatch(_)
             | InternalError(_)
             | NotAStruct(_)
             | MissingFields(_)
             | UnhandledEffectsInMain(_)
+            | InvalidMainSignature(_)
+            | UnsatisfiableImplConstraints(_)
+            | MissingSuperclassImpl(..)
+            | DerivingRequiresNewtype(_)
+            | DerivingViaMismatch(..)
+            | InvalidVariantResultType(..)
+            | CannotDeriveTrait(_)
+            | BreakOutsideOfLoop(_)
+            | BreakOutOfNestedFunction
+            | CaptureNotDeclared(_)
             | MutRefToImmutableVariable(_)
             | MutRefToTemporary
             | FunctionTypeMismatch(..)
             | FunctionEffectsNotSpecified
+            | PureFunctionPerformsEffect(_)
+            | ComptimePerformsEffects(_)
+            | ComptimeNotConstant
+            | PolymorphicRecursionNeedsAnnotation(_)
             | ImplicitEffectVariableMustBeExplicit { .. }
             | EffectVariableAlreadyUsed { .. }
             | NotAStructField(_) => Error,
@@ -459,11 +723,30 @@ impl DiagnosticKind {
 pub struct Diagnostic<'a> {
     msg: DiagnosticKind,
     location: Location<'a>,
+
+    /// A note or suggestion attached to this diagnostic which is displayed directly after it.
+    /// Further notes are chained onto this one. This is boxed to keep `Diagnostic` small.
+    note: Option<Box<Diagnostic<'a>>>,
 }
 
 impl<'a> Diagnostic<'a> {
+    /// Create a new diagnostic. If the location is within a macro expansion, a note
+    /// pointing to the macro's call site is attached for each expansion in its backtrace.
     pub fn new(location: Location<'a>, msg: DiagnosticKind) -> Self {
-        Self { location, msg }
+        let note = location.expansion.map(|expansion| {
+            let msg = DiagnosticKind::InExpansionOfMacro(expansion.macro_name.clone());
+            Box::new(Diagnostic::new(expansion.call_site, msg))
+        });
+        Self { location, msg, note }
+    }
+
+    /// Attach a note, e.g. a suggested fix, to be displayed after this diagnostic
+    pub fn with_note(mut self, location: Location<'a>, note: DiagnosticKind) -> Self {
+        self.note = Some(Box::new(match self.note.take() {
+            Some(previous) => previous.with_note(location, note),
+            None => Diagnostic::new(location, note),
+        }));
+        self
     }
 
     // This is used by ante-ls to avoid displaying the location and
@@ -533,6 +816,11 @@ impl<'a> Diagnostic<'a> {
             let indicator = self.color(&"^".repeat(max(1, actual_len)));
             writeln!(f, "{}{}", padding, indicator)?;
         }
+
+        if let Some(note) = &self.note {
+            writeln!(f)?;
+            note.format(f, cache)?;
+        }
         Ok(())
     }
 }
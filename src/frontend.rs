@@ -37,16 +37,19 @@ pub fn check<'a>(
     // Phase 2: Parsing
     util::timing::start_time("Parsing");
 
-    let root = match parser::parse(&tokens) {
-        Ok(root) => root,
-        Err(parse_error) => {
-            // Parse errors are currently always fatal
-            cache.push_full_diagnostic(parse_error.into_diagnostic());
-            return FrontendResult::Errors;
-        },
-    };
+    let (root, parse_errors) = parser::parse(&tokens);
+    for parse_error in parse_errors {
+        cache.push_full_diagnostic(parse_error.into_diagnostic());
+    }
+
+    // Each statement which failed to parse has been replaced with an `Ast::Error`
+    // so the rest of the program can still be checked, but never compiled.
+    let parse_error_count = cache.error_count();
 
     if phase == FrontendPhase::Parse {
+        if parse_error_count != 0 {
+            return FrontendResult::Errors;
+        }
         println!("{}", root);
         return FrontendResult::Done;
     }
@@ -56,7 +59,7 @@ pub fn check<'a>(
     // break up the declare and define passes
     NameResolver::start(root, cache);
 
-    if cache.error_count() != 0 {
+    if cache.error_count() != parse_error_count {
         return FrontendResult::Errors;
     }
 
@@ -15,6 +15,9 @@ use crate::hir;
 
 impl<'c> Context<'c> {
     pub fn monomorphise_match(&mut self, match_: &ast::Match<'c>) -> hir::Ast {
+        let reference_bindings = match_.reference_bindings.iter().map(|(binding, value)| (*value, *binding));
+        self.reference_bindings.extend(reference_bindings);
+
         let match_prelude = self.store_initial_value(match_);
         let decision_tree = self.monomorphise_tree(match_.decision_tree.as_ref().unwrap());
         let branches = fmap(&match_.branches, |branch| self.monomorphise(&branch.1));
@@ -86,8 +89,18 @@ impl<'c> Context<'c> {
         } else {
             // variable = value = reinterpret match_value as variant_type
             let typ = match_value.typ.as_ref().clone();
+            let lvalue = self.match_lvalues.get(&match_value.definition_id).cloned();
             let value = self.cast_to_variant_type(match_value, case);
             let variable = self.next_unique_id();
+
+            // The cast value still lives at the same address as the value matched on
+            if let (Some(hir::Ast::Builtin(hir::Builtin::Deref(address, _))), hir::Ast::ReinterpretCast(cast)) =
+                (lvalue, &value)
+            {
+                let lvalue = hir::Ast::Builtin(hir::Builtin::Deref(address, cast.target_type.clone()));
+                self.match_lvalues.insert(variable, lvalue);
+            }
+
             let field_bindings = self.bind_patterns(variable, case);
 
             let mut tree = self.monomorphise_tree(&case.branch);
@@ -142,6 +155,7 @@ impl<'c> Context<'c> {
             VariantTag::True => 1,
             VariantTag::False => 0,
             VariantTag::Unit => 0,
+            VariantTag::Reference => 0,
             VariantTag::Literal(literal) => match literal {
                 ast::LiteralKind::Integer(x, _) => (*x).try_into().unwrap(), // TODO: larger tags
                 ast::LiteralKind::Float(_, _) => todo!(),
@@ -179,7 +193,7 @@ impl<'c> Context<'c> {
                     let variant_type = Rc::new(hir::Type::Function(function_type));
                     let variant_variable = hir::Variable::new(variant, variant_type);
 
-                    fmap(case.fields.iter().enumerate(), |(i, field_aliases)| {
+                    let mut definitions = fmap(case.fields.iter().enumerate(), |(i, field_aliases)| {
                         let field_index = start_index + i as u32;
                         let field_variable_id = self.next_unique_id();
                         let mut monomorphized_field_type = None;
@@ -208,15 +222,70 @@ impl<'c> Context<'c> {
                                 monomorphized_field_type.unwrap(),
                             )),
                             name: None,
-                            mutable: false,
+                            mutable: field_aliases.iter().any(|id| self.cache[*id].mutable),
+                        }
+                    });
+
+                    if let Some(lvalue) = self.match_lvalues.get(&variant).cloned() {
+                        for (i, field_aliases) in case.fields.iter().enumerate() {
+                            let field = &definitions[i];
+                            let field_lvalue = Self::extract(lvalue.clone(), start_index + i as u32, field.typ.clone());
+                            self.match_lvalues.insert(field.variable, field_lvalue.clone());
+
+                            for field_alias in field_aliases {
+                                definitions.extend(self.bind_by_reference(*field_alias, Some(&field_lvalue)));
+                            }
                         }
-                    })
+                    }
+                    definitions
                 } else {
                     vec![]
                 }
             },
+            Some(VariantTag::Reference) => {
+                // Bind the value behind the reference to a new variable, then alias
+                // each name for it to that variable.
+                let reference: hir::Ast = hir::Variable::new(variant, Rc::new(hir::Type::pointer())).into();
+                let field_variable_id = self.next_unique_id();
+                let mut monomorphized_field_type = None;
+
+                for field_alias in case.fields.iter().flatten() {
+                    let alias_type = self.cache[*field_alias].typ.as_ref().unwrap().as_monotype();
+                    let field_type = self.follow_all_bindings(alias_type);
+                    let field_hir_type = self.convert_type(&field_type);
+
+                    let field_variable = hir::Variable::new(field_variable_id, Rc::new(field_hir_type.clone()));
+                    self.definitions.insert(*field_alias, field_type, Definition::Normal(field_variable));
+                    monomorphized_field_type.get_or_insert(field_hir_type);
+                }
+
+                match monomorphized_field_type {
+                    Some(typ) => {
+                        let value = hir::Ast::Builtin(hir::Builtin::Deref(Box::new(reference), typ.clone()));
+                        self.match_lvalues.insert(field_variable_id, value.clone());
+
+                        let mut definitions = vec![hir::Definition {
+                            variable: field_variable_id,
+                            expr: Box::new(value.clone()),
+                            typ,
+                            name: None,
+                            // Variables bound through a `!` reference pattern may be mutated
+                            mutable: case.fields.iter().flatten().any(|id| self.cache[*id].mutable),
+                        }];
+
+                        for field_alias in case.fields.iter().flatten() {
+                            definitions.extend(self.bind_by_reference(*field_alias, Some(&value)));
+                        }
+                        definitions
+                    },
+                    None => vec![],
+                }
+            },
             None => {
                 assert!(case.fields.len() <= 1);
+                let lvalue = self.match_lvalues.get(&variant).cloned();
+                let mut definitions = vec![];
+
                 for field_aliases in &case.fields {
                     for field_alias in field_aliases {
                         let alias_type = self.cache[*field_alias].typ.as_ref().unwrap().as_monotype();
@@ -226,17 +295,42 @@ impl<'c> Context<'c> {
                         let variant_variable = hir::Variable::new(variant, monomorphized_field_type);
                         let definition = Definition::Normal(variant_variable);
                         self.definitions.insert(*field_alias, field_type, definition);
+
+                        definitions.extend(self.bind_by_reference(*field_alias, lvalue.as_ref()));
                     }
                 }
                 // We've aliased everything this pattern was bound to and did not
-                // need to create any new Extract instructions to do so, so there is
-                // no need to return any new definitions to insert.
-                vec![]
+                // need to create any new Extract instructions to do so. The only new
+                // definitions needed are for any variables bound by reference.
+                definitions
             },
             Some(VariantTag::True | VariantTag::False | VariantTag::Unit | VariantTag::Literal(_)) => vec![], // No fields to bind
         }
     }
 
+    /// If `field_alias` holds the value of a variable bound by reference, define that variable
+    /// as the address the value was loaded from. `lvalue` is the `Deref` the value was loaded with.
+    fn bind_by_reference(
+        &mut self, field_alias: DefinitionInfoId, lvalue: Option<&hir::Ast>,
+    ) -> Option<hir::Definition> {
+        let binding = *self.reference_bindings.get(&field_alias)?;
+
+        let address = match lvalue {
+            Some(hir::Ast::Builtin(hir::Builtin::Deref(address, _))) => address.as_ref().clone(),
+            _ => unreachable!("Variables bound by reference should only be matched through a reference"),
+        };
+
+        let typ = self.follow_all_bindings(self.cache[binding].typ.as_ref().unwrap().as_monotype());
+        let hir_type = self.convert_type(&typ);
+        let variable = self.next_unique_id();
+
+        let definition = Definition::Normal(hir::Variable::new(variable, Rc::new(hir_type.clone())));
+        self.definitions.insert(binding, typ, definition);
+
+        let name = Some(self.cache[binding].name.clone());
+        Some(hir::Definition { variable, expr: Box::new(address), typ: hir_type, name, mutable: false })
+    }
+
     fn cast_to_variant_type(&mut self, value: hir::DefinitionInfo, case: &Case) -> hir::Ast {
         let value = value.into();
         match &case.tag {
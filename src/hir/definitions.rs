@@ -68,17 +68,19 @@ impl std::hash::Hash for DefinitionType {
                     sharedness.hash(state);
                     mutability.hash(state);
                 },
-                types::Type::Struct(field_names, _) => {
+                types::Type::Struct(field_names, _, _) => {
                     for name in field_names {
                         name.hash(state);
                     }
                 },
+                types::Type::Rec(..) => (),
                 types::Type::Effects(set) => {
                     for (id, _) in &set.effects {
                         id.hash(state);
                     }
                 },
                 types::Type::Tag(tag) => tag.hash(state),
+                types::Type::Universe(level) => level.hash(state),
             }
         })
     }
@@ -120,7 +122,7 @@ fn definition_type_eq(a: &types::Type, b: &types::Type) -> bool {
             args1.iter().zip(args2).all(|(p1, p2)| definition_type_eq(p1, p2))
                 && definition_type_eq(constructor1, constructor2)
         },
-        (Type::Struct(field_names1, _), Type::Struct(field_names2, _)) => {
+        (Type::Struct(field_names1, _, _), Type::Struct(field_names2, _, _)) => {
             if field_names1.len() != field_names2.len() {
                 return false;
             }
@@ -129,6 +131,7 @@ fn definition_type_eq(a: &types::Type, b: &types::Type) -> bool {
                 .zip(field_names2)
                 .all(|((name1, t1), (name2, t2))| name1 == name2 && definition_type_eq(t1, t2))
         },
+        (Type::Rec(_, body1), Type::Rec(_, body2)) => definition_type_eq(body1, body2),
         (Type::Effects(set1), Type::Effects(set2)) => {
             if set1.effects.len() != set2.effects.len() {
                 return false;
@@ -9,15 +9,16 @@ use crate::nameresolution::builtin::BUILTIN_ID;
 use crate::parser::ast::{self, ClosureEnvironment};
 use crate::types::effects::{Effect, EffectSet};
 use crate::types::traits::{Callsite, RequiredImpl, TraitConstraintId};
-use crate::types::typechecker::{self, replace_all_typevars_with_bindings, TypeBindings, UnificationBindings};
+use crate::types::typechecker::{self, replace_all_typevars_with_bindings, TypeBindings};
 use crate::types::typed::Typed;
-use crate::types::{self, LetBindingLevel, TypeInfoId, TypeVariableId};
+use crate::types::{self, TypeInfoId, TypeVariableId};
 use crate::util::{fmap, trustme};
 
 use super::definitions::Definitions;
 use super::types::{IntegerKind, Type};
 
 mod effects;
+mod trait_objects;
 
 const DEFAULT_INTEGER: hir::Type = hir::Type::Primitive(hir::types::PrimitiveType::Integer(IntegerKind::I32));
 const DEFAULT_FLOAT: hir::Type = hir::Type::Primitive(hir::types::PrimitiveType::Float(FloatKind::F64));
@@ -54,6 +55,14 @@ pub struct Context<'c> {
 
     effect_continuations: Vec<Vec<(Effect, hir::Variable)>>,
 
+    /// Maps each value within a decision tree that was loaded through a reference to the
+    /// `Deref` it was loaded with. Used to take the address of values bound by reference.
+    pub match_lvalues: HashMap<hir::DefinitionId, hir::Ast>,
+
+    /// Maps each pattern variable holding the value of a variable bound by
+    /// reference in a match to the variable bound by reference.
+    pub reference_bindings: HashMap<DefinitionInfoId, DefinitionInfoId>,
+
     /// printf is used internally for handler panics.
     /// It isn't normally accessible since it requires varargs in the
     /// general case which cranelift doesn't support.
@@ -61,6 +70,12 @@ pub struct Context<'c> {
 
     /// exit is used internally for handler panics.
     exit_id: hir::DefinitionId,
+
+    /// malloc is used internally to heap-allocate array literals and the values of trait objects.
+    malloc_id: hir::DefinitionId,
+
+    /// puts is used internally to report reaching a typed hole.
+    puts_id: hir::DefinitionId,
 }
 
 type Impls = HashMap<VariableId, Impl>;
@@ -105,11 +120,15 @@ impl<'c> Context<'c> {
             types: HashMap::new(),
             impl_mappings: vec![HashMap::new()],
             effect_continuations: Vec::new(),
+            match_lvalues: HashMap::new(),
+            reference_bindings: HashMap::new(),
             cache,
             printf_id: hir::DefinitionId(0),
             exit_id: hir::DefinitionId(1),
-            // accounts for printf_id and exit_id
-            next_id: 2,
+            malloc_id: hir::DefinitionId(2),
+            puts_id: hir::DefinitionId(3),
+            // accounts for printf_id, exit_id, malloc_id, and puts_id
+            next_id: 4,
         }
     }
 
@@ -143,6 +162,11 @@ impl<'c> Context<'c> {
             Handle(handle) => self.monomorphise_handle(handle),
             NamedConstructor(constructor) => self.monomorphise_named_constructor(constructor),
             Reference(reference) => self.monomorphise_reference(reference),
+            ConditionalCompilation(conditional) => self.monomorphise(conditional.active_branch()),
+            Spread(spread) => self.monomorphise(&spread.expression),
+            ArrayLiteral(array) => self.monomorphise_array_literal(array),
+            Hole(hole) => self.monomorphise_hole(hole),
+            Error(_) => unreachable!("Programs with parse errors are never monomorphised"),
         }
     }
 
@@ -233,7 +257,7 @@ impl<'c> Context<'c> {
                 let lifetime = Box::new(self.follow_all_bindings_inner(lifetime, fuel));
                 Ref { mutability, sharedness, lifetime }
             },
-            Struct(fields, id) => match self.find_binding(*id, fuel) {
+            Struct(fields, id, kind) => match self.find_binding(*id, fuel) {
                 Ok(binding) => self.follow_all_bindings_inner(binding, fuel),
                 Err(_) => {
                     let fields = fields
@@ -241,11 +265,13 @@ impl<'c> Context<'c> {
                         .map(|(name, typ)| (name.clone(), self.follow_all_bindings_inner(typ, fuel)))
                         .collect();
 
-                    Struct(fields, *id)
+                    Struct(fields, *id, *kind)
                 },
             },
+            Rec(binder, body) => Rec(*binder, Box::new(self.follow_all_bindings_inner(body, fuel))),
             Effects(effects) => Effects(self.follow_all_effect_bindings_inner(effects, fuel)),
             Tag(tag) => Tag(*tag),
+            Universe(level) => Universe(*level),
         }
     }
 
@@ -345,6 +371,8 @@ impl<'c> Context<'c> {
             Primitive(CharType) => 1,
             Primitive(BooleanType) => 1,
             Primitive(UnitType) => 1,
+            // Types have no runtime representation, so values of a universe are erased to unit
+            Universe(_) => 1,
             Primitive(Ptr) => Self::ptr_size(),
             Primitive(IntegerType) => {
                 unreachable!("'Int' type constructor without arguments found during size_of_type")
@@ -352,6 +380,7 @@ impl<'c> Context<'c> {
             Primitive(FloatType) => {
                 unreachable!("'Float' type constructor without arguments found during size_of_type")
             },
+            Tag(types::TypeTag::Dyn(trait_id)) => Self::ptr_size() * (1 + self.cache[*trait_id].definitions.len()),
             Tag(tag) => {
                 unreachable!("'{}' found during size_of_type", tag)
             },
@@ -370,7 +399,7 @@ impl<'c> Context<'c> {
                     let id = *id;
                     self.size_of_user_defined_type(id, args)
                 },
-                Ok(Primitive(Ptr)) => Self::ptr_size(),
+                Ok(Primitive(Ptr) | Ref { .. }) => Self::ptr_size(),
                 Ok(Primitive(IntegerType)) => {
                     match self.follow_bindings_shallow(&args[0]) {
                         Ok(typ) => self.size_of_type(&typ.clone()),
@@ -387,7 +416,7 @@ impl<'c> Context<'c> {
             },
 
             Ref { .. } => Self::ptr_size(),
-            Struct(fields, rest) => {
+            Struct(fields, rest, _) => {
                 if let Ok(binding) = self.find_binding(*rest, RECURSION_LIMIT) {
                     let binding = binding.clone();
                     self.size_of_type(&binding)
@@ -395,10 +424,23 @@ impl<'c> Context<'c> {
                     fields.iter().map(|(_, field)| self.size_of_type(field)).sum()
                 }
             },
+            Rec(binder, body) => {
+                let unfolded = self.unfold_rec_type_as_pointer(*binder, body);
+                self.size_of_type(&unfolded)
+            },
             Effects(_) => unreachable!(),
         }
     }
 
+    /// A value of a recursive type such as `rec a. a -> b` would be infinitely large if it
+    /// were unfolded fully, so it is unfolded once with each recursive occurrence of the
+    /// type represented as an opaque pointer instead.
+    fn unfold_rec_type_as_pointer(&self, binder: TypeVariableId, body: &types::Type) -> types::Type {
+        let pointer = types::Type::Primitive(types::PrimitiveType::Ptr);
+        let pointer = types::Type::TypeApplication(Box::new(pointer), vec![types::Type::UNIT]);
+        typechecker::bind_typevars(body, &HashMap::from([(binder, pointer)]), &self.cache)
+    }
+
     fn convert_primitive_type(&mut self, typ: &types::PrimitiveType) -> Type {
         use types::PrimitiveType::*;
         Type::Primitive(match typ {
@@ -602,10 +644,12 @@ impl<'c> Context<'c> {
                     "Kind error during monomorphisation. Attempted to translate a `ref` without a type argument"
                 )
             },
+            Tag(types::TypeTag::Dyn(trait_id)) => self.convert_trait_object_type(*trait_id),
             Tag(tag) => {
                 unreachable!("Kind error during monomorphisation. Attempted to translate a `{}` as a type", tag)
             },
-            Struct(fields, rest) => {
+            Universe(_) => Type::Primitive(hir::types::PrimitiveType::Unit),
+            Struct(fields, rest, _) => {
                 if let Ok(binding) = self.find_binding(*rest, fuel) {
                     let binding = binding.clone();
                     return self.convert_type_inner(&binding, fuel);
@@ -613,6 +657,10 @@ impl<'c> Context<'c> {
 
                 Type::Tuple(fmap(fields, |(_, field)| self.convert_type_inner(field, fuel)))
             },
+            Rec(binder, body) => {
+                let unfolded = self.unfold_rec_type_as_pointer(*binder, body);
+                self.convert_type_inner(&unfolded, fuel)
+            },
             Effects(_) => unreachable!("convert_type given {}", typ.debug(&self.cache)),
         }
     }
@@ -737,6 +785,10 @@ impl<'c> Context<'c> {
         let definition_id = self.get_definition_id(variable);
 
         let typ = variable.typ.as_ref().unwrap();
+        if let Some(function) = self.monomorphise_trait_object_function(definition_id, id, typ) {
+            return function;
+        }
+
         let definition = self.monomorphise_definition_id(definition_id, id, typ, &variable.instantiation_mapping);
 
         definition.reference()
@@ -756,16 +808,7 @@ impl<'c> Context<'c> {
 
             // Bug: We can have instantiation bindings that bind type variables to themselves
             //      in the presense of mutual recursion and rigid type variables.
-            instantiation_mapping.retain(|k, v| {
-                !crate::types::typechecker::occurs(
-                    *k,
-                    LetBindingLevel(0),
-                    v,
-                    &mut UnificationBindings::empty(),
-                    100,
-                    &mut self.cache,
-                )
-            });
+            instantiation_mapping.retain(|k, v| !typechecker::contains_typevar(*k, v, &self.cache));
 
             self.monomorphisation_bindings.push(instantiation_mapping.into());
         }
@@ -791,14 +834,7 @@ impl<'c> Context<'c> {
 
             new_bindings.extend(bindings.bindings);
             for (a, b) in new_bindings.iter() {
-                if crate::types::typechecker::occurs(
-                    *a,
-                    LetBindingLevel(0),
-                    b,
-                    &mut UnificationBindings::empty(),
-                    100,
-                    &mut self.cache,
-                ) {
+                if typechecker::contains_typevar(*a, b, &self.cache) {
                     eprintln!("Binding Recursive3! {} occurs in {}", a.0, b.debug(&self.cache));
                 }
             }
@@ -827,6 +863,11 @@ impl<'c> Context<'c> {
                 continue;
             }
 
+            // A `Trait (dyn Trait)` constraint has no impl, its methods are called through the vtable instead
+            if self.is_trait_object_constraint(required_trait) {
+                continue;
+            }
+
             let impls = self.impl_mappings.last().unwrap().get(&variable_id);
 
             let impls = match impls {
@@ -1073,8 +1114,18 @@ impl<'c> Context<'c> {
         &mut self, definition: &ast::Definition<'c>, definition_id: hir::DefinitionId, name: String,
     ) -> Definition {
         let value = self.monomorphise(&definition.expr);
-        let value = self.fix_recursive_closure_calls(value, definition, definition_id);
-        let typ = self.convert_type(definition.expr.get_type().unwrap());
+        let mut value = self.fix_recursive_closure_calls(value, definition, definition_id);
+
+        let expr_type = definition.expr.get_type().unwrap();
+        let pattern_type = self.follow_all_bindings(definition.pattern.get_type().unwrap());
+
+        let typ = match self.specialize_varargs(&value, expr_type, &pattern_type) {
+            Some(specialized) => {
+                value = specialized;
+                self.convert_type(&pattern_type)
+            },
+            None => self.convert_type(expr_type),
+        };
 
         let expr = Box::new(value);
 
@@ -1088,7 +1139,7 @@ impl<'c> Context<'c> {
         });
 
         let mut nested_definitions = vec![new_definition];
-        let typ = self.follow_all_bindings(definition.pattern.get_type().unwrap());
+        let typ = pattern_type;
 
         self.desugar_pattern(&definition.pattern, definition_id, typ.clone(), &mut nested_definitions);
 
@@ -1119,7 +1170,7 @@ impl<'c> Context<'c> {
         definitions: &mut Vec<hir::Ast>,
     ) {
         use {
-            ast::Ast::{FunctionCall, Literal, TypeAnnotation, Variable},
+            ast::Ast::{FunctionCall, Literal, Reference, TypeAnnotation, Variable},
             ast::LiteralKind,
         };
 
@@ -1155,6 +1206,19 @@ impl<'c> Context<'c> {
                     self.desugar_pattern(arg_pattern, id, arg_type, definitions)
                 }
             },
+            Reference(reference) => {
+                let element_type = self.follow_all_bindings(reference.expression.get_type().unwrap());
+                let monomorphized_type = self.convert_type(&element_type);
+
+                let variable = hir::Variable::new(definition_id, Rc::new(hir::Type::pointer()));
+                let deref = hir::Builtin::Deref(Box::new(variable.into()), monomorphized_type.clone());
+                let deref = hir::Ast::Builtin(deref);
+
+                let (definition, id) = self.fresh_definition(deref, None, monomorphized_type);
+                definitions.push(definition);
+
+                self.desugar_pattern(&reference.expression, id, element_type, definitions)
+            },
             _ => {
                 unreachable!();
             },
@@ -1230,6 +1294,65 @@ impl<'c> Context<'c> {
         hir::Ast::ReinterpretCast(hir::ReinterpretCast { lhs: Box::new(tuple(padded)), target_type })
     }
 
+    /// An upper bound on the size of a value of the given type in memory, including any
+    /// padding the backend may add between fields. No field is aligned to more than 8 bytes.
+    fn allocation_size(typ: &Type) -> u64 {
+        match typ {
+            Type::Tuple(fields) => fields.iter().map(Self::allocation_size).sum(),
+            _ => 8,
+        }
+    }
+
+    /// Returns a call to `malloc` allocating the given number of bytes
+    fn make_malloc_call(&self, size: u64) -> hir::Ast {
+        let parameters = vec![Type::Primitive(hir::PrimitiveType::Integer(IntegerKind::Usz))];
+        let malloc_type = hir::FunctionType { parameters, return_type: Box::new(Type::pointer()), is_varargs: false };
+        call_internal_extern(self.malloc_id, "malloc", malloc_type, vec![int_literal(size, IntegerKind::Usz)])
+    }
+
+    /// A hole is reported as a warning during type checking and aborts the program if it is reached:
+    /// ```pseudocode
+    /// puts "file.an:3:9: Reached a hole of type I32"
+    /// exit 1
+    /// deref (stack_alloc ()) : typeof(hole)
+    /// ```
+    fn monomorphise_hole(&mut self, hole: &ast::Hole<'c>) -> hir::Ast {
+        let typ = hole.typ.as_ref().unwrap();
+        let (location, start) = (hole.location, hole.location.start);
+        let filename = self.cache.strip_root(location.filename).unwrap_or(location.filename);
+        let message = format!(
+            "{}:{}:{}: Reached a hole of type {}",
+            filename.display(),
+            start.line,
+            start.column,
+            typ.display(&self.cache)
+        );
+
+        let int_type = |kind| Type::Primitive(hir::PrimitiveType::Integer(kind));
+
+        let puts_type = hir::FunctionType {
+            parameters: vec![Type::pointer()],
+            return_type: Box::new(int_type(IntegerKind::I32)),
+            is_varargs: false,
+        };
+        let message = hir::Ast::Literal(hir::Literal::CString(message));
+        let puts_call = call_internal_extern(self.puts_id, "puts", puts_type, vec![message]);
+
+        let exit_type = hir::FunctionType {
+            parameters: vec![int_type(IntegerKind::I32)],
+            return_type: Box::new(Type::unit()),
+            is_varargs: false,
+        };
+        let exit_call = call_internal_extern(self.exit_id, "exit", exit_type, vec![int_literal(1, IntegerKind::I32)]);
+
+        // The hole's value is never used but it must still have the hole's type
+        let result_type = self.convert_type(typ);
+        let alloc = hir::Ast::Builtin(hir::Builtin::StackAlloc(Box::new(unit_literal())));
+        let deref = hir::Ast::Builtin(hir::Builtin::Deref(Box::new(alloc), result_type));
+
+        hir::Ast::Sequence(hir::Sequence { statements: vec![puts_call, exit_call, deref] })
+    }
+
     fn size_of_monomorphised_type(typ: &Type) -> u32 {
         use hir::types::PrimitiveType;
         match typ {
@@ -1609,7 +1732,29 @@ impl<'c> Context<'c> {
                 // generalized.
                 // TODO: Review this restriction. `a = Some 2` is no longer generalized due to the
                 // value restriction.
-                let mut args = fmap(&call.args, |arg| self.monomorphise(arg));
+                let parameter_types = match self.follow_all_bindings(call.function.get_type().unwrap()) {
+                    types::Type::Function(function) => function.parameters,
+                    _ => vec![],
+                };
+
+                let mut spread_definitions = vec![];
+                let mut args = Vec::with_capacity(call.args.len());
+                for arg in &call.args {
+                    match arg {
+                        ast::Ast::Spread(spread) => {
+                            self.monomorphise_spread(spread, &mut args, &mut spread_definitions)
+                        },
+                        other => {
+                            let mut value = self.monomorphise(other);
+                            if let Some(parameter_type) = parameter_types.get(args.len()) {
+                                let actual = other.get_type().unwrap();
+                                value = self.specialize_varargs(&value, actual, parameter_type).unwrap_or(value);
+                            }
+                            args.push(value);
+                        },
+                    }
+                }
+
                 let function = self.monomorphise(&call.function);
 
                 let continuation_args = self.get_continuation_args(call.function.get_type().unwrap());
@@ -1640,16 +1785,24 @@ impl<'c> Context<'c> {
                         let environment = Self::extract(function_variable, 1, env_type);
                         args.push(environment);
 
-                        hir::Ast::Sequence(hir::Sequence {
-                            statements: vec![
-                                function_definition,
-                                hir::Ast::FunctionCall(hir::FunctionCall { function, args, function_type }),
-                            ],
-                        })
+                        spread_definitions.push(function_definition);
+                        spread_definitions.push(hir::Ast::FunctionCall(hir::FunctionCall {
+                            function,
+                            args,
+                            function_type,
+                        }));
+                        hir::Ast::Sequence(hir::Sequence { statements: spread_definitions })
                     },
                     Type::Function(function_type) => {
                         let function = Box::new(function);
-                        hir::Ast::FunctionCall(hir::FunctionCall { function, args, function_type })
+                        let call = hir::Ast::FunctionCall(hir::FunctionCall { function, args, function_type });
+
+                        if spread_definitions.is_empty() {
+                            call
+                        } else {
+                            spread_definitions.push(call);
+                            hir::Ast::Sequence(hir::Sequence { statements: spread_definitions })
+                        }
                     },
                     _ => unreachable!(),
                 }
@@ -1657,6 +1810,66 @@ impl<'c> Context<'c> {
         }
     }
 
+    /// A varargs function used where a function of a fixed arity is expected is wrapped in a
+    /// lambda of that arity, e.g. `printf` used as a `String - I32 -> I32` becomes
+    /// `fn a b -> printf a b`. This way it is never called through a non-varargs function type.
+    /// Returns None if `value` does not need to be wrapped.
+    ///
+    /// Since the wrapper captures nothing, only references to global definitions are wrapped.
+    fn specialize_varargs(
+        &mut self, value: &hir::Ast, actual: &types::Type, expected: &types::Type,
+    ) -> Option<hir::Ast> {
+        let is_varargs = |typ: types::Type| matches!(typ, types::Type::Function(function) if function.has_varargs);
+        if !is_varargs(self.follow_all_bindings(actual)) || is_varargs(self.follow_all_bindings(expected)) {
+            return None;
+        }
+
+        if !matches!(value, hir::Ast::Variable(variable) if variable.definition.is_some()) {
+            return None;
+        }
+
+        let (Type::Function(actual), Type::Function(expected)) =
+            (self.convert_type(actual), self.convert_type(expected))
+        else {
+            return None;
+        };
+
+        let args = fmap(&expected.parameters, |parameter| self.fresh_variable(parameter.clone()));
+        let call_args = args.iter().cloned().map(Into::into).collect();
+        let call = hir::FunctionCall { function: Box::new(value.clone()), args: call_args, function_type: actual };
+        Some(hir::Ast::Lambda(hir::Lambda { args, body: Box::new(hir::Ast::FunctionCall(call)), typ: expected }))
+    }
+
+    /// Evaluate the spread tuple once, storing it in a fresh variable defined in `definitions`,
+    /// then push each of its elements onto `args`.
+    fn monomorphise_spread(
+        &mut self, spread: &ast::Spread<'c>, args: &mut Vec<hir::Ast>, definitions: &mut Vec<hir::Ast>,
+    ) {
+        let tuple = self.monomorphise(&spread.expression);
+        let mut tuple_type = self.convert_type(spread.expression.get_type().unwrap());
+
+        let (definition, id) = self.fresh_definition(tuple, None, tuple_type.clone());
+        definitions.push(definition);
+        let mut tuple = hir::Ast::Variable(hir::Variable::new(id, Rc::new(tuple_type.clone())));
+
+        // A tuple of n elements is a chain of n - 1 nested pairs
+        for _ in 1..spread.element_count {
+            let (first, rest) = match tuple_type {
+                Type::Tuple(mut elements) if elements.len() == 2 => {
+                    let rest = elements.pop().unwrap();
+                    (elements.pop().unwrap(), rest)
+                },
+                other => unreachable!("Expected a pair type when spreading arguments, found {}", other),
+            };
+
+            args.push(Self::extract(tuple.clone(), 0, first));
+            tuple = Self::extract(tuple, 1, rest.clone());
+            tuple_type = rest;
+        }
+
+        args.push(tuple);
+    }
+
     fn try_get_pattern_name(pattern: &ast::Ast) -> Option<String> {
         match pattern {
             ast::Ast::Variable(var) => Some(var.to_string()),
@@ -1676,6 +1889,7 @@ impl<'c> Context<'c> {
 
                 let name = Self::try_get_pattern_name(definition.pattern.as_ref());
                 let typ = self.follow_all_bindings(definition.pattern.get_type().unwrap());
+                let expr = self.specialize_varargs(&expr, definition.expr.get_type().unwrap(), &typ).unwrap_or(expr);
 
                 let hir_type = self.convert_type(&typ);
                 let (new_definition, id) =
@@ -1735,7 +1949,7 @@ impl<'c> Context<'c> {
             // This case should only happen when a bottom type is unified with an anonymous field
             // type. Default to alphabetically ordered fields, but it should never actually be
             // accessed anyway.
-            Struct(fields, _binding) => fields.keys().position(|name| name == field_name).unwrap_or_else(|| {
+            Struct(fields, _binding, _) => fields.keys().position(|name| name == field_name).unwrap_or_else(|| {
                 panic!("Expected type {} to have a field named '{}'", typ.display(&self.cache), field_name)
             }) as u32,
             _ => unreachable!(
@@ -1788,6 +2002,10 @@ impl<'c> Context<'c> {
     }
 
     fn monomorphise_assignment(&mut self, assignment: &ast::Assignment<'c>) -> hir::Ast {
+        if let Some(index_assign) = &assignment.index_assign {
+            return self.monomorphise_index_assignment(assignment, index_assign);
+        }
+
         let lhs = match self.monomorphise(&assignment.lhs) {
             hir::Ast::Builtin(hir::Builtin::Deref(value, _)) => *value,
             // TODO: Refactor mutability semantics to make this more resiliant
@@ -1797,10 +2015,91 @@ impl<'c> Context<'c> {
         hir::Ast::Assignment(hir::Assignment { lhs: Box::new(lhs), rhs: Box::new(self.monomorphise(&assignment.rhs)) })
     }
 
+    /// Lower `collection.[index] := value` to the call `index_assign collection index value`
+    fn monomorphise_index_assignment(
+        &mut self, assignment: &ast::Assignment<'c>, index_assign: &ast::Ast<'c>,
+    ) -> hir::Ast {
+        let mut args = match assignment.lhs.as_ref() {
+            ast::Ast::FunctionCall(call) => call.args.clone(),
+            _ => unreachable!("monomorphise_index_assignment: lhs is not an index expression"),
+        };
+        args.push(assignment.rhs.as_ref().clone());
+
+        let call = ast::FunctionCall {
+            function: Box::new(index_assign.clone()),
+            args,
+            location: assignment.location,
+            typ: Some(types::Type::UNIT),
+        };
+        self.monomorphise_call(&call)
+    }
+
     fn monomorphise_named_constructor(&mut self, constructor: &ast::NamedConstructor<'c>) -> hir::Ast {
-        match constructor.sequence.as_ref() {
-            ast::Ast::Sequence(sequence) => self.monomorphise_sequence(sequence),
+        let sequence = match constructor.sequence.as_ref() {
+            ast::Ast::Sequence(sequence) => sequence,
             _ => unreachable!(),
+        };
+
+        if constructor.constructor.is_some() {
+            return self.monomorphise_sequence(sequence);
+        }
+
+        // The record being updated is evaluated once, before any of the updated fields
+        let mut statements = Vec::new();
+        let record = constructor.record.as_ref().map(|record| {
+            let record_type = self.follow_all_bindings(record.get_type().unwrap());
+            let hir_type = self.convert_type(&record_type);
+            let value = self.monomorphise(record);
+            let (definition, variable) = self.fresh_definition_with_variable(value, "record".into(), hir_type.clone());
+            statements.push(definition);
+            (variable, record_type, hir_type)
+        });
+
+        statements.extend(
+            sequence
+                .statements
+                .iter()
+                .filter(|statement| matches!(statement, ast::Ast::Definition(_)))
+                .map(|definition| self.monomorphise(definition)),
+        );
+
+        let mut updated_fields = constructor.field_variables();
+        let fields = match record {
+            // An anonymous record is a tuple of its fields in alphabetical order, matching get_field_index
+            None => {
+                updated_fields.sort_by_key(|variable| variable.kind.name());
+                fmap(updated_fields, |variable| self.monomorphise_variable(variable))
+            },
+            // Any field that isn't updated is copied from the record being updated
+            Some((record, record_type, hir_type)) => {
+                let result_type = constructor.typ.as_ref().unwrap();
+                fmap(self.field_names(result_type), |name| {
+                    match updated_fields.iter().find(|variable| variable.kind.name() == name.as_str()) {
+                        Some(variable) => self.monomorphise_variable(variable),
+                        None => {
+                            let index = self.get_field_index(&name, &record_type);
+                            let Type::Tuple(field_types) = &hir_type else { unreachable!() };
+                            Self::extract(record.clone(), index, field_types[index as usize].clone())
+                        },
+                    }
+                })
+            },
+        };
+
+        statements.push(hir::Ast::Tuple(hir::Tuple { fields }));
+        hir::Ast::Sequence(hir::Sequence { statements })
+    }
+
+    /// Returns the name of each field of the given struct type, in the order they are laid out in memory
+    fn field_names(&self, typ: &types::Type) -> Vec<String> {
+        match self.follow_all_bindings(typ) {
+            types::Type::UserDefined(id) => match &self.cache[id].body {
+                types::TypeInfoBody::Struct(fields) => fmap(fields, |field| field.name.clone()),
+                _ => unreachable!("field_names called on a type that is not a struct"),
+            },
+            types::Type::TypeApplication(constructor, _) => self.field_names(&constructor),
+            types::Type::Struct(fields, _, _) => fields.into_keys().collect(),
+            other => unreachable!("field_names called on non-struct type {}", other.display(&self.cache)),
         }
     }
 
@@ -1809,6 +2108,31 @@ impl<'c> Context<'c> {
         hir::Ast::Reference(hir::Reference { expression, mutability: reference.mutability })
     }
 
+    /// Array literals are lowered to a pointer to their elements, stored together as one tuple
+    /// on the heap, paired with the number of elements:
+    /// ```pseudocode
+    /// data = malloc (length * size_of element_type)
+    /// data := (element1, ..., elementN)
+    /// (data, length)
+    /// ```
+    fn monomorphise_array_literal(&mut self, array: &ast::ArrayLiteral<'c>) -> hir::Ast {
+        let elements = fmap(&array.elements, |element| self.monomorphise(element));
+        let length = hir::Ast::Literal(hir::Literal::Integer(elements.len() as u64, IntegerKind::Usz));
+
+        let element_type = match self.follow_all_bindings(array.typ.as_ref().unwrap()) {
+            types::Type::TypeApplication(_, args) => self.convert_type(&args[0]),
+            other => unreachable!("Array literal has a non-array type: {}", other.debug(&self.cache)),
+        };
+
+        let size = Self::allocation_size(&element_type) * elements.len() as u64;
+        let (data_definition, data) =
+            self.fresh_definition_with_variable(self.make_malloc_call(size), "data".into(), Type::pointer());
+
+        let store =
+            hir::Ast::Assignment(hir::Assignment { lhs: Box::new(data.clone()), rhs: Box::new(tuple(elements)) });
+        hir::Ast::Sequence(hir::Sequence { statements: vec![data_definition, store, tuple(vec![data, length])] })
+    }
+
     pub fn extract(ast: hir::Ast, member_index: u32, result_type: Type) -> hir::Ast {
         use hir::{
             Ast,
@@ -1859,6 +2183,30 @@ fn unwrap_variable<'c>(ast: &'c ast::Ast<'c>) -> &'c ast::Variable<'c> {
     }
 }
 
+/// Calls one of the externs declared by the compiler itself rather than the program
+fn call_internal_extern(id: hir::DefinitionId, name: &str, typ: hir::FunctionType, args: Vec<hir::Ast>) -> hir::Ast {
+    let definition = hir::Ast::Definition(hir::Definition {
+        variable: id,
+        name: Some(name.to_string()),
+        mutable: false,
+        typ: Type::Function(typ.clone()),
+        expr: Box::new(hir::Ast::Extern(hir::Extern { name: name.to_string(), typ: Type::Function(typ.clone()) })),
+    });
+
+    let function = hir::Variable {
+        definition: Some(Rc::new(definition)),
+        definition_id: id,
+        typ: Rc::new(Type::Function(typ.clone())),
+        name: Some(name.to_string()),
+    };
+
+    hir::Ast::FunctionCall(hir::FunctionCall {
+        function: Box::new(hir::Ast::Variable(function)),
+        args,
+        function_type: typ,
+    })
+}
+
 fn tuple(fields: Vec<hir::Ast>) -> hir::Ast {
     hir::Ast::Tuple(hir::Tuple { fields })
 }
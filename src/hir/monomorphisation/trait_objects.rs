@@ -0,0 +1,221 @@
+//! Trait objects are lowered to a pointer to a heap-allocated copy of the value they were
+//! converted from, followed by their vtable: one function per definition of the trait,
+//! each taking the data pointer in place of the value itself.
+//!
+//! ```pseudocode
+//! x as dyn Trait
+//! // lowers into
+//! data = malloc (size_of typeof(x))
+//! data := x
+//! (data, fn d args.. -> impl_method1 (deref d) args.., ..., fn d args.. -> impl_methodN (deref d) args..)
+//!
+//! method1 object args..
+//! // lowers into
+//! (extract object 1) (extract object 0) args..
+//! ```
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{
+    cache::{DefinitionInfoId, DefinitionKind, TraitInfoId, VariableId},
+    hir::{self, Type},
+    types::{
+        self,
+        traits::RequiredTrait,
+        typechecker::{self, TypeBindings},
+        TypeTag,
+    },
+    util::fmap,
+};
+
+use super::{tuple, Context, Impls};
+
+impl<'c> Context<'c> {
+    /// The type of a `dyn Trait` value: a data pointer followed by a function for each definition in `Trait`
+    pub(super) fn convert_trait_object_type(&mut self, trait_id: TraitInfoId) -> Type {
+        let erased = self.erased_pointer_type();
+        let mut fields = vec![Type::pointer()];
+
+        for definition in self.cache[trait_id].definitions.clone() {
+            let method_type = self.trait_object_method_type(definition, erased.clone());
+            fields.push(Type::Function(self.get_function_type(&method_type)));
+        }
+
+        Type::Tuple(fields)
+    }
+
+    /// A variable referring to a trait definition with no impl selected for it is either the
+    /// conversion in `x as dyn Trait` or a call to a method of a `dyn Trait` value. Returns
+    /// the function implementing it in either case, or `None` for any other variable.
+    pub(super) fn monomorphise_trait_object_function(
+        &mut self, definition_id: DefinitionInfoId, variable_id: VariableId, typ: &types::Type,
+    ) -> Option<hir::Ast> {
+        let info = &self.cache[definition_id];
+        if !matches!(info.definition, Some(DefinitionKind::TraitDefinition(_))) {
+            return None;
+        }
+
+        let (definition_trait, _) = info.trait_info.clone()?;
+        let types::Type::Function(function) = self.follow_all_bindings(typ) else {
+            return None;
+        };
+
+        let dyn_trait = |typ: &types::Type| match self.follow_all_bindings(typ) {
+            types::Type::Tag(TypeTag::Dyn(trait_id)) => Some(trait_id),
+            _ => None,
+        };
+
+        if function.parameters.first().and_then(dyn_trait) == Some(definition_trait) {
+            let definitions = &self.cache[definition_trait].definitions;
+            let index = definitions.iter().position(|definition| *definition == definition_id).unwrap();
+            Some(self.make_trait_object_call(index, typ))
+        } else if let Some(trait_id) = dyn_trait(&function.return_type) {
+            Some(self.make_trait_object_conversion(trait_id, variable_id, &function.parameters[0], typ))
+        } else {
+            None
+        }
+    }
+
+    /// `fn object args.. -> (extract object (index + 1)) (extract object 0) args..`
+    fn make_trait_object_call(&mut self, index: usize, typ: &types::Type) -> hir::Ast {
+        let function_type = self.get_function_type(typ);
+        let object = self.fresh_variable(function_type.parameters[0].clone());
+
+        let method_type = match object.typ.as_ref() {
+            Type::Tuple(fields) => match &fields[index + 1] {
+                Type::Function(method_type) => method_type.clone(),
+                other => unreachable!("Expected a function in a trait object's vtable, found {}", other),
+            },
+            other => unreachable!("Expected a trait object, found {}", other),
+        };
+
+        let object_ast = hir::Ast::Variable(object.clone());
+        let data = Self::extract(object_ast.clone(), 0, Type::pointer());
+        let method = Self::extract(object_ast, index as u32 + 1, Type::Function(method_type.clone()));
+
+        let rest = fmap(&function_type.parameters[1..], |parameter| self.fresh_variable(parameter.clone()));
+
+        let mut args = vec![data];
+        args.extend(rest.iter().cloned().map(hir::Ast::Variable));
+
+        let call =
+            hir::Ast::FunctionCall(hir::FunctionCall { function: Box::new(method), args, function_type: method_type });
+
+        let mut parameters = vec![object];
+        parameters.extend(rest);
+        hir::Ast::Lambda(hir::Lambda { args: parameters, body: Box::new(call), typ: function_type })
+    }
+
+    /// `fn x -> (data = malloc (size_of typeof(x)); data := x; (data, vtable..))`
+    fn make_trait_object_conversion(
+        &mut self, trait_id: TraitInfoId, variable_id: VariableId, source: &types::Type, typ: &types::Type,
+    ) -> hir::Ast {
+        let function_type = self.get_function_type(typ);
+        let source_type = function_type.parameters[0].clone();
+        let value = self.fresh_variable(source_type.clone());
+
+        let size = Self::allocation_size(&source_type);
+        let (data_definition, data) =
+            self.fresh_definition_with_variable(self.make_malloc_call(size), "data".into(), Type::pointer());
+
+        let store = hir::Ast::Assignment(hir::Assignment {
+            lhs: Box::new(data.clone()),
+            rhs: Box::new(hir::Ast::Variable(value.clone())),
+        });
+
+        let mut fields = vec![data];
+        fields.append(&mut self.make_vtable(trait_id, variable_id, source));
+
+        let body = hir::Ast::Sequence(hir::Sequence { statements: vec![data_definition, store, tuple(fields)] });
+        hir::Ast::Lambda(hir::Lambda { args: vec![value], body: Box::new(body), typ: function_type })
+    }
+
+    /// Creates a wrapper for each method of the impl of the given trait for `source` which
+    /// the conversion at `variable_id` was resolved to during type checking.
+    fn make_vtable(&mut self, trait_id: TraitInfoId, variable_id: VariableId, source: &types::Type) -> Vec<hir::Ast> {
+        let definitions = self.cache[trait_id].definitions.clone();
+        if definitions.is_empty() {
+            return vec![];
+        }
+
+        // The conversion's constraint is the only one resolved for this variable. Its impl is at
+        // the root of the path and any impls that impl requires are further down it.
+        let impl_ = &self.impl_mappings.last().unwrap()[&variable_id];
+        assert_eq!(impl_.indirect.len(), 1, "Expected a single impl for a trait object conversion");
+        let bindings = impl_.indirect.values().next().unwrap().clone();
+
+        let mut impl_id = None;
+        let mut impls = Impls::new();
+
+        for (mut path, binding) in bindings {
+            if path.is_empty() {
+                impl_id = Some(binding);
+            } else {
+                let top = path.remove(0);
+                impls.entry(variable_id).or_default().indirect.entry(top).or_default().push((path, binding));
+            }
+        }
+
+        let impl_id = impl_id.expect("No impl found for trait object conversion");
+        let source_type = self.convert_type(source);
+        self.impl_mappings.push(impls);
+
+        let vtable = fmap(definitions, |definition| {
+            let name = &self.cache[definition].name;
+            let impl_definitions = &self.cache[impl_id].definitions;
+            let method_id = *impl_definitions.iter().find(|method| self.cache[**method].name == *name).unwrap();
+
+            let method_type = self.trait_object_method_type(definition, source.clone());
+            let no_instantiation = Rc::new(TypeBindings::new());
+            let method = self.monomorphise_definition_id(method_id, variable_id, &method_type, &no_instantiation);
+            let method_type = self.get_function_type(&method_type);
+
+            let data = self.fresh_variable(Type::pointer());
+            let rest = fmap(&method_type.parameters[1..], |parameter| self.fresh_variable(parameter.clone()));
+
+            let value = hir::Builtin::Deref(Box::new(hir::Ast::Variable(data.clone())), source_type.clone());
+            let mut args = vec![hir::Ast::Builtin(value)];
+            args.extend(rest.iter().cloned().map(hir::Ast::Variable));
+
+            let mut parameters = vec![data];
+            parameters.extend(rest);
+
+            let mut typ = method_type.clone();
+            typ.parameters[0] = Type::pointer();
+
+            let function = Box::new(method.reference());
+            let call = hir::Ast::FunctionCall(hir::FunctionCall { function, args, function_type: method_type });
+            hir::Ast::Lambda(hir::Lambda { args: parameters, body: Box::new(call), typ })
+        });
+
+        self.impl_mappings.pop();
+        vtable
+    }
+
+    /// True if the given required trait is `Trait (dyn Trait)`
+    pub(super) fn is_trait_object_constraint(&self, required_trait: &RequiredTrait) -> bool {
+        match required_trait.signature.args.as_slice() {
+            [arg] => match self.follow_all_bindings(arg) {
+                types::Type::Tag(TypeTag::Dyn(trait_id)) => trait_id == required_trait.signature.trait_id,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// The type of the given trait definition with the trait's type argument replaced by `self_type`
+    fn trait_object_method_type(&self, definition: DefinitionInfoId, self_type: types::Type) -> types::Type {
+        let info = &self.cache[definition];
+        let (trait_id, _) = info.trait_info.as_ref().unwrap();
+        let typevar = self.cache[*trait_id].typeargs[0];
+
+        let typ = info.typ.as_ref().unwrap().remove_forall();
+        typechecker::bind_typevars(typ, &HashMap::from([(typevar, self_type)]), &self.cache)
+    }
+
+    /// Trait objects erase the type they were converted from to an opaque pointer
+    fn erased_pointer_type(&self) -> types::Type {
+        let pointer = types::Type::Primitive(types::PrimitiveType::Ptr);
+        types::Type::TypeApplication(Box::new(pointer), vec![types::Type::UNIT])
+    }
+}
@@ -130,6 +130,7 @@ impl<'cache, 'contents> Lexer<'cache, 'contents> {
                 | Token::If
                 | Token::Match
                 | Token::Then
+                | Token::Where
                 | Token::While
                 | Token::With
                 | Token::Equal
@@ -409,6 +410,36 @@ impl<'cache, 'contents> Lexer<'cache, 'contents> {
         }
     }
 
+    /// Returns the length of the user-defined operator starting at the current character,
+    /// or None if there isn't one. Builtin operators like `<=` or `|>` are never user-defined
+    /// and user-defined operators never contain the start of a comment.
+    fn user_defined_operator_length(&self) -> Option<usize> {
+        if !is_operator_char(self.current) || self.current == '=' {
+            return None;
+        }
+
+        let rest = &self.file_contents[self.current_position.index..];
+        let mut length = rest.find(|c| !is_operator_char(c)).unwrap_or(rest.len());
+
+        if let Some(comment_start) = rest[..length].find("//").or_else(|| rest[..length].find("/*")) {
+            length = comment_start;
+        }
+
+        match &rest[..length] {
+            "" | "<" | ">" | "|" | "+" | "*" | "/" | "%" | "<=" | ">=" | "<|" | "|>" | "++" => None,
+            _ => Some(length),
+        }
+    }
+
+    fn lex_user_defined_operator(&mut self, length: usize) -> IterElem<'cache> {
+        let start = self.current_position.index;
+        while self.current_position.index < start + length {
+            self.advance();
+        }
+        let operator = self.get_slice_containing_current_token().to_owned();
+        Some((Token::Operator(operator), self.locate()))
+    }
+
     fn lex_singleline_comment(&mut self) -> IterElem<'cache> {
         self.advance_while(|current, _| current != '\n');
         self.next()
@@ -432,6 +463,11 @@ impl<'cache, 'contents> Lexer<'cache, 'contents> {
     }
 }
 
+/// True if the given character may be part of a user-defined operator
+fn is_operator_char(c: char) -> bool {
+    matches!(c, '<' | '>' | '|' | '+' | '*' | '/' | '%' | '^' | '~' | '=')
+}
+
 impl<'cache, 'contents> Iterator for Lexer<'cache, 'contents> {
     type Item = (Token, Location<'cache>);
 
@@ -465,6 +501,10 @@ impl<'cache, 'contents> Iterator for Lexer<'cache, 'contents> {
             None => false,
         };
 
+        if let Some(length) = self.user_defined_operator_length() {
+            return self.lex_user_defined_operator(length);
+        }
+
         match (self.current, self.next) {
             (c, _) if c.is_ascii_digit() => self.lex_number(),
             (c, _) if c.is_alphanumeric() || c == '_' => self.lex_alphanumeric(),
@@ -556,6 +596,14 @@ impl<'cache, 'contents> Iterator for Lexer<'cache, 'contents> {
                 self.open_braces.square = self.open_braces.square.saturating_sub(1);
                 self.advance_with(Token::BracketRight)
             },
+            ('{', _) => {
+                self.open_braces.curly += 1;
+                self.advance_with(Token::BraceLeft)
+            },
+            ('}', _) => {
+                self.open_braces.curly = self.open_braces.curly.saturating_sub(1);
+                self.advance_with(Token::BraceRight)
+            },
             ('|', _) => self.advance_with(Token::Pipe),
             (':', _) => self.advance_with(Token::Colon),
             (';', _) => self.advance_with(Token::Semicolon),
@@ -566,6 +614,7 @@ impl<'cache, 'contents> Iterator for Lexer<'cache, 'contents> {
             ('\\', _) => self.advance_with(Token::Backslash),
             ('&', _) => self.advance_with(Token::Ampersand),
             ('@', _) => self.advance_with(Token::At),
+            ('#', _) => self.advance_with(Token::Hash),
             ('!', _) => self.advance_with(Token::ExclamationMark),
             ('?', _) => self.advance_with(Token::QuestionMark),
             (c, _) => self.advance_with(Token::Invalid(LexerError::UnknownChar(c))),
@@ -85,6 +85,7 @@ pub enum Token {
     PointerType,
     BooleanType,
     UnitType,
+    UniverseType,
     Mut,
 
     // Keywords
@@ -92,8 +93,13 @@ pub enum Token {
     As,
     Block,
     Boxed,
+    Break,
     Can,
+    Comptime,
+    Continue,
+    Deriving,
     Do,
+    Dyn,
     Effect,
     Else,
     Extern,
@@ -104,6 +110,7 @@ pub enum Token {
     Impl,
     Import,
     In,
+    Linear,
     Loop,
     Match,
     Methods,
@@ -111,13 +118,16 @@ pub enum Token {
     Not,
     Or,
     Owned,
+    Private,
     Pure,
     Ref,
     Return,
+    Sealed,
     Shared,
     Then,
     Trait,
     Type,
+    Where,
     While,
     With,
 
@@ -140,6 +150,8 @@ pub enum Token {
     Add,                // +
     BracketLeft,        // [
     BracketRight,       // ]
+    BraceLeft,          // {
+    BraceRight,         // }
     InterpolateLeft,    // ${
     InterpolateRight,   // }
     Pipe,               // |
@@ -157,11 +169,15 @@ pub enum Token {
     Backslash,          // \
     Ampersand,          // &
     At,                 // @
+    Hash,               // #
     ExclamationMark,    // !
     QuestionMark,       // ?
     Index,              // .[]
     IndexRef,           // .&[]
     IndexMut,           // .![]
+
+    /// A user-defined operator such as `>>` or `<+>`
+    Operator(String),
 }
 
 impl Token {
@@ -193,6 +209,7 @@ impl Token {
                 | Index
                 | IndexRef
                 | IndexMut
+                | Operator(_)
         )
     }
 }
@@ -277,6 +294,7 @@ impl Display for Token {
             Token::PointerType => write!(f, "'Ptr'"),
             Token::BooleanType => write!(f, "'bool'"),
             Token::UnitType => write!(f, "'unit'"),
+            Token::UniverseType => write!(f, "'Universe'"),
             Token::Mut => write!(f, "'mut'"),
 
             // Keywords
@@ -284,8 +302,13 @@ impl Display for Token {
             Token::As => write!(f, "'as'"),
             Token::Block => write!(f, "'block'"),
             Token::Boxed => write!(f, "'boxed'"),
+            Token::Break => write!(f, "'break'"),
             Token::Can => write!(f, "'can'"),
+            Token::Comptime => write!(f, "'comptime'"),
+            Token::Continue => write!(f, "'continue'"),
+            Token::Deriving => write!(f, "'deriving'"),
             Token::Do => write!(f, "'do'"),
+            Token::Dyn => write!(f, "'dyn'"),
             Token::Effect => write!(f, "'effect'"),
             Token::Else => write!(f, "'else'"),
             Token::Extern => write!(f, "'extern'"),
@@ -296,6 +319,7 @@ impl Display for Token {
             Token::Impl => write!(f, "'impl'"),
             Token::Import => write!(f, "'import'"),
             Token::In => write!(f, "'in'"),
+            Token::Linear => write!(f, "'linear'"),
             Token::Loop => write!(f, "'loop'"),
             Token::Match => write!(f, "'match'"),
             Token::Methods => write!(f, "'methods'"),
@@ -305,11 +329,14 @@ impl Display for Token {
             Token::Owned => write!(f, "'owned'"),
             Token::Pure => write!(f, "'pure'"),
             Token::Return => write!(f, "'return'"),
+            Token::Private => write!(f, "'private'"),
+            Token::Sealed => write!(f, "'sealed'"),
             Token::Ref => write!(f, "'ref'"),
             Token::Shared => write!(f, "'shared'"),
             Token::Then => write!(f, "'then'"),
             Token::Trait => write!(f, "'trait'"),
             Token::Type => write!(f, "'type'"),
+            Token::Where => write!(f, "'where'"),
             Token::While => write!(f, "'while'"),
             Token::With => write!(f, "'with'"),
 
@@ -332,6 +359,8 @@ impl Display for Token {
             Token::Add => write!(f, "'+'"),
             Token::BracketLeft => write!(f, "'['"),
             Token::BracketRight => write!(f, "']'"),
+            Token::BraceLeft => write!(f, "'{{'"),
+            Token::BraceRight => write!(f, "'}}'"),
             Token::InterpolateLeft => write!(f, "'${{'"),
             Token::InterpolateRight => write!(f, "'}}'"),
             Token::Pipe => write!(f, "'|'"),
@@ -349,11 +378,13 @@ impl Display for Token {
             Token::Backslash => write!(f, "'\\'"),
             Token::Ampersand => write!(f, "'&'"),
             Token::At => write!(f, "'@'"),
+            Token::Hash => write!(f, "'#'"),
             Token::ExclamationMark => write!(f, "'!'"),
             Token::QuestionMark => write!(f, "'?'"),
             Token::Index => write!(f, "'.[]'"),
             Token::IndexRef => write!(f, "'.&[]'"),
             Token::IndexMut => write!(f, "'.![]'"),
+            Token::Operator(operator) => write!(f, "'{}'", operator),
         }
     }
 }
@@ -379,6 +410,7 @@ pub fn lookup_keyword(word: &str) -> Option<Token> {
         "Ptr" => Some(Token::PointerType),
         "Bool" => Some(Token::BooleanType),
         "Unit" => Some(Token::UnitType),
+        "Universe" => Some(Token::UniverseType),
         "mut" => Some(Token::Mut),
         "true" => Some(Token::BooleanLiteral(true)),
         "false" => Some(Token::BooleanLiteral(false)),
@@ -386,8 +418,13 @@ pub fn lookup_keyword(word: &str) -> Option<Token> {
         "as" => Some(Token::As),
         "block" => Some(Token::Block),
         "boxed" => Some(Token::Boxed),
+        "break" => Some(Token::Break),
         "can" => Some(Token::Can),
+        "comptime" => Some(Token::Comptime),
+        "continue" => Some(Token::Continue),
+        "deriving" => Some(Token::Deriving),
         "do" => Some(Token::Do),
+        "dyn" => Some(Token::Dyn),
         "effect" => Some(Token::Effect),
         "else" => Some(Token::Else),
         "extern" => Some(Token::Extern),
@@ -398,6 +435,7 @@ pub fn lookup_keyword(word: &str) -> Option<Token> {
         "impl" => Some(Token::Impl),
         "import" => Some(Token::Import),
         "in" => Some(Token::In),
+        "linear" => Some(Token::Linear),
         "loop" => Some(Token::Loop),
         "match" => Some(Token::Match),
         "methods" => Some(Token::Methods),
@@ -405,13 +443,16 @@ pub fn lookup_keyword(word: &str) -> Option<Token> {
         "not" => Some(Token::Not),
         "or" => Some(Token::Or),
         "owned" => Some(Token::Owned),
+        "private" => Some(Token::Private),
         "pure" => Some(Token::Pure),
         "ref" => Some(Token::Ref),
         "return" => Some(Token::Return),
+        "sealed" => Some(Token::Sealed),
         "shared" => Some(Token::Shared),
         "then" => Some(Token::Then),
         "trait" => Some(Token::Trait),
         "type" => Some(Token::Type),
+        "where" => Some(Token::Where),
         "while" => Some(Token::While),
         "with" => Some(Token::With),
         _ => None,
@@ -41,6 +41,7 @@ mod llvm;
 use cache::ModuleCache;
 use cli::{Backend, Cli, Completions, EmitTarget};
 use frontend::{check, FrontendPhase, FrontendResult};
+use types::typechecker::StructUnifyMode;
 
 use clap::{CommandFactory, Parser};
 use clap_complete as clap_cmp;
@@ -81,6 +82,14 @@ fn print_definition_types(cache: &ModuleCache) {
     }
 }
 
+/// Called when the "--check --show-inlay-hints" command-line flags are given.
+/// Prints the position and inferred type of each unannotated binding in the first compiled module.
+fn print_inlay_hints<'c>(ast: &parser::ast::Ast<'c>, cache: &ModuleCache<'c>) {
+    for (location, typ) in types::inlay_hints::inlay_hints(ast, cache) {
+        println!("{}:{}\t{}", location.start.line, location.start.column, typ);
+    }
+}
+
 fn print_completions<G: clap_cmp::Generator>(gen: G) {
     let mut cmd = Cli::command();
     let name = cmd.get_name().to_string();
@@ -126,6 +135,14 @@ fn compile(args: Cli) {
     let file_cache = HashMap::from([(filename.clone(), contents.clone())]);
 
     let mut cache = ModuleCache::new(parent, file_cache);
+    cache.warn_unused_values = args.warn_unused_values;
+    cache.implicit_share = !args.explicit_share;
+    cache.implicit_integer_widening = args.implicit_int_widening;
+    cache.trace_unification = args.trace_unification;
+
+    if args.exact_struct_unification {
+        cache.struct_unification = StructUnifyMode::ExactOnly;
+    }
 
     error::color_output(!args.no_color);
 
@@ -139,7 +156,11 @@ fn compile(args: Cli) {
 
     match check(&filename, contents, &mut cache, phase, args.show_time) {
         FrontendResult::Done => return,
-        FrontendResult::ContinueCompilation => (),
+        FrontendResult::ContinueCompilation => {
+            if args.show_warnings {
+                cache.display_diagnostics();
+            }
+        },
         FrontendResult::Errors => {
             cache.display_diagnostics();
 
@@ -156,6 +177,10 @@ fn compile(args: Cli) {
         print_definition_types(&cache);
     }
 
+    if args.show_inlay_hints {
+        print_inlay_hints(ast, &cache);
+    }
+
     if args.check || cache.error_count() != 0 {
         return;
     }
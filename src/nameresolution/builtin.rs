@@ -10,7 +10,8 @@ use crate::lexer::token::{IntegerKind, Token};
 use crate::nameresolution::{declare_module, define_module, NameResolver};
 use crate::types::effects::EffectSet;
 use crate::types::{
-    Field, FunctionType, GeneralizedType, LetBindingLevel, PrimitiveType, Type, TypeInfoBody, PAIR_TYPE, STRING_TYPE,
+    Field, FunctionType, GeneralizedType, LetBindingLevel, PrimitiveType, Type, TypeInfoBody, ARRAY_TYPE, PAIR_TYPE,
+    STRING_TYPE,
 };
 
 use std::collections::HashSet;
@@ -30,6 +31,7 @@ pub const PAIR_ID: DefinitionInfoId = DefinitionInfoId(2);
 
 /// Defines the builtin symbols:
 /// - `type String = c_string: Ptr Char, length: Usz`
+/// - `type Array a = data: Ptr a, length: Usz`
 /// - `builtin : String -> a` used by the codegen pass to implement
 ///   codegen of builtin operations such as adding integers.
 ///
@@ -44,6 +46,7 @@ pub fn define_builtins(cache: &mut ModuleCache) {
 
     let string_type = define_string(cache);
     define_pair(cache);
+    define_array(cache);
 
     let a = cache.next_type_variable_id(LetBindingLevel(1));
     let e = cache.next_type_variable_id(LetBindingLevel(1));
@@ -82,6 +85,7 @@ pub fn import_prelude(resolver: &mut NameResolver, cache: &mut ModuleCache<'_>)
 
     // Manually insert some builtins as if they were defined in the prelude
     resolver.current_scope().types.insert(Token::Comma.to_string(), PAIR_TYPE);
+    resolver.current_scope().types.insert("Array".into(), ARRAY_TYPE);
     resolver.current_scope().definitions.insert(Token::Comma.to_string(), PAIR_ID);
     resolver.current_scope().definitions.insert("String".into(), STRING_ID);
 }
@@ -177,3 +181,24 @@ fn define_pair(cache: &mut ModuleCache) {
     cache.definition_infos[id.0].typ = Some(constructor_type);
     cache.definition_infos[id.0].definition = Some(constructor);
 }
+
+/// The builtin array type is defined here as:
+///
+/// type Array a = data: Ptr a, length: Usz
+///
+/// It has no constructor of its own, values of this type are created with array literals.
+fn define_array(cache: &mut ModuleCache) {
+    let location = Location::builtin();
+
+    let a = cache.next_type_variable_id(LetBindingLevel(0));
+    let array = cache.push_type_info("Array".into(), vec![a], location);
+    assert_eq!(array, ARRAY_TYPE);
+
+    let ptr_type = Type::Primitive(PrimitiveType::Ptr);
+    let data_type = Type::TypeApplication(Box::new(ptr_type), vec![Type::TypeVariable(a)]);
+
+    cache.type_infos[array.0].body = TypeInfoBody::Struct(vec![
+        Field { name: "data".into(), field_type: data_type, location },
+        Field { name: "length".into(), field_type: Type::int(IntegerKind::Usz), location },
+    ]);
+}
@@ -183,6 +183,9 @@ impl<'c> FreeVars for ast::Assignment<'c> {
     fn find_free_vars(&self, ctx: &mut Context) {
         self.lhs.find_free_vars(ctx);
         self.rhs.find_free_vars(ctx);
+        if let Some(index_assign) = &self.index_assign {
+            index_assign.find_free_vars(ctx);
+        }
     }
 }
 
@@ -209,6 +212,9 @@ impl<'c> FreeVars for ast::Handle<'c> {
 
 impl<'c> FreeVars for ast::NamedConstructor<'c> {
     fn find_free_vars(&self, ctx: &mut Context) {
+        if let Some(record) = &self.record {
+            record.find_free_vars(ctx);
+        }
         self.sequence.find_free_vars(ctx);
     }
 }
@@ -218,3 +224,35 @@ impl<'c> FreeVars for ast::Reference<'c> {
         self.expression.find_free_vars(ctx);
     }
 }
+
+impl<'c> FreeVars for ast::ConditionalCompilation<'c> {
+    fn find_free_vars(&self, ctx: &mut Context) {
+        self.active_branch().find_free_vars(ctx);
+    }
+}
+
+impl<'c> FreeVars for ast::Spread<'c> {
+    fn find_free_vars(&self, ctx: &mut Context) {
+        self.expression.find_free_vars(ctx);
+    }
+}
+
+impl<'c> FreeVars for ast::ArrayLiteral<'c> {
+    fn find_free_vars(&self, ctx: &mut Context) {
+        for element in &self.elements {
+            element.find_free_vars(ctx);
+        }
+    }
+}
+
+impl<'c> FreeVars for ast::Hole<'c> {
+    fn find_free_vars(&self, _ctx: &mut Context) {
+        // Nothing to do
+    }
+}
+
+impl<'c> FreeVars for ast::Error<'c> {
+    fn find_free_vars(&self, _ctx: &mut Context) {
+        // Nothing to do
+    }
+}
@@ -37,27 +37,28 @@
 //!   - `trait_info: Option<TraitInfoId>` for `ast::TraitDefinition`s and `ast::TraitImpl`s
 //!   - `impl_id: Option<ImplInfoId>` for `ast::TraitImpl`s
 //!   - `module_id: Option<ModuleId>` for `ast::Import`s,
-use crate::cache::{DefinitionInfoId, EffectInfoId, ModuleCache, ModuleId};
+use crate::cache::{ComptimeExpression, DefinitionInfoId, EffectInfoId, ModuleCache, ModuleId};
 use crate::cache::{DefinitionKind, ImplInfoId, TraitInfoId};
 use crate::error::{
     location::{Locatable, Location},
-    DiagnosticKind as D,
+    DiagnosticKind as D, TypeErrorKind as TE,
 };
 use crate::lexer::{token::Token, Lexer};
 use crate::nameresolution::scope::{FunctionScopes, Scope};
 use crate::parser::ast::{EffectAst, EffectName};
 use crate::parser::{self, ast, ast::Ast};
 use crate::types::effects::EffectSet;
-use crate::types::traits::ConstraintSignature;
+use crate::types::traits::{add_implied_superclasses, Callsite, ConstraintSignature, RequiredTrait};
+use crate::types::typechecker;
 use crate::types::typed::Typed;
 use crate::types::{
     Field, FunctionType, GeneralizedType, LetBindingLevel, PrimitiveType, Type, TypeConstructor, TypeInfoBody,
-    TypeInfoId, TypeTag, TypeVariableId, INITIAL_LEVEL, STRING_TYPE,
+    TypeInfoId, TypeTag, TypeVariableId, ARRAY_TYPE, INITIAL_LEVEL, STRING_TYPE,
 };
 use crate::util::{fmap, timing, trustme};
 
 use std::borrow::Cow;
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
@@ -136,6 +137,18 @@ pub struct NameResolver {
     /// of looked up in the symbol table.
     auto_declare: bool,
 
+    /// True while declaring the names within an `extern` so they can be
+    /// distinguished from native definitions of the same name.
+    declaring_extern: bool,
+
+    /// True while declaring the `private` helpers of a trait impl. These
+    /// are never exported, even when the impl is in the global scope.
+    declaring_impl_helper: bool,
+
+    /// True while defining the fields of a named constructor or record literal.
+    /// Each field is local to its constructor, so these are never exported either.
+    defining_fields: bool,
+
     /// The trait we're currently declaring. While this is Some(id) all
     /// declarations will be declared as part of the trait.
     current_trait: Option<TraitInfoId>,
@@ -152,6 +165,11 @@ pub struct NameResolver {
     /// Keeps track of all the definitions collected within a pattern so they
     /// can all be tagged with the expression they were defined as later
     definitions_collected: Vec<DefinitionInfoId>,
+
+    /// The `private` helpers of each trait impl in the global scope. Like the impl's
+    /// methods, these are globals so referencing them never creates a closure even
+    /// though they are only visible within their impl's scope.
+    global_impl_helpers: HashSet<DefinitionInfoId>,
 }
 
 impl PartialEq for NameResolver {
@@ -242,6 +260,10 @@ impl<'c> NameResolver {
                     return Some(id);
                 }
             } else if let Some(&from) = stack.definitions.get(name) {
+                if self.global_impl_helpers.contains(&from) {
+                    cache.definition_infos[from.0].uses += 1;
+                    return Some(from);
+                }
                 return Some(self.create_closure(from, name, global_index, location, cache));
             }
         }
@@ -249,6 +271,24 @@ impl<'c> NameResolver {
         None
     }
 
+    /// Look up a union variant qualified with the name of its type, e.g. `Maybe.None`.
+    /// Returns None if no type in scope has this name or it has no variant with this name.
+    fn lookup_qualified_variant(
+        &mut self, type_name: &str, name: &str, cache: &mut ModuleCache<'c>,
+    ) -> Option<DefinitionInfoId> {
+        let type_id = self.lookup_type(type_name, cache)?;
+        let variant = cache.type_infos[type_id.0].union_variants()?.iter().find(|variant| variant.name == name)?;
+
+        let id = variant.id;
+        cache.definition_infos[id.0].uses += 1;
+        Some(id)
+    }
+
+    /// True if the given name refers to union variants of more than one type in scope
+    fn is_ambiguous_variant(&self, name: &str) -> bool {
+        self.global_scope().ambiguous_variants.contains(name)
+    }
+
     /// Adds a given environment variable (along with its name and the self.scopes index of the function it
     /// was found in) to a function, thus marking that function as being a closure. This works by
     /// creating a new parameter in the current function and creating a mapping between the
@@ -427,16 +467,54 @@ impl<'c> NameResolver {
         info.trait_info = Some((trait_id, args));
     }
 
+    /// True if exactly one of the existing definition and the definition currently
+    /// being declared is an `extern`, making it ambiguous which one is meant.
+    fn conflicts_with_extern(&self, existing: DefinitionInfoId, cache: &ModuleCache<'c>) -> bool {
+        match &cache[existing].definition {
+            Some(DefinitionKind::Extern(_)) => !self.declaring_extern,
+            Some(DefinitionKind::Definition(_)) => self.declaring_extern,
+            _ => false,
+        }
+    }
+
+    /// Like push_definition, but a union variant may share its name with a variant of another type
+    /// in the global scope. A variant declared in this module shadows any imported variant of the
+    /// same name. Otherwise, such names are ambiguous and must be qualified with their type's name
+    /// when used. The name continues to refer to the first variant declared with it.
+    fn push_variant_definition(
+        &mut self, name: &str, cache: &mut ModuleCache<'c>, location: Location<'c>,
+    ) -> DefinitionInfoId {
+        let existing = self.current_scope().definitions.get(name).copied();
+
+        match existing {
+            Some(existing) if self.in_global_scope() && scope::is_union_variant(existing, cache) => {
+                if cache.definition_infos[existing.0].location.filename != self.filepath {
+                    self.current_scope().definitions.remove(name);
+                    return self.push_definition(name, cache, location);
+                }
+
+                self.current_scope().ambiguous_variants.insert(name.to_owned());
+                cache.push_definition(name, true, location)
+            },
+            _ => self.push_definition(name, cache, location),
+        }
+    }
+
     /// Push a new Definition onto the current scope.
     fn push_definition(&mut self, name: &str, cache: &mut ModuleCache<'c>, location: Location<'c>) -> DefinitionInfoId {
         let in_global_scope = self.in_global_scope();
         let id = cache.push_definition(name, in_global_scope, location);
 
         // if shadows
-        if let Some(existing_definition) = self.current_scope().definitions.get(name) {
+        if let Some(existing_definition) = self.current_scope().definitions.get(name).copied() {
             // disallow shadowing in global scopes
             if in_global_scope {
-                cache.push_diagnostic(location, D::AlreadyInScope(name.to_owned()));
+                let kind = if self.conflicts_with_extern(existing_definition, cache) {
+                    D::ConflictingExternAndNativeDefinition(name.to_owned())
+                } else {
+                    D::AlreadyInScope(name.to_owned())
+                };
+                cache.push_diagnostic(location, kind);
                 let previous_location = cache.definition_infos[existing_definition.0].location;
                 cache.push_diagnostic(previous_location, D::PreviouslyDefinedHere(name.to_owned()));
             } else {
@@ -454,7 +532,11 @@ impl<'c> NameResolver {
             // Prevent _ from being referenced and allow it to be redefined as needed.
             // This can be removed if ante ever allows shadowing by default.
             if name != "_" {
-                if self.in_global_scope() {
+                if self.declaring_impl_helper {
+                    if in_global_scope {
+                        self.global_impl_helpers.insert(id);
+                    }
+                } else if in_global_scope && !self.defining_fields {
                     self.exports.definitions.insert(name.to_owned(), id);
                 }
                 self.current_scope().definitions.insert(name.to_owned(), id);
@@ -471,7 +553,8 @@ impl<'c> NameResolver {
     pub fn push_type_info(
         &mut self, name: String, args: Vec<TypeVariableId>, cache: &mut ModuleCache<'c>, location: Location<'c>,
     ) -> TypeInfoId {
-        if let Some(existing_definition) = self.current_scope().types.get(&name) {
+        // The builtin Array type may be shadowed by a user-defined type of the same name
+        if let Some(existing_definition) = self.current_scope().types.get(&name).filter(|id| **id != ARRAY_TYPE) {
             cache.push_diagnostic(location, D::AlreadyInScope(name.clone()));
             let previous_location = cache.type_infos[existing_definition.0].locate();
             cache.push_diagnostic(previous_location, D::PreviouslyDefinedHere(name.clone()));
@@ -495,7 +578,7 @@ impl<'c> NameResolver {
             cache.push_diagnostic(previous_location, D::PreviouslyDefinedHere(name.clone()));
         }
 
-        let id = cache.push_trait_definition(name.clone(), args, fundeps, Some(node), location);
+        let id = cache.push_trait_definition(name.clone(), args, fundeps, Some(node), self.module_id, location);
         if self.in_global_scope() {
             self.exports.traits.insert(name.clone(), id);
         }
@@ -523,11 +606,11 @@ impl<'c> NameResolver {
     #[allow(clippy::too_many_arguments)]
     fn push_trait_impl(
         &mut self, trait_id: TraitInfoId, args: Vec<Type>, definitions: Vec<DefinitionInfoId>,
-        trait_impl: &'c mut ast::TraitImpl<'c>, given: Vec<ConstraintSignature>, cache: &mut ModuleCache<'c>,
-        location: Location<'c>,
+        trait_impl: &'c mut ast::TraitImpl<'c>, given: Vec<ConstraintSignature>,
+        negative_given: Vec<ConstraintSignature>, cache: &mut ModuleCache<'c>, location: Location<'c>,
     ) -> ImplInfoId {
         // Any overlapping impls are only reported when they're used during typechecking
-        let id = cache.push_trait_impl(trait_id, args, definitions, trait_impl, given, location);
+        let id = cache.push_trait_impl(trait_id, args, definitions, trait_impl, given, negative_given, location);
         if self.in_global_scope() {
             self.exports.impls.entry(trait_id).or_default().push(id);
             cache.impl_scopes[self.exports.impl_scope.0].push(id);
@@ -600,9 +683,11 @@ impl<'c> NameResolver {
             Type::UserDefined(id) => cache[*id].args.len(),
             Type::TypeApplication(_, _) => 0,
             Type::Ref { .. } => 1,
-            Type::Struct(_, _) => 0,
+            Type::Struct(..) => 0,
+            Type::Rec(..) => 0,
             Type::Effects(_) => 0,
             Type::Tag(_) => 0,
+            Type::Universe(_) => 0,
             Type::NamedGeneric(..) => 0,
         }
     }
@@ -654,10 +739,14 @@ impl<'c> NameResolver {
             type_variable_scopes: vec![scope::TypeVariableScope::default()],
             state: NameResolutionState::DeclareInProgress,
             auto_declare: false,
+            declaring_extern: false,
+            declaring_impl_helper: false,
+            defining_fields: false,
             current_trait: None,
             required_definitions: None,
             current_function: None,
             definitions_collected: vec![],
+            global_impl_helpers: HashSet::new(),
             let_binding_level: LetBindingLevel(INITIAL_LEVEL),
             module_id,
         };
@@ -692,6 +781,16 @@ impl<'c> NameResolver {
         self.state = NameResolutionState::Defined;
     }
 
+    fn convert_user_defined_type(&mut self, name: &str, location: Location<'c>, cache: &mut ModuleCache<'c>) -> Type {
+        match self.lookup_type(name, cache) {
+            Some(id) => Type::UserDefined(id),
+            None => {
+                cache.push_diagnostic(location, D::NotInScope("Type", name.to_owned()));
+                Type::UNIT
+            },
+        }
+    }
+
     pub fn convert_type(&mut self, cache: &mut ModuleCache<'c>, ast_type: &ast::Type<'c>) -> Type {
         match ast_type {
             ast::Type::Integer(Some(kind), _) => Type::int(*kind),
@@ -735,12 +834,10 @@ impl<'c> NameResolver {
                     }
                 },
             },
-            ast::Type::UserDefined(name, location) => match self.lookup_type(name, cache) {
-                Some(id) => Type::UserDefined(id),
-                None => {
-                    cache.push_diagnostic(*location, D::NotInScope("Type", name.clone()));
-                    Type::UNIT
-                },
+            // Associated types of a trait are in scope as capitalized type variables
+            ast::Type::UserDefined(name, location) => match self.lookup_type_variable(name) {
+                Some((id, name)) => Type::NamedGeneric(id, name),
+                None => self.convert_user_defined_type(name, *location, cache),
             },
             ast::Type::TypeApplication(constructor, args, _) => {
                 let constructor = Box::new(self.convert_type(cache, constructor));
@@ -783,6 +880,33 @@ impl<'c> NameResolver {
 
                 Type::Ref { sharedness, mutability, lifetime }
             },
+            ast::Type::Nat(n, _) => Type::Tag(TypeTag::Nat(*n)),
+            ast::Type::Universe(level, _) => Type::Universe(*level),
+            ast::Type::Dyn(trait_name, location) => match self.lookup_trait(trait_name, cache) {
+                Some(id) => Type::Tag(TypeTag::Dyn(id)),
+                None => {
+                    cache.push_diagnostic(*location, D::NotInScope("Trait", trait_name.clone()));
+                    Type::UNIT
+                },
+            },
+            ast::Type::NatArithmetic(operator, lhs, rhs, _) => {
+                let args = vec![self.convert_type(cache, lhs), self.convert_type(cache, rhs)];
+                Type::TypeApplication(Box::new(Type::Tag(*operator)), args)
+            },
+            ast::Type::Comptime(expr, location) => {
+                // The expression is boxed before being resolved so that any references
+                // to its nodes stored in the cache remain valid once it is moved. Variables
+                // used within it are never declared, even when the type is within a pattern.
+                let mut expr = expr.clone();
+                let prev_auto_declare = self.auto_declare;
+                self.auto_declare = false;
+                expr.define(self, cache);
+                self.auto_declare = prev_auto_declare;
+
+                let typ = cache.next_type_variable_id(self.let_binding_level);
+                cache.comptime_expressions.push(ComptimeExpression { expr, typ, location: *location });
+                Type::TypeVariable(typ)
+            },
         }
     }
 
@@ -892,6 +1016,7 @@ impl<'c> NameResolver {
     fn resolve_extern_definitions(&mut self, extern_: &mut ast::Extern<'c>, cache: &mut ModuleCache<'c>) {
         self.definitions_collected.clear();
         self.auto_declare = true;
+        self.declaring_extern = true;
 
         for declaration in &mut extern_.declarations {
             self.push_type_variable_scope();
@@ -900,6 +1025,7 @@ impl<'c> NameResolver {
         }
 
         self.auto_declare = false;
+        self.declaring_extern = false;
 
         for id in self.definitions_collected.iter() {
             let extern_ = trustme::extend_lifetime(extern_);
@@ -1029,7 +1155,14 @@ impl<'c> NameResolver {
             _ => None,
         });
 
+        // Unlike function types, a lambda without an effects clause is not implicitly pure.
+        // Keep it as None so that only lambdas explicitly declared `pure` are checked as such.
+        let effects_declared = effects.is_some();
         self.desugar_function_effect_variables(arg_types, effects, cache);
+
+        if !effects_declared && effects.as_ref().is_some_and(|effects| effects.is_empty()) {
+            *effects = None;
+        }
     }
 
     fn desugar_function_effect_variables_in_type<'a>(&self, typ: &mut ast::Type<'a>, cache: &mut ModuleCache) {
@@ -1082,11 +1215,15 @@ impl<'c> Resolvable<'c> for ast::Variable<'c> {
                 let id = resolver.push_definition(&name, cache, self.location);
                 resolver.definitions_collected.push(id);
                 self.definition = Some(id);
+            } else if let [type_name] = self.module_prefix.as_slice() {
+                self.definition = resolver.lookup_qualified_variant(type_name, &name, cache);
+            } else if resolver.is_ambiguous_variant(&name) {
+                cache.push_diagnostic(self.location, D::AmbiguousVariant(name.to_string()));
             } else {
                 self.definition = resolver.reference_definition(&name, self.location, cache);
             }
 
-            self.id = Some(cache.push_variable(name.into_owned(), self.location));
+            self.id = Some(cache.push_variable(name.into_owned(), self.definition, self.location));
             self.impl_scope = Some(resolver.current_scope().impl_scope);
         }
     }
@@ -1103,10 +1240,18 @@ impl<'c> Resolvable<'c> for ast::Variable<'c> {
                     TypeConstructor(name) => Cow::Borrowed(name),
                 };
 
-                if self.module_prefix.is_empty() {
+                let qualified_variant = match self.module_prefix.as_slice() {
+                    [type_name] => resolver.lookup_qualified_variant(type_name, &name, cache),
+                    _ => None,
+                };
+
+                if self.module_prefix.is_empty() && resolver.is_ambiguous_variant(&name) {
+                    cache.push_diagnostic(self.location, D::AmbiguousVariant(name.into_owned()));
+                } else if self.module_prefix.is_empty() || qualified_variant.is_some() {
                     self.impl_scope = Some(resolver.current_scope().impl_scope);
-                    self.definition = resolver.reference_definition(&name, self.location, cache);
-                    self.id = Some(cache.push_variable(name.into_owned(), self.location));
+                    self.definition =
+                        qualified_variant.or_else(|| resolver.reference_definition(&name, self.location, cache));
+                    self.id = Some(cache.push_variable(name.into_owned(), self.definition, self.location));
                 } else {
                     // resolve module
                     let relative_path = self.module_prefix.join("/");
@@ -1119,15 +1264,16 @@ impl<'c> Resolvable<'c> for ast::Variable<'c> {
                     if let Some(module_id) = module_id {
                         self.definition = resolver.module_scopes[&module_id].definitions.get(name.as_ref()).copied();
                         self.impl_scope = Some(resolver.current_scope().impl_scope);
-                        self.id = Some(cache.push_variable(name.into_owned(), self.location));
+                        self.id = Some(cache.push_variable(name.into_owned(), self.definition, self.location));
                     } else {
                         cache.push_diagnostic(self.location, D::CouldNotFindModule(relative_path));
                     }
                 }
             }
 
-            // If it is still not declared, print an error
-            if self.definition.is_none() {
+            // If it is still not declared, print an error. Ambiguous variants have already been reported.
+            let is_ambiguous = self.module_prefix.is_empty() && resolver.is_ambiguous_variant(&self.to_string());
+            if self.definition.is_none() && !is_ambiguous {
                 cache.push_diagnostic(self.location, D::NoDeclarationFoundInScope(self.to_string()));
             }
         } else if resolver.in_global_scope() {
@@ -1141,6 +1287,12 @@ impl<'c> Resolvable<'c> for ast::Lambda<'c> {
     fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
 
     fn define(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
+        // Default arguments are copied into each call omitting them, so they are
+        // resolved outside of the function where its parameters are not in scope.
+        for default in self.default_args.iter_mut() {
+            default.define(resolver, cache);
+        }
+
         resolver.push_lambda(self, cache);
         resolver.try_add_current_function_to_scope();
 
@@ -1156,10 +1308,33 @@ impl<'c> Resolvable<'c> for ast::Lambda<'c> {
         }
 
         self.body.define(resolver, cache);
+
+        for contract in self.contracts.iter_mut() {
+            contract.define(resolver, cache);
+        }
+
         resolver.pop_lambda(cache);
     }
 }
 
+impl<'c> Resolvable<'c> for ast::Contract<'c> {
+    fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
+
+    fn define(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
+        resolver.push_scope(cache);
+
+        // Postconditions may refer to the function's return value via an implicit `result` variable
+        if self.kind == ast::ContractKind::Ensures {
+            let result = resolver.push_definition("result", cache, self.location);
+            cache[result].ignore_unused_warning = true;
+            self.result = Some(result);
+        }
+
+        self.condition.define(resolver, cache);
+        resolver.pop_scope(cache, true, None);
+    }
+}
+
 impl<'c> Resolvable<'c> for ast::FunctionCall<'c> {
     fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
 
@@ -1187,6 +1362,7 @@ impl<'c> Resolvable<'c> for ast::Definition<'c> {
         resolver.resolve_declarations(self.pattern.as_mut(), cache, definition);
         for id in resolver.definitions_collected.iter() {
             cache[*id].mutable = self.mutable;
+            cache[*id].deprecated = self.deprecated.clone();
         }
 
         self.level = Some(resolver.let_binding_level);
@@ -1207,7 +1383,9 @@ impl<'c> Resolvable<'c> for ast::Definition<'c> {
 
         resolver.resolve_definitions(self.pattern.as_mut(), cache, definition);
         for id in resolver.definitions_collected.iter() {
-            cache[*id].mutable = self.mutable;
+            // Keep any mutability from a mutable reference pattern within the definition
+            cache[*id].mutable |= self.mutable;
+            cache[*id].deprecated = self.deprecated.clone();
         }
 
         self.level = Some(resolver.let_binding_level);
@@ -1238,6 +1416,52 @@ impl<'c> Resolvable<'c> for ast::If<'c> {
     }
 }
 
+impl<'c> Resolvable<'c> for ast::ConditionalCompilation<'c> {
+    fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
+
+    fn define(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
+        self.is_active = cache.target.matches(&self.configuration);
+
+        resolver.push_scope(cache);
+        self.then.define(resolver, cache);
+        resolver.pop_scope(cache, true, None);
+
+        resolver.push_scope(cache);
+        self.otherwise.define(resolver, cache);
+        resolver.pop_scope(cache, true, None);
+    }
+}
+
+impl<'c> Resolvable<'c> for ast::Spread<'c> {
+    fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
+
+    fn define(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
+        self.expression.define(resolver, cache);
+    }
+}
+
+impl<'c> Resolvable<'c> for ast::ArrayLiteral<'c> {
+    fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
+
+    fn define(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
+        for element in &mut self.elements {
+            element.define(resolver, cache);
+        }
+    }
+}
+
+impl<'c> Resolvable<'c> for ast::Hole<'c> {
+    fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
+
+    fn define(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
+}
+
+impl<'c> Resolvable<'c> for ast::Error<'c> {
+    fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
+
+    fn define(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
+}
+
 impl<'c> Resolvable<'c> for ast::Match<'c> {
     fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
 
@@ -1257,8 +1481,11 @@ impl<'c> Resolvable<'c> for ast::Match<'c> {
 
 /// Given "type T a b c = ..." return
 /// forall a b c. args -> T a b c
+///
+/// A variant declaring its own result type, e.g. `| IntLit I32 : Expr I32`, constructs that
+/// type instead and is only generalized over the type variables it actually mentions.
 fn create_variant_constructor_type(
-    parent_type_id: TypeInfoId, args: Vec<Type>, cache: &mut ModuleCache,
+    parent_type_id: TypeInfoId, args: Vec<Type>, result_type: Option<Type>, cache: &mut ModuleCache,
 ) -> GeneralizedType {
     let info = &cache.type_infos[parent_type_id.0];
     let mut result = Type::UserDefined(parent_type_id);
@@ -1269,7 +1496,14 @@ fn create_variant_constructor_type(
         result = Type::TypeApplication(Box::new(result), type_variables);
     }
 
-    let type_args = info.args.clone();
+    let mut type_args = info.args.clone();
+
+    if let Some(result_type) = result_type {
+        result = result_type;
+        type_args.retain(|id| {
+            args.iter().chain(std::iter::once(&result)).any(|typ| typechecker::contains_typevar(*id, typ, cache))
+        });
+    }
 
     // Create the arguments to the function type if this type has arguments
     if !args.is_empty() {
@@ -1290,7 +1524,7 @@ fn create_variant_constructor_type(
     }
 }
 
-type Variants<'c> = Vec<(String, Vec<ast::Type<'c>>, Location<'c>)>;
+type Variants<'c> = Vec<(String, Vec<ast::Type<'c>>, Option<ast::Type<'c>>, Location<'c>)>;
 
 /// Declare variants of a sum type given:
 /// vec: A vector of each variant. Has a tuple of the variant's name arguments, and location for each.
@@ -1299,11 +1533,17 @@ fn create_variants<'c>(
     vec: &Variants<'c>, parent_type_id: TypeInfoId, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>,
 ) -> Vec<TypeConstructor<'c>> {
     let mut tag = 0;
-    fmap(vec, |(name, types, location)| {
+    fmap(vec, |(name, types, result_type, location)| {
         let args = fmap(types, |t| resolver.convert_type(cache, t));
+        let result_type = result_type.as_ref().map(|t| resolver.convert_type(cache, t));
+
+        if result_type.as_ref().is_some_and(|t| !constructs_type(t, parent_type_id, cache)) {
+            let type_name = cache.type_infos[parent_type_id.0].name.clone();
+            cache.push_diagnostic(*location, D::InvalidVariantResultType(name.clone(), type_name));
+        }
 
-        let id = resolver.push_definition(name, cache, *location);
-        let constructor_type = create_variant_constructor_type(parent_type_id, args.clone(), cache);
+        let id = resolver.push_variant_definition(name, cache, *location);
+        let constructor_type = create_variant_constructor_type(parent_type_id, args.clone(), result_type, cache);
 
         cache.definition_infos[id.0].typ = Some(constructor_type);
         cache.definition_infos[id.0].definition =
@@ -1314,6 +1554,32 @@ fn create_variants<'c>(
     })
 }
 
+/// True if the given type is the type with the given id, applied to the expected number of arguments
+fn constructs_type(typ: &Type, type_id: TypeInfoId, cache: &ModuleCache) -> bool {
+    let arg_count = cache.type_infos[type_id.0].args.len();
+    match typ {
+        Type::UserDefined(id) => *id == type_id && arg_count == 0,
+        Type::TypeApplication(constructor, args) => {
+            matches!(constructor.as_ref(), Type::UserDefined(id) if *id == type_id) && args.len() == arg_count
+        },
+        _ => false,
+    }
+}
+
+/// Require each `given` constraint of a type definition whenever the given constructor is used,
+/// whether to construct a value of the type or to match on one in a pattern.
+fn add_type_constraints(constructor: DefinitionInfoId, given: &[ConstraintSignature], cache: &mut ModuleCache) {
+    for signature in given {
+        // Nothing within a constructor uses the trait directly, so the constraint is
+        // only passed on indirectly to each callsite of the constructor.
+        let signature = ConstraintSignature { id: cache.next_trait_constraint_id(), ..signature.clone() };
+        let name = cache[signature.trait_id].name.clone();
+        let location = cache[constructor].location;
+        let callsite = Callsite::Indirect(cache.push_variable(name, None, location), vec![signature.id]);
+        cache[constructor].required_traits.push(RequiredTrait { signature, callsite });
+    }
+}
+
 type Fields<'c> = Vec<(String, ast::Type<'c>, Location<'c>)>;
 
 fn create_fields<'c>(vec: &Fields<'c>, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) -> Vec<Field<'c>> {
@@ -1324,10 +1590,26 @@ fn create_fields<'c>(vec: &Fields<'c>, resolver: &mut NameResolver, cache: &mut
     })
 }
 
+/// Issue an error for each field declared more than once in the given struct type
+fn check_for_duplicate_fields<'c>(fields: &Fields<'c>, type_name: &str, cache: &mut ModuleCache<'c>) {
+    let mut declared: HashMap<&String, Location<'c>> = HashMap::new();
+
+    for (name, _, location) in fields {
+        if let Some(previous_location) = declared.get(name) {
+            let error = TE::DuplicateFieldName(Rc::new(name.clone()));
+            cache.push_diagnostic(*location, D::TypeError(error, type_name.to_owned(), type_name.to_owned()));
+            cache.push_diagnostic(*previous_location, D::PreviouslyDefinedHere(name.clone()));
+        } else {
+            declared.insert(name, *location);
+        }
+    }
+}
+
 impl<'c> Resolvable<'c> for ast::TypeDefinition<'c> {
     fn declare(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
         let args = fmap(&self.args, |_| cache.next_type_variable_id(resolver.let_binding_level));
         let id = resolver.push_type_info(self.name.clone(), args, cache, self.location);
+        cache.type_infos[id.0].linear = self.linear;
         self.type_info = Some(id);
     }
 
@@ -1343,14 +1625,22 @@ impl<'c> Resolvable<'c> for ast::TypeDefinition<'c> {
         let existing_ids = cache.type_infos[id.0].args.clone();
         resolver.add_existing_type_variables_to_scope(&self.args, &existing_ids, self.location, cache);
 
+        // Negated constraints on types are not supported, so `not` constraints are skipped here
+        let given: Vec<_> = self.given.iter().filter(|given| !given.negated).cloned().collect();
+        let given = resolver.resolve_required_traits(&given, cache);
+
         let type_id = self.type_info.unwrap();
         match &self.definition {
             ast::TypeDefinitionBody::Union(vec) => {
                 let variants = create_variants(vec, type_id, resolver, cache);
+                for variant in &variants {
+                    add_type_constraints(variant.id, &given, cache);
+                }
                 let type_info = &mut cache.type_infos[type_id.0];
                 type_info.body = TypeInfoBody::Union(variants);
             },
             ast::TypeDefinitionBody::Struct(vec) => {
+                check_for_duplicate_fields(vec, &self.name, cache);
                 let fields = create_fields(vec, resolver, cache);
                 let field_types = fmap(&fields, |field| field.field_type.clone());
 
@@ -1360,11 +1650,12 @@ impl<'c> Resolvable<'c> for ast::TypeDefinition<'c> {
                 // Create the constructor for this type.
                 // This is done inside create_variants for tagged union types
                 let id = resolver.push_definition(&self.name, cache, self.location);
-                let constructor_type = create_variant_constructor_type(type_id, field_types, cache);
+                let constructor_type = create_variant_constructor_type(type_id, field_types, None, cache);
 
                 cache.definition_infos[id.0].typ = Some(constructor_type);
                 cache.definition_infos[id.0].definition =
                     Some(DefinitionKind::TypeConstructor { name: self.name.clone(), tag: None });
+                add_type_constraints(id, &given, cache);
             },
             ast::TypeDefinitionBody::Alias(typ) => {
                 let typ = resolver.convert_type(cache, typ);
@@ -1434,14 +1725,13 @@ pub fn declare_module<'a>(path: &Path, cache: &mut ModuleCache<'a>, error_locati
     let tokens = Lexer::new(path, contents).collect::<Vec<_>>();
 
     timing::start_time("Parsing");
-    let result = parser::parse(&tokens);
+    let (ast, errors) = parser::parse(&tokens);
 
     timing::start_time("Name Resolution (Declare)");
-    if result.is_err() {
+    if !errors.is_empty() {
         return None;
     }
 
-    let ast = result.unwrap();
     let import_resolver = NameResolver::declare(ast, cache);
     Some(import_resolver.module_id)
 }
@@ -1466,6 +1756,53 @@ pub fn define_module<'a>(
     Some(&import.exports)
 }
 
+/// The path of an imported module, e.g. `["Foo", "Bar"]` for `import Foo.Bar`
+pub type ModulePath = Vec<String>;
+
+/// Returns each module imported by the main module along with the imported
+/// names that were actually referenced by a variable in the main module.
+/// Any imported name not included is unused. Only top-level imports are checked.
+#[allow(dead_code)]
+pub fn used_imports(cache: &ModuleCache) -> Vec<(ModulePath, Vec<String>)> {
+    let statements = match cache.parse_trees.get_mut(0) {
+        Some(Ast::Sequence(sequence)) => sequence.statements.as_slice(),
+        Some(statement) => std::slice::from_ref(statement),
+        None => return vec![],
+    };
+
+    let mut used = vec![];
+    for statement in statements {
+        let import = match statement {
+            Ast::Import(import) => import,
+            _ => continue,
+        };
+
+        let module_id = match import.module_id {
+            Some(id) => id,
+            None => continue,
+        };
+
+        let exports = &cache.name_resolvers.get_mut(module_id.0).unwrap().exports.definitions;
+
+        let mut names: Vec<String> = cache
+            .variable_infos
+            .iter()
+            .filter(|variable| variable.location.filename == import.location.filename)
+            .filter_map(|variable| {
+                let definition = variable.definition?;
+                let (name, _) = exports.iter().find(|(_, id)| **id == definition)?;
+                let imported = import.symbols.is_empty() || import.symbols.contains(name);
+                imported.then(|| name.clone())
+            })
+            .collect();
+
+        names.sort();
+        names.dedup();
+        used.push((import.path.clone(), names));
+    }
+    used
+}
+
 impl<'c> Resolvable<'c> for ast::Import<'c> {
     fn declare(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
         let relative_path = self.path.clone().join("/");
@@ -1524,7 +1861,8 @@ impl<'c> Resolvable<'c> for ast::TraitDefinition<'c> {
             self.declare(resolver, cache);
         }
 
-        if self.declarations.get(0).map_or(false, |decl| decl.typ.is_none()) {
+        // Traits without declarations may still have superclasses to resolve
+        if self.declarations.get(0).map_or(true, |decl| decl.typ.is_none()) {
             resolver.push_type_variable_scope();
 
             // Re-add the typevariables we created in TraitDefinition::declare back into scope
@@ -1542,7 +1880,64 @@ impl<'c> Resolvable<'c> for ast::TraitDefinition<'c> {
                 resolver.auto_declare = prev_auto_declare;
                 declaration.typ = Some(rhs);
             }
+
+            // Negated superclasses are not supported, so `not` constraints are skipped here
+            let superclasses: Vec<_> = self.given.iter().filter(|given| !given.negated).cloned().collect();
+            let superclasses = resolver.resolve_required_traits(&superclasses, cache);
+            cache.trait_infos[self.trait_info.unwrap().0].superclasses = superclasses;
             resolver.pop_type_variable_scope();
+
+            if let Some(closed) = &self.closed {
+                resolver.push_type_variable_scope();
+                let prev_auto_declare = resolver.auto_declare;
+                resolver.auto_declare = true;
+                let closed = fmap(closed, |args| fmap(args, |arg| resolver.convert_type(cache, arg)));
+                resolver.auto_declare = prev_auto_declare;
+                resolver.pop_type_variable_scope();
+                cache.trait_infos[self.trait_info.unwrap().0].closed_impls = Some(closed);
+            }
+        }
+    }
+}
+
+impl<'c> ast::TraitImpl<'c> {
+    /// Append each `type Name = Type` definition in this impl to its trait arguments, in the
+    /// order the trait declares its associated types. Associated types are desugared into
+    /// the trait's trailing functional dependencies so any not given explicitly as trait
+    /// arguments are looked up by name here.
+    fn resolve_associated_types(
+        &mut self, trait_id: TraitInfoId, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>,
+    ) {
+        let trait_info = &cache.trait_infos[trait_id.0];
+        let Some(trait_node) = trait_info.trait_node.as_ref() else { return };
+
+        let explicit_fundeps = self.trait_args.len().saturating_sub(trait_info.typeargs.len());
+        let associated_type_names = trait_node.fundeps.iter().skip(explicit_fundeps).cloned().collect::<Vec<_>>();
+
+        // Associated types are always capitalized. If a lowercase fundep is missing then the
+        // impl is missing trait arguments which is reported as an argument count mismatch instead.
+        if associated_type_names.iter().any(|name| !name.starts_with(char::is_uppercase)) {
+            return;
+        }
+
+        for name in &associated_type_names {
+            match self.associated_types.iter().find(|(associated_type, _)| associated_type == name) {
+                Some((_, typ)) => {
+                    let typ = resolver.convert_type(cache, typ);
+                    self.trait_arg_types.push(typ);
+                },
+                None => {
+                    cache.push_diagnostic(self.location, D::MissingImplDefinition(format!("type {name}")));
+                    self.trait_arg_types.push(Type::UNIT);
+                },
+            }
+        }
+
+        for (name, typ) in &self.associated_types {
+            if !associated_type_names.contains(name) {
+                let error = D::ItemNotRequiredByTrait(format!("type {name}"), self.trait_name.clone());
+                cache.push_diagnostic(typ.locate(), error);
+            }
         }
     }
 }
@@ -1567,16 +1962,36 @@ impl<'c> Resolvable<'c> for ast::TraitImpl<'c> {
         let prev_auto_declare = resolver.auto_declare;
         resolver.auto_declare = true;
         self.trait_arg_types = fmap(&self.trait_args, |arg| resolver.convert_type(cache, arg));
+        self.resolve_associated_types(trait_id, resolver, cache);
         resolver.auto_declare = prev_auto_declare;
 
+        if let Some(deriving) = &self.deriving {
+            let via_matches = check_deriving_via(deriving, &self.trait_arg_types, resolver, cache);
+            match via_matches.then(|| derive_impl_definitions(deriving, trait_id, self.location, cache)).flatten() {
+                Some(definitions) => self.definitions = definitions,
+                None => {
+                    resolver.current_trait = None;
+                    resolver.pop_type_variable_scope();
+                    return;
+                },
+            }
+        }
+
+        let trait_info = &cache.trait_infos[trait_id.0];
+        if trait_info.sealed && trait_info.module != resolver.module_id {
+            let name = Rc::new(trait_info.name.clone());
+            let args = fmap(&self.trait_arg_types, |arg| arg.display(cache).to_string()).join(" ");
+            cache.push_diagnostic(self.location, D::TypeError(TE::ImplForSealedTrait(name), args.clone(), args));
+        }
+
         let trait_info = &cache.trait_infos[trait_id.0];
         resolver.required_definitions = Some(trait_info.definitions.clone());
 
         // The user is required to specify all of the trait's typeargs and functional dependencies.
         let required_arg_count = trait_info.typeargs.len() + trait_info.fundeps.len();
-        if self.trait_args.len() != required_arg_count {
+        if self.trait_arg_types.len() != required_arg_count {
             let trait_name = self.trait_name.clone();
-            let error = D::IncorrectImplTraitArgCount(trait_name, required_arg_count, self.trait_args.len());
+            let error = D::IncorrectImplTraitArgCount(trait_name, required_arg_count, self.trait_arg_types.len());
             cache.push_diagnostic(self.location, error);
         }
 
@@ -1597,13 +2012,35 @@ impl<'c> Resolvable<'c> for ast::TraitImpl<'c> {
         resolver.required_definitions = None;
         resolver.current_trait = None;
 
-        // All the names are present, now define them.
+        // Private helpers are declared only after the trait's required definitions are
+        // checked so they aren't mistaken for trait methods. They are still visible
+        // to every definition within the impl since they share its scope.
+        resolver.declaring_impl_helper = true;
+        for helper in self.helpers.iter_mut() {
+            helper.declare(resolver, cache);
+        }
+        resolver.declaring_impl_helper = false;
+
+        // All the names are present, now define them. Helpers are defined first since a
+        // helper with a type annotation is only put in scope once it is defined.
+        resolver.declaring_impl_helper = true;
+        for helper in self.helpers.iter_mut() {
+            helper.define(resolver, cache);
+        }
+        resolver.declaring_impl_helper = false;
+
         for definition in self.definitions.iter_mut() {
             definition.expr.define(resolver, cache);
             definition.level = Some(resolver.let_binding_level);
         }
 
-        let given = resolver.resolve_required_traits(&self.given, cache);
+        let (negative_given, given): (Vec<_>, Vec<_>) = self.given.iter().cloned().partition(|given| given.negated);
+        let mut given = resolver.resolve_required_traits(&given, cache);
+        let negative_given = resolver.resolve_required_traits(&negative_given, cache);
+        check_impl_constraints_are_satisfiable(self, trait_id, &given, &negative_given, cache);
+
+        // A `given Ord a` also provides any superclasses of `Ord a` to the impl's definitions
+        add_implied_superclasses(&mut given, cache);
 
         resolver.pop_let_binding_level();
         resolver.pop_scope(cache, false, None);
@@ -1616,12 +2053,174 @@ impl<'c> Resolvable<'c> for ast::TraitImpl<'c> {
             definitions,
             trait_impl,
             given,
+            negative_given,
             cache,
             self.locate(),
         ));
     }
 }
 
+/// Checks that the type given by the `via` of a deriving clause, if any, is the type of the
+/// newtype's field. Returns false and issues an error if it is not.
+fn check_deriving_via<'c>(
+    deriving: &ast::Deriving<'c>, trait_args: &[Type], resolver: &mut NameResolver, cache: &mut ModuleCache<'c>,
+) -> bool {
+    let (Some(via), Some(field)) = (&deriving.via, &deriving.field) else {
+        return true;
+    };
+
+    let field_type = match trait_args.first() {
+        Some(Type::UserDefined(id)) => match &cache.type_infos[id.0].body {
+            TypeInfoBody::Struct(fields) => fields.iter().find(|f| f.name == *field).map(|f| f.field_type.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    let Some(field_type) = field_type else {
+        return true;
+    };
+
+    let via_type = resolver.convert_type(cache, via);
+    if typechecker::try_unify(&field_type, &via_type, via.locate(), cache, TE::NeverShown).is_ok() {
+        return true;
+    }
+
+    let field_type = field_type.display(cache).to_string();
+    cache.push_diagnostic(via.locate(), D::DerivingViaMismatch(field.clone(), field_type));
+    false
+}
+
+/// Generates the definitions of an impl from a `deriving` clause on a newtype. For a newtype
+/// `type Age = years: U32` deriving a trait over `a`, a method declared as `name: a - Int -> a`
+/// is implemented as `name $0 $1 = Age (name $0.years $1)` which uses the field type's impl.
+///
+/// Returns None if the type is not a newtype or if the trait's type argument is used
+/// anywhere other than directly as a parameter or return type of its methods.
+fn derive_impl_definitions<'c>(
+    deriving: &ast::Deriving<'c>, trait_id: TraitInfoId, location: Location<'c>, cache: &mut ModuleCache<'c>,
+) -> Option<Vec<ast::Definition<'c>>> {
+    let Some(field) = &deriving.field else {
+        cache.push_diagnostic(location, D::DerivingRequiresNewtype(deriving.type_name.clone()));
+        return None;
+    };
+
+    let trait_info = &cache.trait_infos[trait_id.0];
+    let trait_name = trait_info.name.clone();
+    let declarations = match &trait_info.trait_node {
+        Some(node) if node.args.len() == 1 && node.fundeps.is_empty() => {
+            fmap(&node.declarations, |declaration| (declaration.lhs.clone(), declaration.rhs.clone()))
+        },
+        _ => {
+            cache.push_diagnostic(location, D::CannotDeriveTrait(trait_name));
+            return None;
+        },
+    };
+    let type_arg = cache.trait_infos[trait_id.0].trait_node.as_ref().unwrap().args[0].clone();
+
+    // The generated code is attributed to the deriving clause so errors within it can be traced back
+    let expansion = cache.push_expansion(format!("deriving {trait_name}"), location);
+    let expanded = location.in_expansion(expansion);
+
+    let is_type_arg = |typ: &ast::Type| matches!(typ, ast::Type::TypeVariable(name, _) if *name == type_arg);
+    let variable = |name: String| Ast::variable(vec![], name, expanded);
+
+    // Unwrap `$0` into `$0.field`
+    let unwrap = |name: String| Ast::member_access(variable(name), field.clone(), None, expanded);
+    let wrap = |value: Ast<'c>| {
+        let constructor = Ast::type_constructor(vec![], deriving.type_name.clone(), expanded);
+        Ast::function_call(constructor, vec![value], expanded)
+    };
+
+    let mut definitions = Vec::with_capacity(declarations.len());
+    for (lhs, typ) in declarations {
+        let method = match lhs.as_ref() {
+            Ast::Variable(variable) => match &variable.kind {
+                ast::VariableKind::Identifier(name) => Ast::variable(vec![], name.clone(), expanded),
+                ast::VariableKind::Operator(operator) => Ast::operator(operator.clone(), expanded),
+                ast::VariableKind::TypeConstructor(name) => Ast::type_constructor(vec![], name.clone(), expanded),
+            },
+            _ => continue,
+        };
+
+        let (parameters, return_type) = match &typ {
+            ast::Type::Function(function) => (function.parameters.as_slice(), function.return_type.as_ref()),
+            other => (&[][..], other),
+        };
+
+        let mut args = Vec::with_capacity(parameters.len());
+        let mut call_args = Vec::with_capacity(parameters.len());
+
+        for (i, parameter) in parameters.iter().enumerate() {
+            let name = format!("${}", i);
+            args.push(variable(name.clone()));
+
+            if is_type_arg(parameter) {
+                call_args.push(unwrap(name));
+            } else if mentions_type_variable(parameter, &type_arg) {
+                cache.push_diagnostic(location, D::CannotDeriveTrait(trait_name));
+                return None;
+            } else {
+                call_args.push(variable(name));
+            }
+        }
+
+        let mut body =
+            if call_args.is_empty() { method.clone() } else { Ast::function_call(method.clone(), call_args, expanded) };
+
+        if is_type_arg(return_type) {
+            body = wrap(body);
+        } else if mentions_type_variable(return_type, &type_arg) {
+            cache.push_diagnostic(location, D::CannotDeriveTrait(trait_name));
+            return None;
+        }
+
+        let expr = if args.is_empty() { body } else { Ast::lambda(args, None, None, body, expanded) };
+        if let Ast::Definition(definition) = Ast::definition(method, expr, expanded) {
+            definitions.push(definition);
+        }
+    }
+
+    Some(definitions)
+}
+
+fn mentions_type_variable(typ: &ast::Type, type_variable: &str) -> bool {
+    match typ {
+        ast::Type::TypeVariable(name, _) => name == type_variable,
+        ast::Type::Function(function) => {
+            function.parameters.iter().any(|parameter| mentions_type_variable(parameter, type_variable))
+                || mentions_type_variable(&function.return_type, type_variable)
+        },
+        ast::Type::TypeApplication(constructor, args, _) => {
+            mentions_type_variable(constructor, type_variable)
+                || args.iter().any(|arg| mentions_type_variable(arg, type_variable))
+        },
+        ast::Type::Pair(first, rest, _) | ast::Type::NatArithmetic(_, first, rest, _) => {
+            mentions_type_variable(first, type_variable) || mentions_type_variable(rest, type_variable)
+        },
+        _ => false,
+    }
+}
+
+/// Reports impls that can never be used because their constraints directly contradict
+/// each other, e.g. `impl Show (List a) given Show a, not Show a`, or because they
+/// require the absence of the very impl being defined, e.g. `impl Show a given not Show a`.
+fn check_impl_constraints_are_satisfiable<'c>(
+    trait_impl: &ast::TraitImpl<'c>, trait_id: TraitInfoId, given: &[ConstraintSignature],
+    negative_given: &[ConstraintSignature], cache: &mut ModuleCache<'c>,
+) {
+    for negated in negative_given {
+        let contradicts_impl = negated.trait_id == trait_id && negated.args == trait_impl.trait_arg_types;
+        let contradicts_given =
+            given.iter().any(|required| required.trait_id == negated.trait_id && required.args == negated.args);
+
+        if contradicts_impl || contradicts_given {
+            let constraint = negated.display(cache).to_string();
+            cache.push_diagnostic(trait_impl.location, D::UnsatisfiableImplConstraints(constraint));
+        }
+    }
+}
+
 impl<'c> Resolvable<'c> for ast::Return<'c> {
     fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
 
@@ -1678,6 +2277,9 @@ impl<'c> Resolvable<'c> for ast::Assignment<'c> {
     fn define(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
         self.lhs.define(resolver, cache);
         self.rhs.define(resolver, cache);
+        if let Some(index_assign) = &mut self.index_assign {
+            index_assign.define(resolver, cache);
+        }
     }
 }
 
@@ -1806,12 +2408,16 @@ impl<'c> Resolvable<'c> for ast::NamedConstructor<'c> {
     fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
 
     fn define(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
-        let type_name = match self.constructor.as_ref() {
+        let Some(constructor) = &self.constructor else {
+            return self.define_record_literal(resolver, cache);
+        };
+
+        let type_name = match constructor.as_ref() {
             Ast::Variable(ast::Variable { kind, .. }) => kind.name(),
             _ => {
                 // This should never happen since constructor is parsed with the `variant` parser
                 cache.push_diagnostic(
-                    self.constructor.locate(),
+                    constructor.locate(),
                     D::InternalError("Expected constructor field to be a Variable"),
                 );
                 return;
@@ -1834,7 +2440,7 @@ impl<'c> Resolvable<'c> for ast::NamedConstructor<'c> {
         let struct_fields = match &type_info.body {
             TypeInfoBody::Struct(fields) => fields.iter().map(|field| &field.name),
             _ => {
-                cache.push_diagnostic(self.constructor.locate(), D::NotAStruct(type_name.into_owned()));
+                cache.push_diagnostic(constructor.locate(), D::NotAStruct(type_name.into_owned()));
                 return;
             },
         };
@@ -1880,10 +2486,8 @@ impl<'c> Resolvable<'c> for ast::NamedConstructor<'c> {
         let has_unknown_fields = !defined_fields.is_empty();
 
         if has_missing_fields {
-            cache.push_diagnostic(
-                self.constructor.locate(),
-                D::MissingFields(missing_fields.into_iter().cloned().collect()),
-            );
+            cache
+                .push_diagnostic(constructor.locate(), D::MissingFields(missing_fields.into_iter().cloned().collect()));
         }
 
         for (name, (_, location)) in defined_fields {
@@ -1894,15 +2498,37 @@ impl<'c> Resolvable<'c> for ast::NamedConstructor<'c> {
             return;
         }
 
-        let call = ast::Ast::function_call(self.constructor.as_ref().clone(), args, self.location);
+        let call = ast::Ast::function_call(constructor.as_ref().clone(), args, self.location);
 
         // We only want to keep definitions in the sequence to keep the Hir simpler
         statements.retain(|stmt| matches!(stmt, Ast::Definition(_)));
         statements.push(call);
 
+        self.define_fields(resolver, cache);
+    }
+}
+
+impl<'c> ast::NamedConstructor<'c> {
+    /// An anonymous record literal is defined like a block with one definition per field.
+    /// Since its type is just the fields it is given, there are no missing or unknown fields.
+    /// The record being updated by a record update is defined outside of this block.
+    fn define_record_literal(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
+        if let Some(record) = &mut self.record {
+            record.define(resolver, cache);
+        }
+
+        self.define_fields(resolver, cache);
+    }
+
+    /// Define each field within a new scope so that they are not visible outside of the constructor
+    fn define_fields(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
+        let previous = std::mem::replace(&mut resolver.defining_fields, true);
+
         resolver.push_scope(cache);
         self.sequence.define(resolver, cache);
         resolver.pop_scope(cache, false, None);
+
+        resolver.defining_fields = previous;
     }
 }
 
@@ -1910,6 +2536,14 @@ impl<'c> Resolvable<'c> for ast::Reference<'c> {
     fn declare(&mut self, _resolver: &mut NameResolver, _cache: &mut ModuleCache<'c>) {}
 
     fn define(&mut self, resolver: &mut NameResolver, cache: &mut ModuleCache<'c>) {
+        let first_new_definition = resolver.definitions_collected.len();
         self.expression.define(resolver, cache);
+
+        // Variables bound through a mutable reference pattern like `!Some x` are mutable
+        if resolver.auto_declare && self.mutability == ast::Mutability::Mutable {
+            for id in &resolver.definitions_collected[first_new_definition..] {
+                cache[*id].mutable = true;
+            }
+        }
     }
 }
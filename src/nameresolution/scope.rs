@@ -10,11 +10,15 @@
 //! is significant because a type variable's scope is different
 //! than the general Scope for other symbols. See the TypeVariableScope
 //! struct for more details on this.
-use crate::cache::{DefinitionInfoId, EffectInfoId, ImplInfoId, ImplScopeId, ModuleCache, ModuleId, TraitInfoId};
+use crate::cache::{
+    DefinitionInfoId, DefinitionKind, EffectInfoId, ImplInfoId, ImplScopeId, ModuleCache, ModuleId, TraitInfoId,
+};
 use crate::error::location::{Locatable, Location};
 use crate::error::{Diagnostic, DiagnosticKind as D};
+use crate::nameresolution::builtin::prelude_path;
 use crate::parser::ast;
 use crate::types::{TypeInfoId, TypeVariableId};
+use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
@@ -38,6 +42,10 @@ pub struct Scope {
     pub impls: HashMap<TraitInfoId, Vec<ImplInfoId>>,
     pub impl_scope: ImplScopeId,
     pub modules: HashMap<String, ModuleId>,
+
+    /// Names of union variants declared by more than one type in this scope.
+    /// These must be qualified with their type's name when used, e.g. `Maybe.None`.
+    pub ambiguous_variants: HashSet<String>,
 }
 
 impl Scope {
@@ -50,6 +58,7 @@ impl Scope {
             effects: HashMap::new(),
             impls: HashMap::new(),
             modules: HashMap::new(),
+            ambiguous_variants: HashSet::new(),
         }
     }
 
@@ -84,13 +93,19 @@ impl Scope {
         &mut self, other: &Scope, cache: &mut ModuleCache<'a>, location: Location<'a>, symbols: &HashSet<String>,
     ) {
         macro_rules! merge_table {
-            ( $field:tt , $cache_field:tt , $errors:tt ) => {{
+            ( $field:tt , $cache_field:tt , $errors:tt $(, $resolve_conflict:ident)? ) => {{
                 for (k, v) in other.$field.iter() {
                     if !symbols.is_empty() && !symbols.contains(k) {
                         continue;
                     }
 
-                    if let Some(existing) = self.$field.get(k) {
+                    if let Some(existing) = self.$field.get(k).copied() {
+                        $(
+                            if self.$resolve_conflict(k, existing, *v, location, cache) {
+                                continue;
+                            }
+                        )?
+
                         let prev_loc = cache.$cache_field[existing.0].locate();
                         let error = Diagnostic::new(location, D::ImportShadowsPreviousDefinition(k.to_string()));
                         let note = Diagnostic::new(prev_loc, D::PreviouslyDefinedHere(k.to_string()));
@@ -103,7 +118,7 @@ impl Scope {
         }
 
         let mut errors = vec![];
-        merge_table!(definitions, definition_infos, errors);
+        merge_table!(definitions, definition_infos, errors, import_variant);
         merge_table!(types, type_infos, errors);
         merge_table!(traits, trait_infos, errors);
         merge_table!(effects, effect_infos, errors);
@@ -117,6 +132,44 @@ impl Scope {
         }
     }
 
+    /// An imported union variant may share its name with a variant already in scope. Rather than
+    /// being an error, the variant with the higher priority is kept: variants defined in the
+    /// importing module come before explicitly imported variants, which come before the prelude's.
+    /// A name imported by variants of equal priority is ambiguous and must be qualified when used.
+    ///
+    /// Returns false if either definition is not a union variant.
+    fn import_variant(
+        &mut self, name: &str, existing: DefinitionInfoId, imported: DefinitionInfoId, location: Location,
+        cache: &ModuleCache,
+    ) -> bool {
+        if existing == imported || !is_union_variant(existing, cache) || !is_union_variant(imported, cache) {
+            return false;
+        }
+
+        let priority = |id: DefinitionInfoId| {
+            let filename = cache.definition_infos[id.0].location.filename;
+            if filename == location.filename {
+                2
+            } else if filename == prelude_path() {
+                0
+            } else {
+                1
+            }
+        };
+
+        match priority(existing).cmp(&priority(imported)) {
+            Ordering::Less => {
+                self.definitions.insert(name.to_owned(), imported);
+                self.ambiguous_variants.remove(name);
+            },
+            Ordering::Equal => {
+                self.ambiguous_variants.insert(name.to_owned());
+            },
+            Ordering::Greater => (),
+        }
+        true
+    }
+
     /// Check for any unused definitions and issue the appropriate warnings if found.
     /// This is meant to be done at the end of a scope since if we're still in the middle
     /// of name resolution for a particular scope, any currently unused symbol may become
@@ -233,7 +286,12 @@ impl FunctionScopes {
         let function = unsafe { function.as_mut().unwrap() };
 
         let name = cache[existing].name.clone();
-        let fake_var = cache.push_variable(name, location);
+        let fake_var = cache.push_variable(name, Some(existing), location);
         function.closure_environment.insert(existing, (fake_var, parameter, Rc::new(HashMap::new())));
     }
 }
+
+/// True if the given definition is a constructor of a union type
+pub fn is_union_variant(id: DefinitionInfoId, cache: &ModuleCache) -> bool {
+    matches!(cache.definition_infos[id.0].definition, Some(DefinitionKind::TypeConstructor { tag: Some(_), .. }))
+}
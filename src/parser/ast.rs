@@ -23,7 +23,7 @@
 //!
 //! - Type inference fills out:
 //!   `typ: Option<Type>` for all nodes,
-//!   `decision_tree: Option<DecisionTree>` for `ast::Match`s
+//!   `decision_tree: Option<DecisionTree>` and `reference_bindings` for `ast::Match`s
 use crate::cache::{DefinitionInfoId, EffectInfoId, ImplInfoId, ImplScopeId, ModuleId, TraitInfoId, VariableId};
 use crate::error::location::{Locatable, Location};
 use crate::lexer::token::{FloatKind, IntegerKind, Token};
@@ -128,6 +128,12 @@ pub type ClosureEnvironment = BTreeMap<
 #[derive(Debug, Clone)]
 pub struct Lambda<'a> {
     pub args: Vec<Ast<'a>>,
+
+    /// The default values of the last `default_args.len()` parameters, if any.
+    /// E.g. `0` in `f (x: I32) (y: I32 = 0) = ...`. Calls omitting these
+    /// parameters have these values appended to their arguments.
+    pub default_args: Vec<Ast<'a>>,
+
     pub body: Box<Ast<'a>>,
     pub return_type: Option<Type<'a>>,
 
@@ -135,15 +141,56 @@ pub struct Lambda<'a> {
 
     pub closure_environment: ClosureEnvironment,
 
+    /// The explicit capture list of this closure, if any. E.g. `[x, y]` in `fn [x, y] z -> x + y + z`.
+    /// When present, the body may only capture the outer variables named here.
+    pub captures: Option<Vec<String>>,
+
+    /// Any `@requires` or `@ensures` conditions on this function
+    pub contracts: Vec<Contract<'a>>,
+
     #[allow(unused)]
     pub required_traits: Vec<RequiredTrait>,
 
+    /// Loops are desugared into recursive lambdas. This distinguishes
+    /// them so that `break` can find the loop it exits from.
+    pub kind: LambdaKind,
+
     pub location: Location<'a>,
     pub typ: Option<types::Type>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LambdaKind {
+    Function,
+
+    /// The body of a `loop`, with its label if it has one
+    Loop(Option<String>),
+}
+
 pub type EffectAst<'a> = (EffectName, Location<'a>, Vec<Type<'a>>);
 
+/// A `@requires(condition)` or `@ensures(condition)` attribute on a function definition.
+/// These conditions are only typechecked, they are never checked at runtime.
+#[derive(Debug, Clone)]
+pub struct Contract<'a> {
+    pub kind: ContractKind,
+    pub condition: Box<Ast<'a>>,
+
+    /// The implicit `result` variable an `ensures` condition may refer to.
+    /// This is filled out during name resolution.
+    pub result: Option<DefinitionInfoId>,
+    pub location: Location<'a>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ContractKind {
+    /// A precondition which may refer to the function's parameters
+    Requires,
+
+    /// A postcondition which may refer to the function's parameters and its `result`
+    Ensures,
+}
+
 #[derive(Debug, Clone)]
 pub enum EffectName {
     Name(String),
@@ -172,6 +219,15 @@ impl<'a> FunctionCall<'a> {
             false
         }
     }
+
+    /// True if this is a call to the `.[` index operator, e.g. `array.[i]`
+    pub fn is_index(&self) -> bool {
+        if let Ast::Variable(variable) = self.function.as_ref() {
+            variable.kind == VariableKind::Operator(Token::Index) && self.args.len() == 2
+        } else {
+            false
+        }
+    }
 }
 
 /// foo = 23
@@ -181,6 +237,9 @@ pub struct Definition<'a> {
     pub pattern: Box<Ast<'a>>,
     pub expr: Box<Ast<'a>>,
     pub mutable: bool,
+
+    /// The message given by a `@deprecated("message")` attribute, if any
+    pub deprecated: Option<String>,
     pub location: Location<'a>,
     pub level: Option<LetBindingLevel>,
     pub typ: Option<types::Type>,
@@ -210,6 +269,11 @@ pub struct Match<'a> {
     /// step and is used during codegen to efficiently compile each pattern branch.
     pub decision_tree: Option<DecisionTree>,
 
+    /// Each variable bound by reference when matching through a reference, paired with
+    /// the variable in the decision tree holding the value it refers to. Filled out
+    /// alongside the decision tree during type inference.
+    pub reference_bindings: Vec<(DefinitionInfoId, DefinitionInfoId)>,
+
     pub location: Location<'a>,
     pub typ: Option<types::Type>,
 }
@@ -234,6 +298,17 @@ pub enum Type<'a> {
     UserDefined(String, Location<'a>),
     TypeApplication(Box<Type<'a>>, Vec<Type<'a>>, Location<'a>),
     Pair(Box<Type<'a>>, Box<Type<'a>>, Location<'a>),
+    // A type-level natural number, e.g. the `5` in `Array 5 I32`
+    Nat(u64, Location<'a>),
+    // Closed arithmetic on type-level natural numbers, e.g. `(2 + 3)`.
+    // The operator is one of TypeTag::Add, TypeTag::Subtract, or TypeTag::Multiply.
+    NatArithmetic(types::TypeTag, Box<Type<'a>>, Box<Type<'a>>, Location<'a>),
+    // The type of types at the given level, e.g. `Universe 0`
+    Universe(u32, Location<'a>),
+    // A trait object, e.g. `dyn Show`
+    Dyn(String, Location<'a>),
+    // An expression evaluated during typechecking, e.g. the `comptime n * 2` in `Array (comptime n * 2) I32`
+    Comptime(Box<Ast<'a>>, Location<'a>),
 }
 
 #[derive(Debug, Clone)]
@@ -297,12 +372,18 @@ impl Display for Mutability {
 pub struct Trait<'a> {
     pub name: String,
     pub args: Vec<Type<'a>>,
+
+    /// True for a negative constraint `not Trait args` which
+    /// only holds if there is no impl for the given arguments.
+    pub negated: bool,
     pub location: Location<'a>,
 }
 
 #[derive(Debug, Clone)]
 pub enum TypeDefinitionBody<'a> {
-    Union(Vec<(String, Vec<Type<'a>>, Location<'a>)>),
+    /// Each variant may declare the type it constructs, e.g. `| IntLit I32 : Expr I32`,
+    /// restricting it to particular arguments of the type being defined.
+    Union(Vec<(String, Vec<Type<'a>>, Option<Type<'a>>, Location<'a>)>),
     Struct(Vec<(String, Type<'a>, Location<'a>)>),
     Alias(Type<'a>),
 }
@@ -312,8 +393,14 @@ pub enum TypeDefinitionBody<'a> {
 pub struct TypeDefinition<'a> {
     #[allow(unused)]
     pub boxed: bool,
+
+    /// Values of a `linear` type must be used exactly once
+    pub linear: bool,
     pub name: String,
     pub args: Vec<String>,
+
+    /// `given` constraints which must hold to construct or match on values of this type
+    pub given: Vec<Trait<'a>>,
     pub definition: TypeDefinitionBody<'a>,
     pub location: Location<'a>,
     pub type_info: Option<TypeInfoId>,
@@ -327,6 +414,10 @@ pub struct TypeAnnotation<'a> {
     pub rhs: Type<'a>,
     pub location: Location<'a>,
     pub typ: Option<types::Type>,
+
+    /// True if this was desugared from `x as T`, in which case `lhs` is `coerce x`.
+    /// The call to `coerce` is removed during type inference if `x` already has type `T`.
+    pub conversion: bool,
 }
 
 /// import Path1 . Path2 ... PathN
@@ -340,16 +431,29 @@ pub struct Import<'a> {
 }
 
 /// trait Name arg1 arg2 ... argN -> fundep1 fundep2 ... fundepN with
+///     type AssociatedType1
+///     ...
 ///     declaration1
 ///     declaration2
 ///     ...
 ///     declarationN
 #[derive(Debug, Clone)]
 pub struct TraitDefinition<'a> {
+    /// Impls of a `sealed` trait may only be defined in the module declaring the trait
+    pub sealed: bool,
+
+    /// The trait arguments of each impl listed in a `@closed(...)` attribute, if any.
+    /// A closed trait must be implemented for exactly these types.
+    pub closed: Option<Vec<Vec<Type<'a>>>>,
     pub name: String,
     pub args: Vec<String>,
+
+    /// Any associated types `type Item` are desugared into fundeps after the explicit ones
     pub fundeps: Vec<String>,
 
+    /// Superclasses of this trait, e.g. the `Eq a` in `trait Ord a given Eq a`
+    pub given: Vec<Trait<'a>>,
+
     // Storing function declarations as TypeAnnotations here
     // throws away any names given to parameters. In practice
     // this shouldn't matter until refinement types are implemented
@@ -362,6 +466,8 @@ pub struct TraitDefinition<'a> {
 }
 
 /// impl TraitName TraitArg1 TraitArg2 ... TraitArgN
+///     type AssociatedType1 = Type1
+///     ...
 ///     definition1
 ///     definition2
 ///     ...
@@ -372,7 +478,19 @@ pub struct TraitImpl<'a> {
     pub trait_args: Vec<Type<'a>>,
     pub given: Vec<Trait<'a>>,
 
+    /// `type Name = Type` definitions for each of the trait's associated types
+    pub associated_types: Vec<(String, Type<'a>)>,
+
     pub definitions: Vec<Definition<'a>>,
+
+    /// `private` helper definitions local to this impl. These are not part of the
+    /// trait and are only visible to the other definitions within the impl.
+    pub helpers: Vec<Definition<'a>>,
+
+    /// Set if this impl was generated by a `deriving` clause on a newtype.
+    /// The impl's definitions are filled out during name resolution.
+    pub deriving: Option<Deriving<'a>>,
+
     pub location: Location<'a>,
     pub trait_info: Option<TraitInfoId>,
     pub impl_id: Option<ImplInfoId>,
@@ -380,10 +498,32 @@ pub struct TraitImpl<'a> {
     pub trait_arg_types: Vec<types::Type>, // = fmap(trait_args, convert_type)
 }
 
+/// type Age = years: U32 deriving Cmp, Print via U32
+///
+/// Each trait's methods are implemented by unwrapping any arguments of the
+/// newtype to its field, calling the impl for the field's type, and
+/// wrapping the result back into the newtype if the method returns one.
+#[derive(Debug, Clone)]
+pub struct Deriving<'a> {
+    /// The name of the newtype and its only field. The field is None if the
+    /// type is generic or has more than one field, in which case it cannot derive impls.
+    pub type_name: String,
+    pub field: Option<String>,
+
+    /// The type given by `via`, checked against the type of the field
+    pub via: Option<Type<'a>>,
+}
+
 /// return expression
+/// break label: expression
+///
+/// A `return` exits the innermost lambda, while a `break` exits the innermost
+/// loop or the loop with the given label. Since loops are desugared into lambdas,
+/// both are otherwise compiled the same way.
 #[derive(Debug, Clone)]
 pub struct Return<'a> {
     pub expression: Box<Ast<'a>>,
+    pub target: LambdaKind,
     pub location: Location<'a>,
     pub typ: Option<types::Type>,
 }
@@ -432,6 +572,10 @@ pub struct MemberAccess<'a> {
 pub struct Assignment<'a> {
     pub lhs: Box<Ast<'a>>,
     pub rhs: Box<Ast<'a>>,
+
+    /// When the lhs is an index expression `collection.[index]`, this is the
+    /// `index_assign` variable the assignment is resolved through instead.
+    pub index_assign: Option<Box<Ast<'a>>>,
     pub location: Location<'a>,
     pub typ: Option<types::Type>,
 }
@@ -483,12 +627,43 @@ pub struct Handle<'a> {
 ///     field2 = expr2
 #[derive(Debug, Clone)]
 pub struct NamedConstructor<'a> {
-    pub constructor: Box<Ast<'a>>,
+    /// The struct type being constructed. This is None for an anonymous
+    /// record literal like `with x = 1, y = 2`.
+    pub constructor: Option<Box<Ast<'a>>>,
+
+    /// The record being updated in a functional record update like `{ point with x = 3 }`.
+    /// The result has each field of this record, except for the fields given in `sequence`.
+    pub record: Option<Box<Ast<'a>>>,
+
     pub sequence: Box<Ast<'a>>,
     pub location: Location<'a>,
     pub typ: Option<types::Type>,
 }
 
+impl<'a> NamedConstructor<'a> {
+    /// The variable holding the value of each field, in the order they were written.
+    /// This is the variable defined by `field = expr`, or the variable itself for a
+    /// shorthand field like `y` in `with x = 1, y`.
+    pub fn field_variables(&self) -> Vec<&Variable<'a>> {
+        let Ast::Sequence(sequence) = self.sequence.as_ref() else {
+            unreachable!("NamedConstructor::sequence should always be a Sequence")
+        };
+
+        sequence
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Ast::Definition(definition) => match definition.pattern.as_ref() {
+                    Ast::Variable(variable) => Some(variable),
+                    _ => None,
+                },
+                Ast::Variable(variable) => Some(variable),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
 /// &expr or !expr
 #[derive(Debug, Clone)]
 pub struct Reference<'a> {
@@ -498,6 +673,84 @@ pub struct Reference<'a> {
     pub typ: Option<types::Type>,
 }
 
+/// #if configuration then expr1 else expr2
+///
+/// Both branches are always type checked but only the branch
+/// selected by the configuration is part of the resulting program.
+#[derive(Debug, Clone)]
+pub struct ConditionalCompilation<'a> {
+    pub configuration: String,
+    pub then: Box<Ast<'a>>,
+    pub otherwise: Box<Ast<'a>>,
+
+    /// True if the configuration names the target operating system (e.g. `linux`)
+    /// or family (e.g. `unix`) being compiled for. This is filled out during name resolution.
+    pub is_active: bool,
+
+    pub location: Location<'a>,
+    pub typ: Option<types::Type>,
+}
+
+impl<'a> ConditionalCompilation<'a> {
+    pub fn active_branch(&self) -> &Ast<'a> {
+        if self.is_active {
+            &self.then
+        } else {
+            &self.otherwise
+        }
+    }
+}
+
+/// f ...args
+///
+/// Spreads the elements of the tuple `args` as separate arguments to `f`.
+/// Only valid as a function call argument.
+#[derive(Debug, Clone)]
+pub struct Spread<'a> {
+    pub expression: Box<Ast<'a>>,
+
+    /// The number of arguments the tuple was spread into.
+    /// This is filled out during type checking
+    pub element_count: usize,
+
+    pub location: Location<'a>,
+    pub typ: Option<types::Type>,
+}
+
+/// [| a, b, c |]
+///
+/// A literal of the builtin `Array` type. Each element must have the same type.
+#[derive(Debug, Clone)]
+pub struct ArrayLiteral<'a> {
+    pub elements: Vec<Ast<'a>>,
+    pub location: Location<'a>,
+    pub typ: Option<types::Type>,
+}
+
+/// _
+///
+/// A typed hole standing in for an expression which has not been written yet. The type
+/// expected of each hole is reported once the definition containing it is inferred.
+/// Holes passed directly as function arguments are instead desugared into explicitly
+/// curried functions.
+#[derive(Debug, Clone)]
+pub struct Hole<'a> {
+    pub location: Location<'a>,
+    pub typ: Option<types::Type>,
+}
+
+/// A placeholder for a top-level statement which failed to parse.
+///
+/// The parser inserts these in place of the tokens it skipped while recovering from
+/// a parse error so that the rest of the file can still be name resolved and type checked.
+/// Each is given a fresh type variable during type inference so that it cannot cause
+/// any further errors.
+#[derive(Debug, Clone)]
+pub struct Error<'a> {
+    pub location: Location<'a>,
+    pub typ: Option<types::Type>,
+}
+
 #[derive(Debug, Clone)]
 pub enum Ast<'a> {
     Literal(Literal<'a>),
@@ -521,6 +774,11 @@ pub enum Ast<'a> {
     Handle(Handle<'a>),
     NamedConstructor(NamedConstructor<'a>),
     Reference(Reference<'a>),
+    ConditionalCompilation(ConditionalCompilation<'a>),
+    Spread(Spread<'a>),
+    ArrayLiteral(ArrayLiteral<'a>),
+    Hole(Hole<'a>),
+    Error(Error<'a>),
 }
 
 unsafe impl<'c> Send for Ast<'c> {}
@@ -646,16 +904,42 @@ impl<'a> Ast<'a> {
         assert!(!args.is_empty());
         Ast::Lambda(Lambda {
             args,
+            default_args: vec![],
             effects,
             body: Box::new(body),
             closure_environment: BTreeMap::new(),
+            captures: None,
+            contracts: vec![],
             return_type,
             location,
             required_traits: vec![],
+            kind: LambdaKind::Function,
             typ: None,
         })
     }
 
+    pub fn lambda_with_defaults(
+        args: Vec<Ast<'a>>, default_args: Vec<Ast<'a>>, return_type: Option<Type<'a>>,
+        effects: Option<Vec<EffectAst<'a>>>, body: Ast<'a>, location: Location<'a>,
+    ) -> Ast<'a> {
+        let mut lambda = Ast::lambda(args, return_type, effects, body, location);
+        if let Ast::Lambda(lambda) = &mut lambda {
+            lambda.default_args = default_args;
+        }
+        lambda
+    }
+
+    pub fn lambda_with_captures(
+        captures: Option<Vec<String>>, args: Vec<Ast<'a>>, return_type: Option<Type<'a>>,
+        effects: Option<Vec<EffectAst<'a>>>, body: Ast<'a>, location: Location<'a>,
+    ) -> Ast<'a> {
+        let mut lambda = Ast::lambda(args, return_type, effects, body, location);
+        if let Ast::Lambda(lambda) = &mut lambda {
+            lambda.captures = captures;
+        }
+        lambda
+    }
+
     pub fn function_call(function: Ast<'a>, args: Vec<Ast<'a>>, location: Location<'a>) -> Ast<'a> {
         assert!(!args.is_empty());
         Ast::FunctionCall(FunctionCall { function: Box::new(function), args, location, typ: None })
@@ -681,6 +965,7 @@ impl<'a> Ast<'a> {
             expr: Box::new(expr),
             location,
             mutable: false,
+            deprecated: None,
             level: None,
             typ: None,
         })
@@ -700,18 +985,42 @@ impl<'a> Ast<'a> {
             // match, but it is an inconsistency that should be fixed.
             Ast::sequence(vec![definition, rest], location)
         } else {
-            Ast::Match(Match { expression: Box::new(expression), branches, decision_tree: None, location, typ: None })
+            Ast::Match(Match {
+                expression: Box::new(expression),
+                branches,
+                decision_tree: None,
+                reference_bindings: vec![],
+                location,
+                typ: None,
+            })
         }
     }
 
     pub fn type_definition(
-        boxed: bool, name: String, args: Vec<String>, definition: TypeDefinitionBody<'a>, location: Location<'a>,
+        boxed: bool, linear: bool, name: String, args: Vec<String>, given: Vec<Trait<'a>>,
+        definition: TypeDefinitionBody<'a>, location: Location<'a>,
     ) -> Ast<'a> {
-        Ast::TypeDefinition(TypeDefinition { boxed, name, args, definition, location, type_info: None, typ: None })
+        Ast::TypeDefinition(TypeDefinition {
+            boxed,
+            linear,
+            name,
+            args,
+            given,
+            definition,
+            location,
+            type_info: None,
+            typ: None,
+        })
     }
 
     pub fn type_annotation(lhs: Ast<'a>, rhs: Type<'a>, location: Location<'a>) -> Ast<'a> {
-        Ast::TypeAnnotation(TypeAnnotation { lhs: Box::new(lhs), rhs, location, typ: None })
+        Ast::TypeAnnotation(TypeAnnotation { lhs: Box::new(lhs), rhs, location, typ: None, conversion: false })
+    }
+
+    pub fn conversion(value: Ast<'a>, rhs: Type<'a>, location: Location<'a>) -> Ast<'a> {
+        let coerce = Ast::variable(vec![], String::from("coerce"), location);
+        let lhs = Box::new(Ast::function_call(coerce, vec![value], location));
+        Ast::TypeAnnotation(TypeAnnotation { lhs, rhs, location, typ: None, conversion: true })
     }
 
     pub fn import(path: Vec<String>, location: Location<'a>, symbols: HashSet<String>) -> Ast<'a> {
@@ -720,14 +1029,17 @@ impl<'a> Ast<'a> {
     }
 
     pub fn trait_definition(
-        name: String, args: Vec<String>, fundeps: Vec<String>, declarations: Vec<TypeAnnotation<'a>>,
-        location: Location<'a>,
+        sealed: bool, name: String, args: Vec<String>, fundeps: Vec<String>, given: Vec<Trait<'a>>,
+        declarations: Vec<TypeAnnotation<'a>>, location: Location<'a>,
     ) -> Ast<'a> {
         assert!(!args.is_empty());
         Ast::TraitDefinition(TraitDefinition {
+            sealed,
+            closed: None,
             name,
             args,
             fundeps,
+            given,
             declarations,
             location,
             level: None,
@@ -737,7 +1049,8 @@ impl<'a> Ast<'a> {
     }
 
     pub fn trait_impl(
-        trait_name: String, trait_args: Vec<Type<'a>>, given: Vec<Trait<'a>>, definitions: Vec<Definition<'a>>,
+        trait_name: String, trait_args: Vec<Type<'a>>, given: Vec<Trait<'a>>,
+        associated_types: Vec<(String, Type<'a>)>, definitions: Vec<Definition<'a>>, helpers: Vec<Definition<'a>>,
         location: Location<'a>,
     ) -> Ast<'a> {
         assert!(!trait_args.is_empty());
@@ -745,7 +1058,10 @@ impl<'a> Ast<'a> {
             trait_name,
             trait_args,
             given,
+            associated_types,
             definitions,
+            helpers,
+            deriving: None,
             location,
             trait_arg_types: vec![],
             impl_id: None,
@@ -754,8 +1070,22 @@ impl<'a> Ast<'a> {
         })
     }
 
+    pub fn derived_impl(trait_name: String, deriving: Deriving<'a>, location: Location<'a>) -> Ast<'a> {
+        let trait_args = vec![Type::UserDefined(deriving.type_name.clone(), location)];
+        let mut trait_impl = Ast::trait_impl(trait_name, trait_args, vec![], vec![], vec![], vec![], location);
+        if let Ast::TraitImpl(trait_impl) = &mut trait_impl {
+            trait_impl.deriving = Some(deriving);
+        }
+        trait_impl
+    }
+
     pub fn return_expr(expression: Ast<'a>, location: Location<'a>) -> Ast<'a> {
-        Ast::Return(Return { expression: Box::new(expression), location, typ: None })
+        Ast::Return(Return { expression: Box::new(expression), target: LambdaKind::Function, location, typ: None })
+    }
+
+    pub fn break_expr(label: Option<String>, expression: Ast<'a>, location: Location<'a>) -> Ast<'a> {
+        let target = LambdaKind::Loop(label);
+        Ast::Return(Return { expression: Box::new(expression), target, location, typ: None })
     }
 
     pub fn sequence(statements: Vec<Ast<'a>>, location: Location<'a>) -> Ast<'a> {
@@ -782,7 +1112,13 @@ impl<'a> Ast<'a> {
     }
 
     pub fn assignment(lhs: Ast<'a>, rhs: Ast<'a>, location: Location<'a>) -> Ast<'a> {
-        Ast::Assignment(Assignment { lhs: Box::new(lhs), rhs: Box::new(rhs), location, typ: None })
+        let index_assign = match &lhs {
+            Ast::FunctionCall(call) if call.is_index() => {
+                Some(Box::new(Ast::variable(vec![], "index_assign".to_owned(), location)))
+            },
+            _ => None,
+        };
+        Ast::Assignment(Assignment { lhs: Box::new(lhs), rhs: Box::new(rhs), index_assign, location, typ: None })
     }
 
     pub fn effect_definition(
@@ -811,15 +1147,39 @@ impl<'a> Ast<'a> {
         })
     }
 
-    pub fn named_constructor(constructor: Ast<'a>, sequence: Ast<'a>, location: Location<'a>) -> Ast<'a> {
+    pub fn named_constructor(constructor: Option<Ast<'a>>, sequence: Ast<'a>, location: Location<'a>) -> Ast<'a> {
         Ast::NamedConstructor(NamedConstructor {
-            constructor: Box::new(constructor),
+            constructor: constructor.map(Box::new),
+            record: None,
             sequence: Box::new(sequence),
             location,
             typ: None,
         })
     }
 
+    pub fn record_update(record: Ast<'a>, sequence: Ast<'a>, location: Location<'a>) -> Ast<'a> {
+        Ast::NamedConstructor(NamedConstructor {
+            constructor: None,
+            record: Some(Box::new(record)),
+            sequence: Box::new(sequence),
+            location,
+            typ: None,
+        })
+    }
+
+    pub fn conditional_compilation(
+        configuration: String, then: Ast<'a>, otherwise: Ast<'a>, location: Location<'a>,
+    ) -> Ast<'a> {
+        Ast::ConditionalCompilation(ConditionalCompilation {
+            configuration,
+            then: Box::new(then),
+            otherwise: Box::new(otherwise),
+            is_active: false,
+            location,
+            typ: None,
+        })
+    }
+
     /// This is a bit of a hack.
     /// Create a new 'scope' by wrapping body in `match () | () -> body`
     pub fn new_scope(body: Ast<'a>, location: Location<'a>) -> Ast<'a> {
@@ -834,6 +1194,22 @@ impl<'a> Ast<'a> {
         };
         Ast::Reference(Reference { mutability, expression: Box::new(expression), location, typ: None })
     }
+
+    pub fn spread(expression: Ast<'a>, location: Location<'a>) -> Ast<'a> {
+        Ast::Spread(Spread { expression: Box::new(expression), element_count: 1, location, typ: None })
+    }
+
+    pub fn array_literal(elements: Vec<Ast<'a>>, location: Location<'a>) -> Ast<'a> {
+        Ast::ArrayLiteral(ArrayLiteral { elements, location, typ: None })
+    }
+
+    pub fn hole(location: Location<'a>) -> Ast<'a> {
+        Ast::Hole(Hole { location, typ: None })
+    }
+
+    pub fn error(location: Location<'a>) -> Ast<'a> {
+        Ast::Error(Error { location, typ: None })
+    }
 }
 
 /// A macro for calling a method on every variant of an Ast node.
@@ -863,6 +1239,11 @@ macro_rules! dispatch_on_expr {
             $crate::parser::ast::Ast::Handle(inner) =>           $function(inner $(, $($args),* )? ),
             $crate::parser::ast::Ast::NamedConstructor(inner) => $function(inner $(, $($args),* )? ),
             $crate::parser::ast::Ast::Reference(inner) =>        $function(inner $(, $($args),* )? ),
+            $crate::parser::ast::Ast::ConditionalCompilation(inner) => $function(inner $(, $($args),* )? ),
+            $crate::parser::ast::Ast::Spread(inner) =>           $function(inner $(, $($args),* )? ),
+            $crate::parser::ast::Ast::ArrayLiteral(inner) =>     $function(inner $(, $($args),* )? ),
+            $crate::parser::ast::Ast::Hole(inner) =>             $function(inner $(, $($args),* )? ),
+            $crate::parser::ast::Ast::Error(inner) =>            $function(inner $(, $($args),* )? ),
         }
     });
 }
@@ -904,6 +1285,11 @@ impl_locatable_for!(EffectDefinition);
 impl_locatable_for!(Handle);
 impl_locatable_for!(NamedConstructor);
 impl_locatable_for!(Reference);
+impl_locatable_for!(ConditionalCompilation);
+impl_locatable_for!(Spread);
+impl_locatable_for!(ArrayLiteral);
+impl_locatable_for!(Hole);
+impl_locatable_for!(Error);
 
 impl<'a> Locatable<'a> for Type<'a> {
     fn locate(&self) -> Location<'a> {
@@ -921,6 +1307,11 @@ impl<'a> Locatable<'a> for Type<'a> {
             Type::UserDefined(_, location) => *location,
             Type::TypeApplication(_, _, location) => *location,
             Type::Pair(_, _, location) => *location,
+            Type::Nat(_, location) => *location,
+            Type::NatArithmetic(_, _, _, location) => *location,
+            Type::Universe(_, location) => *location,
+            Type::Dyn(_, location) => *location,
+            Type::Comptime(_, location) => *location,
         }
     }
 }
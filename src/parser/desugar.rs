@@ -40,7 +40,7 @@ where
 }
 
 fn matches_underscore(arg: &Ast) -> bool {
-    matches!(arg, Ast::Variable(ast::Variable{ kind: ast::VariableKind::Identifier(x), ..}) if x == "_")
+    matches!(arg, Ast::Hole(_))
 }
 
 /// Turns:
@@ -74,6 +74,51 @@ pub fn desugar_operators<'a>(operator: Token, lhs: Ast<'a>, rhs: Ast<'a>, locati
     desugar_explicit_currying(operator_symbol, vec![lhs, rhs], call_operator_function, location)
 }
 
+/// Desugars a comparison chain `a < b <= c` into `a < b and b <= c`. Each operand is
+/// bound to a variable first so that it is evaluated only once and in order:
+/// ```ante
+/// $cmp0 = a
+/// $cmp1 = b
+/// $cmp0 < $cmp1 and ($cmp1 <= c)
+/// ```
+/// Longer chains nest further definitions on the rhs of each `and` so that later operands
+/// are still only evaluated if the previous comparisons succeed.
+pub fn desugar_comparison_chain<'a>(
+    first: Ast<'a>, mut comparisons: Vec<(Token, Ast<'a>)>, location: Location<'a>,
+) -> Ast<'a> {
+    // Explicitly curried chains like `_ < x < _` are left as nested comparisons
+    let is_curried = matches_underscore(&first) || comparisons.iter().any(|(_, ast)| matches_underscore(ast));
+    if comparisons.len() == 1 || is_curried {
+        let desugar = |lhs, (operator, rhs)| desugar_operators(operator, lhs, rhs, location);
+        return comparisons.into_iter().fold(first, desugar);
+    }
+
+    let variable = |index: usize| Ast::variable(vec![], format!("$cmp{}", index), location);
+    let define = |index: usize, value: Ast<'a>| Ast::definition(variable(index), value, location);
+
+    // The last operand is only used once so it does not need to be bound
+    let (operator, last) = comparisons.pop().unwrap();
+    let index = comparisons.len();
+    let mut result = desugar_operators(operator, variable(index), last, location);
+    let mut first = Some(first);
+
+    for (index, (operator, operand)) in comparisons.into_iter().enumerate().rev() {
+        let comparison = desugar_operators(operator, variable(index), variable(index + 1), location);
+        let conjunction = desugar_operators(Token::And, comparison, result, location);
+
+        // The first operand is bound alongside the second so it is evaluated before it
+        let mut statements = Vec::with_capacity(3);
+        if index == 0 {
+            statements.push(define(0, first.take().unwrap()));
+        }
+        statements.push(define(index + 1, operand));
+        statements.push(conjunction);
+        result = Ast::new_scope(Ast::sequence(statements, location), location);
+    }
+
+    result
+}
+
 /// Desugars `lhs with rhs` into `rhs (fn () -> lhs)`
 fn with_expr<'a>(lhs: Ast<'a>, rhs: Ast<'a>, location: Location<'a>) -> Ast<'a> {
     let lambda = Ast::lambda(vec![Ast::unit_literal(location)], None, None, lhs, location);
@@ -90,14 +135,59 @@ fn prepend_argument_to_function<'a>(f: Ast<'a>, arg: Ast<'a>, location: Location
     }
 }
 
-pub fn desugar_loop<'a>(params_defaults: Vec<(Ast<'a>, Ast<'a>)>, body: Ast<'a>, location: Location<'a>) -> Ast<'a> {
+/// Desugars `loop label: (param = arg) ... -> body` into `(recur = fn params -> body; recur args)`.
+/// The lambda is marked as a loop so that `break` can exit from it.
+pub fn desugar_loop<'a>(
+    label: Option<String>, params_defaults: Vec<(Ast<'a>, Ast<'a>)>, body: Ast<'a>, location: Location<'a>,
+) -> Ast<'a> {
     let (params, args) = params_defaults.into_iter().unzip();
     let recur_name = || Ast::variable(vec![], "recur".to_owned(), location);
-    let recur_def = Ast::definition(recur_name(), Ast::lambda(params, None, None, body, location), location);
+
+    let mut lambda = Ast::lambda(params, None, None, body, location);
+    if let Ast::Lambda(lambda) = &mut lambda {
+        lambda.kind = ast::LambdaKind::Loop(label);
+    }
+
+    let recur_def = Ast::definition(recur_name(), lambda, location);
     let recur_call = Ast::function_call(recur_name(), args, location);
     Ast::new_scope(Ast::sequence(vec![recur_def, recur_call], location), location)
 }
 
+/// Desugars `continue label: args` into `break label: (recur args)`.
+/// Since `recur` always refers to the innermost loop, continuing an outer
+/// loop is rejected when the `break` is checked against its label.
+pub fn desugar_continue<'a>(label: Option<String>, args: Vec<Ast<'a>>, location: Location<'a>) -> Ast<'a> {
+    let recur = Ast::variable(vec![], "recur".to_owned(), location);
+    Ast::break_expr(label, Ast::function_call(recur, args, location), location)
+}
+
+/// Desugars `loop fn args -> body` into `(recur = fn args -> body; recur)`
+/// so that the otherwise anonymous closure may call itself as `recur`.
+pub fn desugar_recursive_lambda<'a>(lambda: Ast<'a>, location: Location<'a>) -> Ast<'a> {
+    let recur_name = || Ast::variable(vec![], "recur".to_owned(), location);
+    let recur_def = Ast::definition(recur_name(), lambda, location);
+    Ast::new_scope(Ast::sequence(vec![recur_def, recur_name()], location), location)
+}
+
+/// Desugars `body where helpers` into `helpers; body` so that the helper
+/// definitions are in scope within the function body but nowhere else.
+pub fn desugar_where<'a>(body: Ast<'a>, helpers: Option<Ast<'a>>, location: Location<'a>) -> Ast<'a> {
+    let Some(helpers) = helpers else {
+        return body;
+    };
+
+    let mut statements = into_statements(helpers);
+    statements.append(&mut into_statements(body));
+    Ast::sequence(statements, location)
+}
+
+fn into_statements(ast: Ast) -> Vec<Ast> {
+    match ast {
+        Ast::Sequence(sequence) => sequence.statements,
+        other => vec![other],
+    }
+}
+
 /// Desugar:
 ///
 /// handle foo + bar
@@ -151,6 +241,62 @@ pub fn desugar_handle_branches_into_matches<'a>(branches: Vec<(Ast<'a>, Ast<'a>)
     })
 }
 
+/// Desugars `expr?` into:
+/// ```ante
+/// match expr
+/// | Error $error -> return (Error $error)
+/// | Ok $value -> $value
+/// ```
+/// The early return requires the enclosing function to return a `Result` with the same error type.
+/// The `Error` branch comes first so that its pattern is checked against `expr` before the return is.
+pub fn desugar_try<'a>(expr: Ast<'a>, location: Location<'a>) -> Ast<'a> {
+    let variable = |name: &str| Ast::variable(vec![], name.to_owned(), location);
+    let constructor = |name: &str| Ast::type_constructor(vec![], name.to_owned(), location);
+
+    let ok_pattern = Ast::function_call(constructor("Ok"), vec![variable("$value")], location);
+    let error_pattern = Ast::function_call(constructor("Error"), vec![variable("$error")], location);
+    let error = Ast::function_call(constructor("Error"), vec![variable("$error")], location);
+
+    let branches = vec![(error_pattern, Ast::return_expr(error, location)), (ok_pattern, variable("$value"))];
+    Ast::match_expr(expr, branches, location)
+}
+
+/// Desugars `type T = field: U deriving Trait1, Trait2 via U` into the type definition
+/// followed by an impl for each derived trait. The definitions for each impl are
+/// generated later during name resolution once the traits' declarations are known.
+pub fn desugar_deriving<'a>(
+    type_definition: Ast<'a>, traits: Vec<(String, Location<'a>)>, via: Option<ast::Type<'a>>, location: Location<'a>,
+) -> Ast<'a> {
+    let Ast::TypeDefinition(definition) = &type_definition else {
+        return type_definition;
+    };
+
+    let field = match &definition.definition {
+        ast::TypeDefinitionBody::Struct(fields) if fields.len() == 1 && definition.args.is_empty() => {
+            Some(fields[0].0.clone())
+        },
+        _ => None,
+    };
+
+    let type_name = definition.name.clone();
+    let mut statements = vec![type_definition];
+
+    for (trait_name, trait_location) in traits {
+        let deriving = ast::Deriving { type_name: type_name.clone(), field: field.clone(), via: via.clone() };
+        statements.push(Ast::derived_impl(trait_name, deriving, trait_location));
+    }
+
+    Ast::sequence(statements, location)
+}
+
+/// Desugars `x as T` into `(coerce x : T)` so that the conversion
+/// is resolved through the `Coerce` trait in the prelude. Conversions
+/// to a trait object `x as dyn Trait` are handled by the traitchecker.
+/// If `x` already has type `T`, the call to `coerce` is removed during type inference.
+pub fn desugar_as<'a>(expr: Ast<'a>, typ: ast::Type<'a>, location: Location<'a>) -> Ast<'a> {
+    Ast::conversion(expr, typ, location)
+}
+
 fn empty_string_literal(ast: &Ast) -> bool {
     match ast {
         Ast::Literal(super::ast::Literal { kind: ast::LiteralKind::String(s), .. }) => s.is_empty(),
@@ -163,6 +309,8 @@ fn append<'a>(lhs: Ast<'a>, rhs: Ast<'a>, location: Location<'a>) -> Ast<'a> {
     Ast::function_call(append, vec![lhs, rhs], location)
 }
 
+/// Desugars an interpolated segment `${x}rest` into `cast x ++ "rest"`. Each interpolated
+/// value is converted separately, so each requires its own `Cast a String` impl.
 pub fn interpolate<'a>(lhs: Ast<'a>, rhs: Ast<'a>, location: Location<'a>) -> Ast<'a> {
     let cast = Ast::variable(vec![], String::from("cast"), location);
     let lhs = Ast::function_call(cast, vec![lhs], location);
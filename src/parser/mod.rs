@@ -1,8 +1,9 @@
 //! parser/mod.rs - This file defines parsing, the second phase of the compiler.
 //! The goal of parsing is to take the `Vec<Token>` output from the lexing phase
 //! and validate the grammar/syntax of the program. If the syntax is invalid,
-//! a parse error is printed out. Otherwise, the resulting Ast is returned and
-//! the compiler moves onto the name resolution pass.
+//! a parse error is printed out and the offending top-level statement is replaced
+//! with an error node. The resulting Ast is returned and the compiler moves onto
+//! the name resolution pass.
 //!
 //! This parser itself is built up from parser combinators. The basic combinators
 //! (as well as the parser! macro) are defined in the parser/combinators.rs module.
@@ -23,10 +24,16 @@ pub mod ast;
 mod desugar;
 pub mod pretty_printer;
 
-use std::{collections::HashSet, iter::FromIterator};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::iter::FromIterator;
 
 use crate::lexer::token::Token;
-use crate::{error::location::Location, parser::ast::Mutability};
+use crate::types::TypeTag;
+use crate::{
+    error::location::{Locatable, Location},
+    parser::ast::Mutability,
+};
 use ast::{Ast, EffectName, Trait, Type, TypeDefinitionBody};
 use combinators::*;
 use error::{ParseError, ParseResult};
@@ -36,19 +43,131 @@ use self::ast::Sharedness;
 
 type AstResult<'a, 'b> = ParseResult<'a, 'b, Ast<'b>>;
 
-/// The entry point to parsing. Parses an entire file, printing any
-/// error found, or returns the Ast if there was no error.
-pub fn parse<'b>(input: Input<'_, 'b>) -> Result<Ast<'b>, ParseError<'b>> {
-    parse_file(input)
+thread_local! {
+    /// The precedence and associativity of each user-defined operator declared
+    /// in the file currently being parsed. See `collect_fixity_declarations`.
+    static USER_DEFINED_OPERATORS: RefCell<OperatorTable> = RefCell::new(HashMap::new());
+}
+
+/// Maps each user-defined operator to its precedence and whether it is right-associative
+type OperatorTable = HashMap<String, (i8, bool)>;
+
+/// The precedence and associativity of user-defined operators without a fixity declaration.
+/// This is the same as that of `++`.
+const DEFAULT_USER_DEFINED_OPERATOR_PRECEDENCE: (i8, bool) = (10, false);
+
+/// The entry point to parsing. Parses an entire file, returning the Ast along with each
+/// error found. Each top-level statement which fails to parse is replaced with an
+/// `Ast::Error` node so that the rest of the file can still be checked.
+pub fn parse<'b>(input: Input<'_, 'b>) -> (Ast<'b>, Vec<ParseError<'b>>) {
+    let (tokens, operators) = collect_fixity_declarations(input);
+    USER_DEFINED_OPERATORS.with(|table| *table.borrow_mut() = operators);
+    parse_file(&tokens)
+}
+
+/// Fixity declarations declare the precedence and associativity of a user-defined operator
+/// in the current file, e.g. `infixr 14 **`. These are collected before parsing so that
+/// operators can be used before they are declared, and are removed from the returned tokens.
+fn collect_fixity_declarations<'b>(input: Input<'_, 'b>) -> (Vec<(Token, Location<'b>)>, OperatorTable) {
+    let mut tokens = Vec::with_capacity(input.len());
+    let mut operators = HashMap::new();
+    let mut i = 0;
+
+    while i < input.len() {
+        let at_statement_start = matches!(tokens.last(), None | Some((Token::Newline, _)));
+
+        if at_statement_start && i + 3 < input.len() {
+            if let [(Token::Identifier(fixity), _), (Token::IntegerLiteral(precedence, None), _), (Token::Operator(operator), _), (Token::Newline | Token::EndOfInput, _)] =
+                &input[i..i + 4]
+            {
+                let right_associative = match fixity.as_str() {
+                    "infixl" => Some(false),
+                    "infixr" => Some(true),
+                    _ => None,
+                };
+
+                if let (Some(right_associative), Ok(precedence)) = (right_associative, i8::try_from(*precedence)) {
+                    operators.insert(operator.clone(), (precedence, right_associative));
+                    // Skip the trailing newline as well, unless it is the end of input
+                    i += if input[i + 3].0 == Token::Newline { 4 } else { 3 };
+                    continue;
+                }
+            }
+        }
+
+        tokens.push(input[i].clone());
+        i += 1;
+    }
+
+    (tokens, operators)
 }
 
 /// A file is a sequence of statements, separated by newlines.
-pub fn parse_file<'b>(input: Input<'_, 'b>) -> Result<Ast<'b>, ParseError<'b>> {
-    let (input, _, _) = maybe_newline(input)?;
-    let (input, ast, _) = statement_list(input)?;
-    let (input, _, _) = maybe_newline(input)?;
-    let _ = expect(Token::EndOfInput)(input)?;
-    Ok(ast)
+///
+/// Unlike `statement_list`, this recovers from a statement failing to parse by skipping
+/// to the start of the next top-level statement. Recovery is only done here since the
+/// combinators within a statement rely on failing to backtrack to other alternatives.
+pub fn parse_file<'b>(mut input: Input<'_, 'b>) -> (Ast<'b>, Vec<ParseError<'b>>) {
+    let mut statements = Vec::new();
+    let mut errors = Vec::new();
+
+    loop {
+        while input[0].0 == Token::Newline {
+            input = &input[1..];
+        }
+
+        if input[0].0 == Token::EndOfInput && !statements.is_empty() {
+            break;
+        }
+
+        let error = match statement(input) {
+            Ok((rest, ast, _)) => match rest[0].0 {
+                Token::Newline | Token::EndOfInput => {
+                    statements.push(ast);
+                    input = rest;
+                    continue;
+                },
+                _ => ParseError::Expected(vec![Token::Newline], rest[0].1),
+            },
+            Err(error) => error,
+        };
+
+        errors.push(error);
+        let (rest, location) = skip_statement(input);
+        statements.push(Ast::error(location));
+        input = rest;
+    }
+
+    let ast = if statements.len() == 1 {
+        statements.pop().unwrap()
+    } else {
+        let location = statements[0].locate().union(statements.last().unwrap().locate());
+        Ast::sequence(statements, location)
+    };
+    (ast, errors)
+}
+
+/// Skip the tokens of the statement at the start of the input, stopping before
+/// the next newline that is not within an indented block. Returns the remaining
+/// input along with the location of the skipped tokens.
+fn skip_statement<'a, 'b>(input: Input<'a, 'b>) -> (Input<'a, 'b>, Location<'b>) {
+    let mut location = input[0].1;
+    let mut depth = 0_usize;
+    let mut i = 0;
+
+    loop {
+        match input[i].0 {
+            Token::EndOfInput => break,
+            Token::Newline if depth == 0 && i != 0 => break,
+            Token::Indent => depth += 1,
+            Token::Unindent => depth = depth.saturating_sub(1),
+            _ => (),
+        }
+        location = location.union(input[i].1);
+        i += 1;
+    }
+
+    (&input[i..], location)
 }
 
 fn maybe_newline<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, Option<Token>> {
@@ -74,14 +193,17 @@ fn statement<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
     match input[0].0 {
         Token::ParenthesisLeft | Token::Identifier(_) => or(&[definition, assignment, expression], "statement")(input),
         Token::Mut => definition(input),
-        Token::Boxed => type_definition(input),
+        Token::Boxed | Token::Linear => type_definition(input),
         Token::Type => or(&[type_definition, type_alias], "statement")(input),
         Token::Import => import(input),
-        Token::Trait => trait_definition(input),
+        Token::Sealed | Token::Trait => trait_definition(input),
         Token::Effect => effect_definition(input),
         Token::Impl => trait_impl(input),
         Token::Return => return_expr(input),
+        Token::Break => break_expr(input),
+        Token::Continue => continue_expr(input),
         Token::Extern => parse_extern(input),
+        Token::At => or(&[attributed_definition, closed_trait_definition, expression], "statement")(input),
         _ => expression(input),
     }
 }
@@ -90,27 +212,137 @@ fn definition<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
     raw_definition(input).map(|(input, definition, location)| (input, Ast::Definition(definition), location))
 }
 
+/// An `@attribute` preceding a definition
+enum Attribute<'b> {
+    Deprecated(String),
+    Contract(ast::Contract<'b>),
+}
+
+/// A definition preceded by one or more attributes, each optionally on its own line.
+/// Contract attributes refer to a function's parameters so they require a function definition.
+fn attributed_definition<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
+    let (input, attributes, start) = many1(attribute)(input)?;
+
+    let (input, mut definition, end) = if attributes.iter().any(|attr| matches!(attr, Attribute::Contract(_))) {
+        no_backtracking(function_definition)(input)?
+    } else {
+        no_backtracking(raw_definition)(input)?
+    };
+
+    for attribute in attributes {
+        match (attribute, definition.expr.as_mut()) {
+            (Attribute::Deprecated(message), _) => definition.deprecated = Some(message),
+            (Attribute::Contract(contract), Ast::Lambda(lambda)) => lambda.contracts.push(contract),
+            (Attribute::Contract(_), _) => unreachable!("function_definition should always create a lambda"),
+        }
+    }
+
+    Ok((input, Ast::Definition(definition), start.union(end)))
+}
+
+parser!(attribute location -> 'b Attribute<'b> =
+    attribute <- or(&[contract_attribute, deprecated_attribute], "attribute");
+    _ <- maybe_newline;
+    attribute
+);
+
+// @requires(condition) or @ensures(condition)
+parser!(contract_attribute location -> 'b Attribute<'b> =
+    _ <- expect(Token::At);
+    kind <- contract_kind;
+    _ <- expect(Token::ParenthesisLeft);
+    condition <- expression;
+    _ <- expect(Token::ParenthesisRight);
+    Attribute::Contract(ast::Contract { kind, condition: Box::new(condition), result: None, location })
+);
+
+fn contract_kind<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, ast::ContractKind> {
+    let kind = match &input[0].0 {
+        Token::Identifier(name) if name == "requires" => ast::ContractKind::Requires,
+        Token::Identifier(name) if name == "ensures" => ast::ContractKind::Ensures,
+        _ => return Err(ParseError::InRule("contract attribute", input[0].1)),
+    };
+    Ok((&input[1..], kind, input[0].1))
+}
+
+// @deprecated("message")
+parser!(deprecated_attribute location -> 'b Attribute<'b> =
+    _ <- expect(Token::At);
+    _ <- expect_if("deprecated attribute", |token| matches!(token, Token::Identifier(name) if name == "deprecated"));
+    _ <- expect(Token::ParenthesisLeft);
+    message <- string_literal_token;
+    _ <- expect(Token::ParenthesisRight);
+    Attribute::Deprecated(message)
+);
+
+/// A trait definition preceded by a `@closed(...)` attribute listing the only types it may be implemented for
+fn closed_trait_definition<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
+    let (input, closed, start) = closed_attribute(input)?;
+    let (input, mut definition, end) = no_backtracking(trait_definition)(input)?;
+
+    if let Ast::TraitDefinition(trait_definition) = &mut definition {
+        trait_definition.closed = Some(closed);
+    }
+    Ok((input, definition, start.union(end)))
+}
+
+// @closed(I32, String)
+parser!(closed_attribute location -> 'b Vec<Vec<ast::Type<'b>>> =
+    _ <- expect(Token::At);
+    _ <- expect_if("closed attribute", |token| matches!(token, Token::Identifier(name) if name == "closed"));
+    _ !<- expect(Token::ParenthesisLeft);
+    impls !<- delimited(many1(basic_type), expect(Token::Comma));
+    _ !<- expect(Token::ParenthesisRight);
+    _ <- maybe_newline;
+    impls
+);
+
 fn raw_definition<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, ast::Definition<'b>> {
     or(&[function_definition, variable_definition], "definition")(input)
 }
 
 parser!(function_definition location -> 'b ast::Definition<'b> =
     name <- pattern_argument;
-    args <- many1(pattern_argument);
+    (args, default_args) <- function_parameters;
     return_type <- maybe(function_return_type);
     effects <- maybe(effect_clause);
     _ <- expect(Token::Equal);
-    body !<- block_or_statement;
+    body !<- function_body;
     ast::Definition {
         pattern: Box::new(name),
-        expr: Box::new(Ast::lambda(args, return_type, effects, body, location)),
+        expr: Box::new(Ast::lambda_with_defaults(args, default_args, return_type, effects, body, location)),
         mutable: false,
+        deprecated: None,
         location,
         level: None,
         typ: None,
     }
 );
 
+/// One or more parameters, the last of which may have default values: `a b (c: I32 = 0)`.
+/// Returns the parameters along with the default values of the trailing parameters.
+fn function_parameters<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, (Vec<Ast<'b>>, Vec<Ast<'b>>)> {
+    let (input, mut args, location) = many0(pattern_argument)(input)?;
+    let (input, defaults, defaults_location) = many0(default_parameter)(input)?;
+
+    if args.is_empty() && defaults.is_empty() {
+        return Err(ParseError::InRule("function parameter", location));
+    }
+
+    let (parameters, default_args): (Vec<_>, Vec<_>) = defaults.into_iter().unzip();
+    args.extend(parameters);
+    Ok((input, (args, default_args), location.union(defaults_location)))
+}
+
+parser!(default_parameter _loc -> 'b (Ast<'b>, Ast<'b>) =
+    _ <- expect(Token::ParenthesisLeft);
+    parameter <- pattern;
+    _ <- expect(Token::Equal);
+    default !<- expression;
+    _ !<- expect(Token::ParenthesisRight);
+    (parameter, default)
+);
+
 fn effect_clause<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, Vec<EffectAst<'b>>> {
     or(&[non_empty_effect_clause, pure_clause], "effect clause")(input)
 }
@@ -162,6 +394,7 @@ parser!(variable_definition location -> 'b ast::Definition<'b> =
         pattern: Box::new(name),
         expr: Box::new(expr),
         mutable: mutable.is_some(),
+        deprecated: None,
         location,
         level: None,
         typ: None,
@@ -176,20 +409,20 @@ parser!(assignment location =
 );
 
 fn pattern<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
-    or(&[pattern_pair, type_annotation_pattern, pattern_function_call, pattern_argument], "pattern")(input)
+    or(&[pattern_pair, type_annotation_pattern, ref_pattern, pattern_function_call, pattern_argument], "pattern")(input)
 }
 
 // TODO: There's a lot of repeated parsing done in patterns due to or combinators
 // being used to express the pair -> type annotation -> call -> argument  lattice.
 parser!(pattern_pair loc =
-    first <- or(&[type_annotation_pattern, pattern_function_call, pattern_argument], "pattern");
+    first <- or(&[type_annotation_pattern, ref_pattern, pattern_function_call, pattern_argument], "pattern");
     _ <- expect(Token::Comma);
     rest !<- pattern;
     Ast::function_call(Ast::operator(Token::Comma, loc), vec![first, rest], loc)
 );
 
 parser!(type_annotation_pattern loc =
-    lhs <- or(&[pattern_function_call, pattern_argument], "pattern");
+    lhs <- or(&[ref_pattern, pattern_function_call, pattern_argument], "pattern");
     _ <- expect(Token::Colon);
     rhs !<- parse_type;
     Ast::type_annotation(lhs, rhs, loc)
@@ -201,12 +434,41 @@ fn parenthesized_irrefutable_pattern<'a, 'b>(input: Input<'a, 'b>) -> AstResult<
 
 parser!(type_definition loc =
     boxed <- maybe(expect(Token::Boxed));
+    linear <- maybe(expect(Token::Linear));
     _ <- expect(Token::Type);
     name <- typename;
     args <- many0(identifier);
+    given <- maybe(given);
     _ <- expect(Token::Equal);
     body <- type_definition_body;
-    Ast::type_definition(boxed.is_some(), name, args, body, loc)
+    deriving <- maybe(deriving_clause);
+    {
+        let given = given.unwrap_or_default();
+        let definition = Ast::type_definition(boxed.is_some(), linear.is_some(), name, args, given, body, loc);
+        match deriving {
+            Some((traits, via)) => desugar::desugar_deriving(definition, traits, via, loc),
+            None => definition,
+        }
+    }
+);
+
+// deriving Cmp, Print via U32
+parser!(deriving_clause loc -> 'b (Vec<(String, Location<'b>)>, Option<ast::Type<'b>>) =
+    _ <- expect(Token::Deriving);
+    traits !<- delimited(deriving_trait, expect(Token::Comma));
+    via <- maybe(via_type);
+    (traits, via)
+);
+
+parser!(deriving_trait loc -> 'b (String, Location<'b>) =
+    name <- typename;
+    (name, loc)
+);
+
+parser!(via_type _loc -> 'b ast::Type<'b> =
+    _ <- expect_if("via", |token| matches!(token, Token::Identifier(name) if name == "via"));
+    typ !<- parse_type;
+    typ
 );
 
 parser!(type_alias loc =
@@ -215,7 +477,7 @@ parser!(type_alias loc =
     args <- many0(identifier);
     _ <- expect(Token::Equal);
     body <- parse_type;
-    Ast::type_definition(false, name, args, TypeDefinitionBody::Alias(body), loc)
+    Ast::type_definition(false, false, name, args, vec![], TypeDefinitionBody::Alias(body), loc)
 );
 
 fn type_definition_body<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, ast::TypeDefinitionBody<'b>> {
@@ -226,11 +488,18 @@ fn type_definition_body<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, ast
     }
 }
 
-parser!(union_variant loc -> 'b (String, Vec<Type<'b>>, Location<'b>) =
+parser!(union_variant loc -> 'b (String, Vec<Type<'b>>, Option<Type<'b>>, Location<'b>) =
     _ <- expect(Token::Pipe);
     variant !<- typename;
     args !<- many0(basic_type);
-    (variant, args, loc)
+    result_type <- maybe(variant_result_type);
+    (variant, args, result_type, loc)
+);
+
+parser!(variant_result_type _loc -> 'b Type<'b> =
+    _ <- expect(Token::Colon);
+    typ !<- parse_type;
+    typ
 );
 
 parser!(union_block_body _loc -> 'b ast::TypeDefinitionBody<'b> =
@@ -272,13 +541,47 @@ parser!(import loc =
 );
 
 parser!(trait_definition loc =
+    sealed <- maybe(expect(Token::Sealed));
     _ <- expect(Token::Trait);
     name !<- typename;
     args !<- many1(identifier);
     _ !<- maybe(expect(Token::RightArrow));
     fundeps !<- many0(identifier);
-    body <- maybe(trait_body);
-    Ast::trait_definition(name, args, fundeps, body.unwrap_or_default(), loc)
+    given !<- maybe(given);
+    body <- maybe(trait_definition_body);
+    {
+        // Associated types are desugared into functional dependencies of the same name
+        let (associated_types, declarations) = body.unwrap_or_default();
+        let fundeps = [fundeps, associated_types].concat();
+        Ast::trait_definition(sealed.is_some(), name, args, fundeps, given.unwrap_or_default(), declarations, loc)
+    }
+);
+
+parser!(trait_definition_body loc -> 'b (Vec<String>, Vec<ast::TypeAnnotation<'b>>) =
+    _ <- expect(Token::With);
+    body <- or(&[trait_definition_body_block, trait_definition_body_single], "trait body");
+    body
+);
+
+parser!(trait_definition_body_single loc -> 'b (Vec<String>, Vec<ast::TypeAnnotation<'b>>) =
+    body <- trait_body_single;
+    (vec![], body)
+);
+
+parser!(trait_definition_body_block loc -> 'b (Vec<String>, Vec<ast::TypeAnnotation<'b>>) =
+    _ <- expect(Token::Indent);
+    associated_types <- many0(associated_type_declaration);
+    body !<- delimited_trailing(declaration, expect(Token::Newline), false);
+    _ !<- expect(Token::Unindent);
+    (associated_types, body)
+);
+
+// type Item
+parser!(associated_type_declaration loc -> 'b String =
+    _ <- expect(Token::Type);
+    name !<- typename;
+    _ !<- expect(Token::Newline);
+    name
 );
 
 parser!(trait_body loc -> 'b Vec<ast::TypeAnnotation<'b>> =
@@ -311,7 +614,7 @@ parser!(declaration loc -> 'b ast::TypeAnnotation<'b> =
     lhs <- pattern_argument;
     _ <- expect(Token::Colon);
     rhs !<- parse_type;
-    ast::TypeAnnotation { lhs: Box::new(lhs), rhs, location: loc, typ: None }
+    ast::TypeAnnotation { lhs: Box::new(lhs), rhs, location: loc, typ: None, conversion: false }
 );
 
 parser!(trait_impl loc =
@@ -319,27 +622,51 @@ parser!(trait_impl loc =
     name !<- typename;
     args !<- many1(basic_type);
     given !<- maybe(given);
-    definitions !<- maybe(impl_body);
-    Ast::trait_impl(name, args, given.unwrap_or_default(), definitions.unwrap_or_default(), loc)
+    body !<- maybe(impl_body);
+    {
+        let (associated_types, items) = body.unwrap_or_default();
+        let (helpers, definitions): (Vec<_>, Vec<_>) = items.into_iter().partition(|(private, _)| *private);
+        let definitions = definitions.into_iter().map(|(_, definition)| definition).collect();
+        let helpers = helpers.into_iter().map(|(_, helper)| helper).collect();
+        Ast::trait_impl(name, args, given.unwrap_or_default(), associated_types, definitions, helpers, loc)
+    }
 );
 
-parser!(impl_body loc -> 'b Vec<ast::Definition<'b>> =
+parser!(impl_body loc -> 'b (Vec<(String, ast::Type<'b>)>, Vec<(bool, ast::Definition<'b>)>) =
     _ <- maybe(expect(Token::Newline));
     _ <- expect(Token::With);
-    definitions <- or(&[impl_body_block, impl_body_single], "impl body");
-    definitions
+    body <- or(&[impl_body_block, impl_body_single], "impl body");
+    body
 );
 
-parser!(impl_body_single loc -> 'b Vec<ast::Definition<'b>> =
-    definition <- raw_definition;
-    vec![definition]
+parser!(impl_body_single loc -> 'b (Vec<(String, ast::Type<'b>)>, Vec<(bool, ast::Definition<'b>)>) =
+    item <- impl_item;
+    (vec![], vec![item])
 );
 
-parser!(impl_body_block loc -> 'b Vec<ast::Definition<'b>> =
+parser!(impl_body_block loc -> 'b (Vec<(String, ast::Type<'b>)>, Vec<(bool, ast::Definition<'b>)>) =
     _ <- expect(Token::Indent);
-    definitions !<- delimited_trailing(raw_definition, expect(Token::Newline), false);
+    associated_types <- many0(associated_type_definition);
+    items !<- delimited_trailing(impl_item, expect(Token::Newline), false);
     _ !<- expect(Token::Unindent);
-    definitions
+    (associated_types, items)
+);
+
+// A definition within an impl, returning true if it is a `private` helper rather than a trait method
+parser!(impl_item loc -> 'b (bool, ast::Definition<'b>) =
+    private <- maybe(expect(Token::Private));
+    definition <- raw_definition;
+    (private.is_some(), definition)
+);
+
+// type Item = I32
+parser!(associated_type_definition loc -> 'b (String, ast::Type<'b>) =
+    _ <- expect(Token::Type);
+    name !<- typename;
+    _ !<- expect(Token::Equal);
+    typ !<- parse_type;
+    _ !<- expect(Token::Newline);
+    (name, typ)
 );
 
 parser!(given loc -> 'b Vec<Trait<'b>> =
@@ -349,9 +676,10 @@ parser!(given loc -> 'b Vec<Trait<'b>> =
 );
 
 parser!(required_trait location -> 'b Trait<'b> =
+    negated <- maybe(expect(Token::Not));
     name <- typename;
     args <- many1(basic_type);
-    Trait { name, args, location }
+    Trait { name, args, negated: negated.is_some(), location }
 );
 
 parser!(return_expr loc =
@@ -360,6 +688,20 @@ parser!(return_expr loc =
     Ast::return_expr(expr, loc)
 );
 
+parser!(break_expr loc =
+    _ <- expect(Token::Break);
+    label <- maybe(loop_label);
+    expr <- maybe(expression);
+    Ast::break_expr(label, expr.unwrap_or_else(|| Ast::unit_literal(loc)), loc)
+);
+
+parser!(continue_expr loc =
+    _ <- expect(Token::Continue);
+    label <- maybe(loop_label);
+    args !<- many1(function_argument);
+    desugar::desugar_continue(label, args, loc)
+);
+
 parser!(parse_extern loc =
     _ <- expect(Token::Extern);
     declarations <- or(&[extern_block, extern_single], "extern");
@@ -378,6 +720,36 @@ parser!(extern_single _loc -> 'b Vec<ast::TypeAnnotation<'b>> =
     vec![declaration]
 );
 
+/// A function's body may end with a `where` clause of helper definitions
+fn function_body<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
+    match input[0].0 {
+        Token::Indent => function_block(input),
+        _ => function_statement(input),
+    }
+}
+
+parser!(function_block loc =
+    _ <- expect(Token::Indent);
+    body !<- statement_list;
+    _ !<- maybe_newline;
+    helpers !<- maybe(where_clause);
+    _ !<- maybe_newline;
+    _ !<- expect(Token::Unindent);
+    desugar::desugar_where(body, helpers, loc)
+);
+
+parser!(function_statement loc =
+    body <- statement;
+    helpers !<- maybe(where_clause);
+    desugar::desugar_where(body, helpers, loc)
+);
+
+parser!(where_clause _loc =
+    _ <- expect(Token::Where);
+    helpers !<- block_or_statement;
+    helpers
+);
+
 fn block_or_statement<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
     match input[0].0 {
         Token::Indent => block(input),
@@ -417,6 +789,9 @@ fn precedence(token: &Token) -> Option<(i8, bool)> {
         Token::Add | Token::Subtract => Some((12, false)),
         Token::Multiply | Token::Divide | Token::Modulus => Some((13, false)),
         Token::As => Some((16, false)),
+        Token::Operator(operator) => USER_DEFINED_OPERATORS.with(|table| {
+            Some(table.borrow().get(operator).copied().unwrap_or(DEFAULT_USER_DEFINED_OPERATOR_PRECEDENCE))
+        }),
         _ => None,
     }
 }
@@ -429,24 +804,81 @@ fn should_continue(operator_on_stack: &Token, r_prec: i8, r_is_right_assoc: bool
     l_prec > r_prec || (l_prec == r_prec && !r_is_right_assoc)
 }
 
-fn pop_operator<'c>(operator_stack: &mut Vec<&Token>, results: &mut Vec<(Ast<'c>, Location<'c>)>) {
-    let (rhs, rhs_location) = results.pop().unwrap();
-    let (lhs, lhs_location) = results.pop().unwrap();
-    let location = lhs_location.union(rhs_location);
+/// Is this one of the ordering comparisons which may be chained, e.g. `a < b <= c`?
+fn is_chained_comparison(token: &Token) -> bool {
+    matches!(token, Token::LessThan | Token::LessThanOrEqual | Token::GreaterThan | Token::GreaterThanOrEqual)
+}
+
+/// A value on the results stack of the shunting-yard algorithm. Comparisons following
+/// `ast` are collected rather than desugared immediately so that a whole comparison
+/// chain can be desugared at once when the operand is used.
+struct Operand<'c> {
+    ast: Ast<'c>,
+    location: Location<'c>,
+    comparisons: Vec<(Token, Ast<'c>)>,
+}
+
+impl<'c> Operand<'c> {
+    fn new(ast: Ast<'c>, location: Location<'c>) -> Self {
+        Operand { ast, location, comparisons: vec![] }
+    }
+
+    fn into_ast(self) -> Ast<'c> {
+        if self.comparisons.is_empty() {
+            self.ast
+        } else {
+            desugar::desugar_comparison_chain(self.ast, self.comparisons, self.location)
+        }
+    }
+}
+
+fn pop_operator<'c>(operator_stack: &mut Vec<&Token>, results: &mut Vec<Operand<'c>>) {
+    let rhs = results.pop().unwrap();
+    let mut lhs = results.pop().unwrap();
+    let location = lhs.location.union(rhs.location);
     let operator = operator_stack.pop().unwrap().clone();
-    let call = desugar::desugar_operators(operator, lhs, rhs, location);
-    results.push((call, location));
+
+    if is_chained_comparison(&operator) {
+        lhs.comparisons.push((operator, rhs.into_ast()));
+        lhs.location = location;
+        results.push(lhs);
+    } else {
+        let call = desugar::desugar_operators(operator, lhs.into_ast(), rhs.into_ast(), location);
+        results.push(Operand::new(call, location));
+    }
 }
 
 /// Parse an arbitrary expression using the shunting-yard algorithm
 fn expression<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
+    expression_above_precedence(input, -1)
+}
+
+/// Parse an expression containing only operators which bind tighter than `min_precedence`,
+/// stopping at the first operator which does not. E.g. with the precedence of `,` this
+/// parses `a + b` out of `a + b, c`.
+fn expression_above_precedence<'a, 'b>(input: Input<'a, 'b>, min_precedence: i8) -> AstResult<'a, 'b> {
     let (mut input, value, location) = term(input)?;
 
     let mut operator_stack = vec![];
-    let mut results = vec![(value, location)];
+    let mut results = vec![Operand::new(value, location)];
 
     // loop while the next token is an operator
     while let Some((prec, right_associative)) = precedence(&input[0].0) {
+        if prec <= min_precedence {
+            break;
+        }
+
+        // `as` has the highest precedence of any operator and takes a type on its rhs
+        // so it is applied to the previous term immediately.
+        if input[0].0 == Token::As {
+            let (new_input, typ, type_location) = no_backtracking(ascribed_type)(&input[1..])?;
+            let operand = results.pop().unwrap();
+            let location = operand.location.union(type_location);
+            results.push(Operand::new(desugar::desugar_as(operand.into_ast(), typ, location), location));
+            input = new_input;
+            continue;
+        }
+
         while !operator_stack.is_empty()
             && should_continue(operator_stack[operator_stack.len() - 1], prec, right_associative)
         {
@@ -457,7 +889,7 @@ fn expression<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
         input = &input[1..];
 
         let (new_input, value, location) = no_backtracking(term)(input)?;
-        results.push((value, location));
+        results.push(Operand::new(value, location));
         input = new_input;
     }
 
@@ -468,16 +900,19 @@ fn expression<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
 
     assert!(operator_stack.is_empty());
     assert!(results.len() == 1);
-    let (value, location) = results.pop().unwrap();
-    Ok((input, value, location))
+    let operand = results.pop().unwrap();
+    let location = operand.location;
+    Ok((input, operand.into_ast(), location))
 }
 
 fn term<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
     match input[0].0 {
         Token::If => if_expr(input),
-        Token::Loop => loop_expr(input),
+        Token::Hash => conditional_compilation(input),
+        Token::Loop => or(&[recursive_lambda, loop_expr], "loop")(input),
         Token::Match => match_expr(input),
         Token::Handle => handle_expr(input),
+        Token::With => record_literal(input),
         _ => or(&[type_annotation, named_constructor_expr, function_call, function_argument], "term")(input),
     }
 }
@@ -492,7 +927,24 @@ parser!(named_constructor_expr loc =
     constructor <- variant;
     _ <- expect(Token::With);
     sequence !<- named_constructor_args;
-    Ast::named_constructor(constructor, sequence, loc)
+    Ast::named_constructor(Some(constructor), sequence, loc)
+);
+
+// An anonymous record literal, e.g. `with x = 1, y = 2`
+parser!(record_literal loc =
+    _ <- expect(Token::With);
+    sequence !<- named_constructor_args;
+    Ast::named_constructor(None, sequence, loc)
+);
+
+// A functional record update, e.g. `{ point with x = 3 }`
+parser!(record_update loc =
+    _ <- expect(Token::BraceLeft);
+    record !<- function_argument;
+    _ !<- expect(Token::With);
+    sequence !<- named_constructor_inline_args;
+    _ !<- expect(Token::BraceRight);
+    Ast::record_update(record, sequence, loc)
 );
 
 fn named_constructor_args<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, Ast<'b>> {
@@ -526,6 +978,18 @@ fn named_constructor_arg<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, As
     Ok((input, expr, start.union(end)))
 }
 
+// A reference pattern like `&Some x` or `!(a, b)` which matches on the value behind a reference.
+// Unlike `ref_expr`, the reference applies to the whole constructor call following it.
+parser!(ref_pattern loc =
+    token <- or(&[expect(Token::Ampersand), expect(Token::ExclamationMark)], "pattern");
+    pattern !<- referenced_pattern;
+    Ast::reference(token, pattern, loc)
+);
+
+fn referenced_pattern<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
+    or(&[pattern_function_call, pattern_argument], "pattern")(input)
+}
+
 parser!(pattern_function_call loc =
     function <- pattern_function_argument;
     args <- many1(pattern_function_argument);
@@ -542,6 +1006,17 @@ parser!(if_expr loc =
     Ast::if_expr(condition, then, otherwise, loc)
 );
 
+parser!(conditional_compilation loc =
+    _ <- expect(Token::Hash);
+    _ !<- expect(Token::If);
+    configuration !<- identifier;
+    _ !<- maybe_newline;
+    _ !<- expect(Token::Then);
+    then !<- block_or_statement;
+    otherwise !<- else_expr;
+    Ast::conditional_compilation(configuration, then, otherwise, loc)
+);
+
 parser!(match_expr loc =
     _ <- expect(Token::Match);
     expression !<- block_or_statement;
@@ -549,12 +1024,25 @@ parser!(match_expr loc =
     Ast::match_expr(expression, branches, loc)
 );
 
+parser!(recursive_lambda loc =
+    _ <- expect(Token::Loop);
+    lambda <- lambda;
+    desugar::desugar_recursive_lambda(lambda, loc)
+);
+
 parser!(loop_expr loc =
     _ <- expect(Token::Loop);
+    label <- maybe(loop_label);
     args !<- many1(loop_param);
     _ !<- expect(Token::RightArrow);
     body !<- block_or_statement;
-    desugar::desugar_loop(args, body, loc)
+    desugar::desugar_loop(label, args, body, loc)
+);
+
+parser!(loop_label _loc -> 'b String =
+    label <- identifier;
+    _ <- expect(Token::Colon);
+    label
 );
 
 fn loop_param<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, (Ast<'b>, Ast<'b>)> {
@@ -593,9 +1081,17 @@ parser!(handle_branch _loc -> 'b (Ast<'b>, Ast<'b>) =
 
 // This gives a type error when inlined into handle_branch for some reason
 fn handle_pattern<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
-    or(&[pattern, return_expr], "pattern")(input)
+    or(&[pattern, return_pattern], "pattern")(input)
 }
 
+// The `return x` pattern of a handle branch. This is parsed as a pattern rather than
+// an expression so that `return _` matches anything instead of containing a hole.
+parser!(return_pattern loc =
+    _ <- expect(Token::Return);
+    pattern !<- pattern;
+    Ast::return_expr(pattern, loc)
+);
+
 parser!(not_expr loc =
     not <- expect(Token::Not);
     expr !<- term;
@@ -608,6 +1104,13 @@ parser!(ref_expr loc =
     Ast::reference(token, expr, loc)
 );
 
+parser!(spread loc =
+    _ <- expect(Token::Range);
+    _ <- expect(Token::MemberAccess);
+    expr !<- member_access;
+    Ast::spread(expr, loc)
+);
+
 parser!(at_expr loc =
     token <- expect(Token::At);
     expr !<- term;
@@ -644,16 +1147,63 @@ fn basic_type<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, Type<'b>> {
         Token::PointerType => pointer_type(input),
         Token::BooleanType => boolean_type(input),
         Token::UnitType => unit_type(input),
-        Token::Ampersand | Token::ExclamationMark => basic_reference_type(input),
+        Token::UniverseType => universe_type(input),
+        Token::Dyn => dyn_type(input),
+        Token::Comptime => comptime_type(input),
+        Token::Ampersand | Token::ExclamationMark | Token::QuestionMark => basic_reference_type(input),
         Token::Identifier(_) => type_variable(input),
         Token::TypeName(_) => user_defined_type(input),
         Token::ParenthesisLeft => parenthesized_type(input),
+        Token::IntegerLiteral(_, None) => nat_type(input),
         _ => Err(ParseError::InRule("type", input[0].1)),
     }
 }
 
+/// The type on the rhs of an `x as T` expression. This excludes pair and
+/// function types which would otherwise consume the rest of the expression.
+fn ascribed_type<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, Type<'b>> {
+    or(&[type_application, basic_type], "type")(input)
+}
+
 fn parenthesized_type<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, Type<'b>> {
-    parenthesized(parse_type)(input)
+    parenthesized(or(&[nat_arithmetic_type, parse_type], "type"))(input)
+}
+
+/// Type-level arithmetic with the usual precedence of `*` over `+` and `-`.
+/// This requires at least one operator so that other parenthesized types are
+/// left to parse_type.
+fn nat_arithmetic_type<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, Type<'b>> {
+    let (input, typ, location) = nat_binary_type(input, &[Token::Add, Token::Subtract], nat_product_type)?;
+    match typ {
+        Type::NatArithmetic(..) => Ok((input, typ, location)),
+        _ => Err(ParseError::InRule("type", location)),
+    }
+}
+
+fn nat_product_type<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, Type<'b>> {
+    nat_binary_type(input, &[Token::Multiply], basic_type)
+}
+
+/// operand (operator operand)*, associating to the left
+fn nat_binary_type<'a, 'b>(
+    input: Input<'a, 'b>, operators: &[Token], operand: fn(Input<'a, 'b>) -> ParseResult<'a, 'b, Type<'b>>,
+) -> ParseResult<'a, 'b, Type<'b>> {
+    let (mut input, mut lhs, mut location) = operand(input)?;
+
+    while operators.contains(&input[0].0) {
+        let operator = match input[0].0 {
+            Token::Add => TypeTag::Add,
+            Token::Subtract => TypeTag::Subtract,
+            _ => TypeTag::Multiply,
+        };
+
+        let (rest, rhs, rhs_location) = operand(&input[1..])?;
+        location = location.union(rhs_location);
+        lhs = Type::NatArithmetic(operator, Box::new(lhs), Box::new(rhs), location);
+        input = rest;
+    }
+
+    Ok((input, lhs, location))
 }
 
 parser!(match_branch _loc -> 'b (Ast<'b>, Ast<'b>) =
@@ -676,6 +1226,7 @@ parser!(else_expr _loc =
 /// 1-n arguments.
 fn function_argument<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
     match input[0].0 {
+        Token::Range => spread(input),
         Token::Not => not_expr(input),
         Token::Ampersand => ref_expr(input),
         Token::ExclamationMark => ref_expr(input),
@@ -699,8 +1250,22 @@ fn member_access<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
 
     while matches!(
         &input[0].0,
-        Token::MemberAccess | Token::MemberRef | Token::MemberMut | Token::Index | Token::IndexRef | Token::IndexMut
+        Token::MemberAccess
+            | Token::MemberRef
+            | Token::MemberMut
+            | Token::Index
+            | Token::IndexRef
+            | Token::IndexMut
+            | Token::QuestionMark
     ) {
+        // Parse a try expression `e?`
+        if input[0].0 == Token::QuestionMark {
+            location = location.union(input[0].1);
+            input = &input[1..];
+            arg = desugar::desugar_try(arg, location);
+            continue;
+        }
+
         let is_index = matches!(&input[0].0, Token::Index | Token::IndexRef | Token::IndexMut);
         let is_reference = match input[0].0 {
             Token::MemberMut | Token::IndexMut => Some(Mutability::Mutable),
@@ -729,8 +1294,9 @@ fn member_access<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
 }
 
 fn argument<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
-    match input[0].0 {
+    match &input[0].0 {
         Token::StringType => variable(input),
+        Token::Identifier(name) if name == "_" => hole(input),
         Token::Identifier(_) => variable(input),
         Token::TypeName(_) => or(&[variable, variant], "argument")(input),
         Token::StringLiteral(_) => string(input),
@@ -741,6 +1307,8 @@ fn argument<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
         Token::UnitLiteral => unit(input),
         Token::Fn => lambda(input),
         Token::ParenthesisLeft => parenthesized_expression(input),
+        Token::BracketLeft => array_literal(input),
+        Token::BraceLeft => record_update(input),
         _ => Err(ParseError::InRule("argument", input[0].1)),
     }
 }
@@ -762,14 +1330,36 @@ fn pattern_argument<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
 
 parser!(lambda loc =
     _ <- expect(Token::Fn);
+    captures <- maybe(capture_list);
     args !<- many1(pattern_argument);
     return_type <- maybe(function_return_type);
     effects <- maybe(effect_clause);
     _ !<- expect(Token::RightArrow);
     body !<- block_or_statement;
-    Ast::lambda(args, return_type, effects, body, loc)
+    Ast::lambda_with_captures(captures, args, return_type, effects, body, loc)
+);
+
+// An explicit list of the outer variables a closure may capture, e.g. `[x, y]`
+fn capture_list<'a, 'b>(input: Input<'a, 'b>) -> ParseResult<'a, 'b, Vec<String>> {
+    bounded(Token::BracketLeft, delimited(identifier, expect(Token::Comma)), Token::BracketRight)(input)
+}
+
+// An array literal, e.g. `[| 1, 2, 3 |]`
+parser!(array_literal loc =
+    _ <- expect(Token::BracketLeft);
+    _ <- expect(Token::Pipe);
+    elements <- maybe(delimited(array_element, expect(Token::Comma)));
+    _ !<- expect(Token::Pipe);
+    _ !<- expect(Token::BracketRight);
+    Ast::array_literal(elements.unwrap_or_default(), loc)
 );
 
+/// Each element of an array literal is an expression which stops before the next `,`
+fn array_element<'a, 'b>(input: Input<'a, 'b>) -> AstResult<'a, 'b> {
+    let (comma_precedence, _) = precedence(&Token::Comma).unwrap();
+    expression_above_precedence(input, comma_precedence)
+}
+
 parser!(operator loc =
     op <- expect_if("operator", |op| op.is_overloadable_operator());
     Ast::operator(op, loc)
@@ -793,6 +1383,11 @@ parser!(variable loc =
     Ast::variable(module_prefix.unwrap_or_default(), name, loc)
 );
 
+parser!(hole loc =
+    _ <- expect_if("hole", |token| matches!(token, Token::Identifier(name) if name == "_"));
+    Ast::hole(loc)
+);
+
 parser!(string_literal loc =
     contents <- string_literal_token;
     Ast::string(contents, loc)
@@ -930,7 +1525,7 @@ parser!(reference_type loc -> 'b Type<'b> =
 );
 
 parser!(reference_operator loc -> 'b Mutability =
-    token <- or(&[expect(Token::Ampersand), expect(Token::ExclamationMark)], "type");
+    token <- or(&[expect(Token::Ampersand), expect(Token::ExclamationMark), expect(Token::QuestionMark)], "type");
     match token {
         Token::Ampersand => Mutability::Immutable,
         Token::ExclamationMark => Mutability::Mutable,
@@ -972,6 +1567,30 @@ parser!(type_variable loc -> 'b Type<'b> =
     Type::TypeVariable(name, loc)
 );
 
+parser!(nat_type loc -> 'b Type<'b> =
+    (value, _kind) <- integer_literal_token;
+    Type::Nat(value, loc)
+);
+
+// `Universe n` is the type of types at level n. A bare `Universe` is level 0.
+parser!(universe_type loc -> 'b Type<'b> =
+    _ <- expect(Token::UniverseType);
+    level <- maybe(integer_literal_token);
+    Type::Universe(level.map_or(0, |(level, _kind)| u32::try_from(level).unwrap_or(u32::MAX)), loc)
+);
+
+parser!(dyn_type loc -> 'b Type<'b> =
+    _ <- expect(Token::Dyn);
+    trait_name !<- typename;
+    Type::Dyn(trait_name, loc)
+);
+
+parser!(comptime_type loc -> 'b Type<'b> =
+    _ <- expect(Token::Comptime);
+    expr !<- expression;
+    Type::Comptime(Box::new(expr), loc)
+);
+
 parser!(user_defined_type loc -> 'b Type<'b> =
     name <- typename;
     Type::UserDefined(name, loc)
@@ -49,9 +49,16 @@ impl<'a> Display for ast::Variable<'a> {
 impl<'a> Display for ast::Lambda<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "(fn")?;
-        for arg in self.args.iter() {
+        if let Some(captures) = &self.captures {
+            write!(f, " [{}]", captures.join(", "))?;
+        }
+        let first_default = self.args.len() - self.default_args.len();
+        for arg in self.args[..first_default].iter() {
             write!(f, " {}", arg)?;
         }
+        for (arg, default) in self.args[first_default..].iter().zip(&self.default_args) {
+            write!(f, " ({} = {})", arg, default)?;
+        }
         if let Some(typ) = &self.return_type {
             write!(f, " : {}", typ)?;
         }
@@ -59,10 +66,22 @@ impl<'a> Display for ast::Lambda<'a> {
             write!(f, " ")?;
             fmt_effects(effects, f)?;
         }
+        for contract in self.contracts.iter() {
+            write!(f, " @{}({})", contract.kind, contract.condition)?;
+        }
         write!(f, " -> {})", self.body)
     }
 }
 
+impl Display for ast::ContractKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ast::ContractKind::Requires => write!(f, "requires"),
+            ast::ContractKind::Ensures => write!(f, "ensures"),
+        }
+    }
+}
+
 fn fmt_effects(effects: &[EffectAst], f: &mut Formatter) -> fmt::Result {
     if effects.is_empty() {
         write!(f, "pure")
@@ -154,6 +173,13 @@ impl<'a> Display for ast::Type<'a> {
             Pair(first, rest, _) => {
                 write!(f, "({}, {})", first, rest)
             },
+            Nat(n, _) => write!(f, "{}", n),
+            Universe(level, _) => write!(f, "(Universe {})", level),
+            Dyn(trait_name, _) => write!(f, "(dyn {})", trait_name),
+            Comptime(expr, _) => write!(f, "(comptime {})", expr),
+            NatArithmetic(operator, lhs, rhs, _) => {
+                write!(f, "({} {} {})", lhs, operator, rhs)
+            },
         }
     }
 }
@@ -163,9 +189,12 @@ impl<'a> Display for ast::TypeDefinitionBody<'a> {
         use ast::TypeDefinitionBody::*;
         match self {
             Union(types) => {
-                for (name, variant_fields, _) in types {
+                for (name, variant_fields, result_type, _) in types {
                     let s = join_with(variant_fields, " ");
                     write!(f, "| {} {}", name, s)?;
+                    if let Some(result_type) = result_type {
+                        write!(f, " : {}", result_type)?;
+                    }
                 }
                 Ok(())
             },
@@ -181,7 +210,12 @@ impl<'a> Display for ast::TypeDefinitionBody<'a> {
 impl<'a> Display for ast::TypeDefinition<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let args = join_with(&self.args, "");
-        write!(f, "(type {} {} = {})", self.name, args, self.definition)
+        if self.given.is_empty() {
+            write!(f, "(type {} {} = {})", self.name, args, self.definition)
+        } else {
+            let given = join_with(&self.given, ", ");
+            write!(f, "(type {} {} given {} = {})", self.name, args, given, self.definition)
+        }
     }
 }
 
@@ -204,18 +238,29 @@ impl<'a> Display for ast::Import<'a> {
 
 impl<'a> Display for ast::TraitDefinition<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "(trait {} {} ", self.name, join_with(&self.args, " "))?;
+        let sealed = if self.sealed { "sealed " } else { "" };
+        write!(f, "(")?;
+        if let Some(closed) = &self.closed {
+            let impls = closed.iter().map(|args| join_with(args, " ")).collect::<Vec<_>>();
+            write!(f, "@closed({}) ", impls.join(", "))?;
+        }
+        write!(f, "{}trait {} {} ", sealed, self.name, join_with(&self.args, " "))?;
         if !self.fundeps.is_empty() {
             write!(f, "-> {} ", join_with(&self.fundeps, " "))?;
         }
+        if !self.given.is_empty() {
+            write!(f, "given {} ", join_with(&self.given, ", "))?;
+        }
         write!(f, "with\n    {}\n)", join_with(&self.declarations, "\n    "))
     }
 }
 
 impl<'a> Display for ast::TraitImpl<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let args = join_with(&self.trait_args, " ");
-        let definitions = join_with(&self.definitions, "\n    ");
+        let associated_types = self.associated_types.iter().map(|(name, typ)| format!(" (type {name} = {typ})"));
+        let args = join_with(&self.trait_args, " ") + &associated_types.collect::<String>();
+        let helpers = self.helpers.iter().map(|helper| format!("\n    private {helper}"));
+        let definitions = join_with(&self.definitions, "\n    ") + &helpers.collect::<String>();
         let given = join_with(&self.given, " ");
         write!(
             f,
@@ -232,13 +277,18 @@ impl<'a> Display for ast::TraitImpl<'a> {
 impl<'a> Display for ast::Trait<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let args = join_with(&self.args, " ");
-        write!(f, "({} {})", self.name, args)
+        let not = if self.negated { "not " } else { "" };
+        write!(f, "({}{} {})", not, self.name, args)
     }
 }
 
 impl<'a> Display for ast::Return<'a> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(f, "(return {})", self.expression)
+        match &self.target {
+            ast::LambdaKind::Function => write!(f, "(return {})", self.expression),
+            ast::LambdaKind::Loop(None) => write!(f, "(break {})", self.expression),
+            ast::LambdaKind::Loop(Some(label)) => write!(f, "(break {}: {})", label, self.expression),
+        }
     }
 }
 
@@ -327,7 +377,11 @@ impl<'a> Display for ast::NamedConstructor<'a> {
             _ => unreachable!(),
         });
 
-        write!(f, "({} with {})", self.constructor, args.join(", "))
+        match (&self.constructor, &self.record) {
+            (Some(constructor), _) => write!(f, "({} with {})", constructor, args.join(", ")),
+            (None, Some(record)) => write!(f, "{{ {} with {} }}", record, args.join(", ")),
+            (None, None) => write!(f, "(with {})", args.join(", ")),
+        }
     }
 }
 
@@ -336,3 +390,33 @@ impl<'a> Display for ast::Reference<'a> {
         write!(f, "{}{}", self.mutability, self.expression)
     }
 }
+
+impl<'a> Display for ast::ConditionalCompilation<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "(#if {} then {} else {})", self.configuration, self.then, self.otherwise)
+    }
+}
+
+impl<'a> Display for ast::Spread<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "...{}", self.expression)
+    }
+}
+
+impl<'a> Display for ast::ArrayLiteral<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "[| {} |]", join_with(&self.elements, ", "))
+    }
+}
+
+impl<'a> Display for ast::Hole<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "_")
+    }
+}
+
+impl<'a> Display for ast::Error<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<error>")
+    }
+}
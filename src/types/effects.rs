@@ -1,6 +1,7 @@
-use crate::cache::{EffectInfoId, ModuleCache};
+use crate::cache::{DefinitionInfoId, DefinitionKind, EffectInfoId, ModuleCache};
 use crate::error::location::Location;
 use crate::error::TypeErrorKind as TE;
+use crate::parser::ast::Ast;
 use crate::types::typechecker::{try_unify_all_with_bindings, TypeBindings};
 use crate::types::Type;
 use crate::util::fmap;
@@ -148,10 +149,13 @@ impl EffectSet {
         Type::Effects(this)
     }
 
+    /// Unify this (actual) effect set with the expected one. On failure, returns each effect
+    /// of this set which is not allowed by the expected set, along with its arguments. This
+    /// is empty if the sets instead failed to unify because of their extensions.
     pub fn try_unify_with_bindings<'c>(
         &self, expected: &EffectSet, bindings: &mut UnificationBindings, location: Location<'c>,
         cache: &mut ModuleCache<'c>,
-    ) -> Result<(), ()> {
+    ) -> Result<(), Vec<Effect>> {
         let a = self.follow_unification_bindings(bindings, cache);
         let b = expected.follow_unification_bindings(bindings, cache);
 
@@ -165,11 +169,13 @@ impl EffectSet {
 
                 for (b_id, b_args) in effects_b {
                     if a_id == b_id {
-                        let new_bindings = UnificationBindings::empty();
+                        // Start from the bindings found so far so that the second pass, which checks
+                        // the same pairs of effects in reverse, cannot bind them in the opposite direction.
+                        let new_bindings = bindings.clone();
                         let result =
                             try_unify_all_with_bindings(a_args, b_args, new_bindings, location, cache, TE::NeverShown);
                         if let Ok(new_bindings) = result {
-                            bindings.extend(new_bindings);
+                            *bindings = new_bindings;
                             handled = true;
                             break;
                         }
@@ -188,9 +194,16 @@ impl EffectSet {
         // Allow extra effects in `a` (actual) if `b` (expected) has extra,
         // but not the reverse. This allows, e.g. passing a pure function
         // into a function expecting a function with an effect.
-        if b.extension.is_none() && !new_effects_in_b.is_empty()
-        {
-            return Err(());
+        if b.extension.is_none() && !new_effects_in_b.is_empty() {
+            return Err(new_effects_in_b);
+        }
+
+        // If both sets share an extension and this set's effects are already a subset of the expected
+        // effects, there is nothing left to unify. Growing the shared extension to match would fail
+        // since it would then contain itself. This happens when e.g. a closure performing only the
+        // effects left over by a handler is passed back into the function performing that handler.
+        if new_effects_in_b.is_empty() && a.extension.is_some() && a.extension == b.extension {
+            return Ok(());
         }
 
         let fresh_extension = typechecker::next_type_variable_id(cache);
@@ -211,17 +224,18 @@ impl EffectSet {
         };
 
         let a_extension = match a.extension {
-            Some(extension) => extend_effects(new_effects_in_a, Some(extension))?,
+            Some(extension) => extend_effects(new_effects_in_a, Some(extension)).map_err(|()| vec![])?,
             None => None,
         };
 
-        let b_extension = extend_effects(new_effects_in_b, b.extension)?;
+        let b_extension = extend_effects(new_effects_in_b, b.extension).map_err(|()| vec![])?;
 
         if let (Some(a), Some(b)) = (a_extension, b_extension) {
             if a != b {
                 let a_type = &Type::TypeVariable(a);
                 let b_type = &Type::TypeVariable(b);
-                typechecker::try_unify_type_variable_with_bindings(a, a_type, b_type, true, bindings, location, cache)?;
+                typechecker::try_unify_type_variable_with_bindings(a, a_type, b_type, true, bindings, location, cache)
+                    .map_err(|()| vec![])?;
             }
         }
 
@@ -344,6 +358,12 @@ impl EffectSet {
     }
 }
 
+/// Display a single effect along with its arguments, e.g. `Throw String`
+pub fn display_effect((id, args): &Effect, cache: &ModuleCache) -> String {
+    let args = args.iter().map(|arg| format!(" {}", arg.display(cache)));
+    format!("{}{}", cache.effect_infos[id.0].name, args.collect::<String>())
+}
+
 fn find_matching_effect(effect: &Effect, set: &[Effect], cache: &mut ModuleCache) -> Result<UnificationBindings, ()> {
     let (effect_id, effect_args) = effect;
     for (other_id, other_args) in set {
@@ -365,3 +385,81 @@ fn find_matching_effect(effect: &Effect, set: &[Effect], cache: &mut ModuleCache
     }
     Err(())
 }
+
+/// The effects a function performs according to its type, along with any
+/// effects it handles internally within its body.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EffectSummary {
+    pub performed: Vec<Effect>,
+    pub handled: Vec<Effect>,
+}
+
+/// Summarize the effects of the given definition. Definitions which are not
+/// functions perform no effects. This should only be called after type inference.
+#[allow(dead_code)]
+pub fn effect_summary(id: DefinitionInfoId, cache: &ModuleCache) -> EffectSummary {
+    let info = &cache[id];
+    let mut summary = EffectSummary::default();
+
+    if let Some(typ) = &info.typ {
+        if let Type::Function(function) = cache.follow_bindings_shallow(typ.remove_forall()) {
+            summary.performed = function.effects.flatten_effects(cache).effects;
+        }
+    }
+
+    if let Some(DefinitionKind::Definition(definition)) = &info.definition {
+        collect_handled_effects(&definition.expr, &mut summary.handled);
+        EffectSet::dedup_effects(&mut summary.handled, cache);
+    }
+
+    summary
+}
+
+fn collect_handled_effects(ast: &Ast, handled: &mut Vec<Effect>) {
+    match ast {
+        Ast::Literal(_) | Ast::Variable(_) | Ast::TypeDefinition(_) | Ast::Import(_) | Ast::Error(_) => (),
+        Ast::TraitDefinition(_) | Ast::Extern(_) | Ast::EffectDefinition(_) | Ast::Hole(_) => (),
+        Ast::Lambda(lambda) => collect_handled_effects(&lambda.body, handled),
+        Ast::FunctionCall(call) => {
+            collect_handled_effects(&call.function, handled);
+            call.args.iter().for_each(|arg| collect_handled_effects(arg, handled));
+        },
+        Ast::Definition(definition) => collect_handled_effects(&definition.expr, handled),
+        Ast::If(if_) => {
+            collect_handled_effects(&if_.condition, handled);
+            collect_handled_effects(&if_.then, handled);
+            collect_handled_effects(&if_.otherwise, handled);
+        },
+        Ast::Match(match_) => {
+            collect_handled_effects(&match_.expression, handled);
+            match_.branches.iter().for_each(|(_, branch)| collect_handled_effects(branch, handled));
+        },
+        Ast::TypeAnnotation(annotation) => collect_handled_effects(&annotation.lhs, handled),
+        Ast::TraitImpl(trait_impl) => {
+            let definitions = trait_impl.definitions.iter().chain(&trait_impl.helpers);
+            definitions.for_each(|definition| collect_handled_effects(&definition.expr, handled));
+        },
+        Ast::Return(return_) => collect_handled_effects(&return_.expression, handled),
+        Ast::Sequence(sequence) => {
+            sequence.statements.iter().for_each(|statement| collect_handled_effects(statement, handled))
+        },
+        Ast::MemberAccess(member_access) => collect_handled_effects(&member_access.lhs, handled),
+        Ast::Assignment(assignment) => {
+            collect_handled_effects(&assignment.lhs, handled);
+            collect_handled_effects(&assignment.rhs, handled);
+        },
+        Ast::Handle(handle) => {
+            handled.extend(handle.effects_handled.iter().cloned());
+            collect_handled_effects(&handle.expression, handled);
+            handle.branches.iter().for_each(|(_, branch)| collect_handled_effects(branch, handled));
+        },
+        Ast::NamedConstructor(constructor) => {
+            constructor.record.iter().for_each(|record| collect_handled_effects(record, handled));
+            collect_handled_effects(&constructor.sequence, handled);
+        },
+        Ast::Reference(reference) => collect_handled_effects(&reference.expression, handled),
+        Ast::ConditionalCompilation(conditional) => collect_handled_effects(conditional.active_branch(), handled),
+        Ast::Spread(spread) => collect_handled_effects(&spread.expression, handled),
+        Ast::ArrayLiteral(array) => array.elements.iter().for_each(|element| collect_handled_effects(element, handled)),
+    }
+}
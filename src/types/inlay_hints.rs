@@ -0,0 +1,241 @@
+//! inlay_hints.rs - Collects the inferred type of each binding without a type annotation.
+//!
+//! This is meant for editors to display inferred types inline next to the variables
+//! they belong to. It must be run after type inference since it only reads the types
+//! already stored in the ModuleCache for each definition.
+use crate::cache::ModuleCache;
+use crate::error::location::Location;
+use crate::parser::ast;
+
+/// Returns the location and compactly rendered type of each variable bound by a
+/// definition or lambda parameter which does not already have a type annotation.
+pub fn inlay_hints<'c>(ast: &ast::Ast<'c>, cache: &ModuleCache<'c>) -> Vec<(Location<'c>, String)> {
+    let mut context = Context { cache, hints: Vec::new() };
+    ast.collect_hints(&mut context);
+    context.hints
+}
+
+struct Context<'local, 'cache> {
+    cache: &'local ModuleCache<'cache>,
+    hints: Vec<(Location<'cache>, String)>,
+}
+
+impl<'local, 'cache> Context<'local, 'cache> {
+    /// Add a hint for each variable in the given pattern, skipping any
+    /// part of the pattern the user has already annotated.
+    fn hint_pattern(&mut self, pattern: &ast::Ast<'cache>) {
+        match pattern {
+            ast::Ast::Variable(variable) => {
+                let Some(id) = variable.definition else { return };
+
+                if let Some(typ) = &self.cache.definition_infos[id.0].typ {
+                    let typ = typ.remove_forall().display(self.cache).to_string();
+                    self.hints.push((variable.location, typ));
+                }
+            },
+            ast::Ast::FunctionCall(call) => {
+                for arg in &call.args {
+                    self.hint_pattern(arg);
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+trait CollectHints<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>);
+}
+
+impl<'c> CollectHints<'c> for ast::Ast<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        dispatch_on_expr!(self, CollectHints::collect_hints, ctx)
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Literal<'c> {
+    fn collect_hints(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Variable<'c> {
+    fn collect_hints(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Lambda<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        for parameter in &self.args {
+            ctx.hint_pattern(parameter);
+        }
+        for default in &self.default_args {
+            default.collect_hints(ctx);
+        }
+        self.body.collect_hints(ctx);
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::FunctionCall<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.function.collect_hints(ctx);
+        for arg in &self.args {
+            arg.collect_hints(ctx);
+        }
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Definition<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        ctx.hint_pattern(&self.pattern);
+        self.expr.collect_hints(ctx);
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::If<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.condition.collect_hints(ctx);
+        self.then.collect_hints(ctx);
+        self.otherwise.collect_hints(ctx);
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Match<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.expression.collect_hints(ctx);
+        for (_, branch) in &self.branches {
+            branch.collect_hints(ctx);
+        }
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::TypeDefinition<'c> {
+    fn collect_hints(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::TypeAnnotation<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.lhs.collect_hints(ctx);
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Import<'c> {
+    fn collect_hints(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::TraitDefinition<'c> {
+    fn collect_hints(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::TraitImpl<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        for definition in self.definitions.iter().chain(&self.helpers) {
+            definition.collect_hints(ctx);
+        }
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Return<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.expression.collect_hints(ctx);
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Sequence<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        for statement in &self.statements {
+            statement.collect_hints(ctx);
+        }
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Extern<'c> {
+    fn collect_hints(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::MemberAccess<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.lhs.collect_hints(ctx);
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Assignment<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.lhs.collect_hints(ctx);
+        self.rhs.collect_hints(ctx);
+        if let Some(index_assign) = &self.index_assign {
+            index_assign.collect_hints(ctx);
+        }
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::EffectDefinition<'c> {
+    fn collect_hints(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Handle<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.expression.collect_hints(ctx);
+        for (_, branch) in &self.branches {
+            branch.collect_hints(ctx);
+        }
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::NamedConstructor<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        if let Some(record) = &self.record {
+            record.collect_hints(ctx);
+        }
+        self.sequence.collect_hints(ctx);
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Reference<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.expression.collect_hints(ctx);
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::ConditionalCompilation<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.active_branch().collect_hints(ctx);
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Spread<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        self.expression.collect_hints(ctx);
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::ArrayLiteral<'c> {
+    fn collect_hints(&self, ctx: &mut Context<'_, 'c>) {
+        for element in &self.elements {
+            element.collect_hints(ctx);
+        }
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Hole<'c> {
+    fn collect_hints(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CollectHints<'c> for ast::Error<'c> {
+    fn collect_hints(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
@@ -0,0 +1,363 @@
+//! linearity.rs - Checks each value of a `linear` type is used exactly once.
+//!
+//! This is a simple usage analysis performed after type inference. Each local variable
+//! with a linear type is found while walking the Ast and each use of it is counted.
+//! Since only one branch of an `if`, `match`, or `handle` expression is taken at runtime,
+//! a variable used once in each branch is only counted as being used once overall. Each
+//! branch must use the variable the same number of times. Closures and loops (which are
+//! desugared into closures) may run any number of times, so using a variable captured by
+//! one within it counts as using it many times.
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::cache::{DefinitionInfoId, ModuleCache};
+use crate::error::location::Location;
+use crate::error::{DiagnosticKind as D, TypeErrorKind as TE};
+use crate::parser::ast;
+use crate::types::Type;
+
+/// Issue an error for each variable of a linear type that is not used exactly once
+pub fn check_linear_usage<'c>(ast: &ast::Ast<'c>, cache: &mut ModuleCache<'c>) {
+    // Linear types are opt-in, so avoid walking the program if there are none
+    if !cache.type_infos.iter().any(|info| info.linear) {
+        return;
+    }
+
+    let mut context = Context {
+        cache,
+        linear_variables: Vec::new(),
+        uses: HashMap::new(),
+        mismatched_branches: HashMap::new(),
+        captures: HashMap::new(),
+    };
+    ast.count_uses(&mut context);
+
+    let Context { linear_variables, uses, mismatched_branches, .. } = context;
+
+    for (id, typ) in linear_variables {
+        let info = &cache.definition_infos[id.0];
+        let name = Rc::new(info.name.clone());
+        let typ = typ.display(cache).to_string();
+
+        let (location, error) = match (uses.get(&id), mismatched_branches.get(&id)) {
+            (Some((MANY, use_location)), _) => (*use_location, TE::LinearValueUsedInClosure(name)),
+            (_, Some(location)) => (*location, TE::LinearValueUsedInSomeBranches(name)),
+            (None, None) => (info.location, TE::LinearValueUnused(name)),
+            (Some((1, _)), None) => continue,
+            (Some((_, second_use)), None) => (*second_use, TE::LinearValueUsedTwice(name)),
+        };
+        cache.push_diagnostic(location, D::TypeError(error, typ.clone(), typ));
+    }
+}
+
+/// The use count of a variable used within a closure or loop which captured it
+const MANY: u32 = u32::MAX;
+
+struct Context<'local, 'cache> {
+    cache: &'local ModuleCache<'cache>,
+
+    /// Each local variable of a linear type found so far, in the order they were defined
+    linear_variables: Vec<(DefinitionInfoId, Type)>,
+
+    /// The number of times each linear variable is used, along with
+    /// the location of the last use which caused the count to increase.
+    uses: HashMap<DefinitionInfoId, (u32, Location<'cache>)>,
+
+    /// Each linear variable used a different number of times in the branches of
+    /// an expression, mapped to the location of the first such expression.
+    mismatched_branches: HashMap<DefinitionInfoId, Location<'cache>>,
+
+    /// Maps the id each closure uses for a variable it captures to the captured variable
+    captures: HashMap<DefinitionInfoId, DefinitionInfoId>,
+}
+
+impl<'local, 'cache> Context<'local, 'cache> {
+    /// Remember any linear variables defined by the given pattern
+    fn define_pattern(&mut self, pattern: &ast::Ast) {
+        match pattern {
+            ast::Ast::Variable(variable) => {
+                let Some(id) = variable.definition else { return };
+                let info = &self.cache.definition_infos[id.0];
+
+                if let Some(typ) = &info.typ {
+                    let typ = typ.remove_forall();
+                    if !info.global && self.is_linear(typ) {
+                        self.linear_variables.push((id, typ.clone()));
+                    }
+                }
+            },
+            ast::Ast::FunctionCall(call) => {
+                for arg in &call.args {
+                    self.define_pattern(arg);
+                }
+            },
+            ast::Ast::TypeAnnotation(annotation) => self.define_pattern(&annotation.lhs),
+            _ => (),
+        }
+    }
+
+    fn is_linear(&self, typ: &Type) -> bool {
+        match self.cache.follow_bindings_shallow(typ) {
+            Type::UserDefined(id) => self.cache.type_infos[id.0].linear,
+            Type::TypeApplication(constructor, _) => self.is_linear(constructor),
+            _ => false,
+        }
+    }
+
+    /// Count the uses within each branch separately, keeping only the largest count of each variable.
+    /// Each branch may have a pattern defining variables which are only visible within that branch.
+    /// Any variable defined before the branches which is not used the same number of times in each
+    /// is remembered in `mismatched_branches`.
+    fn count_branches<'a>(
+        &mut self, branches: impl Iterator<Item = (Option<&'a ast::Ast<'cache>>, &'a ast::Ast<'cache>)>,
+        location: Location<'cache>,
+    ) where
+        'cache: 'a,
+    {
+        let existing_variables = self.linear_variables.len();
+        let before = self.uses.clone();
+        let mut merged = before.clone();
+        let mut branch_counts = Vec::new();
+
+        for (pattern, branch) in branches {
+            if let Some(pattern) = pattern {
+                self.define_pattern(pattern);
+            }
+
+            self.uses = before.clone();
+            branch.count_uses(self);
+
+            let counts = self.linear_variables[..existing_variables]
+                .iter()
+                .map(|(id, _)| self.uses.get(id).map_or(0, |(count, _)| *count))
+                .collect::<Vec<_>>();
+            branch_counts.push(counts);
+
+            for (id, (count, location)) in std::mem::take(&mut self.uses) {
+                let existing = merged.entry(id).or_insert((count, location));
+                if count > existing.0 {
+                    *existing = (count, location);
+                }
+            }
+        }
+
+        self.uses = merged;
+
+        for (index, (id, _)) in self.linear_variables[..existing_variables].iter().enumerate() {
+            if branch_counts.iter().any(|counts| counts[index] != branch_counts[0][index]) {
+                self.mismatched_branches.entry(*id).or_insert(location);
+            }
+        }
+    }
+}
+
+trait CountUses<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>);
+}
+
+impl<'c> CountUses<'c> for ast::Ast<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        dispatch_on_expr!(self, CountUses::count_uses, ctx)
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Literal<'c> {
+    fn count_uses(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Variable<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        let Some(mut id) = self.definition else { return };
+
+        // The closure or loop this is used within may be run any number of times
+        let captured = ctx.captures.contains_key(&id);
+        while let Some(captured_id) = ctx.captures.get(&id) {
+            id = *captured_id;
+        }
+
+        if ctx.linear_variables.iter().any(|(linear_id, _)| *linear_id == id) {
+            let count = ctx.uses.get(&id).map_or(0, |(count, _)| *count);
+            if count != MANY {
+                let count = if captured { MANY } else { count + 1 };
+                ctx.uses.insert(id, (count, self.location));
+            }
+        }
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Lambda<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        for (captured_id, (_, closure_id, _)) in &self.closure_environment {
+            ctx.captures.insert(*closure_id, *captured_id);
+        }
+        for parameter in &self.args {
+            ctx.define_pattern(parameter);
+        }
+        self.body.count_uses(ctx);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::FunctionCall<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.function.count_uses(ctx);
+        for arg in &self.args {
+            arg.count_uses(ctx);
+        }
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Definition<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        ctx.define_pattern(&self.pattern);
+        self.expr.count_uses(ctx);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::If<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.condition.count_uses(ctx);
+        let branches = [(None, self.then.as_ref()), (None, self.otherwise.as_ref())];
+        ctx.count_branches(branches.into_iter(), self.location);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Match<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.expression.count_uses(ctx);
+
+        let branches = self.branches.iter().map(|(pattern, branch)| (Some(pattern), branch));
+        ctx.count_branches(branches, self.location);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::TypeDefinition<'c> {
+    fn count_uses(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CountUses<'c> for ast::TypeAnnotation<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.lhs.count_uses(ctx);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Import<'c> {
+    fn count_uses(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CountUses<'c> for ast::TraitDefinition<'c> {
+    fn count_uses(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CountUses<'c> for ast::TraitImpl<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        for definition in self.definitions.iter().chain(&self.helpers) {
+            definition.count_uses(ctx);
+        }
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Return<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.expression.count_uses(ctx);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Sequence<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        for statement in &self.statements {
+            statement.count_uses(ctx);
+        }
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Extern<'c> {
+    fn count_uses(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CountUses<'c> for ast::MemberAccess<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.lhs.count_uses(ctx);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Assignment<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.lhs.count_uses(ctx);
+        self.rhs.count_uses(ctx);
+        if let Some(index_assign) = &self.index_assign {
+            index_assign.count_uses(ctx);
+        }
+    }
+}
+
+impl<'c> CountUses<'c> for ast::EffectDefinition<'c> {
+    fn count_uses(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Handle<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.expression.count_uses(ctx);
+
+        let branches = self.branches.iter().map(|(pattern, branch)| (Some(pattern), branch));
+        ctx.count_branches(branches, self.location);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::NamedConstructor<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        if let Some(record) = &self.record {
+            record.count_uses(ctx);
+        }
+        self.sequence.count_uses(ctx);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Reference<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.expression.count_uses(ctx);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::ConditionalCompilation<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.active_branch().count_uses(ctx);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Spread<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        self.expression.count_uses(ctx);
+    }
+}
+
+impl<'c> CountUses<'c> for ast::ArrayLiteral<'c> {
+    fn count_uses(&self, ctx: &mut Context<'_, 'c>) {
+        for element in &self.elements {
+            element.count_uses(ctx);
+        }
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Hole<'c> {
+    fn count_uses(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
+
+impl<'c> CountUses<'c> for ast::Error<'c> {
+    fn count_uses(&self, _ctx: &mut Context<'_, 'c>) {
+        // Nothing to do
+    }
+}
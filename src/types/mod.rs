@@ -9,7 +9,7 @@ use std::rc::Rc;
 
 use effects::Effect;
 
-use crate::cache::{DefinitionInfoId, ModuleCache};
+use crate::cache::{DefinitionInfoId, ModuleCache, TraitInfoId};
 use crate::error::location::{Locatable, Location};
 use crate::lexer::token::{FloatKind, IntegerKind};
 use crate::util;
@@ -19,6 +19,8 @@ use self::typeprinter::TypePrinter;
 use crate::types::effects::EffectSet;
 
 pub mod effects;
+pub mod inlay_hints;
+mod linearity;
 mod mutual_recursion;
 pub mod pattern;
 pub mod traitchecker;
@@ -138,8 +140,14 @@ pub enum Type {
     /// A (row-polymorphic) struct type. Unlike normal rho variables,
     /// the type variable used here replaces the entire type if bound.
     /// This makes it so we don't have to remember previous types to combine
-    /// when traversing bindings.
-    Struct(BTreeMap<String, Type>, TypeVariableId),
+    /// when traversing bindings. Closed struct types are never bound this way.
+    Struct(BTreeMap<String, Type>, TypeVariableId, RowKind),
+
+    /// An equi-recursive type `rec a. T` which is equal to its unfolding, T with each
+    /// occurrence of `a` replaced by the whole `rec a. T` type. These are created when
+    /// unifying a type variable with a type containing it, e.g. `a := a -> b` yields
+    /// `rec a. a -> b`. The binding type variable is never bound itself.
+    Rec(TypeVariableId, Box<Type>),
 
     /// Effects are not the same kind (*) as most Type variants, but
     /// are included in it since they are still valid in a type position
@@ -150,6 +158,25 @@ pub enum Type {
     /// a larger type. For example, `shared` is not a type, but a polymorphic
     /// reference's type variable may resolve to a shared reference.
     Tag(TypeTag),
+
+    /// The type of types at the given level. Ordinary types such as `I32` live in
+    /// `Universe 0`, `Universe 0` itself lives in `Universe 1`, and so on. A type may
+    /// never be placed in its own universe since that would allow `Type : Type` paradoxes.
+    Universe(u32),
+}
+
+/// Whether a struct type may still gain fields when unified with another struct type
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum RowKind {
+    /// A struct type inferred from using a value's fields, e.g. from `bar.x`.
+    /// The value may have more fields than these, and its row variable
+    /// is bound once the full type is known.
+    Open,
+
+    /// The type of an anonymous record literal, e.g. `with x = 1, y = 2`.
+    /// These have exactly the given fields and are distinct from any
+    /// nominal struct type with the same fields.
+    Closed,
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -161,6 +188,19 @@ pub enum TypeTag {
     Shared,
     Mutable,
     Immutable,
+
+    // Natural numbers may be used as type arguments, e.g. `Array 5 I32`.
+    // Closed arithmetic on them is represented by applying one of the operator
+    // tags below to two arguments, e.g. `Array (2 + 3) I32`, and is evaluated
+    // during unification.
+    Nat(u64),
+    Add,
+    Subtract,
+    Multiply,
+
+    // A trait object `dyn Trait` standing for some unknown type implementing the trait.
+    // Values are converted to one explicitly via `x as dyn Trait`.
+    Dyn(TraitInfoId),
 }
 
 #[derive(Debug, Clone)]
@@ -221,6 +261,11 @@ impl Type {
         matches!(self, Type::Ref { .. })
     }
 
+    /// True if this is one of the arithmetic operators on type-level natural numbers
+    pub fn is_nat_operator(&self) -> bool {
+        matches!(self, Type::Tag(TypeTag::Add | TypeTag::Subtract | TypeTag::Multiply))
+    }
+
     pub fn is_unit(&self, cache: &ModuleCache<'_>) -> bool {
         match self {
             Type::Primitive(PrimitiveType::UnitType) => true,
@@ -249,21 +294,24 @@ impl Type {
             UserDefined(id) => cache.type_infos[id.0].union_variants(),
             TypeVariable(_) => unreachable!("Constructors should always have concrete types"),
             NamedGeneric(..) => None,
-            Struct(_, _) => None,
+            Struct(..) => None,
+            Rec(..) => None,
             Effects(_) => None,
             Tag(_) => None,
+            Universe(_) => None,
         }
     }
 
     pub fn priority(&self, cache: &ModuleCache<'_>) -> TypePriority {
         use Type::*;
         match self {
-            Primitive(_) | UserDefined(_) | Struct(_, _) | Tag(_) => TypePriority::MAX,
+            Tag(TypeTag::Dyn(_)) => TypePriority::APP,
+            Primitive(_) | UserDefined(_) | Struct(..) | Tag(_) => TypePriority::MAX,
             TypeVariable(id) => match &cache.type_bindings[id.0] {
                 TypeBinding::Bound(typ) => typ.priority(cache),
                 TypeBinding::Unbound(..) => TypePriority::MAX,
             },
-            Function(_) => TypePriority::FUN,
+            Function(_) | Rec(..) => TypePriority::FUN,
             TypeApplication(ctor, args) if ctor.is_polymorphic_int_type() || ctor.is_polymorphic_float_type() => {
                 if matches!(cache.follow_bindings_shallow(&args[0]), Type::TypeVariable(_)) {
                     // type variable is unbound variable
@@ -282,6 +330,7 @@ impl Type {
             },
             NamedGeneric(..) => TypePriority::MAX,
             Ref { .. } => TypePriority::APP,
+            Universe(_) => TypePriority::APP,
             Effects(_) => unimplemented!("Type::priority for Effects"),
         }
     }
@@ -292,6 +341,24 @@ impl Type {
         TypePrinter::display_type(typ, cache)
     }
 
+    /// Like display, but name each typevar the same as it would be named when displaying
+    /// `context`. Used to show part of a type consistently with the type as a whole.
+    pub fn display_within<'a, 'b>(
+        &self, context: &Type, cache: &'a ModuleCache<'b>,
+    ) -> typeprinter::TypePrinter<'a, 'b> {
+        TypePrinter::display_type_within(self.clone(), context, cache)
+    }
+
+    /// Display a generalized type with its `forall` quantifiers, followed by any trait
+    /// constraints after a `given` keyword. Type variables are named a, b, c, etc. in order
+    /// of appearance so the same type and constraints always display identically.
+    #[allow(dead_code)]
+    pub fn display_generalized(
+        typ: &GeneralizedType, constraints: &[traits::TraitConstraint], cache: &ModuleCache<'_>,
+    ) -> String {
+        typeprinter::show_generalized_type(typ, constraints, cache)
+    }
+
     /// Like display but show the real unique TypeVariableId for each typevar instead
     #[allow(dead_code)]
     pub fn debug<'a, 'b>(&self, cache: &'a ModuleCache<'b>) -> typeprinter::TypePrinter<'a, 'b> {
@@ -311,6 +378,7 @@ impl Type {
             Type::UserDefined(_) => (),
             Type::Tag(_) => (),
             Type::NamedGeneric(..) => (),
+            Type::Universe(_) => (),
 
             Type::Function(function) => {
                 for parameter in &function.parameters {
@@ -344,7 +412,7 @@ impl Type {
                     Type::TypeVariable(extension).traverse_rec(cache, f);
                 }
             },
-            Type::Struct(fields, id) => {
+            Type::Struct(fields, id, _) => {
                 if let TypeBinding::Bound(binding) = &cache.type_bindings[id.0] {
                     return binding.traverse_rec(cache, f);
                 }
@@ -352,6 +420,7 @@ impl Type {
                     typ.traverse_rec(cache, f);
                 }
             },
+            Type::Rec(_, body) => body.traverse_rec(cache, f),
         }
     }
 
@@ -367,6 +436,7 @@ impl Type {
             Type::UserDefined(_) => (),
             Type::TypeVariable(_) => (),
             Type::Tag(_) => (),
+            Type::Universe(_) => (),
             Type::NamedGeneric(..) => (),
 
             Type::Function(function) => {
@@ -390,11 +460,12 @@ impl Type {
                     }
                 }
             },
-            Type::Struct(fields, _) => {
+            Type::Struct(fields, _, _) => {
                 for typ in fields.values() {
                     typ.traverse_no_follow_rec(f);
                 }
             },
+            Type::Rec(_, body) => body.traverse_no_follow_rec(f),
             Type::Ref { sharedness, mutability, lifetime: _ } => {
                 sharedness.traverse_no_follow_rec(f);
                 mutability.traverse_no_follow_rec(f);
@@ -402,6 +473,58 @@ impl Type {
         }
     }
 
+    /// Like traverse_no_follow, but allows each type to be rewritten in place.
+    /// `f` is applied to a type before its children, so any children it
+    /// produces are visited as well.
+    pub fn walk_mut(&mut self, mut f: impl FnMut(&mut Type)) {
+        self.walk_mut_rec(&mut f)
+    }
+
+    fn walk_mut_rec(&mut self, f: &mut impl FnMut(&mut Type)) {
+        f(self);
+        match self {
+            Type::Primitive(_) => (),
+            Type::UserDefined(_) => (),
+            Type::TypeVariable(_) => (),
+            Type::Tag(_) => (),
+            Type::Universe(_) => (),
+            Type::NamedGeneric(..) => (),
+
+            Type::Function(function) => {
+                for parameter in &mut function.parameters {
+                    parameter.walk_mut_rec(f)
+                }
+                function.environment.walk_mut_rec(f);
+                function.return_type.walk_mut_rec(f);
+                function.effects.walk_mut_rec(f);
+            },
+            Type::TypeApplication(constructor, args) => {
+                constructor.walk_mut_rec(f);
+                for arg in args {
+                    arg.walk_mut_rec(f);
+                }
+            },
+            Type::Effects(effects) => {
+                for (_, effect_args) in &mut effects.effects {
+                    for arg in effect_args {
+                        arg.walk_mut_rec(f);
+                    }
+                }
+            },
+            Type::Struct(fields, _, _) => {
+                for typ in fields.values_mut() {
+                    typ.walk_mut_rec(f);
+                }
+            },
+            Type::Rec(_, body) => body.walk_mut_rec(f),
+            Type::Ref { sharedness, mutability, lifetime } => {
+                sharedness.walk_mut_rec(f);
+                mutability.walk_mut_rec(f);
+                lifetime.walk_mut_rec(f);
+            },
+        }
+    }
+
     /// Try to create a string from this type without following any type variables
     /// or referencing any names of UserDefined types (as both of these would require a ModuleCache).
     /// This should be used for debugging only when you have no access to a ModuleCache
@@ -429,10 +552,14 @@ impl Type {
                 let lifetime = lifetime.approx_to_string();
                 format!("{}{} '{}", mutable, shared, lifetime)
             },
-            Type::Struct(fields, id) => {
+            Type::Struct(fields, id, kind) => {
                 let fields = fmap(fields, |(name, typ)| format!("{}: {}", name, typ.approx_to_string()));
-                format!("{{ {}, ..tv{} }}", fields.join(", "), id.0)
+                match kind {
+                    RowKind::Open => format!("{{ {}, ..tv{} }}", fields.join(", "), id.0),
+                    RowKind::Closed => format!("{{ {} }}", fields.join(", ")),
+                }
             },
+            Type::Rec(binder, body) => format!("(rec tv{}. {})", binder.0, body.approx_to_string()),
             Type::Effects(set) => {
                 if set.effects.is_empty() {
                     if let Some(extension) = set.extension {
@@ -454,6 +581,7 @@ impl Type {
             },
             Type::NamedGeneric(_, name) => name.to_string(),
             Type::Tag(tag) => tag.to_string(),
+            Type::Universe(level) => format!("Universe {level}"),
         }
     }
 
@@ -485,6 +613,11 @@ impl std::fmt::Display for TypeTag {
             TypeTag::Shared => write!(f, "shared"),
             TypeTag::Mutable => write!(f, "!"),
             TypeTag::Immutable => write!(f, "&"),
+            TypeTag::Nat(n) => write!(f, "{n}"),
+            TypeTag::Add => write!(f, "+"),
+            TypeTag::Subtract => write!(f, "-"),
+            TypeTag::Multiply => write!(f, "*"),
+            TypeTag::Dyn(id) => write!(f, "dyn t{}", id.0),
         }
     }
 }
@@ -567,7 +700,7 @@ pub const INITIAL_LEVEL: usize = 1;
 /// or is unbound and has a given LetBindingLevel as its lifetime.
 /// This LetBindingLevel is used to determine which type variables
 /// can be generalized.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum TypeBinding {
     Bound(Type),
     Unbound(LetBindingLevel, Kind),
@@ -605,6 +738,10 @@ pub const STRING_TYPE: TypeInfoId = TypeInfoId(0);
 /// an operator for its name, but it is otherwise a normal struct type.
 pub const PAIR_TYPE: TypeInfoId = TypeInfoId(1);
 
+/// The array type is builtin so that array literals like `[| 1, 2, 3 |]`
+/// can be given a type without the prelude needing to define one first.
+pub const ARRAY_TYPE: TypeInfoId = TypeInfoId(2);
+
 #[derive(Debug)]
 pub enum TypeInfoBody<'a> {
     Union(Vec<TypeConstructor<'a>>),
@@ -621,6 +758,9 @@ pub struct TypeInfo<'a> {
     pub body: TypeInfoBody<'a>,
     pub uses: u32,
     pub location: Location<'a>,
+
+    /// True if this type was declared `linear`, requiring each of its values to be used exactly once
+    pub linear: bool,
 }
 
 impl<'a> Locatable<'a> for TypeInfo<'a> {
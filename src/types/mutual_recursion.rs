@@ -8,7 +8,7 @@ use crate::{
     parser::ast,
     types::{
         traitchecker,
-        typechecker::{bind_irrefutable_pattern, find_all_typevars},
+        typechecker::{bind_irrefutable_pattern, find_all_typevars, follow_bindings_in_cache, reference_parts},
         typed::Typed,
     },
     util::trustme,
@@ -23,9 +23,13 @@ pub(super) fn try_generalize_definition<'c>(
     definition: &mut ast::Definition<'c>, t: Type, traits: TraitConstraints, cache: &mut ModuleCache<'c>,
 ) -> TraitConstraints {
     if !should_generalize(&definition.expr, cache) {
+        check_value_restriction(definition, &t, cache);
         return traits;
     }
 
+    // Fundeps determined by an impl should be part of the generalized type rather than a constraint on it
+    traitchecker::improve_functional_dependencies(&traits, cache);
+
     let pattern = definition.pattern.as_mut();
     match is_mutually_recursive(pattern, cache) {
         MutualRecursionResult::No => {
@@ -113,6 +117,33 @@ pub(super) fn should_generalize(ast: &ast::Ast, cache: &ModuleCache) -> bool {
     }
 }
 
+/// Issue a note if the given definition is a function which would have been
+/// generalized if it were not for its rhs not being a lambda, e.g. `f = id id`.
+fn check_value_restriction<'c>(definition: &ast::Definition<'c>, t: &Type, cache: &mut ModuleCache<'c>) {
+    if matches!(definition.expr.as_ref(), ast::Ast::Variable(_) | ast::Ast::Lambda(_)) {
+        return;
+    }
+
+    // A reference is only explained once it is used at two different types, see `issue_argument_types_error`
+    if let Some((_, element)) = reference_parts(t, cache) {
+        if let ast::Ast::Variable(variable) = definition.pattern.as_ref() {
+            if !find_all_typevars(&element, true, cache).is_empty() {
+                cache.monomorphic_references.insert(variable.definition.unwrap());
+            }
+        }
+        return;
+    }
+
+    if let Type::Function(function) = follow_bindings_in_cache(t, cache) {
+        let mut types = function.parameters.iter().chain(std::iter::once(function.return_type.as_ref()));
+
+        if types.any(|typ| !find_all_typevars(typ, true, cache).is_empty()) {
+            let name = definition.pattern.to_string();
+            cache.push_diagnostic(definition.location, D::MonomorphicDueToValueRestriction(name));
+        }
+    }
+}
+
 enum MutualRecursionResult {
     No,
     YesGeneralizeLater,
@@ -16,32 +16,68 @@ use crate::error::{DiagnosticKind as D, TypeErrorKind as TE};
 use crate::lexer::token::Token;
 use crate::parser::ast::{self, Ast, LiteralKind};
 use crate::types::pattern::Constructor::*;
-use crate::types::{typechecker, PrimitiveType, Type, TypeInfoBody, TypeInfoId, STRING_TYPE};
+use crate::types::typed::Typed;
+use crate::types::{typechecker, FunctionType, PrimitiveType, Type, TypeInfoBody, TypeInfoId, STRING_TYPE};
 use crate::util::{fmap, join_with, unwrap_clone};
 
 use std::collections::{BTreeMap, BTreeSet};
 
 use super::GeneralizedType;
 
+/// The result of compiling a match expression with `compile`
+pub struct CompiledMatch {
+    pub tree: DecisionTree,
+
+    /// Each variable bound by reference (see `matches_through_reference`) paired
+    /// with the pattern variable holding the value it refers to.
+    pub reference_bindings: Vec<(DefinitionInfoId, DefinitionInfoId)>,
+
+    /// An example of each value no branch matches, e.g. `Some None`.
+    /// This is empty if the match is exhaustive.
+    pub missing_cases: Vec<String>,
+
+    /// The index of each branch whose pattern can never be matched
+    pub unreachable_branches: Vec<usize>,
+}
+
 /// Compiles the given match_expr to a DecisionTree, doing
 /// completeness and redundancy checking in the process.
-pub fn compile<'c>(match_expr: &ast::Match<'c>, cache: &mut ModuleCache<'c>) -> DecisionTree {
-    let mut matrix = PatternMatrix::from_ast(match_expr, cache, match_expr.location);
+/// Reporting any missing cases or unreachable branches is left to the caller.
+pub fn compile<'c>(match_expr: &ast::Match<'c>, cache: &mut ModuleCache<'c>) -> CompiledMatch {
+    let mut reference_bindings = Vec::new();
+    let mut matrix = PatternMatrix::from_ast(match_expr, &mut reference_bindings, cache, match_expr.location);
     let result = matrix.compile(cache, match_expr.location);
 
-    if result.context.reachable_branches.len() != match_expr.branches.len() {
-        for (i, (pattern, _branch)) in match_expr.branches.iter().enumerate() {
-            if !result.context.reachable_branches.contains(&i) {
-                cache.push_diagnostic(pattern.locate(), D::UnreachablePattern);
-            }
-        }
-    }
+    let unreachable_branches =
+        (0..match_expr.branches.len()).filter(|i| !result.context.reachable_branches.contains(i)).collect();
+
+    let missing_cases = if result.context.missed_case_count != 0 { result.missing_cases(cache) } else { vec![] };
+
+    CompiledMatch { tree: result.tree, reference_bindings, missing_cases, unreachable_branches }
+}
 
-    if result.context.missed_case_count != 0 {
-        result.issue_inexhaustive_errors(cache, match_expr.location);
+/// True if this pattern should match the value behind a reference when the value being
+/// matched on is a reference. This is any pattern besides an explicit `&p` or `!p` pattern
+/// and lone variables, which bind the reference itself.
+pub fn matches_through_reference(pattern: &Ast) -> bool {
+    match pattern {
+        Ast::Reference(_) => false,
+        Ast::Variable(variable) => matches!(variable.kind, ast::VariableKind::TypeConstructor(_)),
+        _ => true,
     }
+}
 
-    result.tree
+/// Returns each variable a pattern matched through a reference binds by reference.
+/// Variables within a nested `&p` or `!p` pattern are bound by value as usual.
+pub fn variables_bound_by_reference(pattern: &Ast) -> Vec<DefinitionInfoId> {
+    match pattern {
+        Ast::Variable(variable) => match variable.kind {
+            ast::VariableKind::TypeConstructor(_) => vec![],
+            _ => variable.definition.into_iter().collect(),
+        },
+        Ast::FunctionCall(call) => call.args.iter().flat_map(variables_bound_by_reference).collect(),
+        _ => vec![],
+    }
 }
 
 /// Represents the type of tag value of a matched-upon value. For example,
@@ -56,6 +92,10 @@ pub enum VariantTag {
     Unit,
     UserDefined(DefinitionInfoId),
 
+    /// A reference pattern `&p` or `!p`. This has a single field: the value behind the reference.
+    /// Since references have only one constructor, matching on one is always exhaustive.
+    Reference,
+
     /// This tag signals pattern matching should give up completeness checking
     /// for this constructor. Integers and floats are most notably translated to
     /// this rather than attempting to approximate the types' full ranges.
@@ -168,19 +208,32 @@ impl PatternStack {
         self.0.len()
     }
 
-    /// Converts a given pattern of the match expression into a PatternStack
-    fn from_ast<'c>(ast: &Ast<'c>, cache: &mut ModuleCache<'c>, location: Location<'c>) -> PatternStack {
+    /// Converts a given pattern of the match expression into a PatternStack.
+    ///
+    /// If `reference_bindings` is given, the pattern is being matched through a reference so
+    /// each variable is bound to a fresh pattern variable instead. The variable is then
+    /// recorded alongside it so it can later be bound to the address of its value.
+    fn from_ast<'c>(
+        ast: &Ast<'c>, mut reference_bindings: Option<&mut Vec<(DefinitionInfoId, DefinitionInfoId)>>,
+        cache: &mut ModuleCache<'c>, location: Location<'c>,
+    ) -> PatternStack {
         match ast {
             Ast::Variable(variable) => {
                 use ast::VariableKind::TypeConstructor;
-                let constructor_and_id = match variable.kind {
-                    TypeConstructor(_) => {
+                let constructor_and_id = match (&variable.kind, reference_bindings) {
+                    (TypeConstructor(_), _) => {
                         let tag = VariantTag::UserDefined(variable.definition.unwrap());
                         let fields = PatternStack(vec![]);
                         let variable = new_pattern_variable(".from_ast.TypeConstructor", location, cache);
+                        set_matched_type(variable, ast, cache);
                         (Variant(tag, fields), variable)
                     },
-                    _ => {
+                    (_, Some(reference_bindings)) => {
+                        let id = new_pattern_variable(".from_ast.ReferenceBinding", location, cache);
+                        reference_bindings.push((variable.definition.unwrap(), id));
+                        (MatchAll(id), id)
+                    },
+                    (_, None) => {
                         let variable = variable.definition.unwrap();
                         (MatchAll(variable), variable)
                     },
@@ -207,14 +260,24 @@ impl PatternStack {
                 let variable = new_pattern_variable(".from_ast.Literal", location, cache);
                 PatternStack(vec![(Variant(tag, fields), variable)])
             },
+            Ast::Reference(reference) => {
+                let fields = PatternStack::from_ast(&reference.expression, None, cache, location);
+                let variable = new_pattern_variable(".from_ast.Reference", location, cache);
+                PatternStack(vec![(Variant(VariantTag::Reference, fields), variable)])
+            },
             Ast::FunctionCall(call) => match call.function.as_ref() {
                 Ast::Variable(variable) => {
                     let tag = VariantTag::UserDefined(variable.definition.unwrap());
-                    let fields =
-                        call.args.iter().rev().flat_map(|arg| PatternStack::from_ast(arg, cache, location)).collect();
+                    let fields = call
+                        .args
+                        .iter()
+                        .rev()
+                        .flat_map(|arg| PatternStack::from_ast(arg, reference_bindings.as_deref_mut(), cache, location))
+                        .collect();
 
                     let fields = PatternStack(fields);
                     let variable = new_pattern_variable(".from_ast.FunctionCall", location, cache);
+                    set_matched_type(variable, ast, cache);
                     PatternStack(vec![(Variant(tag, fields), variable)])
                 },
                 _ => {
@@ -272,6 +335,16 @@ fn new_pattern_variable<'c>(name: &str, location: Location<'c>, cache: &mut Modu
     id
 }
 
+/// Remember the type a constructor pattern was inferred to match on. This is needed to exclude any
+/// constructors whose declared result type makes them impossible when checking exhaustiveness.
+fn set_matched_type(variable: DefinitionInfoId, pattern: &Ast, cache: &mut ModuleCache) {
+    cache.definition_infos[variable.0].typ = pattern.get_type().cloned().map(GeneralizedType::MonoType);
+}
+
+fn matched_type(variable: DefinitionInfoId, cache: &ModuleCache) -> Option<Type> {
+    cache.definition_infos[variable.0].typ.as_ref().map(|typ| typ.as_monotype().clone())
+}
+
 fn get_type_info_id(typ: &Type) -> TypeInfoId {
     match typ {
         Type::UserDefined(id) => *id,
@@ -310,7 +383,7 @@ fn get_missing_builtin_cases<T>(variants: &BTreeMap<&VariantTag, T>) -> Option<B
     match (first, second) {
         (Some(True), second) => insert_if(missing_cases, False, second != Some(&False)),
         (Some(False), second) => insert_if(missing_cases, True, second != Some(&True)),
-        (Some(Unit), _) => Some(missing_cases),
+        (Some(Unit | Reference), _) => Some(missing_cases),
         // Literals always require a match-all, so a missing case is always inserted here.
         (Some(Literal(literal)), _) => insert_if(missing_cases, Literal(literal.clone()), true),
         _ => None,
@@ -321,9 +394,29 @@ fn get_covered_constructors<T>(variants: &BTreeMap<&VariantTag, T>) -> BTreeSet<
     variants.iter().map(|(tag, _)| (*tag).clone()).collect()
 }
 
-/// Given a hashmap from variant tag -> arity,
-/// return true if the hashmap covers all constructors for its type.
-fn get_missing_cases<T>(variants: &BTreeMap<&VariantTag, T>, cache: &ModuleCache<'_>) -> BTreeSet<VariantTag> {
+/// Returns true if the given constructor may construct a value of the given type. This is
+/// only false for a variant whose declared result type is incompatible, e.g. a variant
+/// declared as `| IntLit I32 : Expr I32` when matching on an `Expr Bool`.
+fn constructor_is_possible(constructor: DefinitionInfoId, matched_type: Option<&Type>, cache: &ModuleCache) -> bool {
+    let (Some(matched_type), Some(constructor_type)) = (matched_type, &cache.definition_infos[constructor.0].typ)
+    else {
+        return true;
+    };
+
+    let result_type = match constructor_type.remove_forall() {
+        Type::Function(function) => function.return_type.as_ref(),
+        other => other,
+    };
+
+    let mut bindings = typechecker::UnificationBindings::empty();
+    typechecker::types_could_unify(result_type, matched_type, &mut bindings, cache)
+}
+
+/// Given a hashmap from variant tag -> arity, return each constructor of the matched type which
+/// is not covered. Constructors which can never construct the matched type are never missing.
+fn get_missing_cases<T>(
+    variants: &BTreeMap<&VariantTag, T>, matched_type: Option<&Type>, cache: &ModuleCache<'_>,
+) -> BTreeSet<VariantTag> {
     use VariantTag::*;
 
     if let Some(result) = get_missing_builtin_cases(variants) {
@@ -331,7 +424,7 @@ fn get_missing_cases<T>(variants: &BTreeMap<&VariantTag, T>, cache: &ModuleCache
     }
 
     match variants.iter().next().map(|(tag, _)| *tag).unwrap() {
-        True | False | Unit | Literal(_) => {
+        True | False | Unit | Reference | Literal(_) => {
             unreachable!("Found builtin constructor not covered by builtin_is_exhastive")
         },
 
@@ -339,8 +432,11 @@ fn get_missing_cases<T>(variants: &BTreeMap<&VariantTag, T>, cache: &ModuleCache
             let type_id = get_variant_type_from_constructor(*id, cache);
             match &cache.type_infos[type_id.0].body {
                 TypeInfoBody::Union(constructors) => {
-                    let all_constructors: BTreeSet<_> =
-                        constructors.iter().map(|constructor| VariantTag::UserDefined(constructor.id)).collect();
+                    let all_constructors: BTreeSet<_> = constructors
+                        .iter()
+                        .filter(|constructor| constructor_is_possible(constructor.id, matched_type, cache))
+                        .map(|constructor| VariantTag::UserDefined(constructor.id))
+                        .collect();
                     let covered_constructors = get_covered_constructors(variants);
                     all_constructors.difference(&covered_constructors).cloned().collect()
                 },
@@ -389,12 +485,29 @@ struct PatternMatrix {
 }
 
 impl PatternMatrix {
-    fn from_ast<'c>(match_expr: &ast::Match<'c>, cache: &mut ModuleCache<'c>, location: Location<'c>) -> PatternMatrix {
+    /// Patterns matching through a reference are wrapped in an implicit reference pattern,
+    /// with each variable they bind recorded in `reference_bindings`.
+    fn from_ast<'c>(
+        match_expr: &ast::Match<'c>, reference_bindings: &mut Vec<(DefinitionInfoId, DefinitionInfoId)>,
+        cache: &mut ModuleCache<'c>, location: Location<'c>,
+    ) -> PatternMatrix {
+        let matched_type = match_expr.expression.get_type().unwrap();
+        let through_reference = typechecker::reference_parts(matched_type, cache).is_some();
+
         let rows = match_expr
             .branches
             .iter()
             .enumerate()
-            .map(|(branch_index, (pattern, _))| (PatternStack::from_ast(pattern, cache, location), branch_index))
+            .map(|(branch_index, (pattern, _))| {
+                let stack = if through_reference && matches_through_reference(pattern) {
+                    let fields = PatternStack::from_ast(pattern, Some(reference_bindings), cache, location);
+                    let variable = new_pattern_variable(".from_ast.ImplicitReference", location, cache);
+                    PatternStack(vec![(Variant(VariantTag::Reference, fields), variable)])
+                } else {
+                    PatternStack::from_ast(pattern, None, cache, location)
+                };
+                (stack, branch_index)
+            })
             .collect();
 
         PatternMatrix { rows }
@@ -472,16 +585,18 @@ impl PatternMatrix {
         // Generate the set of constructors appearing in the column
         let mut matched_variants: BTreeMap<_, Vec<_>> = BTreeMap::new();
         let mut switching_on = None;
+        let mut typ = None;
 
         for (row, _) in self.rows.iter() {
             if let Some((Variant(tag, fields), var)) = row.head() {
                 switching_on = Some(*var);
+                typ = typ.or_else(|| matched_type(*var, cache));
 
                 matched_variants.entry(tag).or_default().push(fields);
             }
         }
 
-        let missed_cases = get_missing_cases(&matched_variants, cache);
+        let missed_cases = get_missing_cases(&matched_variants, typ.as_ref(), cache);
         let mut context = DecisionTreeContext::default();
 
         let mut cases: Vec<_> = matched_variants
@@ -504,6 +619,12 @@ impl PatternMatrix {
         if !missed_cases.is_empty() {
             let (branch, fields) = self.default_specialize(cache, location);
             switching_on = fields.get(0).copied().or(switching_on);
+
+            // The missing cases are found again from the type of the variable switched on
+            let switching_on = switching_on.unwrap();
+            if cache.definition_infos[switching_on.0].typ.is_none() {
+                cache.definition_infos[switching_on.0].typ = typ.map(GeneralizedType::MonoType);
+            }
             cases.push(Case { tag: None, fields: vec![fields], branch: context.merge(branch) });
         }
 
@@ -619,42 +740,47 @@ impl DecisionTreeResult {
         DecisionTreeResult::new(DecisionTree::Leaf(branch), context)
     }
 
-    fn issue_inexhaustive_errors<'c>(&self, cache: &mut ModuleCache<'c>, location: Location<'c>) {
+    fn missing_cases(&self, cache: &ModuleCache) -> Vec<String> {
         let mut bindings = BTreeMap::new();
-        DecisionTreeResult::issue_inexhaustive_errors_helper(&self.tree, None, &mut bindings, cache, location);
+        let mut missing_cases = Vec::new();
+        DecisionTreeResult::missing_cases_helper(&self.tree, None, &mut bindings, &mut missing_cases, cache);
+        missing_cases
     }
 
     /// Recurses the DecisionTree, searching for Fail nodes and reconstructing the data as it goes.
     /// When this hits a Fail node, the reconstructed piece of data will be a missing case.
-    fn issue_inexhaustive_errors_helper<'c>(
+    fn missing_cases_helper(
         tree: &DecisionTree, starting_id: Option<DefinitionInfoId>, bindings: &mut DebugMatchBindings,
-        cache: &mut ModuleCache<'c>, location: Location<'c>,
+        missing_cases: &mut Vec<String>, cache: &ModuleCache,
     ) {
         use DecisionTree::*;
         match tree {
             Leaf(_) => (),
             Fail => unreachable!("DecisionTree::Fail case should be matched on within DecisionTree::Switch"),
             Switch(id, cases) => {
+                let starting_id = starting_id.unwrap_or(*id);
+
                 for case in cases.iter() {
                     match &case.branch {
                         Fail => {
                             let covered_cases =
                                 cases.iter().filter_map(|case| case.tag.as_ref()).map(|tag| (tag, ())).collect();
 
-                            for tag in get_missing_cases(&covered_cases, cache) {
-                                bindings.insert(*id, DebugConstructor::new(&Some(tag), cache));
-                                DecisionTreeResult::issue_inexhaustive_error(starting_id, bindings, location, cache);
+                            let typ = matched_type(*id, cache);
+                            for tag in get_missing_cases(&covered_cases, typ.as_ref(), cache) {
+                                bindings.insert(*id, DebugConstructor::missing(tag, cache));
+                                let case = DecisionTreeResult::construct_missing_case_string(starting_id, bindings);
+                                missing_cases.push(case);
                             }
                         },
                         _ => {
                             bindings.insert(*id, DebugConstructor::from_case(case, cache));
-                            let starting_id = starting_id.or(Some(*id));
-                            DecisionTreeResult::issue_inexhaustive_errors_helper(
+                            DecisionTreeResult::missing_cases_helper(
                                 &case.branch,
-                                starting_id,
+                                Some(starting_id),
                                 bindings,
+                                missing_cases,
                                 cache,
-                                location,
                             );
                         },
                     }
@@ -664,16 +790,6 @@ impl DecisionTreeResult {
         }
     }
 
-    fn issue_inexhaustive_error<'a>(
-        starting_id: Option<DefinitionInfoId>, bindings: &DebugMatchBindings, location: Location<'a>,
-        cache: &mut ModuleCache<'a>,
-    ) {
-        let case =
-            starting_id.map_or("_".to_string(), |id| DecisionTreeResult::construct_missing_case_string(id, bindings));
-
-        cache.push_diagnostic(location, D::MissingCase(case));
-    }
-
     /// Construct the string representation of the data defined by the starting DefinitionInfoId
     /// and given DebugMatchBindings. This is recursive since the id may refer to a DebugConstructor
     /// which itself has more DefinitionInfoId fields that need to be converted to Strings.
@@ -683,6 +799,7 @@ impl DecisionTreeResult {
             Some(case) => {
                 let mut case_string = case.tag.clone();
                 let case_is_tuple = case.tag == Token::Comma.to_string();
+                let case_is_reference = case.tag == Token::Ampersand.to_string();
 
                 // Parenthesizes an argument string if it contains spaces and it's not a tuple field
                 let parenthesize = |field_string: String| {
@@ -709,7 +826,9 @@ impl DecisionTreeResult {
                         })
                         .collect();
 
-                    if !case_is_tuple {
+                    if case_is_reference {
+                        case_string = format!("&{}", join_with(&fields, " "));
+                    } else if !case_is_tuple {
                         if !fields.is_empty() {
                             case_string = format!("{} {}", case_string, join_with(&fields, " "));
                         } else {
@@ -794,12 +913,29 @@ impl DebugConstructor {
             Some(True) => "true".to_string(),
             Some(False) => "false".to_string(),
             Some(VariantTag::Unit) => "()".to_string(),
+            Some(Reference) => Token::Ampersand.to_string(),
             None => "_".to_string(),
         };
 
         DebugConstructor { tag, fields: vec![] }
     }
 
+    /// A constructor no branch matches. Each of its fields is shown as `_`, e.g. `Some _`.
+    fn missing(tag: VariantTag, cache: &ModuleCache<'_>) -> DebugConstructor {
+        let field_count = match &tag {
+            VariantTag::UserDefined(id) => match cache[*id].typ.as_ref().map(GeneralizedType::remove_forall) {
+                Some(Type::Function(function)) => function.parameters.len(),
+                _ => 0,
+            },
+            VariantTag::Reference => 1,
+            _ => 0,
+        };
+
+        let mut constructor = DebugConstructor::new(&Some(tag), cache);
+        constructor.fields = vec![vec![]; field_count];
+        constructor
+    }
+
     fn from_case(case: &Case, cache: &ModuleCache<'_>) -> DebugConstructor {
         let mut constructor = DebugConstructor::new(&case.tag, cache);
         constructor.fields = case.fields.clone();
@@ -926,6 +1062,23 @@ impl Case {
             Some(True) => Type::Primitive(PrimitiveType::BooleanType),
             Some(False) => Type::Primitive(PrimitiveType::BooleanType),
             Some(VariantTag::Unit) => Type::UNIT,
+            Some(Reference) => {
+                // Matching through either a mutable or immutable reference is allowed
+                let element = typechecker::next_type_variable(cache);
+                let reference = Type::Ref {
+                    mutability: Box::new(typechecker::next_type_variable(cache)),
+                    sharedness: Box::new(typechecker::next_type_variable(cache)),
+                    lifetime: Box::new(typechecker::next_type_variable(cache)),
+                };
+
+                Type::Function(FunctionType {
+                    parameters: vec![element.clone()],
+                    return_type: Box::new(Type::TypeApplication(Box::new(reference), vec![element])),
+                    environment: Box::new(Type::UNIT),
+                    effects: Box::new(typechecker::next_type_variable(cache)),
+                    has_varargs: false,
+                })
+            },
             None => expected_type.clone(),
         }
     }
@@ -19,22 +19,27 @@
 //! a compile-time error will be issued.
 use std::sync::atomic::AtomicBool;
 
-use crate::cache::{ImplInfoId, ModuleCache};
+use std::rc::Rc;
+
+use crate::cache::{ImplInfoId, ModuleCache, TraitInfoId};
 use crate::error::{DiagnosticKind as D, TypeErrorKind as TE};
 use crate::lexer::token::{FloatKind, IntegerKind};
-use crate::types::traits::{RequiredTrait, TraitConstraint, TraitConstraints};
+use crate::types::traits::{Callsite, ConstraintSignature, RequiredTrait, TraitConstraint, TraitConstraints};
 use crate::types::typechecker::{self, TypeBindings};
 use crate::types::TypeVariableId;
 use crate::util::{fmap, trustme};
 
 use super::typechecker::UnificationBindings;
-use super::{PrimitiveType, Type};
+use super::{PrimitiveType, Type, TypeTag};
 
 /// Arbitrary impl requirements can result in arbitrary recursion
 /// when attempting to solve impl constraints. To prevent infinitely
 /// recursing on bad inputs, a limit of 10 recursive calls is arbitrarily chosen.
 const RECURSION_LIMIT: u32 = 10;
 
+/// The maximum number of impls suggested when no impl matches a constraint
+const MAX_SUGGESTED_IMPLS: usize = 3;
+
 static RECURSION_WARNING_PRINTED: AtomicBool = AtomicBool::new(true);
 
 /// The type to default polymorphic integer literals to in the absense of other constraints.
@@ -132,6 +137,87 @@ pub fn force_resolve_trait(constraint: TraitConstraint, cache: &mut ModuleCache)
     solve_normal_constraint(&constraint, cache);
 }
 
+/// Returns true if an impl of the given trait exists for `typ`. Every impl in the program
+/// is considered, regardless of which are in scope at any given point. Any trait arguments
+/// after the first, including functional dependencies, may be any type.
+///
+/// No type variables are bound and no impls are selected by this search. Any other state
+/// the search adds to the cache is also removed afterward so it cannot leak into inference.
+#[allow(dead_code)]
+pub fn type_implements(typ: &Type, trait_id: TraitInfoId, cache: &mut ModuleCache) -> bool {
+    let type_variable_count = cache.type_bindings.len();
+    let impl_scope_count = cache.impl_scopes.len();
+    let variable_count = cache.variable_infos.len();
+
+    let scope = cache.push_impl_scope();
+    cache.impl_scopes[scope.0] = (0..cache.impl_infos.len()).map(ImplInfoId).collect();
+
+    let trait_info = &cache[trait_id];
+    let (name, location) = (trait_info.name.clone(), trait_info.location);
+    let remaining_args = (trait_info.typeargs.len() + trait_info.fundeps.len()).saturating_sub(1);
+
+    let mut args = vec![typ.clone()];
+    args.extend((0..remaining_args).map(|_| typechecker::next_type_variable(cache)));
+
+    let callsite = Callsite::Direct(cache.push_variable(name, None, location));
+    let signature = ConstraintSignature { trait_id, args, id: cache.next_trait_constraint_id() };
+    let constraint = TraitConstraint { required: RequiredTrait { signature, callsite }, scope };
+
+    let bindings = UnificationBindings::empty();
+    let found = !find_matching_impls(&constraint, &bindings, RECURSION_LIMIT, cache).is_empty();
+
+    cache.truncate_type_bindings(type_variable_count);
+    cache.impl_scopes.truncate(impl_scope_count);
+    cache.variable_infos.truncate(variable_count);
+    found
+}
+
+/// Check each `@closed(...)` trait is implemented for exactly the types it lists. An error is
+/// issued for each listed type without an impl and for each impl of a type that is not listed.
+pub fn check_closed_traits(cache: &mut ModuleCache) {
+    for trait_id in (0..cache.trait_infos.len()).map(TraitInfoId) {
+        let trait_info = &cache[trait_id];
+        let Some(closed) = trait_info.closed_impls.clone() else { continue };
+
+        let name = Rc::new(trait_info.name.clone());
+        let (location, arg_count) = (trait_info.location, trait_info.typeargs.len());
+        let display = |args: &[Type], cache: &ModuleCache| fmap(args, |arg| arg.display(cache).to_string()).join(" ");
+
+        let impls = (0..cache.impl_infos.len()).map(ImplInfoId).filter(|id| cache[*id].trait_id == trait_id);
+        let impls: Vec<_> = impls.collect();
+        let mut covered = vec![false; closed.len()];
+
+        for impl_id in impls {
+            // Only the trait's arguments are listed, not its functional dependencies
+            let args: Vec<_> = cache[impl_id].typeargs.iter().take(arg_count).cloned().collect();
+            let (args, _) = typechecker::replace_all_typevars(&args, cache);
+            let mut listed = false;
+
+            for (i, closed_args) in closed.iter().enumerate() {
+                if typechecker::try_unify_all_hide_error(&args, closed_args, cache).is_ok() {
+                    covered[i] = true;
+                    listed = true;
+                }
+            }
+
+            if !listed {
+                let actual = display(&args, cache);
+                let expected = fmap(&closed, |args| display(args, cache)).join(", ");
+                let location = cache[impl_id].location;
+                cache.push_diagnostic(location, D::TypeError(TE::UnexpectedImpl(name.clone()), actual, expected));
+            }
+        }
+
+        for (closed_args, covered) in closed.iter().zip(covered) {
+            if !covered {
+                let args = display(closed_args, cache);
+                let error = D::TypeError(TE::MissingRequiredImpl(name.clone()), args.clone(), args);
+                cache.push_diagnostic(location, error);
+            }
+        }
+    }
+}
+
 /// These just make the signature of sort_traits read better.
 type PropagatedTraits = Vec<RequiredTrait>;
 
@@ -228,9 +314,136 @@ fn solve_normal_constraint(constraint: &TraitConstraint, cache: &mut ModuleCache
                 cache.push_diagnostic(location, D::ImplCandidate(i + 1));
             }
         }
+    } else if let Some((trait_id, source)) = trait_object_conversion(constraint, cache) {
+        let trait_name = Rc::new(cache[trait_id].name.clone());
+        let source = source.display(cache).to_string();
+        let target = constraint.args()[1].display(cache).to_string();
+        let error = if is_object_safe(trait_id, cache) {
+            TE::CannotConvertToTraitObject(trait_name)
+        } else {
+            TE::TraitNotObjectSafe(trait_name)
+        };
+        cache.push_diagnostic(constraint.locate(cache), D::TypeError(error, source, target));
     } else {
         let constraint_str = constraint.display(cache).to_string();
-        cache.push_diagnostic(constraint.locate(cache), D::NoMatchingImpls(constraint_str));
+        cache.push_diagnostic(constraint.locate(cache), D::NoMatchingImpls(constraint_str.clone()));
+
+        for impl_id in find_near_miss_impls(constraint, cache).into_iter().take(MAX_SUGGESTED_IMPLS) {
+            let info = &cache[impl_id];
+            let signature = ConstraintSignature {
+                trait_id: info.trait_id,
+                args: info.typeargs.clone(),
+                id: constraint.required.signature.id,
+            };
+            let impl_str = format!("impl {}", signature.display(cache));
+            cache.push_diagnostic(info.location, D::NearMissImpl(impl_str, constraint_str.clone()));
+        }
+    }
+}
+
+/// Returns each impl for the same trait as the given constraint which almost matches it, either
+/// because its arguments unify but one of its `given` constraints could not be solved, or because
+/// exactly one of its arguments fails to unify with a type of the same type constructor, e.g.
+/// `Vec I32` and `Vec String`. Impls with more arguments that unify come first.
+fn find_near_miss_impls(constraint: &TraitConstraint, cache: &mut ModuleCache<'_>) -> Vec<ImplInfoId> {
+    let scope = cache[constraint.scope].clone();
+    let mut near_misses = vec![];
+
+    for impl_id in scope {
+        if cache[impl_id].trait_id != constraint.trait_id() {
+            continue;
+        }
+
+        let (impl_typeargs, _) = typechecker::replace_all_typevars(&cache[impl_id].typeargs.clone(), cache);
+        if impl_typeargs.len() != constraint.args().len() {
+            continue;
+        }
+
+        if typechecker::try_unify_all_hide_error(constraint.args(), &impl_typeargs, cache).is_ok() {
+            near_misses.push((impl_id, impl_typeargs.len()));
+            continue;
+        }
+
+        let mismatches: Vec<_> = (0..impl_typeargs.len())
+            .filter(|&i| {
+                let arg = std::slice::from_ref(&constraint.args()[i]);
+                typechecker::try_unify_all_hide_error(arg, &impl_typeargs[i..=i], cache).is_err()
+            })
+            .collect();
+
+        if let [index] = mismatches[..] {
+            if same_type_constructor(&constraint.args()[index], &impl_typeargs[index], cache) {
+                near_misses.push((impl_id, impl_typeargs.len() - 1));
+            }
+        }
+    }
+
+    near_misses.sort_by_key(|(_, matched)| std::cmp::Reverse(*matched));
+    fmap(near_misses, |(impl_id, _)| impl_id)
+}
+
+/// True if both types are built from the same outermost type constructor, ignoring its arguments
+fn same_type_constructor(a: &Type, b: &Type, cache: &ModuleCache<'_>) -> bool {
+    let constructor = |typ: &Type| match cache.follow_bindings(typ) {
+        Type::TypeApplication(constructor, _) => cache.follow_bindings(&constructor),
+        other => other,
+    };
+
+    match (constructor(a), constructor(b)) {
+        (Type::Primitive(a), Type::Primitive(b)) => a == b,
+        (Type::UserDefined(a), Type::UserDefined(b)) => a == b,
+        (a, b) => std::mem::discriminant(&a) == std::mem::discriminant(&b),
+    }
+}
+
+/// If the given constraint is a `Coerce a (dyn Trait)` from `x as dyn Trait`, returns
+/// the trait converted to along with the source type `a` which must implement it.
+fn trait_object_conversion(constraint: &TraitConstraint, cache: &ModuleCache<'_>) -> Option<(TraitInfoId, Type)> {
+    if cache[constraint.trait_id()].name != "Coerce" || constraint.args().len() != 2 {
+        return None;
+    }
+
+    let source = cache.follow_bindings(&constraint.args()[0]);
+    match cache.follow_bindings(&constraint.args()[1]) {
+        target @ Type::Tag(TypeTag::Dyn(trait_id)) if source != target => Some((trait_id, source)),
+        _ => None,
+    }
+}
+
+/// A trait can be used as a trait object if it has a single type argument and each of its
+/// definitions is a pure function taking that type argument as its first parameter and
+/// mentioning no other type variables. Calls through a trait object can then pass the
+/// object's data pointer in place of the value it was converted from.
+fn is_object_safe(trait_id: TraitInfoId, cache: &ModuleCache<'_>) -> bool {
+    let trait_info = &cache[trait_id];
+    if trait_info.typeargs.len() != 1 || !trait_info.fundeps.is_empty() {
+        return false;
+    }
+
+    let self_type = trait_info.typeargs[0];
+    trait_info.definitions.iter().all(|definition| {
+        let Some(Type::Function(function)) = cache[*definition].typ.as_ref().map(|typ| typ.remove_forall()) else {
+            return false;
+        };
+
+        let takes_self = match function.parameters.first().map(|parameter| cache.follow_bindings(parameter)) {
+            Some(Type::TypeVariable(id) | Type::NamedGeneric(id, _)) => id == self_type,
+            _ => false,
+        };
+
+        let rest = function.parameters.iter().skip(1).chain([&*function.return_type, &*function.effects]);
+        takes_self && rest.into_iter().all(|typ| typechecker::find_all_typevars(typ, false, cache).is_empty())
+    })
+}
+
+/// True if the given constraint is `Trait (dyn Trait)`
+fn is_trait_object_constraint(constraint: &TraitConstraint, cache: &ModuleCache<'_>) -> bool {
+    match constraint.args() {
+        [arg] => match cache.follow_bindings(arg) {
+            Type::Tag(TypeTag::Dyn(trait_id)) => trait_id == constraint.trait_id() && is_object_safe(trait_id, cache),
+            _ => false,
+        },
+        _ => false,
     }
 }
 
@@ -259,6 +472,16 @@ fn find_matching_impls(
         }
 
         vec![]
+    } else if let Some((trait_id, source)) =
+        trait_object_conversion(constraint, cache).filter(|(trait_id, _)| is_object_safe(*trait_id, cache))
+    {
+        // Converting to `dyn Trait` is built in: it only requires an impl of `Trait` for the source type
+        let id = constraint.required.signature.id;
+        let constraint = TraitConstraint::impl_given_constraint(id, trait_id, vec![source], constraint, cache);
+        find_matching_impls(&constraint, bindings, fuel - 1, cache)
+    } else if is_trait_object_constraint(constraint, cache) {
+        // A trait object implements its own trait by calling through its vtable
+        vec![(vec![], bindings.clone())]
     } else {
         find_matching_normal_impls(constraint, bindings, fuel - 1, cache)
     }
@@ -339,9 +562,89 @@ fn check_given_constraints(
         }
     }
 
+    // A negated `given not Trait2 a` clause only holds if there is no impl for it at all
+    for signature in cache[impl_id].negative_given.clone() {
+        let args = fmap(&signature.args, |typ| {
+            typechecker::replace_all_typevars_with_bindings(typ, &mut impl_bindings, cache)
+        });
+
+        let constraint =
+            TraitConstraint::impl_given_constraint(signature.id, signature.trait_id, args, constraint, cache);
+
+        if !find_matching_impls(&constraint, &unification_bindings, fuel, cache).is_empty() {
+            return None;
+        }
+    }
+
     Some((required_impls, unification_bindings))
 }
 
+/// Improve each constraint of a trait with functional dependencies by binding its fundep
+/// arguments to those of the only impl its other arguments are an instance of, even when
+/// the constraint still contains type variables. The impl's `given` constraints are improved
+/// in turn, so an impl defined recursively over nested pairs like
+/// `impl Uncons (List a, rest) (a, elems) given Uncons rest elems` acts as one variadic impl
+/// for tuples of every size: `Uncons (List x, List y, List z) e` binds `e := x, y, z`.
+///
+/// The constraints themselves are left unsolved. This only determines their fundeps earlier
+/// than solving them would, so that their types are known before being generalized.
+pub(super) fn improve_functional_dependencies(constraints: &TraitConstraints, cache: &mut ModuleCache<'_>) {
+    for constraint in constraints {
+        improve_constraint(constraint, RECURSION_LIMIT, cache);
+    }
+}
+
+fn improve_constraint(constraint: &TraitConstraint, fuel: u32, cache: &mut ModuleCache<'_>) {
+    let trait_info = &cache[constraint.trait_id()];
+    if fuel == 0 || trait_info.fundeps.is_empty() {
+        return;
+    }
+
+    let arg_count = trait_info.typeargs.len();
+    let (args, fundeps) = constraint.args().split_at(arg_count);
+    let trait_id = constraint.trait_id();
+    let impls = cache[constraint.scope].iter().copied().filter(|impl_id| cache[*impl_id].trait_id == trait_id);
+    let impls = impls.collect::<Vec<_>>();
+
+    // Only the impl's type variables may be bound for the constraint to be an instance of it
+    let constraint_typevars =
+        args.iter().flat_map(|arg| typechecker::find_all_typevars(arg, false, cache)).collect::<Vec<_>>();
+    let mut matching_impls = Vec::new();
+
+    for impl_id in impls {
+        let (impl_typeargs, impl_bindings) = typechecker::replace_all_typevars(&cache[impl_id].typeargs.clone(), cache);
+        let (impl_args, impl_fundeps) = impl_typeargs.split_at(arg_count);
+
+        if let Ok(bindings) = typechecker::try_unify_all_hide_error(impl_args, args, cache) {
+            if !constraint_typevars.iter().any(|id| bindings.bindings.contains_key(id)) {
+                matching_impls.push((impl_id, impl_fundeps.to_vec(), impl_bindings, bindings));
+            }
+        }
+    }
+
+    if matching_impls.len() != 1 {
+        return;
+    }
+
+    let (impl_id, impl_fundeps, mut impl_bindings, bindings) = matching_impls.remove(0);
+    bindings.perform(cache);
+
+    match typechecker::try_unify_all_hide_error(fundeps, &impl_fundeps, cache) {
+        Ok(bindings) => bindings.perform(cache),
+        Err(_) => return,
+    }
+
+    for signature in cache[impl_id].given.clone() {
+        let args = fmap(&signature.args, |typ| {
+            typechecker::replace_all_typevars_with_bindings(typ, &mut impl_bindings, cache)
+        });
+
+        let given = TraitConstraint::impl_given_constraint(signature.id, signature.trait_id, args, constraint, cache);
+
+        improve_constraint(&given, fuel - 1, cache);
+    }
+}
+
 /// Binds a selected impl to its callsite. This attaches the relevant impl definition to the
 /// callsite variable so that static dispatch may occur during codegen.
 fn bind_impl(impl_id: ImplInfoId, constraint: TraitConstraint, cache: &mut ModuleCache) {
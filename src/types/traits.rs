@@ -30,8 +30,9 @@
 //! methods on them for displaying them or converting between them.
 use crate::cache::{ImplInfoId, ImplScopeId, ModuleCache, TraitInfoId, VariableId};
 use crate::error::location::Location;
-use crate::types::typechecker::find_all_typevars;
+use crate::types::typechecker::{bind_typevars, find_all_typevars, TypeBindings};
 use crate::types::{Type, TypeVariableId};
+use crate::util::fmap;
 
 use super::typeprinter::ConstraintSignaturePrinter;
 
@@ -141,6 +142,51 @@ impl ConstraintSignature {
         }
         typevars
     }
+
+    /// Returns each superclass implied by this constraint, transitively, with the trait's type
+    /// arguments substituted in. E.g. given `trait Ord a given Eq a`, `Ord I32` implies `Eq I32`.
+    /// The returned signatures all share this constraint's id.
+    pub fn implied_superclasses(&self, cache: &ModuleCache<'_>) -> Vec<ConstraintSignature> {
+        let mut implied: Vec<ConstraintSignature> = vec![];
+        let mut queue = vec![self.clone()];
+
+        while let Some(constraint) = queue.pop() {
+            let info = &cache[constraint.trait_id];
+            let typevars = info.typeargs.iter().chain(&info.fundeps).copied();
+            let bindings: TypeBindings = typevars.zip(constraint.args).collect();
+
+            for superclass in &info.superclasses {
+                let args = fmap(&superclass.args, |arg| bind_typevars(arg, &bindings, cache));
+                let superclass = ConstraintSignature { trait_id: superclass.trait_id, args, id: self.id };
+
+                // Superclasses may be cyclic or reachable through several paths
+                let is_self = superclass.trait_id == self.trait_id && superclass.args == self.args;
+                if !is_self && !implied.contains(&superclass) {
+                    implied.push(superclass.clone());
+                    queue.push(superclass);
+                }
+            }
+        }
+
+        implied
+    }
+}
+
+/// Extend the given list of constraints with each superclass they imply that is not already
+/// present. Each added superclass constraint is given a fresh TraitConstraintId.
+pub fn add_implied_superclasses(constraints: &mut Vec<ConstraintSignature>, cache: &mut ModuleCache<'_>) {
+    let implied: Vec<_> = constraints.iter().flat_map(|constraint| constraint.implied_superclasses(cache)).collect();
+
+    for superclass in implied {
+        let already_present = constraints
+            .iter()
+            .any(|constraint| constraint.trait_id == superclass.trait_id && constraint.args == superclass.args);
+
+        if !already_present {
+            let id = cache.next_trait_constraint_id();
+            constraints.push(ConstraintSignature { id, ..superclass });
+        }
+    }
 }
 
 impl TraitConstraint {
@@ -25,27 +25,29 @@
 //! Ast will be filled out:
 //! - `typ: Option<Type>` for all nodes,
 //! - `trait_binding: Option<TraitBindingId>` for `ast::Variable`s,
-//! - `decision_tree: Option<DecisionTree>` for `ast::Match`s
+//! - `decision_tree: Option<DecisionTree>` and `reference_bindings` for `ast::Match`s
 use crate::cache::{DefinitionInfoId, DefinitionKind, EffectInfoId, ModuleCache, TraitInfoId};
-use crate::cache::{ImplScopeId, VariableId};
+use crate::cache::{ImplInfoId, ImplScopeId, ReturnTarget, VariableId};
 use crate::error::location::{Locatable, Location};
 use crate::error::{Diagnostic, DiagnosticKind as D, TypeErrorKind, TypeErrorKind as TE};
+use crate::lexer::token::{IntegerKind, Token};
+use crate::nameresolution::builtin::BUILTIN_ID;
 use crate::parser::ast::{self, ClosureEnvironment, Mutability};
 use crate::types::traits::{RequiredTrait, TraitConstraint, TraitConstraints};
-use crate::types::typed::Typed;
+use crate::types::typed::{clear_definition_types, clear_inferred_types, Typed};
 use crate::types::EffectSet;
 use crate::types::{
-    pattern, traitchecker, FunctionType, LetBindingLevel, PrimitiveType, Type, Type::*, TypeBinding, TypeBinding::*,
-    TypeInfo, TypeVariableId, INITIAL_LEVEL, PAIR_TYPE, STRING_TYPE,
+    linearity, pattern, traitchecker, FunctionType, LetBindingLevel, PrimitiveType, RowKind, Type, Type::*,
+    TypeBinding, TypeBinding::*, TypeInfo, TypeVariableId, ARRAY_TYPE, INITIAL_LEVEL, PAIR_TYPE, STRING_TYPE,
 };
 use crate::util::*;
 
 use std::borrow::Cow;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::rc::Rc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-use super::effects::Effect;
+use super::effects::{self, Effect};
 use super::mutual_recursion::{definition_is_mutually_recursive, try_generalize_definition};
 use super::traits::{Callsite, ConstraintSignature, TraitConstraintId};
 use super::{GeneralizedType, TypeInfoBody, TypeTag};
@@ -70,15 +72,90 @@ type LevelBindings = Vec<(TypeVariableId, LetBindingLevel)>;
 /// Expected not to happen but leads to better errors than a stack overflow when it does.
 const RECURSION_LIMIT: u32 = 100;
 
+/// Recursing deeper than this within `occurs` issues a one-time warning so users
+/// can notice types which are close to hitting RECURSION_LIMIT.
+const DEEP_RECURSION_WARNING_THRESHOLD: u32 = RECURSION_LIMIT * 4 / 5;
+
 #[derive(Debug, Clone)]
 pub struct UnificationBindings {
     pub bindings: TypeBindings,
     level_bindings: LevelBindings,
+
+    /// Set when unification failed because an owned reference was used where a shared one
+    /// was expected while implicit sharing is disabled, to report it as an OwnershipMismatch.
+    ownership_mismatch: bool,
+
+    /// Set when unification failed because a type was expected to be within a universe
+    /// that it itself mentions, e.g. `Universe 0` in `Universe 0`, to report it as a
+    /// UniverseInconsistency rather than a plain type mismatch.
+    universe_inconsistency: bool,
+
+    /// When `ModuleCache::trace_unification` is set, each nested unification which is either
+    /// still in progress or has failed, indented by its depth. Successful ones are removed.
+    trace: Vec<String>,
+    trace_depth: usize,
+
+    /// When unification fails, the steps taken from the two top-level types
+    /// down to the pair of subterms which conflicted, along with those subterms.
+    /// Steps are only kept on the failing branch; successful ones are removed.
+    path: Vec<UnificationStep>,
+    conflict: Option<(Type, Type)>,
+
+    /// The number of recursive types currently being unfolded, limited to RECURSION_LIMIT
+    unfold_depth: u32,
+
+    /// The deepest type nesting found by the occurs check while binding type variables.
+    /// Used to warn when a type nears RECURSION_LIMIT, but only for unifications whose
+    /// errors would be shown.
+    deepest_type: u32,
+
+    /// When unification failed because of two effect sets, the effects
+    /// of the actual set which the expected set does not allow.
+    missing_effects: Vec<Effect>,
+}
+
+/// A single step into a pair of types being unified, used to
+/// describe where a mismatch occurred within two larger types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnificationStep {
+    /// The parameter at the given index of a function type, starting from 0
+    Parameter(usize),
+    ReturnType,
+    Environment,
+    Effects,
+    /// The argument at the given index of a type application, starting from 0
+    TypeArgument(usize),
+    Field(String),
+}
+
+impl std::fmt::Display for UnificationStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnificationStep::Parameter(index) => write!(f, "parameter {}", index + 1),
+            UnificationStep::ReturnType => write!(f, "the return type"),
+            UnificationStep::Environment => write!(f, "the closure environment"),
+            UnificationStep::Effects => write!(f, "the effects"),
+            UnificationStep::TypeArgument(index) => write!(f, "type argument {}", index + 1),
+            UnificationStep::Field(name) => write!(f, "field `{name}`"),
+        }
+    }
 }
 
 impl UnificationBindings {
     pub fn empty() -> UnificationBindings {
-        UnificationBindings { bindings: HashMap::new(), level_bindings: vec![] }
+        UnificationBindings {
+            bindings: HashMap::new(),
+            level_bindings: vec![],
+            ownership_mismatch: false,
+            universe_inconsistency: false,
+            trace: vec![],
+            trace_depth: 0,
+            path: vec![],
+            conflict: None,
+            unfold_depth: 0,
+            deepest_type: 0,
+            missing_effects: vec![],
+        }
     }
 
     pub fn perform(self, cache: &mut ModuleCache) {
@@ -116,6 +193,20 @@ impl TypeResult {
         Self { typ, traits: vec![], effects: EffectSet::any(cache) }
     }
 
+    /// The effects performed by the inferred expression. Each extension of the effect row is
+    /// followed to its current binding and each effect's arguments have all of their type
+    /// variable bindings applied, so this reflects the state of inference when it is called.
+    #[allow(dead_code)]
+    pub fn effects(&self, cache: &ModuleCache) -> EffectSet {
+        let mut effects = self.effects.flatten(cache);
+        for (_, args) in &mut effects.effects {
+            for arg in args.iter_mut() {
+                *arg = cache.follow_bindings(arg);
+            }
+        }
+        effects
+    }
+
     fn with_type(mut self, typ: Type) -> TypeResult {
         self.typ = typ;
         self
@@ -169,6 +260,17 @@ fn ref_of(mutability: Mutability, typ: Type, cache: &mut ModuleCache) -> Type {
     TypeApplication(constructor, vec![typ])
 }
 
+/// If the given type is a reference type, returns its `Ref` constructor and the type it refers to
+pub fn reference_parts(typ: &Type, cache: &ModuleCache) -> Option<(Type, Type)> {
+    match cache.follow_bindings_shallow(typ) {
+        TypeApplication(constructor, args) if args.len() == 1 => {
+            let constructor = cache.follow_bindings_shallow(constructor);
+            constructor.is_reference_type().then(|| (constructor.clone(), args[0].clone()))
+        },
+        _ => None,
+    }
+}
+
 /// Replace any typevars found in typevars_to_replace with the
 /// associated value in the same table, leave them otherwise
 fn replace_typevars(
@@ -200,6 +302,7 @@ pub fn replace_all_typevars_with_bindings(
     match typ {
         Primitive(p) => Primitive(*p),
         Tag(tag) => Tag(*tag),
+        Universe(level) => Universe(*level),
 
         TypeVariable(id) | NamedGeneric(id, _) => replace_typevar_with_binding(*id, new_bindings, cache),
 
@@ -227,7 +330,7 @@ pub fn replace_all_typevars_with_bindings(
             let args = fmap(args, |arg| replace_all_typevars_with_bindings(arg, new_bindings, cache));
             TypeApplication(Box::new(typ), args)
         },
-        Struct(fields, id) => {
+        Struct(fields, id, kind) => {
             if let Bound(typ) = &cache.type_bindings[id.0] {
                 replace_all_typevars_with_bindings(&typ.clone(), new_bindings, cache)
             } else if let Some(binding) = new_bindings.get(id) {
@@ -241,9 +344,18 @@ pub fn replace_all_typevars_with_bindings(
                     })
                     .collect();
 
-                Struct(fields, *id)
+                Struct(fields, *id, *kind)
             }
         },
+        Rec(binder, body) => {
+            // Rename the binder consistently with each of its occurrences in the body
+            let binder = match replace_typevar_with_binding(*binder, new_bindings, cache) {
+                TypeVariable(binder) => binder,
+                _ => unreachable!("The binder of a rec type is never bound"),
+            };
+            let body = replace_all_typevars_with_bindings(body, new_bindings, cache);
+            Rec(binder, Box::new(body))
+        },
         Effects(effects) => effects.replace_all_typevars_with_bindings(new_bindings, cache),
     }
 }
@@ -274,6 +386,7 @@ pub fn bind_typevars(typ: &Type, type_bindings: &TypeBindings, cache: &ModuleCac
     match typ {
         Primitive(p) => Primitive(*p),
         Tag(tag) => Tag(*tag),
+        Universe(level) => Universe(*level),
 
         TypeVariable(id) => bind_typevar(*id, type_bindings, cache),
 
@@ -307,14 +420,14 @@ pub fn bind_typevars(typ: &Type, type_bindings: &TypeBindings, cache: &ModuleCac
             let args = fmap(args, |arg| bind_typevars(arg, type_bindings, cache));
             TypeApplication(Box::new(typ), args)
         },
-        Struct(fields, id) => {
+        Struct(fields, id, kind) => {
             match type_bindings.get(id) {
                 Some(TypeVariable(binding_id)) => {
                     let fields = fields
                         .iter()
                         .map(|(name, field)| (name.clone(), bind_typevars(field, type_bindings, cache)))
                         .collect();
-                    Struct(fields, *binding_id)
+                    Struct(fields, *binding_id, *kind)
                 },
                 // TODO: Should we follow all typevars here?
                 Some(binding) => binding.clone(),
@@ -330,11 +443,12 @@ pub fn bind_typevars(typ: &Type, type_bindings: &TypeBindings, cache: &ModuleCac
                             })
                             .collect();
 
-                        Struct(fields, *id)
+                        Struct(fields, *id, *kind)
                     }
                 },
             }
         },
+        Rec(binder, body) => Rec(*binder, Box::new(bind_typevars(body, type_bindings, cache))),
         Effects(effects) => effects.bind_typevars(type_bindings, cache),
     }
 }
@@ -359,6 +473,13 @@ fn bind_typevar(id: TypeVariableId, type_bindings: &TypeBindings, cache: &Module
     }
 }
 
+/// Can the TypeVariable(id) be found inside this type?
+/// Unlike `occurs`, this never changes the LetBindingLevel of any type variable
+/// so it is safe to call outside of unification.
+pub fn contains_typevar(id: TypeVariableId, typ: &Type, cache: &ModuleCache<'_>) -> bool {
+    contains_any_typevars_from_list(typ, &[id], cache)
+}
+
 /// Recurse on typ, returning true if it contains any of the TypeVariableIds
 /// contained within list.
 pub fn contains_any_typevars_from_list(typ: &Type, list: &[TypeVariableId], cache: &ModuleCache<'_>) -> bool {
@@ -366,6 +487,7 @@ pub fn contains_any_typevars_from_list(typ: &Type, list: &[TypeVariableId], cach
         Primitive(_) => false,
         UserDefined(_) => false,
         Tag(_) => false,
+        Universe(_) => false,
 
         TypeVariable(id) => type_variable_contains_any_typevars_from_list(*id, list, cache),
         NamedGeneric(id, _) => type_variable_contains_any_typevars_from_list(*id, list, cache),
@@ -387,10 +509,11 @@ pub fn contains_any_typevars_from_list(typ: &Type, list: &[TypeVariableId], cach
             contains_any_typevars_from_list(typ, list, cache)
                 || args.iter().any(|arg| contains_any_typevars_from_list(arg, list, cache))
         },
-        Struct(fields, id) => {
+        Struct(fields, id, _) => {
             type_variable_contains_any_typevars_from_list(*id, list, cache)
                 || fields.iter().any(|(_, field)| contains_any_typevars_from_list(field, list, cache))
         },
+        Rec(_, body) => contains_any_typevars_from_list(body, list, cache),
         Effects(effects) => effects.contains_any_typevars_from_list(list, cache),
     }
 }
@@ -430,14 +553,22 @@ fn to_trait_constraints(
     // If this definition is from a trait, we must add the initial constraint directly
     if let Some((trait_id, args)) = &info.trait_info {
         let id = current_constraint_id.next();
+        let signature = ConstraintSignature { trait_id: *trait_id, args: args.clone(), id };
+        let superclasses = signature.implied_superclasses(cache);
 
         traits.push(TraitConstraint {
-            required: RequiredTrait {
-                signature: ConstraintSignature { trait_id: *trait_id, args: args.clone(), id },
-                callsite: Callsite::Direct(callsite),
-            },
+            required: RequiredTrait { signature, callsite: Callsite::Direct(callsite) },
             scope,
         });
+
+        // Using a trait also requires each of its superclasses. These have no definition to
+        // replace at the callsite so they are only attached to it indirectly.
+        for superclass in superclasses {
+            let id = cache.current_trait_constraint_id.next();
+            let signature = ConstraintSignature { id, ..superclass };
+            let callsite = Callsite::Indirect(callsite, vec![id]);
+            traits.push(TraitConstraint { required: RequiredTrait { signature, callsite }, scope });
+        }
     }
 
     traits
@@ -523,18 +654,58 @@ fn find_binding(id: TypeVariableId, map: &UnificationBindings, cache: &ModuleCac
     }
 }
 
+/// Evaluate closed arithmetic on type-level natural numbers, e.g. `2 + 3` to `5`.
+/// Returns None if the given type is not an arithmetic expression, or if it contains
+/// an unbound type variable or would overflow or underflow.
+fn evaluate_nat_arithmetic(typ: &Type, bindings: &UnificationBindings, cache: &ModuleCache<'_>) -> Option<u64> {
+    match typ {
+        TypeApplication(constructor, args) if constructor.is_nat_operator() && args.len() == 2 => {
+            let lhs = evaluate_nat(&args[0], bindings, cache)?;
+            let rhs = evaluate_nat(&args[1], bindings, cache)?;
+
+            match constructor.as_ref() {
+                Tag(TypeTag::Add) => lhs.checked_add(rhs),
+                Tag(TypeTag::Subtract) => lhs.checked_sub(rhs),
+                Tag(TypeTag::Multiply) => lhs.checked_mul(rhs),
+                _ => unreachable!("Already verified by is_nat_operator"),
+            }
+        },
+        _ => None,
+    }
+}
+
+fn evaluate_nat(typ: &Type, bindings: &UnificationBindings, cache: &ModuleCache<'_>) -> Option<u64> {
+    match typ {
+        Tag(TypeTag::Nat(n)) => Some(*n),
+        TypeVariable(id) | NamedGeneric(id, _) => match find_binding(*id, bindings, cache) {
+            Bound(binding) => evaluate_nat(&binding, bindings, cache),
+            Unbound(..) => None,
+        },
+        other => evaluate_nat_arithmetic(other, bindings, cache),
+    }
+}
+
 pub(super) struct OccursResult {
     occurs: bool,
     level_bindings: LevelBindings,
+
+    /// The least amount of fuel remaining at any point during the check,
+    /// used to warn when a type is nearing the recursion limit.
+    min_fuel: u32,
 }
 
 impl OccursResult {
     pub(super) fn does_not_occur() -> OccursResult {
-        OccursResult { occurs: false, level_bindings: vec![] }
+        OccursResult { occurs: false, level_bindings: vec![], min_fuel: u32::MAX }
     }
 
     fn new(occurs: bool, level_bindings: LevelBindings) -> OccursResult {
-        OccursResult { occurs, level_bindings }
+        OccursResult { occurs, level_bindings, min_fuel: u32::MAX }
+    }
+
+    fn with_fuel(mut self, fuel: u32) -> OccursResult {
+        self.min_fuel = self.min_fuel.min(fuel);
+        self
     }
 
     fn then(mut self, mut f: impl FnMut() -> OccursResult) -> OccursResult {
@@ -542,6 +713,7 @@ impl OccursResult {
             let mut other = f();
             self.occurs = other.occurs;
             self.level_bindings.append(&mut other.level_bindings);
+            self.min_fuel = self.min_fuel.min(other.min_fuel);
         }
         self
     }
@@ -554,6 +726,7 @@ impl OccursResult {
                 let mut other = f(typ);
                 self.occurs = other.occurs;
                 self.level_bindings.append(&mut other.level_bindings);
+                self.min_fuel = self.min_fuel.min(other.min_fuel);
                 if self.occurs {
                     return self;
                 }
@@ -568,6 +741,7 @@ impl OccursResult {
 /// Doing so increases the lifetime of the typevariable and lets us keep
 /// track of which type variables to generalize later on. It also means
 /// that occurs should only be called during unification however.
+#[allow(dead_code)]
 pub fn occurs(
     id: TypeVariableId, level: LetBindingLevel, typ: &Type, bindings: &mut UnificationBindings, fuel: u32,
     cache: &mut ModuleCache<'_>,
@@ -589,10 +763,11 @@ pub(super) fn occurs_helper(
     }
 
     let fuel = fuel - 1;
-    match typ {
+    let result = match typ {
         Primitive(_) => OccursResult::does_not_occur(),
         UserDefined(_) => OccursResult::does_not_occur(),
         Tag(_) => OccursResult::does_not_occur(),
+        Universe(_) => OccursResult::does_not_occur(),
 
         TypeVariable(var_id) => typevars_match(id, level, *var_id, bindings, fuel, cache),
         NamedGeneric(var_id, _) => typevars_match(id, level, *var_id, bindings, fuel, cache),
@@ -602,12 +777,15 @@ pub(super) fn occurs_helper(
         Ref { mutability, sharedness, lifetime } => occurs_helper(id, level, mutability, bindings, fuel, cache)
             .then(|| occurs_helper(id, level, sharedness, bindings, fuel, cache))
             .then(|| occurs_helper(id, level, lifetime, bindings, fuel, cache)),
-        Struct(fields, var_id) => typevars_match(id, level, *var_id, bindings, fuel, cache)
+        Struct(fields, var_id, _) => typevars_match(id, level, *var_id, bindings, fuel, cache)
             .then_all(fields.iter().map(|(_, typ)| typ), |field| {
                 occurs_helper(id, level, field, bindings, fuel, cache)
             }),
+        // The binder is never bound so only the body needs to be checked
+        Rec(_, body) => occurs_helper(id, level, body, bindings, fuel, cache),
         Effects(effects) => effects.occurs(id, level, bindings, fuel, cache),
-    }
+    };
+    result.with_fuel(fuel)
 }
 
 pub(super) fn occurs_in_function(
@@ -667,11 +845,79 @@ pub fn follow_bindings_in_cache(typ: &Type, cache: &ModuleCache<'_>) -> Type {
 /// and the given bindings set may still be modified with prior type bindings.
 ///
 /// This function performs the bulk of the work for the various unification functions.
-#[allow(clippy::nonminimal_bool)]
 pub fn try_unify_with_bindings_inner<'b>(
     actual: &Type, expected: &Type, bindings: &mut UnificationBindings, location: Location<'b>,
     cache: &mut ModuleCache<'b>,
 ) -> Result<(), ()> {
+    let result = try_unify_with_bindings_traced(actual, expected, bindings, location, cache);
+
+    // The innermost failing unification is the first to return, so it is the one remembered
+    if result.is_err() && bindings.conflict.is_none() {
+        bindings.conflict = Some((actual.clone(), expected.clone()));
+    }
+    result
+}
+
+/// Unify the two types within a step into some larger pair of types being unified.
+/// The step is kept in the unification path only if this fails.
+fn try_unify_step<'b>(
+    step: UnificationStep, actual: &Type, expected: &Type, bindings: &mut UnificationBindings, location: Location<'b>,
+    cache: &mut ModuleCache<'b>,
+) -> Result<(), ()> {
+    bindings.path.push(step);
+    let result = try_unify_with_bindings_inner(actual, expected, bindings, location, cache);
+    if result.is_ok() {
+        bindings.path.pop();
+    }
+    result
+}
+
+/// Unify the two types, recording them in the unification trace if it is enabled
+fn try_unify_with_bindings_traced<'b>(
+    actual: &Type, expected: &Type, bindings: &mut UnificationBindings, location: Location<'b>,
+    cache: &mut ModuleCache<'b>,
+) -> Result<(), ()> {
+    if !cache.trace_unification {
+        return unify_untraced(actual, expected, bindings, location, cache);
+    }
+
+    // Some cases recur on the same types after e.g. following type variable bindings.
+    // These are collapsed into their parent unification in the trace.
+    let step = format!("{} ~ {}", actual.debug(cache), expected.debug(cache));
+    if bindings.trace.last().is_some_and(|parent| parent.trim_start() == step) {
+        return unify_untraced(actual, expected, bindings, location, cache);
+    }
+
+    let index = bindings.trace.len();
+    let indent = "  ".repeat(bindings.trace_depth + 1);
+    bindings.trace.push(format!("{indent}{step}"));
+
+    bindings.trace_depth += 1;
+    let result = unify_untraced(actual, expected, bindings, location, cache);
+    bindings.trace_depth -= 1;
+
+    // Only the paths leading to a failure are kept
+    if result.is_ok() {
+        bindings.trace.truncate(index);
+    }
+    result
+}
+
+/// Unify the two types without recording them in the unification trace.
+/// Nested unifications are still traced since they recur on `try_unify_with_bindings_inner`.
+#[allow(clippy::nonminimal_bool)]
+fn unify_untraced<'b>(
+    actual: &Type, expected: &Type, bindings: &mut UnificationBindings, location: Location<'b>,
+    cache: &mut ModuleCache<'b>,
+) -> Result<(), ()> {
+    // Normalize any closed type-level arithmetic before comparing
+    if let Some(n) = evaluate_nat_arithmetic(actual, bindings, cache) {
+        return try_unify_with_bindings_inner(&Tag(TypeTag::Nat(n)), expected, bindings, location, cache);
+    }
+    if let Some(n) = evaluate_nat_arithmetic(expected, bindings, cache) {
+        return try_unify_with_bindings_inner(actual, &Tag(TypeTag::Nat(n)), bindings, location, cache);
+    }
+
     match (actual, expected) {
         (Primitive(p1), Primitive(p2)) if p1 == p2 => Ok(()),
 
@@ -691,6 +937,22 @@ pub fn try_unify_with_bindings_inner<'b>(
             try_unify_type_variable_with_bindings(*id, expected, actual, false, bindings, location, cache)
         },
 
+        // Recursive types are equal to their unfolding, so any type other than an identical
+        // recursive type is unified against the unfolded type instead.
+        (Rec(..), Rec(..)) if actual == expected => Ok(()),
+        (Rec(binder, body), _) => {
+            let unfolded = unfold_rec_type(*binder, body, cache);
+            with_unfold_fuel(bindings, |bindings| {
+                try_unify_with_bindings_inner(&unfolded, expected, bindings, location, cache)
+            })
+        },
+        (_, Rec(binder, body)) => {
+            let unfolded = unfold_rec_type(*binder, body, cache);
+            with_unfold_fuel(bindings, |bindings| {
+                try_unify_with_bindings_inner(actual, &unfolded, bindings, location, cache)
+            })
+        },
+
         (Function(function1), Function(function2)) => {
             if function1.parameters.len() != function2.parameters.len() {
                 // Whether a function is varargs or not is never unified,
@@ -702,27 +964,47 @@ pub fn try_unify_with_bindings_inner<'b>(
                 }
             }
 
-            for (a_arg, b_arg) in function1.parameters.iter().zip(function2.parameters.iter()) {
-                try_unify_with_bindings_inner(a_arg, b_arg, bindings, location, cache)?
+            for (i, (a_arg, b_arg)) in function1.parameters.iter().zip(function2.parameters.iter()).enumerate() {
+                try_unify_step(UnificationStep::Parameter(i), a_arg, b_arg, bindings, location, cache)?
             }
 
             // Reverse the arguments when checking return types to preserve
             // some subtyping relations with mutable & immutable references.
-            try_unify_with_bindings_inner(&function2.return_type, &function1.return_type, bindings, location, cache)?;
-            try_unify_with_bindings_inner(&function1.environment, &function2.environment, bindings, location, cache)?;
-            try_unify_with_bindings_inner(&function1.effects, &function2.effects, bindings, location, cache)
+            let (return1, return2) = (&function1.return_type, &function2.return_type);
+            if try_unify_step(UnificationStep::ReturnType, return2, return1, bindings, location, cache).is_err() {
+                // Undo the reversal so the conflicting subterms are still reported as (actual, expected)
+                bindings.conflict = bindings.conflict.take().map(|(expected, actual)| (actual, expected));
+                return Err(());
+            }
+
+            let (environment1, environment2) = (&function1.environment, &function2.environment);
+            try_unify_step(UnificationStep::Environment, environment1, environment2, bindings, location, cache)?;
+            try_unify_step(UnificationStep::Effects, &function1.effects, &function2.effects, bindings, location, cache)
         },
 
         (TypeApplication(a_constructor, a_args), TypeApplication(b_constructor, b_args)) => {
             // Unify the constructors before checking the arg lengths, it gives better error messages
-            try_unify_with_bindings_inner(a_constructor, b_constructor, bindings, location, cache)?;
+            if try_unify_with_bindings_inner(a_constructor, b_constructor, bindings, location, cache).is_err() {
+                // A constructor alone like `Int` is less clear than the whole type application
+                bindings.conflict = Some((actual.clone(), expected.clone()));
+                return Err(());
+            }
 
             if a_args.len() != b_args.len() {
                 return Err(());
             }
 
-            for (a_arg, b_arg) in a_args.iter().zip(b_args.iter()) {
-                try_unify_with_bindings_inner(a_arg, b_arg, bindings, location, cache)?;
+            // `Int I32` is displayed as just `I32` so there is no separate argument to point to
+            let constructor = follow_bindings_in_cache_and_map(a_constructor, bindings, cache);
+            let is_number = matches!(constructor, Primitive(PrimitiveType::IntegerType | PrimitiveType::FloatType));
+
+            for (i, (a_arg, b_arg)) in a_args.iter().zip(b_args.iter()).enumerate() {
+                if !is_number {
+                    try_unify_step(UnificationStep::TypeArgument(i), a_arg, b_arg, bindings, location, cache)?;
+                } else if try_unify_with_bindings_inner(a_arg, b_arg, bindings, location, cache).is_err() {
+                    bindings.conflict = Some((actual.clone(), expected.clone()));
+                    return Err(());
+                }
             }
 
             Ok(())
@@ -740,18 +1022,34 @@ pub fn try_unify_with_bindings_inner<'b>(
 
         // Follow any bindings here for convenience so we don't have to check if a or b
         // are bound in all Struct cases below.
-        (Struct(_, var), t2) | (t2, Struct(_, var)) if matches!(&cache.type_bindings[var.0], Bound(_)) => {
+        (Struct(_, var, _), t2) | (t2, Struct(_, var, _)) if matches!(&cache.type_bindings[var.0], Bound(_)) => {
             match &cache.type_bindings[var.0] {
                 Bound(bound) => try_unify_with_bindings_inner(&bound.clone(), t2, bindings, location, cache),
                 _ => unreachable!(),
             }
         },
 
-        (Struct(fields1, rest1), Struct(fields2, rest2)) => {
-            bind_struct_fields(fields1, fields2, *rest1, *rest2, bindings, location, cache)
+        (Struct(fields1, rest1, kind1), Struct(fields2, rest2, kind2)) => {
+            bind_struct_fields(fields1, fields2, (*rest1, *kind1), (*rest2, *kind2), bindings, location, cache)
         },
 
-        (Struct(fields1, rest), other) | (other, Struct(fields1, rest)) => {
+        // Anonymous record types are distinct from any nominal type, even one with the same fields
+        (Struct(_, _, RowKind::Closed), _) | (_, Struct(_, _, RowKind::Closed)) => Err(()),
+
+        // A struct value used where a data type is expected
+        (Struct(fields1, rest, _), other) => {
+            let fields2 = get_fields(other, &[], bindings, cache)?;
+            bind_struct_fields_subset(fields1, &fields2, bindings, location, cache)?;
+
+            if cache.struct_unification == StructUnifyMode::ExactOnly && fields1.len() != fields2.len() {
+                return Err(());
+            }
+
+            bindings.bindings.insert(*rest, other.clone());
+            Ok(())
+        },
+        // A data type used where a struct with some fields is expected, e.g. in a field access
+        (other, Struct(fields1, rest, _)) => {
             let fields2 = get_fields(other, &[], bindings, cache)?;
             bind_struct_fields_subset(fields1, &fields2, bindings, location, cache)?;
             bindings.bindings.insert(*rest, other.clone());
@@ -794,33 +1092,66 @@ pub fn try_unify_with_bindings_inner<'b>(
             Ok(())
         },
 
-        (Effects(effects1), Effects(effects2)) => effects1.try_unify_with_bindings(effects2, bindings, location, cache),
+        (Effects(effects1), Effects(effects2)) => {
+            effects1.try_unify_with_bindings(effects2, bindings, location, cache).map_err(|missing_effects| {
+                bindings.missing_effects = missing_effects;
+            })
+        },
 
         (Tag(tag1), Tag(tag2)) if tag1 == tag2 => Ok(()),
 
         // ! <: &
         (Tag(TypeTag::Mutable), Tag(TypeTag::Immutable)) => Ok(()),
 
-        // owned <: shared
-        (Tag(TypeTag::Owned), Tag(TypeTag::Shared)) => Ok(()),
+        // owned <: shared, unless an explicit `share` is required
+        (Tag(TypeTag::Owned), Tag(TypeTag::Shared)) => {
+            if cache.implicit_share {
+                Ok(())
+            } else {
+                bindings.ownership_mismatch = true;
+                Err(())
+            }
+        },
+
+        (Universe(level1), Universe(level2)) if level1 == level2 => Ok(()),
+
+        // Universes are not cumulative, so this is always an error. It is only separated out
+        // to give a better error message when it would otherwise allow `Universe n : Universe n`
+        (other, Universe(level)) => {
+            let other = follow_bindings_in_cache_and_map(other, bindings, cache);
+            if universe_level(&other, cache) > *level {
+                bindings.universe_inconsistency = true;
+            }
+            Err(())
+        },
 
         _ => Err(()),
     }
 }
 
+/// Unify two struct types, binding the row variable of each open struct to a struct containing
+/// the fields of both. A closed struct may not gain any fields, so the other struct must have
+/// a subset of its fields, or exactly the same fields if both are closed.
 fn bind_struct_fields<'c>(
-    fields1: &BTreeMap<String, Type>, fields2: &BTreeMap<String, Type>, rest1: TypeVariableId, rest2: TypeVariableId,
-    bindings: &mut UnificationBindings, location: Location<'c>, cache: &mut ModuleCache<'c>,
+    fields1: &BTreeMap<String, Type>, fields2: &BTreeMap<String, Type>, (rest1, kind1): (TypeVariableId, RowKind),
+    (rest2, kind2): (TypeVariableId, RowKind), bindings: &mut UnificationBindings, location: Location<'c>,
+    cache: &mut ModuleCache<'c>,
 ) -> Result<(), ()> {
     let mut new_fields = fields1.clone();
     for (name, typ2) in fields2 {
         if let Some(typ1) = new_fields.get(name) {
-            try_unify_with_bindings_inner(typ1, typ2, bindings, location, cache)?;
+            try_unify_step(UnificationStep::Field(name.clone()), typ1, typ2, bindings, location, cache)?;
         } else {
             new_fields.insert(name.clone(), typ2.clone());
         }
     }
 
+    let gains_fields =
+        |fields: &BTreeMap<String, Type>, kind| kind == RowKind::Closed && new_fields.len() != fields.len();
+    if gains_fields(fields1, kind1) || gains_fields(fields2, kind2) {
+        return Err(());
+    }
+
     if new_fields.len() != fields1.len() && new_fields.len() != fields2.len() {
         try_unify_type_variable_with_bindings(
             rest1,
@@ -832,16 +1163,16 @@ fn bind_struct_fields<'c>(
             cache,
         )?;
         let new_rest = new_row_variable(rest1, rest2, cache);
-        let new_struct = Struct(new_fields, new_rest);
+        let new_struct = Struct(new_fields, new_rest, RowKind::Open);
         // We set rest1 := rest2 above, so we should insert into rest2 to bind both structs
         bindings.bindings.insert(rest2, new_struct);
     } else if new_fields.len() != fields1.len() {
         // Set 1 := 2
-        let struct2 = Struct(new_fields, rest2);
+        let struct2 = Struct(new_fields, rest2, kind2);
         try_unify_type_variable_with_bindings(rest1, &TypeVariable(rest1), &struct2, true, bindings, location, cache)?;
     } else if new_fields.len() != fields2.len() {
         // Set 2 := 1
-        let struct1 = Struct(new_fields, rest1);
+        let struct1 = Struct(new_fields, rest1, kind1);
         try_unify_type_variable_with_bindings(rest2, &TypeVariable(rest2), &struct1, false, bindings, location, cache)?;
     }
 
@@ -861,12 +1192,65 @@ fn new_row_variable(row1: TypeVariableId, row2: TypeVariableId, cache: &mut Modu
     }
 }
 
+/// Unify the `result` type of a functional record update `{ record with field = x, .. }`
+/// given the type of the `record` being updated and the types of each updated field.
+///
+/// Only an anonymous record's closed struct type may gain fields or have a field change
+/// its type: the result then shares the record's row variable with each updated field's
+/// type replaced. Otherwise the result has the same type as the record. An open row is
+/// extended with any updated field it does not yet contain so that later field accesses
+/// on either value can still add to the rest of the row.
+pub fn unify_record_update<'c>(
+    record: &Type, updated_fields: &BTreeMap<String, Type>, result: &Type, location: Location<'c>,
+    cache: &mut ModuleCache<'c>,
+) {
+    // A struct with a bound row variable is represented entirely by that binding
+    let mut record = follow_bindings_in_cache(record, cache);
+    while let Struct(_, rest, _) = &record {
+        match &cache.type_bindings[rest.0] {
+            Bound(binding) => record = follow_bindings_in_cache(&binding.clone(), cache),
+            Unbound(..) => break,
+        }
+    }
+
+    let rest = match &record {
+        Struct(fields, rest, RowKind::Closed) => {
+            let mut fields = fields.clone();
+            fields.extend(updated_fields.iter().map(|(name, typ)| (name.clone(), typ.clone())));
+            let updated = Struct(fields, *rest, RowKind::Closed);
+            return unify(result, &updated, location, cache, TE::NeverShown);
+        },
+        Struct(_, rest, RowKind::Open) => new_row_variable(*rest, *rest, cache),
+        TypeVariable(id) => new_row_variable(*id, *id, cache),
+        _ => next_type_variable_id(cache),
+    };
+
+    let updated = Struct(updated_fields.clone(), rest, RowKind::Open);
+    unify(&record, &updated, location, cache, TE::RecordUpdateMismatch);
+    unify(result, &record, location, cache, TE::NeverShown);
+}
+
+/// Controls how a struct type is unified with a data type when the struct is on the
+/// actual (value) side. Unifying in the other direction, e.g. for a field access on a value
+/// of a data type, always only requires the struct's fields to be a subset.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum StructUnifyMode {
+    /// The struct's fields must be a subset of the data type's fields. This is the default.
+    #[default]
+    SubsetPermissive,
+
+    /// The struct's fields must exactly match the data type's fields. This rejects cases like
+    /// `baz { x: 3 }` where `baz` expects a type with fields `x` and `y`.
+    ExactOnly,
+}
+
 /// Like bind_struct_fields but enforces `fields` must be a subset of the fields in the template.
+/// See `StructUnifyMode` for requiring an exact match instead.
 fn bind_struct_fields_subset<'c>(
     fields: &BTreeMap<String, Type>, template: &BTreeMap<String, Type>, bindings: &mut UnificationBindings,
     location: Location<'c>, cache: &mut ModuleCache<'c>,
 ) -> Result<(), ()> {
-    // FIXME: Enforcing a struct type's fields are a subset of
+    // Enforcing a struct type's fields are a subset of
     // a data type's fields works for cases like
     // ```
     // foo bar = bar.x
@@ -874,13 +1258,14 @@ fn bind_struct_fields_subset<'c>(
     // type T = x: i32, y: i32
     // foo (T 2)
     // ```
-    // But for the following case it'd be unsound if we ever allowed struct literals:
+    // But for the following case it'd be unsound for struct literals:
     // ```
     // baz (t: T) = t.x + t.y
     //
-    // baz { x: 3 }
+    // baz (with x = 3)
     // ```
-    // Since the struct has a subset of T's fields this would currently pass.
+    // Since the struct has a subset of T's fields this would pass. This is why anonymous
+    // record literals have closed struct types, which never unify with a data type.
     if fields.len() > template.len() {
         return Err(());
     }
@@ -888,7 +1273,8 @@ fn bind_struct_fields_subset<'c>(
     for (name, field) in fields {
         match template.get(name) {
             Some(template_field) => {
-                try_unify_with_bindings_inner(template_field, field, bindings, location, cache)?;
+                let step = UnificationStep::Field(name.clone());
+                try_unify_step(step, template_field, field, bindings, location, cache)?;
             },
             None => return Err(()),
         }
@@ -930,7 +1316,7 @@ fn get_fields(
         TypeApplication(constructor, args) => {
             get_fields(&follow_bindings_in_cache_and_map(constructor, bindings, cache), args, bindings, cache)
         },
-        Struct(fields, rest) => match &cache.type_bindings[rest.0] {
+        Struct(fields, rest, _) => match &cache.type_bindings[rest.0] {
             Bound(binding) => get_fields(&binding.clone(), args, bindings, cache),
             Unbound(_, _) => Ok(fields.clone()),
         },
@@ -942,6 +1328,75 @@ fn get_fields(
     }
 }
 
+/// Unfold the recursive type `rec binder. body` once by replacing each
+/// occurrence of the binder in the body with the whole recursive type.
+fn unfold_rec_type(binder: TypeVariableId, body: &Type, cache: &ModuleCache<'_>) -> Type {
+    let rec_type = Rec(binder, Box::new(body.clone()));
+    bind_typevars(body, &HashMap::from([(binder, rec_type)]), cache)
+}
+
+/// Run the given unification of an unfolded recursive type, failing instead if too many
+/// recursive types are already being unfolded. Two recursive types which are equal but
+/// differ in how they are folded would otherwise be unfolded forever.
+fn with_unfold_fuel(
+    bindings: &mut UnificationBindings, f: impl FnOnce(&mut UnificationBindings) -> Result<(), ()>,
+) -> Result<(), ()> {
+    if bindings.unfold_depth >= RECURSION_LIMIT {
+        return Err(());
+    }
+
+    bindings.unfold_depth += 1;
+    let result = f(bindings);
+    bindings.unfold_depth -= 1;
+    result
+}
+
+/// Fold the type `typ`, which the unbound type variable `id` occurs within, into the recursive
+/// type `rec a. typ` where each occurrence of `id` is replaced with the fresh binder `a`. This
+/// fails if any occurrence of `id` is not guarded by a function or pointer type since values
+/// of the resulting type would be infinitely large, e.g. `a := (a, a)`.
+fn fold_rec_type(
+    id: TypeVariableId, level: LetBindingLevel, typ: &Type, bindings: &mut UnificationBindings,
+    cache: &mut ModuleCache<'_>,
+) -> Result<Type, ()> {
+    if occurs_unguarded(id, typ, bindings, cache) {
+        return Err(());
+    }
+
+    let binder = cache.next_type_variable_id(level);
+    let mut body = typ.clone();
+    body.walk_mut(|typ| {
+        *typ = follow_bindings_in_cache_and_map(typ, bindings, cache);
+        if *typ == TypeVariable(id) {
+            *typ = TypeVariable(binder);
+        }
+    });
+
+    if occurs_helper(id, level, &body, bindings, RECURSION_LIMIT, cache).occurs {
+        Err(())
+    } else {
+        Ok(Rec(binder, Box::new(body)))
+    }
+}
+
+/// True if the type variable `id` occurs within `typ` anywhere other than
+/// within a function type or behind a pointer or reference.
+fn occurs_unguarded(id: TypeVariableId, typ: &Type, bindings: &UnificationBindings, cache: &ModuleCache<'_>) -> bool {
+    match follow_bindings_in_cache_and_map(typ, bindings, cache) {
+        TypeVariable(var) => var == id,
+        TypeApplication(constructor, args) => {
+            let constructor = follow_bindings_in_cache_and_map(&constructor, bindings, cache);
+            !matches!(constructor, Primitive(PrimitiveType::Ptr) | Ref { .. })
+                && args.iter().any(|arg| occurs_unguarded(id, arg, bindings, cache))
+        },
+        Struct(fields, rest, _) => {
+            rest == id || fields.values().any(|field| occurs_unguarded(id, field, bindings, cache))
+        },
+        Rec(_, body) => occurs_unguarded(id, &body, bindings, cache),
+        _ => false,
+    }
+}
+
 /// Unify a single type variable (id arising from the type a) with an expected type b.
 /// Follows the given TypeBindings in bindings and the cache if a is Bound.
 pub fn try_unify_type_variable_with_bindings<'c>(
@@ -962,9 +1417,11 @@ pub fn try_unify_type_variable_with_bindings<'c>(
             let b = follow_bindings_in_cache_and_map(b, bindings, cache);
             if *a != b {
                 let result = occurs_helper(id, a_level, &b, bindings, RECURSION_LIMIT, cache);
+                bindings.deepest_type = bindings.deepest_type.max(RECURSION_LIMIT.saturating_sub(result.min_fuel));
                 if result.occurs {
-                    // TODO: Need better error messages for recursive types
-                    Err(())
+                    let rec_type = fold_rec_type(id, a_level, &b, bindings, cache)?;
+                    bindings.bindings.insert(id, rec_type);
+                    Ok(())
                 } else {
                     bindings.bindings.insert(id, b);
                     Ok(())
@@ -976,20 +1433,111 @@ pub fn try_unify_type_variable_with_bindings<'c>(
     }
 }
 
+/// Returns the lowest universe the given type may live in. Ordinary types live in
+/// `Universe 0` while a type mentioning `Universe n` lives in at least `Universe (n + 1)`.
+fn universe_level(typ: &Type, cache: &ModuleCache<'_>) -> u32 {
+    let mut level = 0;
+    typ.traverse(cache, |typ| {
+        if let Universe(inner) = typ {
+            level = level.max(inner.saturating_add(1));
+        }
+    });
+    level
+}
+
+/// Issue a warning the first time a type is found to nest deeper than
+/// DEEP_RECURSION_WARNING_THRESHOLD, before it hits the hard RECURSION_LIMIT.
+fn warn_if_near_recursion_limit<'c>(depth: u32, location: Location<'c>, cache: &mut ModuleCache<'c>) {
+    if depth >= DEEP_RECURSION_WARNING_THRESHOLD && !cache.warned_deep_type_recursion {
+        cache.warned_deep_type_recursion = true;
+        cache.push_diagnostic(location, D::DeepTypeRecursion(depth));
+    }
+}
+
 pub fn try_unify_with_bindings<'b>(
     actual: &Type, expected: &Type, bindings: &mut UnificationBindings, location: Location<'b>,
     cache: &mut ModuleCache<'b>, error: TypeErrorKind,
 ) -> Result<(), Diagnostic<'b>> {
-    match try_unify_with_bindings_inner(actual, expected, bindings, location, cache) {
+    let result = try_unify_with_bindings_inner(actual, expected, bindings, location, cache);
+
+    // Speculative unifications, e.g. while searching for impls, never show their errors or warnings
+    let depth = std::mem::take(&mut bindings.deepest_type);
+    if !matches!(error, TE::NeverShown) {
+        warn_if_near_recursion_limit(depth, location, cache);
+    }
+
+    match result {
         Ok(()) => Ok(()),
         Err(()) => {
+            let trace = std::mem::take(&mut bindings.trace);
+
+            // Errors which are never shown come from speculative unifications, e.g. while searching for impls
+            if cache.trace_unification && !matches!(error, TE::NeverShown) {
+                print_unification_trace(&trace, bindings, location, cache);
+            }
+
+            let error = if std::mem::take(&mut bindings.ownership_mismatch) {
+                TE::OwnershipMismatch
+            } else if std::mem::take(&mut bindings.universe_inconsistency) {
+                TE::UniverseInconsistency
+            } else {
+                error
+            };
             let t1 = actual.display(cache).to_string();
             let t2 = expected.display(cache).to_string();
-            Err(Diagnostic::new(location, D::TypeError(error, t1, t2)))
+            let mut diagnostic = Diagnostic::new(location, D::TypeError(error, t1, t2));
+
+            // Any macro expansion is already noted on the error itself
+            let note_location = Location { expansion: None, ..location };
+
+            let path = std::mem::take(&mut bindings.path);
+            match bindings.conflict.take() {
+                Some((actual_subterm, expected_subterm)) if !path.is_empty() => {
+                    // Name each subterm's typevars as they are named in the type it is a part of above
+                    let display = |typ, context, cache: &ModuleCache| {
+                        let typ = follow_bindings_in_cache_and_map(typ, bindings, cache);
+                        typ.display_within(context, cache).to_string()
+                    };
+                    let actual = display(&actual_subterm, actual, cache);
+                    let expected = display(&expected_subterm, expected, cache);
+                    let path = path.iter().rev().map(ToString::to_string).collect::<Vec<_>>().join(" of ");
+                    diagnostic =
+                        diagnostic.with_note(note_location, D::MismatchedSubterms(actual, expected, Rc::new(path)));
+                },
+                _ => (),
+            }
+
+            for effect in std::mem::take(&mut bindings.missing_effects) {
+                let effect = effects::display_effect(&effect, cache);
+                diagnostic = diagnostic.with_note(note_location, D::EffectNotAllowed(effect));
+            }
+            Err(diagnostic)
         },
     }
 }
 
+/// Print the path of nested unifications leading to a failed unification along
+/// with any type bindings made before it failed. This is only meant for debugging
+/// the typechecker itself.
+fn print_unification_trace(trace: &[String], bindings: &UnificationBindings, location: Location, cache: &ModuleCache) {
+    eprintln!("Unification failed at {}:{}", location.start.line, location.start.column);
+    for line in trace {
+        eprintln!("{line}");
+    }
+
+    let mut partial_bindings = bindings.bindings.iter().collect::<Vec<_>>();
+    partial_bindings.sort_by_key(|(id, _)| id.0);
+
+    if partial_bindings.is_empty() {
+        eprintln!("  with no partial bindings");
+    } else {
+        eprintln!("  with partial bindings:");
+        for (id, typ) in partial_bindings {
+            eprintln!("    {} := {}", TypeVariable(*id).debug(cache), typ.debug(cache));
+        }
+    }
+}
+
 /// A convenience wrapper for try_unify_with_bindings, creating an empty
 /// set of type bindings, and returning all the newly-created bindings on success,
 /// or the unification error message on error.
@@ -1000,6 +1548,205 @@ pub fn try_unify<'c>(
     try_unify_with_bindings(actual, expected, &mut bindings, location, cache, error_kind).map(|()| bindings)
 }
 
+/// Like `try_unify` but if unification fails and the two types are close to each other,
+/// e.g. they differ only by a reference or by the kind of an integer, a suggestion on how
+/// to fix the mismatch is attached to the error as a note.
+pub fn try_unify_soft<'c>(
+    actual: &Type, expected: &Type, location: Location<'c>, cache: &mut ModuleCache<'c>, error_kind: TypeErrorKind,
+) -> UnificationResult<'c> {
+    match try_unify(actual, expected, location, cache, error_kind) {
+        Ok(bindings) => Ok(bindings),
+        Err(diagnostic) => match suggest_fix(actual, expected, cache) {
+            Some(suggestion) => Err(diagnostic.with_note(Location { expansion: None, ..location }, suggestion)),
+            None => Err(diagnostic),
+        },
+    }
+}
+
+/// Returns a suggestion for how to fix a failed unification between the given two
+/// types if they are similar enough, without binding any type variables.
+fn suggest_fix(actual: &Type, expected: &Type, cache: &mut ModuleCache) -> Option<D> {
+    let unifies = |actual: &Type, expected: &Type, cache: &mut ModuleCache| {
+        try_unify(actual, expected, Location::builtin(), cache, TE::NeverShown).is_ok()
+    };
+
+    if let Some(element) = referenced_type(actual, cache) {
+        if unifies(&element, expected, cache) {
+            return Some(D::SuggestDereference);
+        }
+    }
+
+    if let Some(element) = referenced_type(expected, cache) {
+        if unifies(actual, &element, cache) {
+            return Some(D::SuggestReference);
+        }
+    }
+
+    match (integer_kind(actual, cache), integer_kind(expected, cache)) {
+        (Some(from), Some(to)) if from != to => Some(D::SuggestIntegerCast(from.to_string(), to.to_string())),
+        _ => None,
+    }
+}
+
+/// Returns the element type of the given reference type, or None if it is not a reference
+fn referenced_type(typ: &Type, cache: &ModuleCache) -> Option<Type> {
+    match cache.follow_bindings_shallow(typ) {
+        TypeApplication(constructor, args) if args.len() == 1 => match cache.follow_bindings_shallow(constructor) {
+            Ref { .. } => Some(args[0].clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// An implicit widening of an integer value from one integer type to a larger one,
+/// inserted by `try_unify_or_coerce` in place of a type error.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IntegerCoercion {
+    pub from: IntegerKind,
+    pub to: IntegerKind,
+}
+
+/// Like `try_unify` but if `actual`, the type of `value`, is an integer type which can be
+/// losslessly widened into the integer type `expected`, e.g. `I32` into `I64`, the two types
+/// are not unified. Instead `value` is wrapped in a node performing the coercion and the
+/// coercion inserted is returned.
+///
+/// Only the outermost types are coerced; `Ptr I32` still fails to unify with `Ptr I64`.
+/// Integers are never narrowed and polymorphic integer types like `Int a` with an unbound
+/// `a` unify normally without a coercion.
+pub fn try_unify_or_coerce<'c>(
+    value: &mut ast::Ast<'c>, actual: &Type, expected: &Type, location: Location<'c>, cache: &mut ModuleCache<'c>,
+    error_kind: TypeErrorKind,
+) -> Result<(UnificationBindings, Option<IntegerCoercion>), Diagnostic<'c>> {
+    match (integer_kind(actual, cache), integer_kind(expected, cache)) {
+        (Some(from), Some(to)) if from != to && widens_losslessly(from, to) => {
+            let coercion = IntegerCoercion { from, to };
+            insert_integer_coercion(value, coercion, cache);
+            Ok((UnificationBindings::empty(), Some(coercion)))
+        },
+        _ => try_unify(actual, expected, location, cache, error_kind).map(|bindings| (bindings, None)),
+    }
+}
+
+/// Replace `value` with `builtin "SignExtend" value` or `builtin "ZeroExtend" value`
+/// depending on the signedness of the integer type being widened.
+fn insert_integer_coercion<'c>(value: &mut ast::Ast<'c>, coercion: IntegerCoercion, cache: &mut ModuleCache<'c>) {
+    let location = value.locate();
+    let (from, to) = (Type::int(coercion.from), Type::int(coercion.to));
+    let extension = if is_unsigned(coercion.from) { "ZeroExtend" } else { "SignExtend" };
+
+    let builtin_type = Type::Function(FunctionType {
+        parameters: vec![Type::UserDefined(STRING_TYPE), from],
+        return_type: Box::new(to.clone()),
+        environment: Box::new(Type::UNIT),
+        effects: Box::new(Type::Effects(EffectSet::pure())),
+        has_varargs: false,
+    });
+
+    let mut builtin = ast::Ast::variable(vec![], "builtin".into(), location);
+    if let ast::Ast::Variable(variable) = &mut builtin {
+        variable.definition = Some(BUILTIN_ID);
+        variable.id = Some(cache.push_variable("builtin".into(), Some(BUILTIN_ID), location));
+        variable.typ = Some(builtin_type);
+    }
+
+    let mut extension = ast::Ast::string(extension.into(), location);
+    extension.set_type(Type::UserDefined(STRING_TYPE));
+
+    let original = std::mem::replace(value, ast::Ast::Error(ast::Error { location, typ: None }));
+    *value = ast::Ast::function_call(builtin, vec![extension, original], location);
+    value.set_type(to);
+}
+
+/// Returns the kind of the given integer type, or None if it is not an integer type
+/// or is a polymorphic integer type whose kind is not yet known.
+fn integer_kind(typ: &Type, cache: &ModuleCache) -> Option<IntegerKind> {
+    match cache.follow_bindings(typ) {
+        TypeApplication(constructor, args) => match (constructor.as_ref(), args.as_slice()) {
+            (Primitive(PrimitiveType::IntegerType), [Primitive(PrimitiveType::IntegerTag(kind))]) => Some(*kind),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// True if every value of the integer type `from` can be represented by the integer type `to`
+fn widens_losslessly(from: IntegerKind, to: IntegerKind) -> bool {
+    use IntegerKind::*;
+
+    // The (minimum, maximum) possible size in bits of each kind.
+    // Isz and Usz may be either 32 or 64 bits depending on the target.
+    let bits = |kind| match kind {
+        I8 | U8 => (8, 8),
+        I16 | U16 => (16, 16),
+        I32 | U32 => (32, 32),
+        I64 | U64 => (64, 64),
+        Isz | Usz => (32, 64),
+    };
+
+    let (_, from_max) = bits(from);
+    let (to_min, _) = bits(to);
+
+    match (is_unsigned(from), is_unsigned(to)) {
+        // A signed integer's negative values never fit into an unsigned integer
+        (false, true) => false,
+        // An unsigned integer needs one more bit to fit into a signed integer
+        (true, false) => from_max < to_min,
+        _ => from_max <= to_min,
+    }
+}
+
+fn is_unsigned(kind: IntegerKind) -> bool {
+    use IntegerKind::*;
+    matches!(kind, U8 | U16 | U32 | U64 | Usz)
+}
+
+/// Which side's type variable is kept when unifying two unbound type variables
+/// with `try_unify_biased`. The type variable on the other side is bound to it.
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Bias {
+    Left,
+    Right,
+}
+
+/// Like `try_unify` but if both types are unbound type variables, the variable on the side
+/// given by `bias` is kept and the other is bound to it. Normal unification always binds the
+/// `actual` type variable, so this is useful where the outcome of defaulting should not
+/// depend on the order the types happened to be unified in.
+#[allow(dead_code)]
+pub fn try_unify_biased<'c>(
+    actual: &Type, expected: &Type, bias: Bias, location: Location<'c>, cache: &mut ModuleCache<'c>,
+    error_kind: TypeErrorKind,
+) -> UnificationResult<'c> {
+    let actual_followed = cache.follow_bindings_shallow(actual).clone();
+    let expected_followed = cache.follow_bindings_shallow(expected).clone();
+
+    match (&actual_followed, &expected_followed, bias) {
+        (TypeVariable(_), TypeVariable(id), Bias::Left) => {
+            let mut bindings = UnificationBindings::empty();
+            match try_unify_type_variable_with_bindings(
+                *id,
+                &expected_followed,
+                &actual_followed,
+                false,
+                &mut bindings,
+                location,
+                cache,
+            ) {
+                Ok(()) => Ok(bindings),
+                Err(()) => {
+                    let t1 = actual.display(cache).to_string();
+                    let t2 = expected.display(cache).to_string();
+                    Err(Diagnostic::new(location, D::TypeError(error_kind, t1, t2)))
+                },
+            }
+        },
+        _ => try_unify(actual, expected, location, cache, error_kind),
+    }
+}
+
 /// Try to unify all the given type, with the given bindings in scope.
 /// Will add new bindings to the given TypeBindings and return them all on success.
 pub fn try_unify_all_with_bindings<'c>(
@@ -1078,6 +1825,7 @@ pub(super) fn find_all_typevars_helper(
         Primitive(_) => vec![],
         UserDefined(_) => vec![],
         Tag(_) => vec![],
+        Universe(_) => vec![],
         TypeVariable(id) => find_typevars_in_typevar_binding(*id, polymorphic_only, cache, fuel),
         NamedGeneric(id, _) => find_typevars_in_typevar_binding(*id, polymorphic_only, cache, fuel),
         Function(function) => {
@@ -1104,22 +1852,35 @@ pub(super) fn find_all_typevars_helper(
             type_variables.append(&mut find_all_typevars_helper(lifetime, polymorphic_only, cache, fuel));
             type_variables
         },
-        Struct(fields, id) => match &cache.type_bindings[id.0] {
+        Struct(fields, id, kind) => match &cache.type_bindings[id.0] {
             Bound(t) => find_all_typevars_helper(t, polymorphic_only, cache, fuel),
             Unbound(..) => {
-                let mut vars = find_typevars_in_typevar_binding(*id, polymorphic_only, cache, fuel);
+                // A closed struct's row variable is never bound so there is no need to generalize it
+                let mut vars = match kind {
+                    RowKind::Open => find_typevars_in_typevar_binding(*id, polymorphic_only, cache, fuel),
+                    RowKind::Closed => vec![],
+                };
                 for field in fields.values() {
                     vars.append(&mut find_all_typevars_helper(field, polymorphic_only, cache, fuel));
                 }
                 vars
             },
         },
+        Rec(binder, body) => {
+            let mut vars = find_all_typevars_helper(body, polymorphic_only, cache, fuel);
+            vars.retain(|var| var != binder);
+            vars
+        },
         Effects(effects) => effects.find_all_typevars(polymorphic_only, cache, fuel),
     }
 }
 
 /// Helper for find_all_typevars which gets the TypeBinding for a given
 /// TypeVariableId and either recurses on it if it is bound or returns it.
+///
+/// The free type variables of a bound type variable are memoized in `cache.free_typevars`
+/// regardless of `polymorphic_only`. Since type variable levels may still be lowered after
+/// memoizing them, whether each is polymorphic is only checked when they are returned.
 pub(super) fn find_typevars_in_typevar_binding(
     id: TypeVariableId, polymorphic_only: bool, cache: &ModuleCache, fuel: u32,
 ) -> Vec<TypeVariableId> {
@@ -1130,7 +1891,29 @@ pub(super) fn find_typevars_in_typevar_binding(
     let fuel = fuel - 1;
 
     match &cache.type_bindings[id.0] {
-        Bound(t) => find_all_typevars_helper(t, polymorphic_only, cache, fuel),
+        Bound(t) => {
+            let typevars = match memoized_free_typevars(id, cache) {
+                Some(typevars) => {
+                    cache.free_typevars_memo_hits.set(cache.free_typevars_memo_hits.get() + 1);
+                    typevars
+                },
+                None => {
+                    let typevars = Rc::new(find_all_typevars_helper(t, false, cache, fuel));
+                    cache.free_typevars.borrow_mut().insert(id, typevars.clone());
+                    typevars
+                },
+            };
+
+            let is_polymorphic = |id: &&TypeVariableId| match &cache.type_bindings[id.0] {
+                Unbound(level, _) => level_is_polymorphic(*level),
+                Bound(_) => unreachable!("memoized free type variables should still be unbound"),
+            };
+
+            match polymorphic_only {
+                true => typevars.iter().filter(is_polymorphic).copied().collect(),
+                false => typevars.to_vec(),
+            }
+        },
         Unbound(level, _) => {
             if !polymorphic_only || level_is_polymorphic(*level) {
                 vec![id]
@@ -1141,6 +1924,14 @@ pub(super) fn find_typevars_in_typevar_binding(
     }
 }
 
+/// Returns the memoized free type variables of the given bound type variable, if there
+/// are any and none of them have since been bound.
+fn memoized_free_typevars(id: TypeVariableId, cache: &ModuleCache) -> Option<Rc<Vec<TypeVariableId>>> {
+    let typevars = cache.free_typevars.borrow().get(&id).cloned()?;
+    let still_free = typevars.iter().all(|typevar| matches!(cache.type_bindings[typevar.0], Unbound(..)));
+    still_free.then_some(typevars)
+}
+
 fn find_all_typevars_in_traits(traits: &TraitConstraints, cache: &ModuleCache<'_>) -> Vec<TypeVariableId> {
     let mut typevars = vec![];
     for constraint in traits.iter() {
@@ -1165,6 +1956,215 @@ fn generalize(typ: &Type, cache: &ModuleCache<'_>) -> GeneralizedType {
     }
 }
 
+/// Infer the most general type of a standalone expression, e.g. `fn x -> x`
+/// yields `forall a. (a -> a)`. Unlike `infer`, the resulting type is generalized
+/// over any type variables introduced while inferring the expression.
+///
+/// The expression is inferred one LetBindingLevel deeper than the current level so
+/// that only its own type variables are generalized, and CURRENT_LEVEL is restored
+/// afterward. Any trait constraints required by the expression are not included.
+#[allow(dead_code)]
+pub fn principal_type<'a>(ast: &mut ast::Ast<'a>, cache: &mut ModuleCache<'a>) -> GeneralizedType {
+    let previous_level = CURRENT_LEVEL.fetch_add(1, Ordering::SeqCst);
+    let result = infer(ast, cache);
+
+    // Generalize from the outer level, the same as is done for the lhs of a Definition
+    CURRENT_LEVEL.store(previous_level, Ordering::SeqCst);
+    generalize(&result.typ, cache)
+}
+
+/// Infer the type of a standalone expression, e.g. one entered into a REPL, with all of
+/// its type variable bindings applied. Unlike `infer_ast`, this is safe to call repeatedly:
+/// CURRENT_LEVEL is restored afterward and any diagnostics issued while inferring the
+/// expression are returned instead of being added to the cache. Warnings are discarded
+/// if there were no errors.
+#[allow(dead_code)]
+pub fn infer_expression<'a>(ast: &mut ast::Ast<'a>, cache: &mut ModuleCache<'a>) -> Result<Type, Vec<Diagnostic<'a>>> {
+    let previous_level = CURRENT_LEVEL.swap(INITIAL_LEVEL, Ordering::SeqCst);
+    let previous_diagnostic_count = cache.diagnostics.len();
+    let previous_error_count = cache.error_count;
+
+    let result = infer(ast, cache);
+    traitchecker::resolve_traits(result.traits, &[], cache);
+    CURRENT_LEVEL.store(previous_level, Ordering::SeqCst);
+
+    let diagnostics = cache.diagnostics.split_off(previous_diagnostic_count);
+    let had_errors = cache.error_count != previous_error_count;
+    cache.error_count = previous_error_count;
+
+    if had_errors {
+        Err(diagnostics)
+    } else {
+        Ok(cache.follow_bindings(&result.typ))
+    }
+}
+
+/// Search every already type-checked definition for ones whose type unifies with the
+/// given query type, e.g. searching for `a -> a` would return `id`, among others.
+///
+/// Since this cannot bind any type variables in the cache, the forall-quantified variables
+/// of each definition are matched against the query directly rather than instantiated first.
+/// The effects and environments of function types are ignored when searching.
+#[allow(dead_code)]
+pub fn find_by_type(query: &Type, cache: &ModuleCache) -> Vec<DefinitionInfoId> {
+    let mut results = vec![];
+
+    for (index, info) in cache.definition_infos.iter().enumerate() {
+        let typ = match &info.typ {
+            Some(GeneralizedType::MonoType(typ) | GeneralizedType::PolyType(_, typ)) => typ,
+            None => continue,
+        };
+
+        let mut bindings = UnificationBindings::empty();
+        if types_could_unify(typ, query, &mut bindings, cache) {
+            results.push(DefinitionInfoId(index));
+        }
+    }
+
+    results
+}
+
+/// A read-only approximation of unification used by `find_by_type` and exhaustiveness checking.
+/// Any type variable bindings needed are recorded in `bindings` rather than the cache.
+pub(super) fn types_could_unify(a: &Type, b: &Type, bindings: &mut UnificationBindings, cache: &ModuleCache) -> bool {
+    match (a, b) {
+        (TypeVariable(id), other) | (other, TypeVariable(id)) => type_variable_could_unify(*id, other, bindings, cache),
+        (Primitive(a), Primitive(b)) => a == b,
+        (UserDefined(a), UserDefined(b)) => a == b,
+        (NamedGeneric(a, _), NamedGeneric(b, _)) => a == b,
+        (Function(a), Function(b)) => {
+            a.parameters.len() == b.parameters.len()
+                && a.parameters.iter().zip(&b.parameters).all(|(a, b)| types_could_unify(a, b, bindings, cache))
+                && types_could_unify(&a.return_type, &b.return_type, bindings, cache)
+        },
+        (TypeApplication(a_constructor, a_args), TypeApplication(b_constructor, b_args)) => {
+            a_args.len() == b_args.len()
+                && types_could_unify(a_constructor, b_constructor, bindings, cache)
+                && a_args.iter().zip(b_args).all(|(a, b)| types_could_unify(a, b, bindings, cache))
+        },
+        (Ref { mutability, sharedness, lifetime }, Ref { mutability: m, sharedness: s, lifetime: l }) => {
+            types_could_unify(mutability, m, bindings, cache)
+                && types_could_unify(sharedness, s, bindings, cache)
+                && types_could_unify(lifetime, l, bindings, cache)
+        },
+        (a, b) => {
+            follow_bindings_in_cache_and_map(a, bindings, cache) == follow_bindings_in_cache_and_map(b, bindings, cache)
+        },
+    }
+}
+
+fn type_variable_could_unify(
+    id: TypeVariableId, other: &Type, bindings: &mut UnificationBindings, cache: &ModuleCache,
+) -> bool {
+    match find_binding(id, bindings, cache) {
+        Bound(typ) => types_could_unify(&typ, other, bindings, cache),
+        Unbound(..) => {
+            if *other != TypeVariable(id) {
+                bindings.bindings.insert(id, other.clone());
+            }
+            true
+        },
+    }
+}
+
+/// The trait constraints a definition requires of its callers, e.g. `Print a, Cmp b`
+/// for a function which prints its first argument and compares its second.
+///
+/// These are the constraints propagated out of the definition rather than resolved locally,
+/// as bound when the definition was generalized. Constraints differing only in the callsite
+/// they originate from are returned once.
+#[allow(dead_code)]
+pub fn pending_constraints(id: DefinitionInfoId, cache: &ModuleCache) -> Vec<ConstraintSignature> {
+    let mut constraints: Vec<ConstraintSignature> = vec![];
+
+    for required_trait in &cache[id].required_traits {
+        let signature = &required_trait.signature;
+        let args = fmap(&signature.args, |arg| follow_bindings_in_cache(arg, cache));
+
+        let is_duplicate = constraints.iter().any(|constraint| {
+            constraint.trait_id == signature.trait_id
+                && fmap(&constraint.args, |arg| follow_bindings_in_cache(arg, cache)) == args
+        });
+
+        if !is_duplicate {
+            constraints.push(signature.clone());
+        }
+    }
+
+    constraints
+}
+
+/// The location of every variable referring to the given definition, e.g. to find each
+/// usage that must change when renaming it. These are recorded during type inference,
+/// so this is empty for definitions in code which has not yet been type checked.
+#[allow(dead_code)]
+pub fn references_to<'c>(id: DefinitionInfoId, cache: &ModuleCache<'c>) -> Vec<Location<'c>> {
+    cache.references.get(&id).cloned().unwrap_or_default()
+}
+
+/// The distinct type arguments the given definition was instantiated with at each of its uses,
+/// in the order of the type variables in its generalized type. For example, if `id` is used
+/// at I32 and String, this returns `[[I32], [String]]`. Uses within the definition itself, made
+/// before it was generalized, are not included. This is empty for monomorphic definitions.
+#[allow(dead_code)]
+pub fn monomorphizations(id: DefinitionInfoId, cache: &ModuleCache) -> Vec<Vec<Type>> {
+    let Some(GeneralizedType::PolyType(typevars, _)) = &cache[id].typ else {
+        return vec![];
+    };
+
+    let mut monomorphizations = Vec::new();
+    for mapping in cache.instantiations.get(&id).into_iter().flatten() {
+        let args = typevars.iter().map(|typevar| mapping.get(typevar).map(|typ| cache.follow_bindings(typ)));
+
+        if let Some(args) = args.collect::<Option<Vec<_>>>() {
+            if !monomorphizations.contains(&args) {
+                monomorphizations.push(args);
+            }
+        }
+    }
+    monomorphizations
+}
+
+/// Each type variable which is still unbound in the type of a global definition but was
+/// not generalized, along with that definition and its location. These indicate the type
+/// of the definition is ambiguous, e.g. `empty = Vec.empty ()` leaves the element type unknown.
+/// The kinds of `Int a` types are not included since these are defaulted to I32 later on.
+/// This should be called after `infer_ast` since every type is still unresolved before then.
+#[allow(dead_code)]
+pub fn unresolved_variables<'c>(cache: &ModuleCache<'c>) -> Vec<(DefinitionInfoId, TypeVariableId, Location<'c>)> {
+    let mut unresolved = vec![];
+
+    for (index, info) in cache.definition_infos.iter().enumerate() {
+        let Some(typ) = info.typ.as_ref().filter(|_| info.global) else {
+            continue;
+        };
+
+        let generalized = match typ {
+            GeneralizedType::PolyType(typevars, _) => typevars.as_slice(),
+            GeneralizedType::MonoType(_) => &[],
+        };
+
+        let mut integer_kinds = vec![];
+        cache.follow_bindings(typ.remove_forall()).walk_mut(|typ| {
+            if let TypeApplication(constructor, args) = typ {
+                if let (Primitive(PrimitiveType::IntegerType), [TypeVariable(id)]) =
+                    (constructor.as_ref(), args.as_slice())
+                {
+                    integer_kinds.push(*id);
+                }
+            }
+        });
+
+        for typevar in dedup(find_all_typevars(typ.remove_forall(), false, cache)) {
+            if !generalized.contains(&typevar) && !integer_kinds.contains(&typevar) {
+                unresolved.push((DefinitionInfoId(index), typevar, info.location));
+            }
+        }
+    }
+
+    unresolved
+}
+
 /// Mark a given DefinitionInfoId as currently being type checked
 fn mark_id_in_progress(id: DefinitionInfoId, cache: &mut ModuleCache) {
     cache.call_stack.push(id);
@@ -1195,6 +2195,17 @@ fn mark_id_finished(id: DefinitionInfoId, cache: &mut ModuleCache) {
 fn infer_nested_definition(
     definition_id: DefinitionInfoId, impl_scope: ImplScopeId, callsite: VariableId, cache: &mut ModuleCache,
 ) -> (GeneralizedType, TraitConstraints) {
+    let mut constraints = infer_definition_kind(definition_id, cache);
+    constraints.append(&mut to_trait_constraints(definition_id, impl_scope, callsite, cache));
+
+    let info = &cache.definition_infos[definition_id.0];
+    (info.typ.clone().unwrap(), constraints)
+}
+
+/// Infer the type of the given definition from wherever it was defined, returning the trait
+/// constraints found while doing so. Unlike `infer_nested_definition`, the constraints the
+/// definition requires of its callers are not included since there is no callsite.
+fn infer_definition_kind(definition_id: DefinitionInfoId, cache: &mut ModuleCache) -> TraitConstraints {
     let definition = cache[definition_id].definition.as_mut().unwrap();
 
     // DefinitionKind::Definition marks its ids internally when we call infer(definition, _).
@@ -1207,7 +2218,7 @@ fn infer_nested_definition(
 
     let definition = cache[definition_id].definition.as_mut().unwrap();
 
-    let mut constraints = match definition {
+    let constraints = match definition {
         DefinitionKind::Definition(definition) => {
             let definition = trustme::extend_lifetime(*definition);
             infer(definition, cache).traits
@@ -1233,10 +2244,126 @@ fn infer_nested_definition(
         mark_id_finished(definition_id, cache);
     }
 
-    constraints.append(&mut to_trait_constraints(definition_id, impl_scope, callsite, cache));
+    constraints
+}
 
-    let info = &cache.definition_infos[definition_id.0];
-    (info.typ.clone().unwrap(), constraints)
+/// Re-infer the type of the given global definition after its body has changed, reusing the
+/// types already inferred for each unchanged definition. The cached types of this definition,
+/// each definition transitively depending on it, and any definitions mutually recursive with
+/// these are cleared first so that all of them are inferred again. Definitions belonging to
+/// trait impls keep their types since these are determined by the trait being implemented.
+///
+/// Returns the new type of the given definition.
+#[allow(dead_code)]
+pub fn reset_for_incremental<'c>(id: DefinitionInfoId, cache: &mut ModuleCache<'c>) -> GeneralizedType {
+    let invalidated = definitions_to_reinfer(id, cache);
+
+    for id in &invalidated {
+        clear_definition_for_reinference(*id, cache);
+    }
+
+    let previous_level = CURRENT_LEVEL.swap(INITIAL_LEVEL, Ordering::SeqCst);
+    let mut traits = vec![];
+
+    // Inferring one definition may infer others it depends on, so skip any already inferred
+    for id in invalidated {
+        if cache[id].typ.is_none() && cache[id].definition.is_some() {
+            traits.append(&mut infer_definition_kind(id, cache));
+        }
+    }
+
+    CURRENT_LEVEL.store(INITIAL_LEVEL - 1, Ordering::SeqCst);
+    let exposed_traits = traitchecker::resolve_traits(traits, &[], cache);
+    assert!(exposed_traits.is_empty());
+    CURRENT_LEVEL.store(previous_level, Ordering::SeqCst);
+
+    cache[id].typ.clone().unwrap()
+}
+
+/// Returns the given definition along with each global definition transitively referring to it,
+/// including every definition in the mutual recursion set of any of these, in the order they
+/// were defined. References are those recorded during the previous type inference pass.
+fn definitions_to_reinfer(id: DefinitionInfoId, cache: &ModuleCache) -> Vec<DefinitionInfoId> {
+    let global_definitions =
+        cache.definition_infos.iter().enumerate().filter_map(|(index, info)| match &info.definition {
+            Some(DefinitionKind::Definition(definition)) if info.global => {
+                Some((DefinitionInfoId(index), definition.location))
+            },
+            _ => None,
+        });
+    let global_definitions: Vec<_> = global_definitions.collect();
+
+    let dependents = |id: DefinitionInfoId| {
+        let references = cache.references.get(&id).map_or(&[][..], |references| references.as_slice());
+        global_definitions.iter().filter_map(move |(dependent, location)| {
+            let refers_to_id = references.iter().any(|reference| {
+                reference.filename == location.filename && location.contains_index(&reference.start.index)
+            });
+            refers_to_id.then_some(*dependent)
+        })
+    };
+
+    let mut invalidated = BTreeSet::new();
+    let mut queue = vec![id];
+
+    while let Some(id) = queue.pop() {
+        if !invalidated.insert(id) {
+            continue;
+        }
+
+        // Mutually recursive definitions are generalized together, so none of them may be
+        // re-inferred without the others.
+        if let Some(set) = cache[id].mutually_recursive_set {
+            let set = &cache.mutual_recursion_sets[set.0];
+            queue.push(set.root_definition);
+            queue.extend(set.definitions.iter().copied());
+        }
+
+        queue.extend(dependents(id));
+    }
+
+    invalidated.into_iter().collect()
+}
+
+/// Clear the inferred types of the given definition so that it may be inferred again. This
+/// includes its Ast and each local variable, parameter, and other name bound within it.
+/// Other kinds of definitions, e.g. type constructors, have types which cannot change
+/// without name resolution being redone, so they are left as is.
+fn clear_definition_for_reinference(id: DefinitionInfoId, cache: &mut ModuleCache) {
+    let info = &mut cache[id];
+    let location = match &mut info.definition {
+        Some(DefinitionKind::Definition(definition)) if info.trait_impl.is_none() => {
+            let definition = trustme::extend_lifetime(*definition);
+            clear_definition_types(definition);
+            definition.location
+        },
+        _ => return,
+    };
+
+    let within = |other: Location| other.filename == location.filename && location.contains_index(&other.start.index);
+
+    for (index, info) in cache.definition_infos.iter_mut().enumerate() {
+        let is_binding = matches!(
+            info.definition,
+            Some(DefinitionKind::Definition(_) | DefinitionKind::Parameter | DefinitionKind::MatchPattern)
+        );
+
+        if is_binding && within(info.location) {
+            info.typ = None;
+            info.required_traits.clear();
+            info.undergoing_type_inference = false;
+            info.mutually_recursive_set = None;
+            info.mutually_recursive_variables.clear();
+            cache.instantiations.remove(&DefinitionInfoId(index));
+        }
+    }
+
+    // Impls are selected for each variable again when its constraints are resolved
+    for info in cache.variable_infos.iter_mut() {
+        if within(info.location) {
+            info.required_impls.clear();
+        }
+    }
 }
 
 /// Infer the type of all the closed-over variables within a lambda so when we
@@ -1257,9 +2384,17 @@ fn bind_closure_environment(environment: &mut ClosureEnvironment, cache: &mut Mo
     }
 }
 
-fn infer_closure_environment(environment: &ClosureEnvironment, cache: &mut ModuleCache<'_>) -> Type {
-    let mut environment =
-        fmap(environment, |(_from, (_, to, _))| cache[*to].typ.as_ref().unwrap().clone().into_monotype());
+fn infer_closure_environment<'c>(
+    environment: &ClosureEnvironment, location: Location<'c>, cache: &mut ModuleCache<'c>,
+) -> Type {
+    let mut environment = fmap(environment, |(_from, (_, to, _))| {
+        let typ = cache[*to].typ.as_ref().unwrap().clone().into_monotype();
+        if reference_is_more_local_than_closure(&typ, cache) {
+            let name = cache[*to].name.clone();
+            cache.push_diagnostic(location, D::PotentialDanglingCapture(name));
+        }
+        typ
+    });
 
     if environment.is_empty() {
         // Non-closure functions have an environment of type unit
@@ -1271,6 +2406,43 @@ fn infer_closure_environment(environment: &ClosureEnvironment, cache: &mut Modul
     }
 }
 
+/// Issue an error for each variable captured by a closure which is missing from its explicit
+/// capture list. This must be done after the closure's body is inferred so each error can
+/// point to the first use of the captured variable.
+fn check_capture_list<'c>(
+    captures: &[String], environment: &ClosureEnvironment, location: Location<'c>, cache: &mut ModuleCache<'c>,
+) {
+    for (_, to, _) in environment.values() {
+        let name = &cache[*to].name;
+        if !captures.contains(name) {
+            let name = name.clone();
+            let location = cache.references.get(to).and_then(|uses| uses.first().copied()).unwrap_or(location);
+            cache.push_diagnostic(location, D::CaptureNotDeclared(name));
+        }
+    }
+}
+
+/// A captured reference is more local than the closure capturing it if its lifetime
+/// variable was created at a deeper LetBindingLevel than the one the closure is defined
+/// at and was never unified with anything at the closure's level or above. If the
+/// closure is returned, the reference may outlive the value it refers to.
+fn reference_is_more_local_than_closure(typ: &Type, cache: &ModuleCache<'_>) -> bool {
+    let TypeApplication(constructor, _) = cache.follow_bindings_shallow(typ) else {
+        return false;
+    };
+    let Ref { lifetime, .. } = cache.follow_bindings_shallow(constructor) else {
+        return false;
+    };
+
+    match cache.follow_bindings_shallow(lifetime) {
+        TypeVariable(id) => match &cache.type_bindings[id.0] {
+            Unbound(level, _) => level.0 > CURRENT_LEVEL.load(Ordering::SeqCst),
+            Bound(_) => false,
+        },
+        _ => false,
+    }
+}
+
 /// Makes a tuple out of nested pairs with elements from the
 /// given Vec of types. Since this is made from nested pairs
 /// and includes no type terminator, it requires at least 2
@@ -1287,6 +2459,23 @@ fn make_tuple_type(mut types: Vec<Type>) -> Type {
     ret
 }
 
+/// A varargs function used where a function of a fixed arity is expected takes on that fixed
+/// arity, e.g. `print_int: String - I32 -> I32 = printf`. Returns `expected` if this is the case
+/// and `actual` otherwise, including when `expected` has fewer parameters than the varargs
+/// function's fixed parameters.
+fn specialize_varargs<'t>(actual: &'t Type, expected: &'t Type, cache: &ModuleCache) -> &'t Type {
+    match (cache.follow_bindings_shallow(actual), cache.follow_bindings_shallow(expected)) {
+        (Function(actual_function), Function(expected_function))
+            if actual_function.has_varargs
+                && !expected_function.has_varargs
+                && expected_function.parameters.len() >= actual_function.parameters.len() =>
+        {
+            expected
+        },
+        _ => actual,
+    }
+}
+
 /// Binds a given type to an irrefutable pattern, recursing on the pattern and verifying
 /// that it is indeed irrefutable. If should_generalize is true, this generalizes the type given
 /// to any variable encountered. Appends the given required_traits list in the DefinitionInfo's
@@ -1305,6 +2494,13 @@ pub(super) fn bind_irrefutable_pattern<'c>(
             },
             _ => cache.push_diagnostic(ast.locate(), D::PatternIsNotIrrefutable),
         },
+        // `_` discards its value without binding a name. Since it can never be referenced,
+        // there is no previous type to check against and no use for any required traits.
+        Variable(variable) if is_wildcard(variable) => {
+            let typ = if should_generalize { generalize(typ, cache) } else { GeneralizedType::MonoType(typ.clone()) };
+            variable.typ = Some(typ.remove_forall().clone());
+            cache.definition_infos[variable.definition.unwrap().0].typ = Some(typ);
+        },
         Variable(variable) => {
             let definition_id = variable.definition.unwrap();
             let info = &cache.definition_infos[definition_id.0];
@@ -1317,8 +2513,21 @@ pub(super) fn bind_irrefutable_pattern<'c>(
                     should_generalize = true;
                 }
 
-                let existing_type = existing_type.remove_forall();
-                unify(&existing_type.clone(), typ, variable.location, cache, TE::VariableDoesNotMatchDeclaredType);
+                let existing_type = existing_type.remove_forall().clone();
+                let error_kind = TE::VariableDoesNotMatchDeclaredType;
+
+                match try_unify(&existing_type, typ, variable.location, cache, error_kind) {
+                    Ok(bindings) => bindings.perform(cache),
+                    Err(error) => {
+                        if !should_generalize && needs_polymorphic_recursion(definition_id, &existing_type, typ, cache)
+                        {
+                            let name = cache[definition_id].name.clone();
+                            cache.push_diagnostic(variable.location, D::PolymorphicRecursionNeedsAnnotation(name));
+                        } else {
+                            cache.push_full_diagnostic(error);
+                        }
+                    },
+                }
             }
 
             let typ = if should_generalize { generalize(typ, cache) } else { GeneralizedType::MonoType(typ.clone()) };
@@ -1337,6 +2546,7 @@ pub(super) fn bind_irrefutable_pattern<'c>(
                 cache,
                 TE::PatternTypeDoesNotMatchAnnotatedType,
             );
+            let typ = specialize_varargs(typ, annotation.typ.as_ref().unwrap(), cache);
             bind_irrefutable_pattern(annotation.lhs.as_mut(), typ, required_traits, should_generalize, cache);
         },
         // TODO: All struct patterns
@@ -1367,12 +2577,31 @@ pub(super) fn bind_irrefutable_pattern<'c>(
                 _ => unreachable!(),
             }
         },
+        Reference(reference) => {
+            let element = next_type_variable(cache);
+            let reference_type = reference_pattern_type(reference.mutability, cache);
+            let reference_type = Type::TypeApplication(Box::new(reference_type), vec![element.clone()]);
+            unify(&reference_type, typ, reference.location, cache, TE::ExpectedReferenceTypeFromPattern);
+
+            reference.typ = Some(reference_type);
+            let expression = reference.expression.as_mut();
+            bind_irrefutable_pattern(expression, &element, required_traits, should_generalize, cache);
+        },
         _ => {
             cache.push_diagnostic(ast.locate(), D::InvalidSyntaxInIrrefutablePattern);
         },
     }
 }
 
+/// The reference type a reference pattern like `&x` or `!x` is expected to match on
+fn reference_pattern_type(mutability: ast::Mutability, cache: &mut ModuleCache) -> Type {
+    Type::Ref {
+        mutability: Box::new(Type::Tag(mutability.as_tag())),
+        sharedness: Box::new(Type::Tag(TypeTag::Shared)),
+        lifetime: Box::new(next_type_variable(cache)),
+    }
+}
+
 fn get_pattern_type<'local, 'c>(
     pattern: &'local ast::Ast<'c>, cache: &mut ModuleCache<'c>,
 ) -> Option<Cow<'local, Type>> {
@@ -1383,6 +2612,7 @@ fn get_pattern_type<'local, 'c>(
             LiteralKind::Unit => Some(Cow::Owned(Type::UNIT)),
             _ => None,
         },
+        Variable(variable) if is_wildcard(variable) => Some(Cow::Owned(next_type_variable(cache))),
         Variable(variable) => {
             let definition_id = variable.definition.unwrap();
             let info = &cache.definition_infos[definition_id.0];
@@ -1407,10 +2637,20 @@ fn get_pattern_type<'local, 'c>(
             let pair_type = Box::new(Type::UserDefined(PAIR_TYPE));
             Some(Cow::Owned(Type::TypeApplication(pair_type, vec![arg1, arg2])))
         },
+        Reference(reference) => {
+            let element = get_pattern_type(&reference.expression, cache)?.into_owned();
+            let reference_type = reference_pattern_type(reference.mutability, cache);
+            Some(Cow::Owned(Type::TypeApplication(Box::new(reference_type), vec![element])))
+        },
         _ => None,
     }
 }
 
+/// True if this variable is the `_` pattern which discards its value
+fn is_wildcard(variable: &ast::Variable) -> bool {
+    matches!(&variable.kind, ast::VariableKind::Identifier(name) if name == "_")
+}
+
 fn lookup_definition_type_in_trait(name: &str, trait_id: TraitInfoId, cache: &mut ModuleCache<'_>) -> GeneralizedType {
     let trait_info = &cache.trait_infos[trait_id.0];
     for definition_id in trait_info.definitions.iter() {
@@ -1475,6 +2715,7 @@ pub(super) fn foreach_variable<'a>(
     match pattern {
         Variable(variable) => f(variable, cache),
         TypeAnnotation(annotation) => foreach_variable(annotation.lhs.as_ref(), cache, f),
+        Reference(reference) => foreach_variable(reference.expression.as_ref(), cache, f),
         FunctionCall(call) => {
             for arg in &call.args {
                 foreach_variable(arg, cache, f);
@@ -1514,6 +2755,34 @@ fn bind_irrefutable_pattern_in_impl<'a>(
     });
 }
 
+/// True if a function's inferred type conflicts with the monomorphic placeholder type
+/// it was given while being inferred only because a recursive reference to it uses it
+/// at a different type. E.g. `f x = f (x, x)` would need `f : forall a. a -> b`.
+/// Since placeholders are never generalized, these functions need a type signature.
+fn needs_polymorphic_recursion(
+    definition_id: DefinitionInfoId, placeholder: &Type, inferred: &Type, cache: &mut ModuleCache,
+) -> bool {
+    let info = &cache[definition_id];
+    let Some(DefinitionKind::Definition(definition)) = &info.definition else {
+        return false;
+    };
+
+    let body = definition.expr.locate();
+    let is_recursive = cache.references.get(&definition_id).is_some_and(|references| {
+        references
+            .iter()
+            .any(|reference| reference.filename == body.filename && body.contains_index(&reference.start.index))
+    });
+
+    if !info.undergoing_type_inference || !is_recursive {
+        return false;
+    }
+
+    // If a fresh copy of the placeholder would have been compatible, the only conflict is in how it was instantiated
+    let (fresh, _) = replace_all_typevars(std::slice::from_ref(placeholder), cache);
+    try_unify(&fresh[0], inferred, body, cache, TE::NeverShown).is_ok()
+}
+
 /// Checks that the traits used in `pattern` are a subset of traits used in the `given` list of
 /// an impl or in the `given` list of the corresponding function in the trait declaration.
 fn check_impl_propagated_traits<'a>(
@@ -1552,6 +2821,115 @@ fn check_impl_propagated_traits<'a>(
     });
 }
 
+/// Resolves the traits left over from a `private` helper of an impl. A helper which is not
+/// generalized may still use the traits in the impl's `given` clause, so these are attached
+/// to the helper rather than resolved here. Any other trait must be resolved now so that
+/// it is not propagated outside of the impl.
+fn check_impl_helper_traits<'a>(
+    pattern: &ast::Ast<'a>, traits: TraitConstraints, given: &[ConstraintSignature], cache: &mut ModuleCache<'a>,
+) {
+    let mut given_traits = vec![];
+
+    for mut trait_ in traits {
+        if let Some(id) = find_matching_trait(&trait_.required, &[], given, cache) {
+            trait_.required.signature.id = id;
+            given_traits.push(trait_.required);
+        } else {
+            traitchecker::force_resolve_trait(trait_, cache);
+        }
+    }
+
+    if !given_traits.is_empty() {
+        foreach_variable(pattern, cache, &mut |variable, cache| {
+            let definition_id = variable.definition.unwrap();
+            cache[definition_id].required_traits.extend(given_traits.iter().cloned());
+        });
+    }
+}
+
+/// Checks that the given impl respects its trait's functional dependencies. That is,
+/// no earlier impl of the same trait may agree with this impl on the trait's arguments
+/// while disagreeing on the types they determine. E.g. `impl Foo I32 String` conflicts
+/// with a previous `impl Foo I32 Char` for `trait Foo a -> b`.
+fn check_functional_dependencies<'a>(impl_id: ImplInfoId, cache: &mut ModuleCache<'a>) {
+    let info = &cache[impl_id];
+    let trait_info = &cache.trait_infos[info.trait_id.0];
+    let arg_count = trait_info.typeargs.len();
+
+    // Mismatched argument counts are already reported during name resolution
+    if trait_info.fundeps.is_empty() || info.typeargs.len() != arg_count + trait_info.fundeps.len() {
+        return;
+    }
+
+    let trait_id = info.trait_id;
+    let trait_name = trait_info.name.clone();
+    let location = info.location;
+    let (args, determined) = info.typeargs.split_at(arg_count);
+    let (args, determined) = (args.to_vec(), determined.to_vec());
+
+    for previous_id in 0..impl_id.0 {
+        let previous = &cache.impl_infos[previous_id];
+        if previous.trait_id != trait_id || previous.typeargs.len() != args.len() + determined.len() {
+            continue;
+        }
+
+        let (previous_args, previous_determined) = previous.typeargs.split_at(arg_count);
+        let (previous_args, previous_determined) = (previous_args.to_vec(), previous_determined.to_vec());
+        let previous_location = previous.location;
+
+        if let Ok(bindings) = try_unify_all_hide_error(&args, &previous_args, cache) {
+            let result = try_unify_all_with_bindings(
+                &determined,
+                &previous_determined,
+                bindings,
+                location,
+                cache,
+                TE::NeverShown,
+            );
+
+            if result.is_err() {
+                let actual = fmap(&determined, |typ| typ.display(cache).to_string()).join(" ");
+                let previous_typeargs = previous_args.iter().chain(&previous_determined);
+                let previous_impl = fmap(previous_typeargs, |typ| typ.display(cache).to_string()).join(" ");
+                let previous_impl = format!("{trait_name} {previous_impl}");
+
+                let error = TE::FunctionalDependencyViolation(Rc::new(trait_name));
+                cache.push_diagnostic(location, D::TypeError(error, actual, previous_impl.clone()));
+                cache.push_diagnostic(previous_location, D::PreviouslyDefinedHere(previous_impl));
+                return;
+            }
+        }
+    }
+}
+
+/// Checks that an impl of a trait with superclasses also has an impl for each superclass.
+/// E.g. `impl Ord Foo` for `trait Ord a given Eq a` requires an `impl Eq Foo` to exist as well.
+/// A superclass may also be provided by the impl's own `given` clause.
+fn check_superclass_impls<'a>(impl_id: ImplInfoId, cache: &mut ModuleCache<'a>) {
+    let info = &cache[impl_id];
+    let location = info.location;
+    let given = info.given.clone();
+    let signature =
+        ConstraintSignature { trait_id: info.trait_id, args: info.typeargs.clone(), id: TraitConstraintId(0) };
+
+    for superclass in signature.implied_superclasses(cache) {
+        let is_given = given.iter().any(|given| given.trait_id == superclass.trait_id && given.args == superclass.args);
+
+        let candidates = cache.impl_infos.iter().filter(|other| other.trait_id == superclass.trait_id);
+        let candidates = candidates.map(|other| other.typeargs.clone()).collect::<Vec<_>>();
+        let has_impl = candidates.iter().any(|args| {
+            let (args, _) = replace_all_typevars(args, cache);
+            try_unify_all_hide_error(&superclass.args, &args, cache).is_ok()
+        });
+
+        if !is_given && !has_impl {
+            let constraint = signature.display(cache).to_string();
+            let superclass = superclass.display(cache).to_string();
+            cache.push_diagnostic(location, D::MissingSuperclassImpl(constraint, superclass));
+        }
+    }
+}
+
 // TODO: `useable_traits` here is always going to be empty. We'll likely need a
 // `Vec<ConstraintSignature>` field on each definition to account for trait definitions
 // with no body.
@@ -1635,6 +3013,60 @@ fn initialize_function_type<'a>(definition: &ast::Definition<'a>, cache: &mut Mo
     }
 }
 
+/// Returns each type variable named in the signature of a function definition which was
+/// introduced by the definition itself rather than an enclosing one. These are the type
+/// variables a user would expect the definition to be generalized over.
+fn named_generics_introduced_by<'a>(
+    definition: &ast::Definition<'a>, cache: &ModuleCache<'a>,
+) -> Vec<(TypeVariableId, Rc<String>)> {
+    let (ast::Ast::Lambda(_), ast::Ast::Variable(variable)) = (definition.expr.as_ref(), definition.pattern.as_ref())
+    else {
+        return vec![];
+    };
+    let Some(typ) = variable.definition.and_then(|id| cache[id].typ.as_ref()) else {
+        return vec![];
+    };
+
+    let level = definition.level.unwrap();
+    let mut named_generics: Vec<(TypeVariableId, Rc<String>)> = vec![];
+
+    typ.remove_forall().traverse(cache, |typ| {
+        if let NamedGeneric(id, name) = typ {
+            let introduced = matches!(&cache.type_bindings[id.0], Unbound(original, _) if *original >= level);
+            if introduced && named_generics.iter().all(|(existing, _)| existing != id) {
+                named_generics.push((*id, name.clone()));
+            }
+        }
+    });
+    named_generics
+}
+
+/// Issue a note for each of the given type variables which escaped into the type of a variable
+/// captured from an outer scope. Since the outer variable's type is fixed before this definition
+/// is generalized, the type variable cannot be generalized either.
+fn check_for_captured_named_generics<'a>(
+    definition: &ast::Definition<'a>, named_generics: Vec<(TypeVariableId, Rc<String>)>, cache: &mut ModuleCache<'a>,
+) {
+    let ast::Ast::Lambda(lambda) = definition.expr.as_ref() else {
+        return;
+    };
+
+    for (id, name) in named_generics {
+        if !matches!(&cache.type_bindings[id.0], Unbound(..)) {
+            continue;
+        }
+
+        let captured = lambda.closure_environment.keys().find(|from| {
+            cache[**from].typ.as_ref().is_some_and(|typ| contains_typevar(id, typ.remove_forall(), cache))
+        });
+
+        if let Some(from) = captured {
+            let captured = cache[*from].name.clone();
+            cache.push_diagnostic(definition.location, D::TypeVariableEscapes(name, captured));
+        }
+    }
+}
+
 fn mark_pattern_ids_in_progress<'a>(pattern: &ast::Ast<'a>, cache: &mut ModuleCache<'a>) {
     foreach_variable(pattern, cache, &mut |variable, cache| {
         mark_id_in_progress(variable.definition.unwrap(), cache);
@@ -1654,7 +3086,12 @@ pub trait Inferable<'a> {
 /// Compile an entire program, starting from main then lazily compiling
 /// each used function as it is called.
 pub fn infer_ast<'a>(ast: &mut ast::Ast<'a>, cache: &mut ModuleCache<'a>) {
+    if cache.keep_inference_checkpoint {
+        cache.save_inference_checkpoint();
+    }
+
     CURRENT_LEVEL.store(INITIAL_LEVEL, Ordering::SeqCst);
+    evaluate_comptime_expressions(cache);
     let result = infer(ast, cache);
     CURRENT_LEVEL.store(INITIAL_LEVEL - 1, Ordering::SeqCst);
 
@@ -1662,11 +3099,182 @@ pub fn infer_ast<'a>(ast: &mut ast::Ast<'a>, cache: &mut ModuleCache<'a>) {
     // No traits should be propogated above the top-level main function
     assert!(exposed_traits.is_empty());
 
+    // Report any holes outside of a global definition, e.g. in a top-level expression
+    report_type_holes(0, cache);
+
     // TODO: Check for IO effect
     if !result.effects.effects.is_empty() {
-        let effects = Type::Effects(result.effects.clone());
-        let effects = effects.display(cache).to_string();
-        cache.push_diagnostic(ast.locate(), D::UnhandledEffectsInMain(effects));
+        let effects = fmap(&result.effects.effects, |effect| effects::display_effect(effect, cache));
+        cache.push_diagnostic(ast.locate(), D::UnhandledEffectsInMain(effects.join(", ")));
+    }
+
+    check_main_signature(ast, cache);
+    check_for_escaped_named_generics(ast, cache);
+    traitchecker::check_closed_traits(cache);
+    linearity::check_linear_usage(ast, cache);
+}
+
+/// Infer and evaluate each `comptime` expression found during name resolution, binding
+/// the type variable standing in for each to the resulting type-level natural number.
+/// This is done before inferring the rest of the program so that the types these
+/// expressions are used in are known wherever they are referred to.
+fn evaluate_comptime_expressions<'a>(cache: &mut ModuleCache<'a>) {
+    let mut expressions = std::mem::take(&mut cache.comptime_expressions);
+
+    for comptime in &mut expressions {
+        let result = infer(comptime.expr.as_mut(), cache);
+        traitchecker::resolve_traits(result.traits, &[], cache);
+
+        let effects = result.effects.flatten(cache);
+        if !effects.effects.is_empty() {
+            let performed = Type::Effects(EffectSet::only(effects.effects)).display(cache).to_string();
+            cache.push_diagnostic(comptime.location, D::ComptimePerformsEffects(performed));
+            continue;
+        }
+
+        // Following global definitions is limited to avoid looping on cyclic (and already erroneous) constants
+        match evaluate_comptime(&comptime.expr, cache, 64) {
+            Some(value) => {
+                let typ = TypeVariable(comptime.typ);
+                unify(&typ, &Tag(TypeTag::Nat(value)), comptime.location, cache, TE::NeverShown);
+            },
+            None => cache.push_diagnostic(comptime.location, D::ComptimeNotConstant),
+        }
+    }
+
+    // Keep the expressions around in case inference is reset and ran again
+    cache.comptime_expressions = expressions;
+}
+
+/// Evaluate a `comptime` expression to a natural number. Only integer literals, global
+/// constants, and the arithmetic operators on them are supported. Returns None for any
+/// other expression, or if the arithmetic would overflow or underflow.
+fn evaluate_comptime(ast: &ast::Ast, cache: &ModuleCache, fuel: u32) -> Option<u64> {
+    match ast {
+        ast::Ast::Literal(ast::Literal { kind: ast::LiteralKind::Integer(value, _), .. }) => Some(*value),
+        ast::Ast::TypeAnnotation(annotation) => evaluate_comptime(&annotation.lhs, cache, fuel),
+        ast::Ast::Variable(variable) => {
+            let info = &cache[variable.definition?];
+            match &info.definition {
+                Some(DefinitionKind::Definition(definition))
+                    if info.global && fuel > 0 && matches!(definition.pattern.as_ref(), ast::Ast::Variable(_)) =>
+                {
+                    evaluate_comptime(&definition.expr, cache, fuel - 1)
+                },
+                _ => None,
+            }
+        },
+        ast::Ast::FunctionCall(call) if call.args.len() == 2 => {
+            let ast::Ast::Variable(ast::Variable { kind: ast::VariableKind::Operator(operator), .. }) =
+                call.function.as_ref()
+            else {
+                return None;
+            };
+
+            let lhs = evaluate_comptime(&call.args[0], cache, fuel)?;
+            let rhs = evaluate_comptime(&call.args[1], cache, fuel)?;
+
+            match operator {
+                Token::Add => lhs.checked_add(rhs),
+                Token::Subtract => lhs.checked_sub(rhs),
+                Token::Multiply => lhs.checked_mul(rhs),
+                Token::Divide => lhs.checked_div(rhs),
+                Token::Modulus => lhs.checked_rem(rhs),
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Rigid type variables (`NamedGeneric`s) should always be quantified by the definition
+/// that declares them. One remaining unbound yet unquantified in a top-level definition's
+/// type has escaped its scope, which indicates a bug in the typechecker rather than the program.
+fn check_for_escaped_named_generics<'a>(ast: &ast::Ast<'a>, cache: &mut ModuleCache<'a>) {
+    let statements = match ast {
+        ast::Ast::Sequence(sequence) => sequence.statements.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+
+    for statement in statements {
+        let (variable, location) = match statement {
+            ast::Ast::Definition(definition) => match definition.pattern.as_ref() {
+                ast::Ast::Variable(variable) => (variable, definition.location),
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        let Some(typ) = variable.definition.and_then(|id| cache[id].typ.as_ref()) else {
+            continue;
+        };
+
+        let (quantified, typ) = match typ {
+            GeneralizedType::MonoType(typ) => (&[][..], typ),
+            GeneralizedType::PolyType(typevars, typ) => (typevars.as_slice(), typ),
+        };
+
+        if let Some(name) = find_escaped_named_generic(typ, quantified, cache) {
+            let actual = typ.display(cache).to_string();
+            let expected = variable.to_string();
+            cache.push_diagnostic(location, D::TypeError(TE::SkolemEscape(name), actual, expected));
+        }
+    }
+}
+
+/// Returns the name of the first unbound `NamedGeneric` within the given type that is not
+/// one of the `quantified` type variables. Unlike `Type::traverse`, this also looks through
+/// named generics that were bound during inference.
+fn find_escaped_named_generic(typ: &Type, quantified: &[TypeVariableId], cache: &ModuleCache) -> Option<Rc<String>> {
+    let mut escaped = None;
+    typ.traverse(cache, |typ| {
+        if let (NamedGeneric(id, name), None) = (typ, &escaped) {
+            escaped = match &cache.type_bindings[id.0] {
+                TypeBinding::Bound(binding) => find_escaped_named_generic(binding, quantified, cache),
+                TypeBinding::Unbound(..) if !quantified.contains(id) => Some(name.clone()),
+                TypeBinding::Unbound(..) => None,
+            };
+        }
+    });
+    escaped
+}
+
+/// A `main` function defined at the top-level of the program should take and return Unit.
+/// A `main` taking an argument is most likely a mistake since nothing will ever pass it one.
+fn check_main_signature<'a>(ast: &ast::Ast<'a>, cache: &mut ModuleCache<'a>) {
+    let statements = match ast {
+        ast::Ast::Sequence(sequence) => sequence.statements.as_slice(),
+        other => std::slice::from_ref(other),
+    };
+
+    for statement in statements {
+        let (variable, location) = match statement {
+            ast::Ast::Definition(definition) => match definition.pattern.as_ref() {
+                ast::Ast::Variable(variable) => (variable, definition.location),
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        let is_main = matches!(&variable.kind, ast::VariableKind::Identifier(name) if name == "main");
+        let typ = match variable.definition.and_then(|id| cache[id].typ.as_ref()) {
+            Some(typ) if is_main => typ.remove_forall(),
+            _ => continue,
+        };
+
+        let valid = match follow_bindings_in_cache(typ, cache) {
+            Function(function) => {
+                function.parameters.len() == 1
+                    && function.parameters[0].is_unit(cache)
+                    && function.return_type.is_unit(cache)
+            },
+            _ => false,
+        };
+
+        if !valid {
+            let typ = typ.display(cache).to_string();
+            cache.push_diagnostic(location, D::InvalidMainSignature(typ));
+        }
     }
 }
 
@@ -1726,6 +3334,18 @@ impl<'a> Inferable<'a> for ast::Variable<'a> {
         let impl_scope = self.impl_scope.unwrap();
         let id = self.id.unwrap();
 
+        let references = cache.references.entry(definition_id).or_default();
+        if !references.contains(&self.location) {
+            references.push(self.location);
+        }
+
+        let info = &cache[definition_id];
+
+        if let Some(message) = &info.deprecated {
+            let diagnostic = D::DeprecatedUsage(info.name.clone(), message.clone());
+            cache.push_diagnostic(self.location, diagnostic);
+        }
+
         let info = &cache[definition_id];
 
         // Lookup the type of the definition.
@@ -1758,6 +3378,7 @@ impl<'a> Inferable<'a> for ast::Variable<'a> {
 
         let (t, traits2, mapping) = s.instantiate(traits.clone(), cache);
         self.instantiation_mapping = Rc::new(mapping);
+        cache.instantiations.entry(definition_id).or_default().push(self.instantiation_mapping.clone());
         TypeResult::new(t, traits2, cache)
     }
 }
@@ -1769,28 +3390,90 @@ impl<'a> Inferable<'a> for ast::Variable<'a> {
  */
 impl<'a> Inferable<'a> for ast::Lambda<'a> {
     fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
+        self.infer_with_expected_parameters(&[], cache)
+    }
+}
+
+impl<'a> ast::Lambda<'a> {
+    /// Infer this lambda, first unifying each of its parameter types with the corresponding
+    /// type in `expected_parameters` if possible. This lets the body of a lambda passed as an
+    /// argument see the parameter types the callee expects, e.g. `x : I32` in `map (fn x -> x + 1) xs`
+    /// when `xs : List I32`. Any mismatch is left to be reported when the call itself is checked.
+    fn infer_with_expected_parameters(
+        &mut self, expected_parameters: &[Type], cache: &mut ModuleCache<'a>,
+    ) -> TypeResult {
         // The newvars for the parameters are filled out during name resolution
         let parameter_types = fmap(&self.args, |_| next_type_variable(cache));
 
+        for (parameter_type, expected) in parameter_types.iter().zip(expected_parameters) {
+            if let Ok(bindings) = try_unify(parameter_type, expected, self.location, cache, TE::NeverShown) {
+                bindings.perform(cache);
+            }
+        }
+
         for (parameter, parameter_type) in self.args.iter_mut().zip(parameter_types.iter()) {
             bind_irrefutable_pattern(parameter, parameter_type, &[], false, cache);
         }
 
         bind_closure_environment(&mut self.closure_environment, cache);
 
+        // The environment must be inferred before the body since uses of a captured
+        // reference within the body lower the level of its lifetime to the closure's.
+        let environment = infer_closure_environment(&self.closure_environment, self.location, cache);
+
+        // Any `return` within the body is checked against the declared return type if there is one
+        let annotated = self.body.get_type().is_some();
+        let return_type = self.body.get_type().cloned().unwrap_or_else(|| next_type_variable(cache));
+        let deferred_returns = (!annotated && self.kind == ast::LambdaKind::Function).then(Vec::new);
+        let kind = self.kind.clone();
+        cache.return_types.push(ReturnTarget { return_type: return_type.clone(), kind, deferred_returns });
+
         // return_type, traits
-        let body = if let Some(typ) = self.body.get_type() {
+        let body = if annotated {
             // Check if user specified a return type
-            let typ = typ.clone();
             let body = self.body.infer_impl(cache);
-            unify(&body.typ, &typ, self.location, cache, TE::FunctionBodyDoesNotMatchReturnType);
+            unify(&body.typ, &return_type, self.location, cache, TE::FunctionBodyDoesNotMatchReturnType);
             body
         } else {
-            infer(self.body.as_mut(), cache)
+            // Bind the return type to the body's type rather than the reverse so that the
+            // body's type variables are the ones kept in the function's type.
+            let body = infer(self.body.as_mut(), cache);
+            unify(&return_type, &body.typ, self.location, cache, TE::ReturnedValueDoesNotMatchReturnType);
+            body
         };
 
+        // The return type is now known from the body so each `return` is checked against it
+        let target = cache.return_types.pop().unwrap();
+        for (returned, location) in target.deferred_returns.into_iter().flatten() {
+            unify(&returned, &return_type, location, cache, TE::ReturnedValueDoesNotMatchReturnType);
+        }
+
+        if let Some(captures) = &self.captures {
+            check_capture_list(captures, &self.closure_environment, self.location, cache);
+        }
+
+        let mut traits = body.traits;
+        for contract in self.contracts.iter_mut() {
+            traits.append(&mut infer_contract(contract, &body.typ, cache));
+        }
+
+        // Default arguments are evaluated at each callsite which omits them, so only
+        // their types and traits are checked here, not their effects.
+        let first_default = parameter_types.len() - self.default_args.len();
+        for (default, parameter_type) in self.default_args.iter_mut().zip(&parameter_types[first_default..]) {
+            let mut default_result = infer(default, cache);
+            unify(&default_result.typ, parameter_type, default.locate(), cache, TE::DefaultArgumentTypeMismatch);
+            traits.append(&mut default_result.traits);
+        }
+
         let mut effects = body.effects.flatten(cache);
 
+        // A `pure` clause is parsed as an empty effects list
+        if self.effects.as_ref().is_some_and(|effects| effects.is_empty()) && !effects.effects.is_empty() {
+            let performed = Type::Effects(EffectSet::only(effects.effects.clone())).display(cache).to_string();
+            cache.push_diagnostic(self.location, D::PureFunctionPerformsEffect(performed));
+        }
+
         // To check if the function can be effect polymorphic we need to remove the extension
         // variable so we can see if it occurs in the rest of the function type.
         let extension = effects.extension.take();
@@ -1798,7 +3481,7 @@ impl<'a> Inferable<'a> for ast::Lambda<'a> {
         let mut typ = FunctionType {
             parameters: parameter_types,
             return_type: Box::new(body.typ),
-            environment: Box::new(infer_closure_environment(&self.closure_environment, cache)),
+            environment: Box::new(environment),
             effects: Box::new(Type::Effects(effects.clone())),
             has_varargs: false,
         };
@@ -1815,8 +3498,24 @@ impl<'a> Inferable<'a> for ast::Lambda<'a> {
             }
         }
 
-        TypeResult::new(Type::Function(typ), body.traits, cache)
+        TypeResult::new(Type::Function(typ), traits, cache)
+    }
+}
+
+/// Check the condition of a `@requires` or `@ensures` attribute is a Bool. Since these
+/// conditions are never evaluated, only the traits they require are kept, not their effects.
+fn infer_contract<'a>(
+    contract: &mut ast::Contract<'a>, return_type: &Type, cache: &mut ModuleCache<'a>,
+) -> TraitConstraints {
+    if let Some(result) = contract.result {
+        cache[result].typ = Some(GeneralizedType::MonoType(return_type.clone()));
     }
+
+    let condition = infer(contract.condition.as_mut(), cache);
+    let bool_type = Type::Primitive(PrimitiveType::BooleanType);
+    let location = contract.condition.locate();
+    unify(&condition.typ, &bool_type, location, cache, TE::NonBoolInContract(contract.kind));
+    condition.traits
 }
 
 /*
@@ -1826,13 +3525,40 @@ impl<'a> Inferable<'a> for ast::Lambda<'a> {
  */
 impl<'a> Inferable<'a> for ast::FunctionCall<'a> {
     fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
+        fill_in_default_arguments(self, cache);
         let mut f = infer(self.function.as_mut(), cache);
 
-        let parameters = fmap(&mut self.args, |arg| {
+        // Lambda arguments are inferred last so that the types of the other arguments can
+        // first refine the parameter types expected of them. Spreads make it unclear which
+        // parameter each argument corresponds to, so every argument is inferred in order then.
+        let defer_lambdas = !self.args.iter().any(|arg| matches!(arg, ast::Ast::Spread(_)));
+
+        let mut parameters = Vec::with_capacity(self.args.len());
+        for arg in self.args.iter_mut() {
+            if defer_lambdas && matches!(arg, ast::Ast::Lambda(_)) {
+                parameters.push(next_type_variable(cache));
+                continue;
+            }
+
             let mut arg_result = infer(arg, cache);
             f.combine(&mut arg_result, cache);
-            arg_result.typ
-        });
+
+            if let ast::Ast::Spread(spread) = arg {
+                let elements = spread_element_types(&arg_result.typ, cache);
+                spread.element_count = elements.len();
+                parameters.extend(elements);
+            } else {
+                parameters.push(arg_result.typ);
+            }
+        }
+
+        if defer_lambdas {
+            infer_lambda_arguments(self, &mut f, &mut parameters, cache);
+
+            if cache.implicit_integer_widening {
+                coerce_integer_arguments(self, &f.typ, &mut parameters, cache);
+            }
+        }
 
         let return_type = next_type_variable(cache);
         let effects_var = next_type_variable_id(cache);
@@ -1862,6 +3588,90 @@ impl<'a> Inferable<'a> for ast::FunctionCall<'a> {
     }
 }
 
+/// Widen each integer argument of the given call whose type is smaller than its parameter's,
+/// e.g. an `I32` passed where an `I64` is expected. Opt-in via `--implicit-int-widening`.
+fn coerce_integer_arguments<'a>(
+    call: &mut ast::FunctionCall<'a>, function: &Type, parameters: &mut [Type], cache: &mut ModuleCache<'a>,
+) {
+    let expected = match cache.follow_bindings_shallow(function) {
+        Type::Function(function) if function.parameters.len() == parameters.len() => function.parameters.clone(),
+        _ => return,
+    };
+
+    for ((arg, parameter), expected) in call.args.iter_mut().zip(parameters).zip(expected) {
+        let location = arg.locate();
+        if let Ok((_, Some(_))) = try_unify_or_coerce(arg, parameter, &expected, location, cache, TE::NeverShown) {
+            *parameter = expected;
+        }
+    }
+}
+
+/// Infer each lambda passed directly as an argument to the given call, pushing the parameter
+/// types of the corresponding function parameter into it. Before this is done, the rest of the
+/// arguments are unified with the called function's parameters so that, e.g. the element type
+/// of `xs` in `map (fn x -> x + 1) xs` is known while checking the lambda's body.
+fn infer_lambda_arguments<'a>(
+    call: &mut ast::FunctionCall<'a>, f: &mut TypeResult, parameters: &mut [Type], cache: &mut ModuleCache<'a>,
+) {
+    let expected = match cache.follow_bindings_shallow(&f.typ) {
+        Type::Function(function) if function.parameters.len() == parameters.len() => function.parameters.clone(),
+        _ => vec![],
+    };
+
+    // Any mismatches here are reported once the whole call is unified with the function's type
+    for ((arg, parameter), expected) in call.args.iter().zip(parameters.iter()).zip(&expected) {
+        if !matches!(arg, ast::Ast::Lambda(_)) {
+            if let Ok(bindings) = try_unify(parameter, expected, arg.locate(), cache, TE::NeverShown) {
+                bindings.perform(cache);
+            }
+        }
+    }
+
+    for (i, arg) in call.args.iter_mut().enumerate() {
+        if let ast::Ast::Lambda(lambda) = arg {
+            let expected_parameters = match expected.get(i).map(|typ| cache.follow_bindings_shallow(typ)) {
+                Some(Type::Function(function)) => function.parameters.clone(),
+                _ => vec![],
+            };
+
+            let mut arg_result = lambda.infer_with_expected_parameters(&expected_parameters, cache);
+            arg.set_type(arg_result.typ.clone());
+            f.combine(&mut arg_result, cache);
+            parameters[i] = arg_result.typ;
+        }
+    }
+}
+
+/// Append the default values of any trailing parameters omitted from a call to a function
+/// declared with them. E.g. with `f (x: I32) (y: I32 = 0) = ...`, `f 1` becomes `f 1 0`.
+/// Each default is copied into the call so that it is type checked and evaluated there.
+fn fill_in_default_arguments<'a>(call: &mut ast::FunctionCall<'a>, cache: &ModuleCache<'a>) {
+    let ast::Ast::Variable(variable) = call.function.as_ref() else {
+        return;
+    };
+    let Some(DefinitionKind::Definition(definition)) = variable.definition.and_then(|id| cache[id].definition.as_ref())
+    else {
+        return;
+    };
+    let ast::Ast::Lambda(lambda) = definition.expr.as_ref() else {
+        return;
+    };
+
+    let first_default = lambda.args.len() - lambda.default_args.len();
+    let arg_count = call.args.len();
+    let has_spread = call.args.iter().any(|arg| matches!(arg, ast::Ast::Spread(_)));
+
+    if has_spread || arg_count < first_default || arg_count >= lambda.args.len() {
+        return;
+    }
+
+    let defaults = lambda.default_args[arg_count - first_default..].to_vec();
+    for mut default in defaults {
+        clear_inferred_types(&mut default);
+        call.args.push(default);
+    }
+}
+
 fn issue_argument_types_error<'c>(
     call: &ast::FunctionCall<'c>, f: Type, new_function: Type, original_error: Diagnostic<'c>,
     cache: &mut ModuleCache<'c>,
@@ -1878,23 +3688,82 @@ fn issue_argument_types_error<'c>(
                 );
             }
 
-            for ((arg, param), arg_ast) in actual.parameters.iter().zip(&expected.parameters).zip(&call.args) {
-                unify(arg, param, arg_ast.locate(), cache, TE::ArgumentTypeMismatch);
+            // Each element of a spread argument is reported at the location of the spread
+            let args = call.args.iter().flat_map(|arg| match arg {
+                ast::Ast::Spread(spread) => vec![(spread.location, None); spread.element_count],
+                other => vec![(other.locate(), monomorphic_reference(other, cache))],
+            });
+            let args = args.collect::<Vec<_>>();
+
+            for ((arg, param), (location, reference)) in actual.parameters.iter().zip(&expected.parameters).zip(args) {
+                match (try_unify_soft(arg, param, location, cache, TE::ArgumentTypeMismatch), reference) {
+                    (Ok(bindings), _) => bindings.perform(cache),
+                    (Err(diagnostic), None) => cache.push_full_diagnostic(diagnostic),
+                    (Err(diagnostic), Some(id)) => {
+                        let info = &cache[id];
+                        let note = D::MonomorphicReference(info.name.clone());
+                        cache.push_full_diagnostic(diagnostic.with_note(info.location, note));
+                    },
+                }
             }
 
             // No error was issued, the type difference must be an effect or environment
             // difference. Just issue the original error with the full function type.
             if cache.error_count() == error_count {
+                let missing_effects = missing_effects(&actual.effects, &expected.effects, call.location, cache);
                 let actual = Type::Function(actual).display(cache).to_string();
                 let expected = Type::Function(expected).display(cache).to_string();
-                let diagnostic = D::FunctionTypeMismatch(actual, expected);
-                cache.push_diagnostic(call.location, diagnostic);
+                let mut diagnostic = Diagnostic::new(call.location, D::FunctionTypeMismatch(actual, expected));
+
+                for effect in missing_effects {
+                    let effect = effects::display_effect(&effect, cache);
+                    diagnostic = diagnostic.with_note(call.location, D::EffectNotAllowed(effect));
+                }
+                cache.push_full_diagnostic(diagnostic);
             }
         },
         None => cache.push_full_diagnostic(original_error),
     }
 }
 
+/// If the given argument is a variable referring to a reference kept monomorphic by the
+/// value restriction, returns the id of that reference's definition.
+fn monomorphic_reference(arg: &ast::Ast, cache: &ModuleCache) -> Option<DefinitionInfoId> {
+    match arg {
+        ast::Ast::Variable(variable) => variable.definition.filter(|id| cache.monomorphic_references.contains(id)),
+        _ => None,
+    }
+}
+
+/// Returns each effect in the `actual` effects which the `expected` effects do not allow
+fn missing_effects<'c>(
+    actual: &Type, expected: &Type, location: Location<'c>, cache: &mut ModuleCache<'c>,
+) -> Vec<Effect> {
+    let actual = actual.flatten_effects(cache);
+    let expected = expected.flatten_effects(cache);
+    let mut bindings = UnificationBindings::empty();
+    actual.try_unify_with_bindings(&expected, &mut bindings, location, cache).err().unwrap_or_default()
+}
+
+/// Returns the type of each element of a tuple type to be spread as separate arguments.
+/// The tuple type must already be known at the call site. Any other type is treated as
+/// a single element.
+fn spread_element_types(typ: &Type, cache: &ModuleCache) -> Vec<Type> {
+    let mut elements = vec![];
+    let mut typ = cache.follow_bindings_shallow(typ);
+
+    while let Type::TypeApplication(constructor, args) = typ {
+        if !cache.follow_bindings_shallow(constructor).is_pair_type() || args.len() != 2 {
+            break;
+        }
+        elements.push(args[0].clone());
+        typ = cache.follow_bindings_shallow(&args[1]);
+    }
+
+    elements.push(typ.clone());
+    elements
+}
+
 fn try_unwrap_functions(f: Type, new_function: Type, cache: &ModuleCache) -> Option<(FunctionType, FunctionType)> {
     let f = follow_bindings_in_cache(&f, cache);
 
@@ -1925,6 +3794,8 @@ impl<'a> Inferable<'a> for ast::Definition<'a> {
         self.typ = Some(unit.clone());
         initialize_function_type(self, cache);
         mark_pattern_ids_in_progress(&self.pattern, cache);
+        let named_generics = named_generics_introduced_by(self, cache);
+        let first_hole = cache.type_holes.len();
 
         let level = self.level.unwrap();
         let previous_level = CURRENT_LEVEL.swap(level.0, Ordering::SeqCst);
@@ -1952,6 +3823,13 @@ impl<'a> Inferable<'a> for ast::Definition<'a> {
         // which entails wrapping type variables in a forall, and finding which traits
         // usages of this definition require.
         let traits = try_generalize_definition(self, expr_result.typ, expr_result.traits, cache);
+        check_for_captured_named_generics(self, named_generics, cache);
+
+        // Holes within local definitions are left for the enclosing global definition to report
+        // so that any uses of the local definition can further constrain their types.
+        if is_global_definition(self, cache) {
+            report_type_holes(first_hole, cache);
+        }
 
         // TODO: Can these operations on the LetBindingLevel be simplified?
         CURRENT_LEVEL.store(previous_level, Ordering::SeqCst);
@@ -1984,6 +3862,80 @@ impl<'a> Inferable<'a> for ast::If<'a> {
     }
 }
 
+impl<'a> Inferable<'a> for ast::ConditionalCompilation<'a> {
+    /// Both branches are checked for errors but only the active branch's
+    /// type, effects, and trait constraints are used by the surrounding code.
+    fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
+        let then = infer(self.then.as_mut(), cache);
+        let otherwise = infer(self.otherwise.as_mut(), cache);
+
+        if self.is_active {
+            then
+        } else {
+            otherwise
+        }
+    }
+}
+
+impl<'a> Inferable<'a> for ast::Spread<'a> {
+    /// The type of a spread is the type of the whole tuple. It is expanded into
+    /// separate arguments by the enclosing function call.
+    fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
+        infer(self.expression.as_mut(), cache)
+    }
+}
+
+impl<'a> Inferable<'a> for ast::ArrayLiteral<'a> {
+    /// Each element is unified with the same element type, so an empty
+    /// array's element type is determined by its context instead.
+    fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
+        let element_type = next_type_variable(cache);
+        let mut result = TypeResult::of(Type::UNIT, cache);
+
+        for element in &mut self.elements {
+            let mut element_result = infer(element, cache);
+            result.combine(&mut element_result, cache);
+            unify(&element_result.typ, &element_type, element.locate(), cache, TE::ArrayElementMismatch);
+        }
+
+        let array = Box::new(Type::UserDefined(ARRAY_TYPE));
+        result.with_type(Type::TypeApplication(array, vec![element_type]))
+    }
+}
+
+impl<'a> Inferable<'a> for ast::Hole<'a> {
+    /// A hole is given a fresh type variable so that it is still constrained by its
+    /// context. Its type is reported later once this variable is as solved as it can be.
+    fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
+        let typ = next_type_variable(cache);
+        cache.type_holes.push((self.location, typ.clone()));
+        TypeResult::of(typ, cache)
+    }
+}
+
+/// Issue a diagnostic with the inferred type of each hole found since `first_hole`
+fn report_type_holes(first_hole: usize, cache: &mut ModuleCache) {
+    for (location, typ) in cache.type_holes.split_off(first_hole) {
+        let typ = typ.display(cache).to_string();
+        cache.push_diagnostic(location, D::TypeHole(typ));
+    }
+}
+
+fn is_global_definition<'a>(definition: &ast::Definition<'a>, cache: &ModuleCache<'a>) -> bool {
+    match definition.pattern.as_ref() {
+        ast::Ast::Variable(variable) => variable.definition.is_some_and(|id| cache[id].global),
+        _ => false,
+    }
+}
+
+impl<'a> Inferable<'a> for ast::Error<'a> {
+    /// The type of a statement which failed to parse is left unconstrained
+    /// so that any use of it does not issue further errors.
+    fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
+        TypeResult::of(next_type_variable(cache), cache)
+    }
+}
+
 impl<'a> Inferable<'a> for ast::Match<'a> {
     fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
         let error_count = cache.error_count();
@@ -1997,7 +3949,9 @@ impl<'a> Inferable<'a> for ast::Match<'a> {
             let mut pattern = infer(&mut self.branches[0].0, cache);
             result.combine(&mut pattern, cache);
 
-            unify(&pattern.typ, &result.typ, self.branches[0].0.locate(), cache, TE::MatchPatternTypeDiffers);
+            // The value matched on flows into each pattern, so a pattern like `!Some x`
+            // may not match on an immutable reference but `&Some x` may match on a mutable one.
+            unify_match_pattern(&self.branches[0].0, &pattern.typ, &result.typ, cache);
 
             let mut branch = infer(&mut self.branches[0].1, cache);
             result.combine(&mut branch, cache);
@@ -2005,9 +3959,9 @@ impl<'a> Inferable<'a> for ast::Match<'a> {
 
             for (pattern, branch) in self.branches.iter_mut().skip(1) {
                 let mut pattern_result = infer(pattern, cache);
-                let mut branch_result = infer(branch, cache);
+                unify_match_pattern(pattern, &pattern_result.typ, &result.typ, cache);
 
-                unify(&pattern_result.typ, &result.typ, pattern.locate(), cache, TE::MatchPatternTypeDiffers);
+                let mut branch_result = infer(branch, cache);
                 unify(&branch_result.typ, &return_type, branch.locate(), cache, TE::MatchReturnTypeDiffers);
 
                 result.combine(&mut pattern_result, cache);
@@ -2018,17 +3972,56 @@ impl<'a> Inferable<'a> for ast::Match<'a> {
         // Compiling the decision tree for this pattern requires each pattern is well-typed.
         // So skip this step if there was an error in inferring types for this match expression.
         if cache.error_count() == error_count {
-            let mut tree = pattern::compile(self, cache);
+            let mut compiled = pattern::compile(self, cache);
+
+            for index in compiled.unreachable_branches {
+                cache.push_diagnostic(self.branches[index].0.locate(), D::UnreachablePattern);
+            }
+
+            if !compiled.missing_cases.is_empty() {
+                cache.push_diagnostic(self.location, D::NonExhaustiveMatch(compiled.missing_cases));
+            }
+
             // TODO: Infer new variables created by a decision tree within pattern::compile.
             //       It is done separately currently only for convenience/ease of implementation.
-            tree.infer(self.expression.get_type().unwrap(), self.location, cache);
-            self.decision_tree = Some(tree);
+            compiled.tree.infer(self.expression.get_type().unwrap(), self.location, cache);
+            self.decision_tree = Some(compiled.tree);
+            self.reference_bindings = compiled.reference_bindings;
         }
 
         result.with_type(return_type)
     }
 }
 
+/// Unify the type of a match pattern with the type of the value being matched on.
+///
+/// Matching a reference with a pattern that isn't a reference pattern itself matches the
+/// value behind the reference instead. Each variable bound by such a pattern is then a
+/// reference into the matched value sharing the same mutability and lifetime.
+fn unify_match_pattern<'c>(
+    pattern: &ast::Ast<'c>, pattern_type: &Type, matched_type: &Type, cache: &mut ModuleCache<'c>,
+) {
+    let reference = reference_parts(matched_type, cache).filter(|_| pattern::matches_through_reference(pattern));
+
+    let Some((reference, element)) = reference else {
+        // The value matched on flows into a reference pattern, so `!Some x` may not match on
+        // an immutable reference but `&Some x` may match on a mutable one. The matched type is
+        // the actual type here, unlike for other patterns.
+        if let ast::Ast::Reference(_) = pattern {
+            return unify(matched_type, pattern_type, pattern.locate(), cache, TE::MatchReferencePatternTypeDiffers);
+        }
+        return unify(pattern_type, matched_type, pattern.locate(), cache, TE::MatchPatternTypeDiffers);
+    };
+
+    unify(pattern_type, &element, pattern.locate(), cache, TE::MatchPatternTypeDiffers);
+
+    for id in pattern::variables_bound_by_reference(pattern) {
+        let typ = cache[id].typ.as_ref().unwrap().as_monotype().clone();
+        let typ = TypeApplication(Box::new(reference.clone()), vec![typ]);
+        cache[id].typ = Some(GeneralizedType::MonoType(typ));
+    }
+}
+
 impl<'a> Inferable<'a> for ast::TypeDefinition<'a> {
     fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
         TypeResult::of(Type::UNIT, cache)
@@ -2037,10 +4030,50 @@ impl<'a> Inferable<'a> for ast::TypeDefinition<'a> {
 
 impl<'a> Inferable<'a> for ast::TypeAnnotation<'a> {
     fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
+        if self.conversion {
+            return infer_conversion(self, cache);
+        }
+
         let lhs = infer(self.lhs.as_mut(), cache);
-        unify(self.typ.as_mut().unwrap(), &lhs.typ, self.location, cache, TE::DoesNotMatchAnnotatedType);
-        lhs
+        let annotation = self.typ.clone().unwrap();
+        unify(&annotation, &lhs.typ, self.location, cache, TE::DoesNotMatchAnnotatedType);
+        // Keep the annotated type as this node's type so that it survives `ModuleCache::reset_inference`
+        lhs.with_type(annotation)
+    }
+}
+
+/// Infer `x as T`, desugared into `(coerce x : T)`. If the type of `x` unifies with `T`
+/// this is only an annotation and the call to `coerce` is removed. Otherwise the call is
+/// kept so that the conversion is resolved through a `Coerce` impl.
+fn infer_conversion<'a>(annotation: &mut ast::TypeAnnotation<'a>, cache: &mut ModuleCache<'a>) -> TypeResult {
+    let typ = annotation.typ.clone().unwrap();
+    let ast::Ast::FunctionCall(call) = annotation.lhs.as_mut() else {
+        unreachable!("Expected `x as T` to be desugared into a call to `coerce`")
+    };
+
+    let mut value = infer(&mut call.args[0], cache);
+
+    if let Ok(bindings) = try_unify(&value.typ, &typ, annotation.location, cache, TE::DoesNotMatchAnnotatedType) {
+        bindings.perform(cache);
+        let value_ast = call.args.pop().unwrap();
+        *annotation.lhs = value_ast;
+        return value.with_type(typ);
     }
+
+    let mut result = infer(call.function.as_mut(), cache);
+    result.combine(&mut value, cache);
+
+    let expected = Function(FunctionType {
+        parameters: vec![value.typ],
+        return_type: Box::new(typ.clone()),
+        environment: Box::new(next_type_variable(cache)),
+        effects: Box::new(next_type_variable(cache)),
+        has_varargs: false,
+    });
+
+    unify(&expected, &result.typ, call.location, cache, TE::CalledValueIsNotAFunction);
+    call.set_type(typ.clone());
+    result.with_type(typ)
 }
 
 impl<'a> Inferable<'a> for ast::Import<'a> {
@@ -2071,6 +4104,9 @@ impl<'a> Inferable<'a> for ast::TraitImpl<'a> {
             return TypeResult::of(Type::UNIT, cache);
         }
 
+        check_functional_dependencies(self.impl_id.unwrap(), cache);
+        check_superclass_impls(self.impl_id.unwrap(), cache);
+
         let trait_info = &cache.trait_infos[self.trait_info.unwrap().0];
 
         let mut typevars_to_replace = trait_info.typeargs.clone();
@@ -2095,6 +4131,14 @@ impl<'a> Inferable<'a> for ast::TraitImpl<'a> {
         // the types declared in self.typeargs
         let mut impl_bindings: HashMap<_, _> = typevars_to_replace.into_iter().zip(trait_arg_types).collect();
 
+        // Helpers have no corresponding trait signature, so they are inferred like any other
+        // definition. They still share the impl's type variables from name resolution.
+        let given = cache[self.impl_id.unwrap()].given.clone();
+        for helper in self.helpers.iter_mut() {
+            let helper_result = infer(helper, cache);
+            check_impl_helper_traits(helper.pattern.as_ref(), helper_result.traits, &given, cache);
+        }
+
         for definition in self.definitions.iter_mut() {
             bind_irrefutable_pattern_in_impl(
                 definition.pattern.as_ref(),
@@ -2129,21 +4173,75 @@ impl<'a> Inferable<'a> for ast::TraitImpl<'a> {
 impl<'a> Inferable<'a> for ast::Return<'a> {
     fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
         let result = infer(self.expression.as_mut(), cache);
+
+        match &self.target {
+            ast::LambdaKind::Function => match cache.return_types.last_mut() {
+                Some(ReturnTarget { deferred_returns: Some(returns), .. }) => {
+                    returns.push((result.typ.clone(), self.expression.locate()));
+                },
+                Some(target) => {
+                    let return_type = target.return_type.clone();
+                    let error = TE::ReturnedValueDoesNotMatchReturnType;
+                    unify(&result.typ, &return_type, self.expression.locate(), cache, error);
+                },
+                None => (),
+            },
+            ast::LambdaKind::Loop(label) => {
+                // Each break site of a loop is unified with the loop's result type
+                let target = cache.return_types.iter().rposition(|target| match (&target.kind, label) {
+                    (ast::LambdaKind::Loop(_), None) => true,
+                    (ast::LambdaKind::Loop(Some(loop_label)), Some(label)) => loop_label == label,
+                    _ => false,
+                });
+
+                match target {
+                    None => cache.push_diagnostic(self.location, D::BreakOutsideOfLoop(label.clone())),
+                    // Loops are desugared into lambdas so exiting an outer one would require a non-local return
+                    Some(index) if index + 1 != cache.return_types.len() => {
+                        cache.push_diagnostic(self.location, D::BreakOutOfNestedFunction);
+                    },
+                    Some(index) => {
+                        let loop_type = cache.return_types[index].return_type.clone();
+                        let error = TE::BreakValueDoesNotMatchLoopType;
+                        unify(&result.typ, &loop_type, self.expression.locate(), cache, error);
+                    },
+                }
+            },
+        }
+
+        // Effects performed while evaluating the returned value are included in `result`.
+        // Since control never continues past a `return`, its type is left unconstrained.
         result.with_type(next_type_variable(cache))
     }
 }
 
+/// Warns if a non-final statement in a sequence produces a value which is then discarded.
+/// Unbound type variables are not warned on since they may be the result of an expression
+/// which never returns, like `return`.
+fn warn_if_value_unused<'a>(statement: &ast::Ast<'a>, typ: &Type, cache: &mut ModuleCache<'a>) {
+    let typ = cache.follow_bindings_shallow(typ);
+    if !matches!(typ, Type::TypeVariable(_)) && !typ.is_unit(cache) {
+        let typ = typ.display(cache).to_string();
+        cache.push_diagnostic(statement.locate(), D::UnusedValue(typ));
+    }
+}
+
 impl<'a> Inferable<'a> for ast::Sequence<'a> {
     fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
         let ignore_len = self.statements.len() - 1;
         let mut result = TypeResult::of(Type::UNIT, cache);
 
         for statement in self.statements.iter_mut().take(ignore_len) {
-            result.combine(&mut infer(statement, cache), cache);
+            let mut statement_result = infer(statement, cache);
+            if cache.warn_unused_values {
+                warn_if_value_unused(statement, &statement_result.typ, cache);
+            }
+            result.combine(&mut statement_result, cache);
         }
 
         let mut last = infer(self.statements.last_mut().unwrap(), cache);
         result.combine(&mut last, cache);
+        check_redundant_field_assignments(&self.statements, cache);
         result.with_type(last.typ)
     }
 }
@@ -2172,10 +4270,10 @@ impl<'a> Inferable<'a> for ast::MemberAccess<'a> {
 
         // The '..' or 'rest of the struct' stand-in variable
         let rho = cache.next_type_variable_id(level);
-        let struct_type = Type::Struct(fields, rho);
+        let struct_type = Type::Struct(fields, rho, RowKind::Open);
 
         let mut bindings =
-            try_unify(&result.typ, &struct_type, self.location, cache, TE::NoFieldOfType(self.field.clone()));
+            try_unify(&result.typ, &struct_type, self.location, cache, TE::NoFieldOfType(Rc::new(self.field.clone())));
 
         if bindings.is_ok() && self.offset == Some(Mutability::Mutable) {
             // If unification succeeded (`bindings.is_ok()`) and this is a
@@ -2230,11 +4328,15 @@ fn check_field_access_lhs_is_mutable<'c>(
     variable: &ast::Ast<'c>, allow_mut_ref_to_temporary: bool, cache: &mut ModuleCache<'c>,
 ) {
     match variable {
-        ast::Ast::Variable(variable) => {
+        // Constructors like `None` in a `!None` pattern create new values, so they are treated as temporaries
+        ast::Ast::Variable(variable) if !matches!(variable.kind, ast::VariableKind::TypeConstructor(_)) => {
             let Some(definition) = variable.definition else { return };
             if !cache[definition].mutable {
                 let name = cache[definition].name.to_string();
-                cache.push_diagnostic(variable.location, D::MutRefToImmutableVariable(name));
+                let declaration = cache[definition].location;
+                let diagnostic = Diagnostic::new(variable.location, D::MutRefToImmutableVariable(name.clone()))
+                    .with_note(declaration, D::MakeBindingMutable(name));
+                cache.push_full_diagnostic(diagnostic);
             }
         },
         // Assume we've already checked the recursive case from MemberAccess::infer_impl
@@ -2246,8 +4348,75 @@ fn check_field_access_lhs_is_mutable<'c>(
     }
 }
 
+/// Warn when the same field is assigned to twice in a row within a sequence with no
+/// statement in between that may read it, since the first assignment has no effect.
+///
+/// This is conservative: any statement other than a field assignment, or an assignment
+/// whose rhs is anything other than a literal or an unrelated variable, may read the
+/// field through an alias and thus counts as a read of every field assigned so far.
+fn check_redundant_field_assignments<'c>(statements: &[ast::Ast<'c>], cache: &mut ModuleCache<'c>) {
+    // Each field path assigned to which has not been read since, along with the assignment's location
+    let mut unread: Vec<(DefinitionInfoId, Vec<&str>, Location<'c>)> = Vec::new();
+
+    for statement in statements {
+        let assigned = match statement {
+            ast::Ast::Assignment(assignment) if assignment.index_assign.is_none() => {
+                assigned_field_path(&assignment.lhs).map(|path| (path, assignment.rhs.as_ref()))
+            },
+            _ => None,
+        };
+
+        let Some(((root, fields), rhs)) = assigned else {
+            unread.clear();
+            continue;
+        };
+
+        match rhs {
+            ast::Ast::Literal(_) => (),
+            ast::Ast::Variable(variable) if variable.definition != Some(root) => (),
+            _ => unread.clear(),
+        }
+
+        if let Some(index) = unread.iter().position(|(id, path, _)| *id == root && *path == fields) {
+            let (_, _, location) = unread.remove(index);
+            let path = format!("{}.{}", cache[root].name, fields.join("."));
+            cache.push_diagnostic(location, D::RedundantFieldAssignment(path));
+        }
+
+        unread.push((root, fields, statement.locate()));
+    }
+}
+
+/// Returns the variable and field names of an assignment's lhs of the form `a.b.c`, if it is one.
+/// The lhs is expected to already be wrapped in the implicit mutable reference added by inference.
+fn assigned_field_path<'a>(lhs: &'a ast::Ast) -> Option<(DefinitionInfoId, Vec<&'a str>)> {
+    fn field_path<'a>(ast: &'a ast::Ast) -> Option<(DefinitionInfoId, Vec<&'a str>)> {
+        match ast {
+            ast::Ast::Variable(variable) => Some((variable.definition?, vec![])),
+            ast::Ast::MemberAccess(access) => {
+                let (root, mut fields) = field_path(&access.lhs)?;
+                fields.push(&access.field);
+                Some((root, fields))
+            },
+            _ => None,
+        }
+    }
+
+    match lhs {
+        ast::Ast::Reference(reference) => match field_path(&reference.expression)? {
+            (_, fields) if fields.is_empty() => None,
+            path => Some(path),
+        },
+        _ => None,
+    }
+}
+
 impl<'a> Inferable<'a> for ast::Assignment<'a> {
     fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
+        if self.index_assign.is_some() {
+            return infer_index_assignment(self, cache);
+        }
+
         let mut result = infer(self.lhs.as_mut(), cache);
         let mut rhs = infer(self.rhs.as_mut(), cache);
         result.combine(&mut rhs, cache);
@@ -2271,6 +4440,8 @@ impl<'a> Inferable<'a> for ast::Assignment<'a> {
             return result.with_type(Type::UNIT);
         }
 
+        // If the lhs is a polymorphically-mutable reference `?t`, unifying it here binds its
+        // mutability variable to `!`, so later uses of the same reference see the refined type.
         let mut_ref = mut_polymorphically_shared_ref(cache);
         let mutref = Type::TypeApplication(Box::new(mut_ref), vec![rhs.typ.clone()]);
 
@@ -2283,6 +4454,49 @@ impl<'a> Inferable<'a> for ast::Assignment<'a> {
     }
 }
 
+/// Infers `collection.[index] := value` as the call `index_assign collection index value`.
+/// The element type is then determined by the `IndexAssign` impl for the container and
+/// index types rather than by taking a mutable reference to the result of indexing.
+fn infer_index_assignment<'a>(assignment: &mut ast::Assignment<'a>, cache: &mut ModuleCache<'a>) -> TypeResult {
+    let index_assign = assignment.index_assign.as_mut().unwrap();
+    let mut result = infer(index_assign.as_mut(), cache);
+
+    let args = match assignment.lhs.as_mut() {
+        ast::Ast::FunctionCall(call) => &mut call.args,
+        _ => unreachable!("infer_index_assignment: lhs is not an index expression"),
+    };
+
+    let mut parameters = Vec::with_capacity(args.len() + 1);
+    for arg in args.iter_mut() {
+        let mut arg_result = infer(arg, cache);
+        result.combine(&mut arg_result, cache);
+        parameters.push(arg_result.typ);
+    }
+
+    let element_type = next_type_variable(cache);
+    parameters.push(element_type.clone());
+
+    let effects_var = next_type_variable_id(cache);
+    let expected = Function(FunctionType {
+        parameters,
+        return_type: Box::new(Type::UNIT),
+        environment: Box::new(next_type_variable(cache)),
+        effects: Box::new(Type::TypeVariable(effects_var)),
+        has_varargs: false,
+    });
+
+    unify(&expected, &result.typ, assignment.location, cache, TE::NeverShown);
+
+    let mut rhs = infer(assignment.rhs.as_mut(), cache);
+    result.combine(&mut rhs, cache);
+    unify(&element_type, &rhs.typ, assignment.location, cache, TE::AssignToWrongType);
+
+    let effects = Type::Effects(result.effects.clone());
+    unify(&Type::TypeVariable(effects_var), &effects, assignment.location, cache, TE::NeverShown);
+
+    result.with_type(Type::UNIT)
+}
+
 fn mut_polymorphically_shared_ref(cache: &mut ModuleCache) -> Type {
     let mutability = Box::new(Type::Tag(TypeTag::Mutable));
     let sharedness = Box::new(next_type_variable(cache));
@@ -2312,6 +4526,12 @@ fn issue_assignment_error<'c>(
 
 impl<'a> Inferable<'a> for ast::EffectDefinition<'a> {
     fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
+        // This may have already been inferred if one of its declarations was used
+        // before the definition itself, e.g. within a `comptime` expression.
+        if self.typ.is_some() {
+            return TypeResult::of(Type::UNIT, cache);
+        }
+
         let previous_level = CURRENT_LEVEL.swap(self.level.unwrap().0, Ordering::SeqCst);
 
         let effect_id = self.effect_info.unwrap();
@@ -2363,21 +4583,50 @@ impl<'a> Inferable<'a> for ast::Handle<'a> {
         let mut result = infer(self.expression.as_mut(), cache);
 
         let mut pattern_results = Vec::with_capacity(self.branches.len());
+        let mut pattern_types = Vec::with_capacity(self.branches.len());
         let mut branch_results = Vec::with_capacity(self.branches.len());
 
+        for (pattern, _) in self.branches.iter_mut() {
+            let pattern_type = infer(pattern, cache);
+
+            // Only the effects named by the pattern itself are handled. Its extension must be dropped
+            // here, otherwise once it is combined with the handled expression's effects below, every
+            // effect the expression performs would appear to be handled by this pattern.
+            let handled = EffectSet::only(pattern_type.effects.flatten(cache).effects);
+            pattern_results.push((pattern_type.traits, handled));
+            pattern_types.push(pattern_type.typ);
+        }
+
+        // Before we handle the effects we need to add them to the handled expression
+        // in case that expression was not known to have them already (e.g. invoking a
+        // parameter with an inferred function type).
+        for (_, effects) in &pattern_results {
+            result.effects.combine(&effects, cache);
+        }
+
+        // Must remove all the handled effects from each pattern first
+        let mut handled_effects = Vec::new();
+        for (traits, effects) in pattern_results {
+            result.handle_effects_from(traits, effects, &mut handled_effects, cache);
+        }
+
+        self.effects_handled = handled_effects;
+
+        // Each `resume` performs the effects left unhandled by this handler. These are known
+        // before the branches are checked so that a branch which re-handles the continuation
+        // by calling back into the function performing this handle, e.g. `run_state (fn () -> resume ()) new`,
+        // sees the same effects and result type as the rest of the recursion.
+        let resume_effects = Type::Effects(result.effects.clone());
+
         // All `resume` variables (there is a separate one per branch) have the same environment
         // type that is the free variables in this `Handle` + a continuation type. We can't set
         // this until these free variables have their types set though so this is a type variable
         // for now and we unify it after the Handle branches are finished type checking.
         let resume_environment_type_var = next_type_variable(cache);
-        let resume_effects = next_type_variable(cache);
-
-        for ((pattern, branch), resume) in self.branches.iter_mut().zip(&self.resumes) {
-            let pattern_type = infer(pattern, cache);
-            pattern_results.push((pattern_type.traits, pattern_type.effects));
 
+        for (((_, branch), resume), pattern_type) in self.branches.iter_mut().zip(&self.resumes).zip(pattern_types) {
             let expected_resume_type = Type::Function(FunctionType {
-                parameters: vec![pattern_type.typ],
+                parameters: vec![pattern_type],
                 return_type: Box::new(result.typ.clone()),
                 environment: Box::new(resume_environment_type_var.clone()),
                 effects: Box::new(resume_effects.clone()),
@@ -2410,24 +4659,9 @@ impl<'a> Inferable<'a> for ast::Handle<'a> {
             TE::ResumeEnvironmentMismatch,
         );
 
-        // Before we handle the effects we need to add them to the handled expression
-        // in case that expression was not known to have them already (e.g. invoking a
-        // parameter with an inferred function type).
-        for (_, effects) in &pattern_results {
-            result.effects.combine(&effects, cache);
-        }
-
-        // Must remove all the handled effects from each pattern first
-        let mut handled_effects = Vec::new();
-        for (traits, effects) in pattern_results {
-            result.handle_effects_from(traits, effects, &mut handled_effects, cache);
-        }
-
-        self.effects_handled = handled_effects;
-
-        unify(&resume_effects, &Type::Effects(result.effects.clone()), self.location, cache, TE::ResumeEffectsMismatch);
-
-        // So that we can later add the effects from the branches without accidentally removing them
+        // The handler branches may perform effects of their own, including ones this handler
+        // handles (which are then forwarded to an outer handler). These must be added only after
+        // handle_effects_from so that they are not accidentally removed from the result.
         for mut branch in branch_results {
             result.combine(&mut branch, cache);
         }
@@ -2456,7 +4690,26 @@ fn resume_environment_type(free_variables: BTreeMap<DefinitionInfoId, Type>) ->
 
 impl<'a> Inferable<'a> for ast::NamedConstructor<'a> {
     fn infer_impl(&mut self, cache: &mut ModuleCache<'a>) -> TypeResult {
-        self.sequence.infer_impl(cache)
+        let record = self.record.as_mut().map(|record| infer(record.as_mut(), cache));
+        let mut result = self.sequence.infer_impl(cache);
+        if self.constructor.is_some() {
+            return result;
+        }
+
+        let fields =
+            fmap(self.field_variables(), |variable| (variable.kind.name().into_owned(), variable.typ.clone().unwrap()));
+
+        // A functional record update's type depends on the type of the record being updated
+        if let Some(mut record) = record {
+            let result_type = next_type_variable(cache);
+            unify_record_update(&record.typ, &fields.into_iter().collect(), &result_type, self.location, cache);
+            record.combine(&mut result, cache);
+            return record.with_type(result_type);
+        }
+
+        // An anonymous record's type is exactly the fields it is given
+        let rest = next_type_variable_id(cache);
+        result.with_type(Type::Struct(fields.into_iter().collect(), rest, RowKind::Closed))
     }
 }
 
@@ -54,3 +54,143 @@ impl_typed_for!(EffectDefinition);
 impl_typed_for!(Handle);
 impl_typed_for!(NamedConstructor);
 impl_typed_for!(Reference);
+impl_typed_for!(ConditionalCompilation);
+impl_typed_for!(Spread);
+impl_typed_for!(ArrayLiteral);
+impl_typed_for!(Hole);
+impl_typed_for!(Error);
+
+/// Clear the type of the given node and each of its children that was filled in by type inference.
+/// Types given by explicit type annotations are filled in during name resolution and are kept.
+pub fn clear_inferred_types(ast: &mut Ast) {
+    match ast {
+        Ast::Literal(literal) => literal.typ = None,
+        Ast::Hole(hole) => hole.typ = None,
+        Ast::Error(error) => error.typ = None,
+        Ast::Variable(variable) => {
+            variable.typ = None;
+            variable.instantiation_mapping = Default::default();
+        },
+        Ast::Lambda(lambda) => {
+            lambda.typ = None;
+            lambda.required_traits.clear();
+            lambda.args.iter_mut().for_each(clear_inferred_types);
+            lambda.contracts.iter_mut().for_each(|contract| clear_inferred_types(&mut contract.condition));
+
+            // Name resolution stores any return type annotation as the type of the lambda's body
+            let return_type = lambda.return_type.as_ref().and_then(|_| lambda.body.get_type().cloned());
+            clear_inferred_types(&mut lambda.body);
+            if let Some(return_type) = return_type {
+                lambda.body.set_type(return_type);
+            }
+        },
+        Ast::FunctionCall(call) => {
+            call.typ = None;
+            clear_inferred_types(&mut call.function);
+            call.args.iter_mut().for_each(clear_inferred_types);
+        },
+        Ast::Definition(definition) => clear_definition_types(definition),
+        Ast::If(if_) => {
+            if_.typ = None;
+            clear_inferred_types(&mut if_.condition);
+            clear_inferred_types(&mut if_.then);
+            clear_inferred_types(&mut if_.otherwise);
+        },
+        Ast::Match(match_) => {
+            match_.typ = None;
+            match_.decision_tree = None;
+            match_.reference_bindings.clear();
+            clear_inferred_types(&mut match_.expression);
+            for (pattern, branch) in match_.branches.iter_mut() {
+                clear_inferred_types(pattern);
+                clear_inferred_types(branch);
+            }
+        },
+        Ast::TypeDefinition(definition) => definition.typ = None,
+        // The type of an annotation is the annotated type, so it is kept
+        Ast::TypeAnnotation(annotation) => clear_inferred_types(&mut annotation.lhs),
+        Ast::Import(import) => import.typ = None,
+        Ast::TraitDefinition(definition) => {
+            definition.typ = None;
+            definition.declarations.iter_mut().for_each(|declaration| clear_inferred_types(&mut declaration.lhs));
+        },
+        Ast::TraitImpl(trait_impl) => {
+            trait_impl.typ = None;
+            trait_impl.definitions.iter_mut().for_each(clear_definition_types);
+            trait_impl.helpers.iter_mut().for_each(clear_definition_types);
+        },
+        Ast::Return(return_) => {
+            return_.typ = None;
+            clear_inferred_types(&mut return_.expression);
+        },
+        Ast::Sequence(sequence) => {
+            sequence.typ = None;
+            sequence.statements.iter_mut().for_each(clear_inferred_types);
+        },
+        Ast::Extern(extern_) => {
+            extern_.typ = None;
+            extern_.declarations.iter_mut().for_each(|declaration| clear_inferred_types(&mut declaration.lhs));
+        },
+        Ast::MemberAccess(member_access) => {
+            member_access.typ = None;
+            clear_inferred_types(&mut member_access.lhs);
+        },
+        Ast::Assignment(assignment) => {
+            assignment.typ = None;
+            clear_inferred_types(&mut assignment.lhs);
+            clear_inferred_types(&mut assignment.rhs);
+            if let Some(index_assign) = &mut assignment.index_assign {
+                clear_inferred_types(index_assign);
+            }
+        },
+        Ast::EffectDefinition(definition) => {
+            definition.typ = None;
+            definition.declarations.iter_mut().for_each(|declaration| clear_inferred_types(&mut declaration.lhs));
+        },
+        Ast::Handle(handle) => {
+            handle.typ = None;
+            handle.effects_handled.clear();
+            clear_inferred_types(&mut handle.expression);
+            for (pattern, branch) in handle.branches.iter_mut() {
+                clear_inferred_types(pattern);
+                clear_inferred_types(branch);
+            }
+        },
+        Ast::NamedConstructor(constructor) => {
+            constructor.typ = None;
+            if let Some(constructor) = &mut constructor.constructor {
+                clear_inferred_types(constructor);
+            }
+            if let Some(record) = &mut constructor.record {
+                clear_inferred_types(record);
+            }
+            clear_inferred_types(&mut constructor.sequence);
+        },
+        Ast::Reference(reference) => {
+            reference.typ = None;
+            clear_inferred_types(&mut reference.expression);
+        },
+        Ast::ConditionalCompilation(conditional) => {
+            conditional.typ = None;
+            clear_inferred_types(&mut conditional.then);
+            clear_inferred_types(&mut conditional.otherwise);
+        },
+        Ast::Spread(spread) => {
+            spread.typ = None;
+            clear_inferred_types(&mut spread.expression);
+        },
+        Ast::ArrayLiteral(array) => {
+            array.typ = None;
+            for element in &mut array.elements {
+                clear_inferred_types(element);
+            }
+        },
+    }
+}
+
+/// Clear the types of the given definition, its pattern, and its body. See `clear_inferred_types`.
+pub fn clear_definition_types(definition: &mut Definition) {
+    definition.typ = None;
+    clear_inferred_types(&mut definition.pattern);
+    clear_inferred_types(&mut definition.expr);
+}
@@ -4,10 +4,12 @@
 //! types/traits are displayed via `type.display(cache)` rather than directly having
 //! a Display impl.
 use crate::cache::{ModuleCache, TraitInfoId};
-use crate::types::traits::{ConstraintSignature, RequiredTrait, TraitConstraintId};
+use crate::types::traits::{ConstraintSignature, RequiredTrait, TraitConstraint, TraitConstraintId};
 use crate::types::typechecker::find_all_typevars;
-use crate::types::{FunctionType, PrimitiveType, Type, TypeBinding, TypeInfoId, TypeVariableId};
+use crate::types::{FunctionType, PrimitiveType, RowKind, Type, TypeBinding, TypeInfoId, TypeTag, TypeVariableId};
+use crate::util::fmap;
 
+use std::cell::RefCell;
 use std::collections::hash_map::Entry;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Display, Formatter};
@@ -30,6 +32,11 @@ pub struct TypePrinter<'a, 'b> {
     /// Controls whether to show or hide some hidden data, like ref lifetimes
     debug: bool,
 
+    /// Each unbound type variable written out so far, in the order they were printed.
+    /// Variables which are never printed, like those in closure environments, are
+    /// left out of the type's `forall` when it is displayed.
+    shown_typevars: RefCell<Vec<TypeVariableId>>,
+
     cache: &'a ModuleCache<'b>,
 }
 
@@ -38,6 +45,9 @@ struct TypeVarNames {
     map: HashMap<TypeVariableId, String>,
     used_named_generics: HashSet<Rc<String>>,
     next_unused_name: char,
+
+    /// The binders of any recursive types found, which are named after all other typevars
+    rec_binders: Vec<TypeVariableId>,
 }
 
 impl<'a, 'b> Display for TypePrinter<'a, 'b> {
@@ -54,7 +64,12 @@ impl<'a, 'b> Debug for TypePrinter<'a, 'b> {
 
 impl TypeVarNames {
     fn new() -> Self {
-        Self { map: Default::default(), used_named_generics: Default::default(), next_unused_name: 'a' }
+        Self {
+            map: Default::default(),
+            used_named_generics: Default::default(),
+            next_unused_name: 'a',
+            rec_binders: Default::default(),
+        }
     }
 
     /// Fill `self.map` with human readable names for each typevar in the given Vec.
@@ -65,7 +80,8 @@ impl TypeVarNames {
             self.increment_next_unused_name();
         }
 
-        for typevar in typevars {
+        let rec_binders = std::mem::take(&mut self.rec_binders);
+        for typevar in typevars.into_iter().chain(rec_binders) {
             if let Entry::Vacant(entry) = self.map.entry(typevar) {
                 entry.insert(self.next_unused_name.to_string());
                 self.increment_next_unused_name();
@@ -87,7 +103,7 @@ impl TypeVarNames {
 
     fn collect_named_generic_names(&mut self, typ: &Type, cache: &ModuleCache) {
         match typ {
-            Type::Primitive(_) | Type::Tag(_) | Type::UserDefined(_) => (),
+            Type::Primitive(_) | Type::Tag(_) | Type::UserDefined(_) | Type::Universe(_) => (),
             Type::TypeVariable(id) => match &cache.type_bindings[id.0] {
                 TypeBinding::Bound(binding) => self.collect_named_generic_names(binding, cache),
                 TypeBinding::Unbound(..) => (),
@@ -119,7 +135,11 @@ impl TypeVarNames {
                 self.collect_named_generic_names(sharedness, cache);
                 self.collect_named_generic_names(lifetime, cache);
             },
-            Type::Struct(fields, replacement) => {
+            Type::Rec(binder, body) => {
+                self.rec_binders.push(*binder);
+                self.collect_named_generic_names(body, cache);
+            },
+            Type::Struct(fields, replacement, _) => {
                 if let TypeBinding::Bound(binding) = &cache.type_bindings[replacement.0] {
                     self.collect_named_generic_names(binding, cache);
                 } else {
@@ -154,15 +174,91 @@ pub fn show_type_and_traits(
     name: &str, typ: &GeneralizedType, traits: &[RequiredTrait], trait_info: &Option<(TraitInfoId, Vec<Type>)>,
     cache: &ModuleCache<'_>, debug: bool,
 ) -> String {
+    format!("{} : {}", name, show_signature(typ, traits, trait_info, cache, debug))
+}
+
+/// Returns the type of each definition in the first compiled module (so excluding imports)
+/// along with the traits it requires, keyed by the definition's name. Type variables are named
+/// in order of appearance and colors are removed so that the result is stable across runs.
+/// This is useful for checking inference does not unexpectedly change between compiler versions.
+#[allow(dead_code)]
+pub fn type_signatures_snapshot(cache: &ModuleCache<'_>) -> BTreeMap<String, String> {
+    let Some(resolver) = cache.name_resolvers.get_mut(0) else {
+        return BTreeMap::new();
+    };
+
+    let signatures = resolver.exports.definitions.iter().map(|(name, definition_id)| {
+        let info = &cache[*definition_id];
+        let signature = match &info.typ {
+            Some(typ) => show_signature(typ, &info.required_traits, &info.trait_info, cache, true),
+            None => "(none)".to_string(),
+        };
+        (name.clone(), remove_colors(&signature))
+    });
+
+    signatures.collect()
+}
+
+/// Remove the terminal escape codes used to color output from the given string
+fn remove_colors(string: &str) -> String {
+    let mut result = String::with_capacity(string.len());
+    let mut chars = string.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // Skip the rest of the escape code, which always ends in an 'm'
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Formats the given type followed by the traits it requires, if any.
+/// See `show_type_and_traits`.
+fn show_signature(
+    typ: &GeneralizedType, traits: &[RequiredTrait], trait_info: &Option<(TraitInfoId, Vec<Type>)>,
+    cache: &ModuleCache<'_>, debug: bool,
+) -> String {
+    let (type_string, traits) = show_type_and_trait_strings(typ, traits, trait_info, cache, debug);
+    if traits.is_empty() {
+        type_string
+    } else {
+        format!("{}\n  given {}", type_string, traits.join(", "))
+    }
+}
+
+/// Formats the given type on a single line, including its `forall` quantifiers,
+/// followed by ` given ` and each trait constraint it requires, if any.
+pub fn show_generalized_type(
+    typ: &GeneralizedType, constraints: &[TraitConstraint], cache: &ModuleCache<'_>,
+) -> String {
+    let traits = fmap(constraints, |constraint| constraint.required.clone());
+    let (type_string, traits) = show_type_and_trait_strings(typ, &traits, &None, cache, false);
+    if traits.is_empty() {
+        type_string
+    } else {
+        format!("{} given {}", type_string, traits.join(", "))
+    }
+}
+
+/// Returns the given type and each (deduplicated) trait it requires as separate strings,
+/// naming the type variables consistently across all of them.
+fn show_type_and_trait_strings(
+    typ: &GeneralizedType, traits: &[RequiredTrait], trait_info: &Option<(TraitInfoId, Vec<Type>)>,
+    cache: &ModuleCache<'_>, debug: bool,
+) -> (String, Vec<String>) {
     let mut names = TypeVarNames::new();
 
     let typevars = typ.find_all_typevars(false, cache);
     names.collect_named_generic_names(typ.remove_forall(), cache);
-    names.fill_typevar_map_with_lowercase_names(typevars);
+    let (shown, hidden) = partition_shown_typevars(typevars, typ, debug, cache);
+    names.fill_typevar_map_with_lowercase_names(shown);
+    names.fill_typevar_map_with_lowercase_names(hidden);
 
-    let typ = typ.clone();
-    let printer = TypePrinter { typ, cache, debug, typevar_names: names.clone() };
-    let type_string = format!("{} : {}", name, printer);
+    let printer = TypePrinter::new(typ.clone(), names.clone(), debug, cache);
+    let type_string = printer.to_string();
 
     let mut traits = traits
         .iter()
@@ -198,11 +294,16 @@ pub fn show_type_and_traits(
     // isn't used in their Display impl so they look like duplicates.
     traits.sort();
     traits.dedup();
-    if traits.is_empty() {
-        type_string
-    } else {
-        format!("{}\n  given {}", type_string, traits.join(", "))
-    }
+    (type_string, traits)
+}
+
+/// Splits the given type variables into those written out when printing the given type and
+/// those which are hidden, so that the hidden variables can be named after the rest.
+fn partition_shown_typevars(
+    typevars: Vec<TypeVariableId>, typ: &GeneralizedType, debug: bool, cache: &ModuleCache<'_>,
+) -> (Vec<TypeVariableId>, Vec<TypeVariableId>) {
+    let shown = TypePrinter::find_shown_typevars(typ.remove_forall(), debug, cache);
+    typevars.into_iter().partition(|typevar| shown.contains(typevar))
 }
 
 impl ConstraintSignature {
@@ -240,7 +341,14 @@ impl ConstraintSignature {
 
 impl<'a, 'b> TypePrinter<'a, 'b> {
     fn new(typ: GeneralizedType, typevar_names: TypeVarNames, debug: bool, cache: &'a ModuleCache<'b>) -> Self {
-        TypePrinter { typ, typevar_names, debug, cache }
+        TypePrinter { typ, typevar_names, debug, shown_typevars: RefCell::new(vec![]), cache }
+    }
+
+    /// Returns each unbound type variable which is written out when printing the given type.
+    fn find_shown_typevars(typ: &Type, debug: bool, cache: &'a ModuleCache<'b>) -> Vec<TypeVariableId> {
+        let printer = Self::new(GeneralizedType::MonoType(typ.clone()), TypeVarNames::new(), debug, cache);
+        printer.to_string();
+        printer.shown_typevars.into_inner()
     }
 
     pub fn debug_type(typ: GeneralizedType, cache: &'a ModuleCache<'b>) -> Self {
@@ -258,14 +366,29 @@ impl<'a, 'b> TypePrinter<'a, 'b> {
     }
 
     pub fn display_type(typ: GeneralizedType, cache: &'a ModuleCache<'b>) -> Self {
-        let typevars = typ.find_all_typevars(false, cache);
+        let mut typevars = typ.find_all_typevars(false, cache);
+        typevars.retain(|typevar| !cache.type_variable_names.contains_key(typevar));
         let mut typevar_names = TypeVarNames::new();
 
         typevar_names.collect_named_generic_names(typ.remove_forall(), cache);
-        typevar_names.fill_typevar_map_with_lowercase_names(typevars);
+        let (shown, hidden) = partition_shown_typevars(typevars, &typ, false, cache);
+        typevar_names.fill_typevar_map_with_lowercase_names(shown);
+        typevar_names.fill_typevar_map_with_lowercase_names(hidden);
         Self::new(typ, typevar_names, false, cache)
     }
 
+    pub fn display_type_within(typ: Type, context: &Type, cache: &'a ModuleCache<'b>) -> Self {
+        let mut typevars = find_all_typevars(context, false, cache);
+        typevars.append(&mut find_all_typevars(&typ, false, cache));
+        typevars.retain(|typevar| !cache.type_variable_names.contains_key(typevar));
+        let mut typevar_names = TypeVarNames::new();
+
+        typevar_names.collect_named_generic_names(context, cache);
+        typevar_names.collect_named_generic_names(&typ, cache);
+        typevar_names.fill_typevar_map_with_lowercase_names(typevars);
+        Self::new(GeneralizedType::MonoType(typ), typevar_names, false, cache)
+    }
+
     fn fmt_generalized_type(&self, typ: &GeneralizedType, f: &mut Formatter) -> std::fmt::Result {
         match typ {
             GeneralizedType::MonoType(typ) => self.fmt_type(typ, f),
@@ -281,10 +404,13 @@ impl<'a, 'b> TypePrinter<'a, 'b> {
             Type::UserDefined(id) => self.fmt_user_defined_type(*id, f),
             Type::TypeApplication(constructor, args) => self.fmt_type_application(constructor, args, f),
             Type::Ref { sharedness, mutability, lifetime } => self.fmt_ref(sharedness, mutability, lifetime, f),
-            Type::Struct(fields, rest) => self.fmt_struct(fields, *rest, f),
+            Type::Struct(fields, rest, kind) => self.fmt_struct(fields, *rest, *kind, f),
+            Type::Rec(binder, body) => self.fmt_rec(*binder, body, f),
             Type::Effects(effects) => self.fmt_effects(effects, f),
             Type::NamedGeneric(id, name) => self.fmt_named_generic(*id, name, f),
+            Type::Tag(TypeTag::Dyn(id)) => write!(f, "{} {}", "dyn".blue(), self.cache[*id].name.blue()),
             Type::Tag(tag) => write!(f, "{}", tag.to_string().blue()),
+            Type::Universe(level) => write!(f, "{} {}", "Universe".blue(), level.to_string().blue()),
         }
     }
 
@@ -353,6 +479,13 @@ impl<'a, 'b> TypePrinter<'a, 'b> {
         match &self.cache.type_bindings[id.0] {
             TypeBinding::Bound(typ) => self.fmt_type(typ, f),
             TypeBinding::Unbound(..) => {
+                self.shown_typevars.borrow_mut().push(id);
+
+                // Names pinned in the cache take priority over automatically-assigned names
+                if let Some(name) = self.cache.type_variable_names.get(&id) {
+                    return write!(f, "{}", name.blue());
+                }
+
                 let default = "?".to_string();
                 let name = self.typevar_names.map.get(&id).unwrap_or(&default).blue();
                 write!(f, "{}", name)
@@ -360,10 +493,17 @@ impl<'a, 'b> TypePrinter<'a, 'b> {
         }
     }
 
+    fn fmt_rec(&self, binder: TypeVariableId, body: &Type, f: &mut Formatter) -> std::fmt::Result {
+        let name = self.typevar_names.map.get(&binder).cloned().unwrap_or_else(|| binder.0.to_string());
+        write!(f, "{} {}{} ", "rec".blue(), name.blue(), ".".blue())?;
+        self.fmt_type(body, f)
+    }
+
     fn fmt_named_generic(&self, id: TypeVariableId, name: &str, f: &mut Formatter) -> std::fmt::Result {
         if let Some(binding) = self.cache.get_binding(id) {
             self.fmt_type(binding, f)
         } else {
+            self.shown_typevars.borrow_mut().push(id);
             write!(f, "{}", name.blue())
         }
     }
@@ -387,6 +527,12 @@ impl<'a, 'b> TypePrinter<'a, 'b> {
                 write!(f, " ")?;
             }
             self.fmt_type(&args[0], f)
+        } else if constructor.is_nat_operator() && args.len() == 2 {
+            self.fmt_nat_operand(&args[0], f)?;
+            write!(f, " ")?;
+            self.fmt_type(constructor, f)?;
+            write!(f, " ")?;
+            self.fmt_nat_operand(&args[1], f)
         } else {
             if constructor.is_pair_type() {
                 self.fmt_pair(&args[0], &args[1], f)?;
@@ -408,13 +554,26 @@ impl<'a, 'b> TypePrinter<'a, 'b> {
         }
     }
 
+    fn fmt_nat_operand(&self, arg: &Type, f: &mut Formatter) -> std::fmt::Result {
+        if TypePriority::APP >= arg.priority(self.cache) {
+            write!(f, "{}", "(".blue())?;
+        }
+        self.fmt_type(arg, f)?;
+        if TypePriority::APP >= arg.priority(self.cache) {
+            write!(f, "{}", ")".blue())?;
+        }
+        Ok(())
+    }
+
+    /// True if anything is printed after the `&` or `!` of this reference type,
+    /// in which case the element type must be separated from it by a space.
     fn reference_type_has_shared_specifier(&self, typ: &Type) -> bool {
         let Type::Ref { mutability: _, sharedness, lifetime: _ } = typ else {
             return false;
         };
 
         let sharedness = self.cache.follow_bindings_shallow(sharedness);
-        matches!(sharedness, Type::Tag(_))
+        self.debug || matches!(sharedness, Type::Tag(_))
     }
 
     fn fmt_pair(&self, arg1: &Type, arg2: &Type, f: &mut Formatter) -> std::fmt::Result {
@@ -457,14 +616,15 @@ impl<'a, 'b> TypePrinter<'a, 'b> {
             _ => write!(f, "{}", "?".blue())?,
         }
 
-        if let Type::Tag(tag) = shared {
-            write!(f, "{}", tag.to_string().blue())?;
-            if self.debug {
-                write!(f, " ")?;
-            }
+        match shared {
+            Type::Tag(tag) => write!(f, "{}", tag.to_string().blue())?,
+            // Polymorphic sharedness is only shown when debugging, along with the lifetime
+            _ if self.debug => self.fmt_type(&shared, f)?,
+            _ => (),
         }
 
         if self.debug {
+            write!(f, " ")?;
             self.fmt_type(lifetime, f)?;
         }
 
@@ -472,6 +632,13 @@ impl<'a, 'b> TypePrinter<'a, 'b> {
     }
 
     fn fmt_forall(&self, typevars: &[TypeVariableId], typ: &Type, f: &mut Formatter) -> std::fmt::Result {
+        // Only quantify the variables that are actually printed
+        let shown = Self::find_shown_typevars(typ, self.debug, self.cache);
+        let typevars = typevars.iter().filter(|typevar| shown.contains(typevar)).collect::<Vec<_>>();
+        if typevars.is_empty() {
+            return self.fmt_type(typ, f);
+        }
+
         write!(f, "{}", "forall".blue())?;
         for typevar in typevars {
             write!(f, " ")?;
@@ -489,20 +656,27 @@ impl<'a, 'b> TypePrinter<'a, 'b> {
     }
 
     fn fmt_struct(
-        &self, fields: &BTreeMap<String, Type>, rest: TypeVariableId, f: &mut Formatter,
+        &self, fields: &BTreeMap<String, Type>, rest: TypeVariableId, kind: RowKind, f: &mut Formatter,
     ) -> Result<(), std::fmt::Error> {
         match &self.cache.type_bindings[rest.0] {
             TypeBinding::Bound(typ) => self.fmt_type(typ, f),
             TypeBinding::Unbound(..) => {
                 write!(f, "{}", "{ ".blue())?;
 
-                for (name, field_type) in fields.iter() {
+                for (i, (name, field_type)) in fields.iter().enumerate() {
                     write!(f, "{}{}", name.blue(), ": ".blue())?;
                     self.fmt_type(field_type, f)?;
-                    write!(f, "{}", ", ".blue())?;
+
+                    // Closed structs have no trailing `..` so the last field needs no separator
+                    if kind == RowKind::Open || i + 1 != fields.len() {
+                        write!(f, "{}", ", ".blue())?;
+                    }
                 }
 
-                if self.debug {
+                if kind == RowKind::Closed {
+                    write!(f, "{}", " }".blue())
+                } else if self.debug {
+                    self.shown_typevars.borrow_mut().push(rest);
                     let default = "?".to_string();
                     let name = self.typevar_names.map.get(&rest).unwrap_or(&default).blue();
                     write!(f, "{}{}{}", "..".blue(), name, " }".blue())
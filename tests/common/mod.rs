@@ -0,0 +1,52 @@
+//! Fixtures shared between the integration tests. Each test binary only uses some of them.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use ante::cache::ModuleCache;
+use ante::frontend::{self, FrontendPhase, FrontendResult};
+use ante::types::effects::EffectSet;
+use ante::types::{FunctionType, Type};
+
+/// The path of the given file within the examples directory. The file need not exist,
+/// its source is provided to the ModuleCache directly.
+pub fn example_path(name: &str) -> PathBuf {
+    std::env::current_dir().unwrap().join("examples").join(name)
+}
+
+/// Create a ModuleCache containing the given source for `filename`.
+/// Each of the given modules is placed next to it so that the source may import it.
+pub fn module_cache<'a>(filename: &'a Path, source: &str, modules: &[(&str, &str)]) -> ModuleCache<'a> {
+    let mut file_cache = HashMap::from([(filename.to_owned(), source.to_string())]);
+    for (name, module) in modules {
+        file_cache.insert(filename.with_file_name(name), module.to_string());
+    }
+
+    ModuleCache::new(filename.parent().unwrap(), file_cache)
+}
+
+/// Type check the given source as its own module in an existing cache, panicking if there are any errors
+pub fn type_check<'a>(filename: &'a Path, source: &str, cache: &mut ModuleCache<'a>) {
+    let result = frontend::check(filename, source.to_string(), cache, FrontendPhase::TypeCheck, false);
+    assert!(matches!(result, FrontendResult::ContinueCompilation));
+}
+
+/// Type check the given source as its own module, panicking if there are any errors.
+/// Each of the given modules may be imported by the source.
+pub fn check<'a>(filename: &'a Path, source: &str, modules: &[(&str, &str)]) -> ModuleCache<'a> {
+    let mut cache = module_cache(filename, source, modules);
+    type_check(filename, source, &mut cache);
+    cache
+}
+
+/// A pure function type with no environment
+pub fn function(parameters: Vec<Type>, return_type: Type) -> Type {
+    Type::Function(FunctionType {
+        parameters,
+        return_type: Box::new(return_type),
+        environment: Box::new(Type::UNIT),
+        effects: Box::new(Type::Effects(EffectSet::pure())),
+        has_varargs: false,
+    })
+}
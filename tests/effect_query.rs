@@ -0,0 +1,50 @@
+mod common;
+
+use ante::parser::ast::Ast;
+use ante::types::effects::display_effect;
+use ante::types::typechecker;
+
+/// The effects of an expression should be queryable after inference, with the arguments
+/// of each effect resolved to the types they were unified with.
+#[test]
+fn effects_of_inferred_expression() {
+    let source = [
+        "effect Log with",
+        "    log: String -> Unit",
+        "",
+        "effect Use a with",
+        "    get: Unit -> a",
+        "    set: a -> Unit",
+        "",
+        "log_and_set () =",
+        "    log \"setting\"",
+        "    set 3i32",
+        "    log \"set\"",
+    ]
+    .join("\n");
+
+    let filename = common::example_path("effect_query.an");
+    let mut cache = common::check(&filename, &source, &[]);
+
+    let Some(Ast::Sequence(sequence)) = cache.parse_trees.get_mut(0) else { panic!("Expected a sequence") };
+    let body = sequence
+        .statements
+        .iter_mut()
+        .find_map(|statement| match statement {
+            Ast::Definition(definition) if definition.pattern.to_string() == "log_and_set" => {
+                match definition.expr.as_mut() {
+                    Ast::Lambda(lambda) => Some(lambda.body.as_mut()),
+                    _ => None,
+                }
+            },
+            _ => None,
+        })
+        .expect("Expected a definition of log_and_set");
+
+    let result = typechecker::infer(body, &mut cache);
+    let effects = result.effects(&cache);
+
+    let mut effects = effects.effects.iter().map(|effect| display_effect(effect, &cache)).collect::<Vec<_>>();
+    effects.sort();
+    assert_eq!(effects, ["Log", "Use I32"]);
+}
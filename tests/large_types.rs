@@ -0,0 +1,28 @@
+mod common;
+
+/// Each parameter of `copies` below is unified with a `Maybe` wrapping the same type variable
+/// which is bound to the 1000-parameter constructor of `Big`. Memoizing the free type variables
+/// of each bound type variable means generalizing `copies` walks this constructor type once
+/// rather than once per parameter.
+#[test]
+fn generalize_function_over_1000_field_struct() {
+    let fields = 1000;
+    let field_declarations: Vec<_> = (0..fields).map(|i| format!("f{i}: a")).collect();
+    let parameters: Vec<_> = (0..fields).map(|i| format!("p{i}")).collect();
+    let arguments = vec!["x"; fields].join(" ");
+
+    let mut source = vec![
+        format!("type Big a = {}", field_declarations.join(", ")),
+        "same (_: a) (_: a) = ()".to_string(),
+        format!("copies (x: I32) make {} =", parameters.join(" ")),
+        format!("    big = (make {arguments} : Big I32)"),
+        "    wrapped = Some make".to_string(),
+    ];
+    source.extend(parameters.iter().map(|parameter| format!("    same wrapped {parameter}")));
+    source.push("    big".to_string());
+    let source = source.join("\n");
+
+    let filename = common::example_path("large_types.an");
+    let cache = common::check(&filename, &source, &[]);
+    assert!(cache.free_typevars_memo_hits.get() >= fields);
+}
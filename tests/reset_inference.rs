@@ -0,0 +1,66 @@
+mod common;
+
+use std::path::Path;
+
+use ante::cache::{DefinitionInfoId, ModuleCache};
+use ante::types::typechecker;
+use ante::types::typeprinter::type_signatures_snapshot;
+
+const SOURCE: &[&str] = &[
+    "type Pair a b = first: a, second: b",
+    "swap (pair: Pair a b) = Pair pair.second pair.first",
+    "add3 a b c = a + b + c",
+    "greet name = print name",
+    "ignore unused = 0",
+    "even n = if n == 0 then true else odd (n - 1)",
+    "odd n = if n == 0 then false else even (n - 1)",
+    "main () = greet (add3 1 2 3)",
+];
+
+/// Type check `SOURCE` as its own module, keeping the checkpoint needed to reset inference afterward
+fn check(filename: &Path) -> ModuleCache<'_> {
+    let source = SOURCE.join("\n");
+    let mut cache = common::module_cache(filename, &source, &[]);
+    cache.keep_inference_checkpoint = true;
+    common::type_check(filename, &source, &mut cache);
+    cache
+}
+
+/// Inferring the same Ast a second time after resetting inference should give identical types.
+/// Diagnostics issued before inference, such as the unused variable warning, are kept.
+#[test]
+fn reset_inference_then_infer_again() {
+    let filename = common::example_path("reset_inference.an");
+    let mut cache = check(&filename);
+
+    let first_snapshot = type_signatures_snapshot(&cache);
+    assert_eq!(first_snapshot["swap"], "forall a b. (Pair a b -> Pair b a pure)");
+    assert_eq!(cache.diagnostics.len(), 1);
+
+    let ast = cache.parse_trees.get_mut(0).unwrap();
+    cache.reset_inference(ast);
+    assert_eq!(cache.diagnostics.len(), 1);
+
+    typechecker::infer_ast(ast, &mut cache);
+    assert_eq!(cache.error_count(), 0);
+    assert_eq!(type_signatures_snapshot(&cache), first_snapshot);
+}
+
+/// Re-inferring an unchanged definition incrementally should leave every type as it was,
+/// including those of the definitions depending on it or mutually recursive with it
+#[test]
+fn reset_for_incremental_keeps_identical_types() {
+    let filename = common::example_path("reset_for_incremental.an");
+    let mut cache = check(&filename);
+    let first_snapshot = type_signatures_snapshot(&cache);
+
+    for name in ["add3", "even"] {
+        let index = cache.definition_infos.iter().rposition(|info| info.name == name).unwrap();
+        let previous_type = cache.definition_infos[index].typ.clone().unwrap();
+
+        let typ = typechecker::reset_for_incremental(DefinitionInfoId(index), &mut cache);
+        assert_eq!(cache.error_count(), 0);
+        assert_eq!(typ.display(&cache).to_string(), previous_type.display(&cache).to_string());
+        assert_eq!(type_signatures_snapshot(&cache), first_snapshot);
+    }
+}
@@ -0,0 +1,372 @@
+mod common;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use ante::cache::{
+    definition_dependency_graph, CompilationTarget, DefinitionInfoId, ImplScopeId, ModuleCache, TraitInfoId, VariableId,
+};
+use ante::error::location::Location;
+use ante::error::TypeErrorKind;
+use ante::lexer::token::IntegerKind;
+use ante::lexer::Lexer;
+use ante::nameresolution::{used_imports, Resolvable};
+use ante::parser::{self, ast::Ast};
+use ante::types::effects::{display_effect, effect_summary};
+use ante::types::traitchecker::type_implements;
+use ante::types::traits::TraitConstraintId;
+use ante::types::typechecker::{
+    self, find_by_type, monomorphizations, pending_constraints, principal_type, references_to, try_unify_biased, Bias,
+};
+use ante::types::{GeneralizedType, Type, TypeBinding, TypeInfoId, STRING_TYPE};
+use common::{check, example_path, function};
+
+/// The last definition with the given name, so that any prelude definitions are shadowed
+fn definition(name: &str, cache: &ModuleCache) -> DefinitionInfoId {
+    let index = cache.definition_infos.iter().rposition(|info| info.name == name);
+    DefinitionInfoId(index.unwrap_or_else(|| panic!("No definition named {name}")))
+}
+
+/// Parse the given expression and resolve it within the scope of the main module
+fn resolve_expression<'a>(filename: &'a Path, expression: &str, cache: &mut ModuleCache<'a>) -> Ast<'a> {
+    let tokens: Vec<_> = Lexer::new(filename, expression).collect();
+    let (mut ast, errors) = parser::parse(&tokens);
+    assert!(errors.is_empty());
+
+    let resolver = cache.name_resolvers.get_mut(0).unwrap();
+    ast.define(resolver, cache);
+    assert_eq!(cache.error_count(), 0);
+    ast
+}
+
+/// Searching for `a -> a` should find `id`, but not functions of a different arity
+#[test]
+fn find_definitions_by_type() {
+    let source = ["id x = x", "pick_first a _ = a", "double (x: I32) = x * 2"].join("\n");
+    let filename = example_path("find_by_type.an");
+    let mut cache = check(&filename, &source, &[]);
+
+    let a = typechecker::next_type_variable(&mut cache);
+    let query = function(vec![a.clone()], a);
+
+    let results = find_by_type(&query, &cache);
+    let names: Vec<_> = results.into_iter().map(|id| cache[id].name.as_str()).collect();
+
+    assert!(names.contains(&"id"));
+    assert!(names.contains(&"double"));
+    assert!(!names.contains(&"pick_first"));
+
+    let string = Type::UserDefined(ante::types::STRING_TYPE);
+    let query = function(vec![string.clone()], string);
+    let names: Vec<_> = find_by_type(&query, &cache).into_iter().map(|id| cache[id].name.as_str()).collect();
+
+    assert!(names.contains(&"id"));
+    assert!(!names.contains(&"double"));
+}
+
+/// Only imported names which are actually referenced should be reported as used
+#[test]
+fn used_imports_excludes_unreferenced_names() {
+    let helpers = ["one = 1", "two = 2", "three = 3"].join("\n");
+    let source = ["import Helpers.one two three", "x = two + 1"].join("\n");
+
+    let filename = example_path("used_imports.an");
+    let cache = check(&filename, &source, &[("Helpers.an", &helpers)]);
+
+    let expected = vec![(vec!["Helpers".to_string()], vec!["two".to_string()])];
+    assert_eq!(used_imports(&cache), expected);
+}
+
+/// A function's summary should list the effects in its type separately from those it handles
+#[test]
+fn effect_summary_of_function_with_handler() {
+    let source = [
+        "effect State a with",
+        "    get: Unit -> a",
+        "    put: a -> Unit",
+        "",
+        "effect IO with",
+        "    log: String -> Unit",
+        "",
+        "logged_state () =",
+        "    handle put (get () + 1i32)",
+        "    | get () ->",
+        "        log \"get\"",
+        "        resume 0",
+        "    | put _ -> resume ()",
+    ]
+    .join("\n");
+
+    let filename = example_path("effect_summary.an");
+    let cache = check(&filename, &source, &[]);
+
+    let summary = effect_summary(definition("logged_state", &cache), &cache);
+    let performed: Vec<_> = summary.performed.iter().map(|effect| display_effect(effect, &cache)).collect();
+    let handled: Vec<_> = summary.handled.iter().map(|effect| display_effect(effect, &cache)).collect();
+
+    assert_eq!(performed, ["IO"]);
+    assert_eq!(handled, ["State I32"]);
+}
+
+/// The principal type of a standalone expression should be generalized over its type variables
+#[test]
+fn principal_type_of_identity_lambda() {
+    let filename = example_path("principal_type.an");
+    let mut cache = check(&filename, "one = 1", &[]);
+
+    let mut ast = resolve_expression(&filename, "fn x -> x", &mut cache);
+    let typ = principal_type(&mut ast, &mut cache);
+
+    assert!(matches!(&typ, GeneralizedType::PolyType(typevars, _) if typevars.len() == 1));
+    assert_eq!(Type::display_generalized(&typ, &[], &cache), "forall a. (a -> a pure)");
+}
+
+/// Errors in an expression are returned rather than added to the cache, so
+/// later expressions can still be inferred as if the failed one never happened
+#[test]
+fn infer_standalone_expressions() {
+    let filename = example_path("infer_expression.an");
+    let mut cache = check(&filename, "double (x: I32) = x * 2", &[]);
+
+    let mut invalid = resolve_expression(&filename, "double \"two\"", &mut cache);
+    let errors = typechecker::infer_expression(&mut invalid, &mut cache).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(cache.error_count(), 0);
+
+    let mut valid = resolve_expression(&filename, "double 2", &mut cache);
+    let typ = typechecker::infer_expression(&mut valid, &mut cache).unwrap();
+    assert_eq!(typ, Type::int(IntegerKind::I32));
+}
+
+/// Each definition should depend on exactly the global definitions it refers to
+#[test]
+fn definition_dependency_graph_follows_calls() {
+    let source = ["top () = middle 1 + bottom ()", "middle x = x + bottom ()", "bottom () = 3"].join("\n");
+
+    let filename = example_path("dependency_graph.an");
+    let cache = check(&filename, &source, &[]);
+    let graph = definition_dependency_graph(&cache);
+
+    let local = ["top", "middle", "bottom"];
+    let dependencies = |name: &str| -> Vec<&str> {
+        let dependencies = graph.get(&definition(name, &cache)).cloned().unwrap_or_default();
+        let names = dependencies.into_iter().map(|id| cache[id].name.as_str());
+        names.filter(|name| local.contains(name)).collect()
+    };
+
+    assert_eq!(dependencies("top"), ["middle", "bottom"]);
+    assert_eq!(dependencies("middle"), ["bottom"]);
+    assert!(dependencies("bottom").is_empty());
+}
+
+/// Unifying two unbound type variables binds the variable opposite the bias,
+/// regardless of which side is the actual type
+#[test]
+fn try_unify_biased_binds_the_other_variable() {
+    let filename = example_path("biased.an");
+    let mut cache = ModuleCache::new(filename.parent().unwrap(), HashMap::new());
+
+    for bias in [Bias::Left, Bias::Right] {
+        let actual = typechecker::next_type_variable(&mut cache);
+        let expected = typechecker::next_type_variable(&mut cache);
+        let location = Location::builtin();
+
+        let result = try_unify_biased(&actual, &expected, bias, location, &mut cache, TypeErrorKind::NeverShown);
+        typechecker::perform_bindings_or_push_error(result, &mut cache);
+
+        let (kept, bound) = match bias {
+            Bias::Left => (&actual, &expected),
+            Bias::Right => (&expected, &actual),
+        };
+        assert_eq!(cache.follow_bindings(bound), *kept);
+        assert_eq!(cache.follow_bindings(kept), *kept);
+    }
+}
+
+/// A name pinned to a type variable should be shown in place of its automatic name,
+/// leaving the remaining type variables named a, b, etc. in order of appearance
+#[test]
+fn pinned_type_variable_names_are_displayed() {
+    colored::control::set_override(false);
+
+    let filename = example_path("pinned_names.an");
+    let mut cache = ModuleCache::new(filename.parent().unwrap(), HashMap::new());
+
+    let [a, elem, b] = [(); 3].map(|_| typechecker::next_type_variable(&mut cache));
+    let Type::TypeVariable(elem_id) = &elem else { unreachable!() };
+    cache.type_variable_names.insert(*elem_id, "the_elem_type".into());
+
+    let typ = function(vec![a.clone(), elem.clone(), b], elem.clone());
+    assert_eq!(typ.display(&cache).to_string(), "a - the_elem_type - b -> the_elem_type pure");
+
+    // Once bound, the pinned variable displays as the type it is bound to instead
+    let bindings = typechecker::try_unify(&elem, &a, Location::builtin(), &mut cache, TypeErrorKind::NeverShown);
+    typechecker::perform_bindings_or_push_error(bindings, &mut cache);
+    assert_eq!(typ.display(&cache).to_string(), "a - a - b -> a pure");
+}
+
+/// A generalized type displays its quantifiers followed by the constraints it is given
+#[test]
+fn display_generalized_type_with_constraints() {
+    colored::control::set_override(false);
+
+    let source = "larger (x: a) (y: a) = if x > y then x else y";
+    let filename = example_path("display_generalized.an");
+    let cache = check(&filename, source, &[]);
+    let info = &cache[definition("larger", &cache)];
+
+    let constraints: Vec<_> = info
+        .required_traits
+        .iter()
+        .enumerate()
+        .map(|(i, required)| required.as_constraint(ImplScopeId(0), VariableId(0), TraitConstraintId(i as u32)))
+        .collect();
+
+    let typ = info.typ.as_ref().unwrap();
+    assert_eq!(Type::display_generalized(typ, &constraints, &cache), "forall a. (a - a -> a pure) given Cmp a");
+    assert_eq!(Type::display_generalized(typ, &[], &cache), "forall a. (a - a -> a pure)");
+}
+
+/// Each `#if` selects its branch by the compilation target rather than by the host platform
+#[test]
+fn conditional_compilation_uses_target() {
+    colored::control::set_override(false);
+
+    let source = [
+        "separator = #if target_os then \"\\\\\" else '/'",
+        "line_ending = #if target_family then \"\\r\\n\" else '\\n'",
+    ]
+    .join("\n");
+
+    let filename = example_path("conditional_target.an");
+    let mut cache = common::module_cache(&filename, &source, &[]);
+    cache.target = CompilationTarget { os: "target_os".into(), family: "target_family".into() };
+    common::type_check(&filename, &source, &mut cache);
+
+    for name in ["separator", "line_ending"] {
+        let typ = cache[definition(name, &cache)].typ.as_ref().unwrap();
+        assert_eq!(typ.display(&cache).to_string(), "String");
+    }
+}
+
+/// Each trait constraint propagated out of a definition is reported once, on the parameter requiring it
+#[test]
+fn pending_constraints_of_function() {
+    let source = [
+        "show_larger (x: a) (y: b) (z: b) =",
+        "    print x",
+        "    if y > z then print \"y\" else print \"z\"",
+        "    print x",
+    ]
+    .join("\n");
+
+    let filename = example_path("pending_constraints.an");
+    let cache = check(&filename, &source, &[]);
+    let id = definition("show_larger", &cache);
+
+    let Some(GeneralizedType::PolyType(_, Type::Function(function))) = &cache[id].typ else {
+        panic!("show_larger should be a generic function");
+    };
+
+    let constraints = pending_constraints(id, &cache);
+    let mut constraints: Vec<_> = constraints
+        .iter()
+        .map(|constraint| {
+            let args = constraint.args.iter().map(|arg| cache.follow_bindings(arg));
+            (cache.trait_infos[constraint.trait_id.0].name.as_str(), args.collect::<Vec<_>>())
+        })
+        .collect();
+    constraints.sort_by_key(|(name, _)| *name);
+
+    let x = cache.follow_bindings(&function.parameters[0]);
+    let y = cache.follow_bindings(&function.parameters[1]);
+    assert_eq!(constraints, [("Cmp", vec![y]), ("Print", vec![x])]);
+}
+
+/// Only the uses which resolve to a definition are its references, not uses of a definition shadowing it
+#[test]
+fn references_to_shadowed_definition() {
+    let source = [
+        "double x = x * 2",
+        "a = double 1",
+        "b = double (double 2)",
+        "shadowed () =",
+        "    double = 3",
+        "    double + 1",
+    ]
+    .join("\n");
+
+    let filename = example_path("references_to.an");
+    let cache = check(&filename, &source, &[]);
+
+    let ids = cache.definition_infos.iter().enumerate().filter(|(_, info)| info.name == "double");
+    let ids: Vec<_> = ids.filter(|(_, info)| info.location.filename == filename).map(|(id, _)| id).collect();
+    let [global, local] = ids[..] else { panic!("Expected two definitions of double, found {ids:?}") };
+
+    let positions = |id| {
+        let references = references_to(DefinitionInfoId(id), &cache);
+        references.iter().map(|location| (location.start.line, location.start.column)).collect::<Vec<_>>()
+    };
+
+    assert_eq!(positions(global), [(2, 5), (3, 5), (3, 13)]);
+    assert_eq!(positions(local), [(6, 5)]);
+}
+
+/// Each distinct set of type arguments a generic function is used at is reported once
+#[test]
+fn monomorphizations_of_generic_function() {
+    let source = ["identity x = x", "a = identity 3i32", "b = identity \"three\"", "c = identity 4i32"].join("\n");
+
+    let filename = example_path("monomorphizations.an");
+    let cache = check(&filename, &source, &[]);
+
+    let string = Type::UserDefined(STRING_TYPE);
+    let monomorphizations = monomorphizations(definition("identity", &cache), &cache);
+    assert_eq!(monomorphizations, [vec![Type::int(IntegerKind::I32)], vec![string]]);
+}
+
+/// An impl with a `given` clause only applies if its constraints are also implemented
+#[test]
+fn type_implements_trait() {
+    let source = [
+        "trait Describe a with",
+        "    describe: a -> String",
+        "type Box a = contents: a",
+        "type Opaque = value: I32",
+        "impl Describe I32 with",
+        "    describe _ = \"I32\"",
+        "impl Describe (Box a) given Describe a with",
+        "    describe b = describe b.contents",
+    ]
+    .join("\n");
+
+    let filename = example_path("type_implements.an");
+    let mut cache = check(&filename, &source, &[]);
+
+    let describe = cache.trait_infos.iter().rposition(|info| info.name == "Describe").map(TraitInfoId).unwrap();
+    let type_id = |name: &str| TypeInfoId(cache.type_infos.iter().rposition(|info| info.name == name).unwrap());
+    let (box_, opaque) = (Type::UserDefined(type_id("Box")), Type::UserDefined(type_id("Opaque")));
+    let boxed = |typ| Type::TypeApplication(Box::new(box_.clone()), vec![typ]);
+
+    let i32 = Type::int(IntegerKind::I32);
+    assert!(type_implements(&i32, describe, &mut cache));
+    assert!(!type_implements(&opaque, describe, &mut cache));
+    assert!(type_implements(&boxed(i32), describe, &mut cache));
+    assert!(!type_implements(&boxed(opaque), describe, &mut cache));
+}
+
+/// Only type variables which are neither generalized nor integer kinds are unresolved
+#[test]
+fn unresolved_variables_of_ambiguous_definition() {
+    let source = ["nothing = (fn () -> None) ()", "identity x = x", "one = 1"].join("\n");
+
+    let filename = example_path("unresolved_variables.an");
+    let cache = check(&filename, &source, &[]);
+
+    let unresolved = typechecker::unresolved_variables(&cache);
+    let unresolved: Vec<_> = unresolved.into_iter().filter(|(_, _, location)| location.filename == filename).collect();
+
+    let [(id, typevar, _)] = unresolved[..] else { panic!("Expected one unresolved variable, found {unresolved:?}") };
+    assert_eq!(id, definition("nothing", &cache));
+    assert!(matches!(cache.type_bindings[typevar.0], TypeBinding::Unbound(..)));
+}
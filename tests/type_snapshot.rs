@@ -0,0 +1,32 @@
+mod common;
+
+use std::collections::BTreeMap;
+
+use ante::types::typeprinter::type_signatures_snapshot;
+
+/// The snapshot of a module's types should be deterministic and free of
+/// any terminal colors so that it can be compared between compiler versions.
+#[test]
+fn type_signatures_snapshot_of_small_module() {
+    let source = [
+        "type Pair a b = first: a, second: b",
+        "swap (pair: Pair a b) = Pair pair.second pair.first",
+        "add3 a b c = a + b + c",
+        "answer = 42u8",
+        "greet name = print name",
+    ]
+    .join("\n");
+
+    let filename = common::example_path("snapshot.an");
+    let cache = common::check(&filename, &source, &[]);
+
+    let expected = BTreeMap::from([
+        ("Pair".to_string(), "forall a b. (a - b -> Pair a b pure)".to_string()),
+        ("add3".to_string(), "forall a. (a - a - a -> a pure)\n  given Add a".to_string()),
+        ("answer".to_string(), "U8".to_string()),
+        ("greet".to_string(), "forall a. (a -> Unit pure)\n  given Print a".to_string()),
+        ("swap".to_string(), "forall a b. (Pair a b -> Pair b a pure)".to_string()),
+    ]);
+
+    assert_eq!(type_signatures_snapshot(&cache), expected);
+}
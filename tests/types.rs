@@ -0,0 +1,26 @@
+mod common;
+
+use ante::lexer::token::IntegerKind;
+use ante::types::{PrimitiveType, Type, PAIR_TYPE, STRING_TYPE};
+use common::function;
+
+/// Build `I32 - (I32, String) -> I32`, with each integer type given by `int`
+fn compound_type(int: Type) -> Type {
+    let string = Type::UserDefined(STRING_TYPE);
+    let application = Type::TypeApplication(Box::new(Type::UserDefined(PAIR_TYPE)), vec![int.clone(), string]);
+    function(vec![int.clone(), application], int)
+}
+
+/// walk_mut should visit every nested type, allowing each to be rewritten in place
+#[test]
+fn walk_mut_rewrites_every_leaf() {
+    let mut typ = compound_type(Type::int(IntegerKind::I32));
+
+    typ.walk_mut(|typ| {
+        if *typ == Type::Primitive(PrimitiveType::IntegerTag(IntegerKind::I32)) {
+            *typ = Type::Primitive(PrimitiveType::IntegerTag(IntegerKind::I64));
+        }
+    });
+
+    assert_eq!(typ, compound_type(Type::int(IntegerKind::I64)));
+}